@@ -0,0 +1,154 @@
+//! Minimal integrator of `adaptive_cpamm`: forwards a swap through
+//! `adaptive_cpamm::cpi::swap`, reads `amount_out` from the return data and
+//! pays the trader a rebate of `rebate_bps` on it out of a router-owned vault.
+//! Build it with `event-cpi` exactly when the pool is built with it: the
+//! pool's swap then also takes its event authority, which callers pass as
+//! `cpamm_event_authority`.
+
+use adaptive_cpamm::cpi::accounts::Swap as CpammSwap;
+use adaptive_cpamm::program::AdaptiveCpamm;
+use adaptive_cpamm::{Pool, POOL_SEED};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
+
+pub const ROUTER_SEED: &[u8] = b"router";
+
+#[program]
+pub mod fee_router {
+    use super::*;
+
+    pub fn initialize(ctx: Context<Initialize>, rebate_bps: u16) -> Result<()> {
+        require!(rebate_bps <= 10_000, RouterError::BadRebate);
+        let router = &mut ctx.accounts.router;
+        router.authority = ctx.accounts.authority.key();
+        router.rebate_bps = rebate_bps;
+        router.bump = ctx.bumps.router;
+        Ok(())
+    }
+
+    /// Swap through the pool, then rebate `rebate_bps` of the output
+    /// (capped at what the rebate vault holds). Returns the total received.
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, RouterSwap<'info>>,
+        token_in_is_0: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        deadline: i64,
+    ) -> Result<u64> {
+        // The user's signature carries through the CPI; the pool signs its
+        // own vault transfer with the pool PDA, so no seeds are needed here.
+        let cpi_accounts = CpammSwap {
+            user: ctx.accounts.user.to_account_info(),
+            pool: ctx.accounts.pool.to_account_info(),
+            vault0: ctx.accounts.vault0.to_account_info(),
+            vault1: ctx.accounts.vault1.to_account_info(),
+            mint_out: ctx.accounts.mint_out.to_account_info(),
+            user_token_in: ctx.accounts.user_token_in.to_account_info(),
+            recipient: ctx.accounts.user.to_account_info(),
+            user_token_out: ctx.accounts.user_token_out.to_account_info(),
+            pool_signer: ctx.accounts.pool_signer.to_account_info(),
+            token_program: ctx.accounts.token_program.to_account_info(),
+            associated_token_program: ctx.accounts.associated_token_program.to_account_info(),
+            system_program: ctx.accounts.system_program.to_account_info(),
+            instructions: Some(ctx.accounts.instructions.to_account_info()),
+            #[cfg(feature = "event-cpi")]
+            event_authority: ctx.accounts.cpamm_event_authority.to_account_info(),
+            #[cfg(feature = "event-cpi")]
+            program: ctx.accounts.cpamm_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.cpamm_program.to_account_info(), cpi_accounts)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        let amount_out =
+            adaptive_cpamm::cpi::swap(cpi_ctx, token_in_is_0, amount_in, min_amount_out, deadline)?
+                .get();
+
+        let router = &ctx.accounts.router;
+        let rebate = (amount_out as u128 * router.rebate_bps as u128 / 10_000) as u64;
+        let rebate = rebate.min(ctx.accounts.rebate_vault.amount);
+        if rebate > 0 {
+            let signer: &[&[&[u8]]] = &[&[ROUTER_SEED, &[router.bump]]];
+            token::transfer(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    Transfer {
+                        from: ctx.accounts.rebate_vault.to_account_info(),
+                        to: ctx.accounts.user_token_out.to_account_info(),
+                        authority: router.to_account_info(),
+                    },
+                    signer,
+                ),
+                rebate,
+            )?;
+        }
+        msg!("amount_out {} rebate {}", amount_out, rebate);
+        Ok(amount_out + rebate)
+    }
+}
+
+#[account]
+pub struct Router {
+    pub authority: Pubkey,
+    pub rebate_bps: u16,
+    pub bump: u8,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(init, payer = authority, space = 8 + 32 + 2 + 1, seeds = [ROUTER_SEED], bump)]
+    pub router: Account<'info, Router>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RouterSwap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(seeds = [ROUTER_SEED], bump = router.bump)]
+    pub router: Account<'info, Router>,
+    /// Output-side tokens owned by the router
+    #[account(mut, token::mint = mint_out, token::authority = router)]
+    pub rebate_vault: Account<'info, TokenAccount>,
+
+    // Pool accounts, validated again by adaptive_cpamm
+    #[account(mut, seeds = [POOL_SEED], bump = pool.bump, seeds::program = cpamm_program.key())]
+    pub pool: Account<'info, Pool>,
+    /// CHECK: pool vault, checked by the pool
+    #[account(mut)]
+    pub vault0: UncheckedAccount<'info>,
+    /// CHECK: pool vault, checked by the pool
+    #[account(mut)]
+    pub vault1: UncheckedAccount<'info>,
+    pub mint_out: Account<'info, Mint>,
+    /// CHECK: checked by the pool
+    #[account(mut)]
+    pub user_token_in: UncheckedAccount<'info>,
+    /// Must already exist: the rebate is paid into it after the swap
+    #[account(mut, token::mint = mint_out, token::authority = user)]
+    pub user_token_out: Account<'info, TokenAccount>,
+    /// CHECK: pool signer PDA, checked by the pool
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub cpamm_program: Program<'info, AdaptiveCpamm>,
+    pub token_program: Program<'info, Token>,
+    /// CHECK: associated token program, checked by the pool
+    pub associated_token_program: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: instructions sysvar, checked by the pool; lets pools that
+    /// only accept routed swaps see this program as the caller
+    pub instructions: UncheckedAccount<'info>,
+    /// CHECK: adaptive_cpamm's event authority PDA, checked by the pool;
+    /// present only with `event-cpi`, matching the pool's build
+    #[cfg(feature = "event-cpi")]
+    pub cpamm_event_authority: UncheckedAccount<'info>,
+}
+
+#[error_code]
+pub enum RouterError {
+    #[msg("Rebate above 100%")]
+    BadRebate,
+}
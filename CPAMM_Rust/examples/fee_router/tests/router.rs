@@ -0,0 +1,141 @@
+//! Both programs in one bank: the router CPIs into the pool's `swap`, reads
+//! `amount_out` from the return data and pays the rebate on top.
+
+#[path = "../../../tests/common/mod.rs"]
+mod common;
+
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::associated_token;
+use anchor_spl::token;
+use common::*;
+use solana_program_test::processor;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::Signer;
+use solana_sdk::{system_program, sysvar};
+
+fn process_router(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // same lifetime shim as the pool's entry in common
+    let accounts = unsafe { std::mem::transmute::<&[AccountInfo], &[AccountInfo]>(accounts) };
+    fee_router::entry(program_id, accounts, data)
+}
+
+fn router_address() -> Pubkey {
+    Pubkey::find_program_address(&[fee_router::ROUTER_SEED], &fee_router::ID).0
+}
+
+/// A pool with 10M/10M and a router rebating `rebate_bps`, its token1
+/// rebate vault holding `rebate_funds`.
+async fn setup(rebate_bps: u16, rebate_funds: u64) -> (TestPool, Pubkey) {
+    let mut program_test = program_test();
+    program_test.add_program("fee_router", fee_router::ID, processor!(process_router));
+    let mut t = TestPool::start(program_test, PoolSetup::default()).await;
+    t.seed_liquidity(10_000_000, 10_000_000).await;
+
+    let init = Instruction {
+        program_id: fee_router::ID,
+        accounts: fee_router::accounts::Initialize {
+            authority: t.ctx.payer.pubkey(),
+            router: router_address(),
+            system_program: system_program::ID,
+        }
+        .to_account_metas(None),
+        data: fee_router::instruction::Initialize { rebate_bps }.data(),
+    };
+    t.send(&[init], &[]).await.unwrap();
+    let mint1 = t.keys.token1_mint;
+    let rebate_vault = t.create_token_account(&router_address(), &mint1, rebate_funds).await;
+    (t, rebate_vault)
+}
+
+fn router_swap_ix(t: &TestPool, user: &User, rebate_vault: &Pubkey, amount_in: u64, min_out: u64) -> Instruction {
+    Instruction {
+        program_id: fee_router::ID,
+        accounts: fee_router::accounts::RouterSwap {
+            user: user.pubkey(),
+            router: router_address(),
+            rebate_vault: *rebate_vault,
+            pool: t.keys.pool,
+            vault0: t.keys.vault0,
+            vault1: t.keys.vault1,
+            mint_out: t.keys.token1_mint,
+            user_token_in: user.token0,
+            user_token_out: user.token1,
+            pool_signer: t.keys.pool,
+            cpamm_program: PROGRAM_ID,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            instructions: sysvar::instructions::ID,
+            #[cfg(feature = "event-cpi")]
+            cpamm_event_authority: Pubkey::find_program_address(&[b"__event_authority"], &PROGRAM_ID).0,
+        }
+        .to_account_metas(None),
+        data: fee_router::instruction::Swap {
+            token_in_is_0: true,
+            amount_in,
+            min_amount_out: min_out,
+            deadline: NO_DEADLINE,
+        }
+        .data(),
+    }
+}
+
+fn returned_u64(out: &TxOutput) -> u64 {
+    u64::from_le_bytes(out.return_data.as_ref().unwrap()[..8].try_into().unwrap())
+}
+
+#[tokio::test]
+async fn routed_swap_pays_the_pool_output_plus_the_rebate() {
+    let (mut t, rebate_vault) = setup(100, 1_000_000).await;
+    let trader = t.fund_user(100_000, 0).await;
+    let ix = router_swap_ix(&t, &trader, &rebate_vault, 100_000, 1);
+    let out = t.send(&[ix], &[&trader.keypair]).await.unwrap();
+
+    // read from the reserves rather than the SwapEvent, which an event-cpi
+    // build emits by self-CPI instead of logging
+    let pool = t.pool().await;
+    assert_eq!(pool.reserve0, 10_100_000);
+    let amount_out = 10_000_000 - pool.reserve1;
+    let rebate = amount_out / 100;
+    assert!(rebate > 0);
+    // the router's own return data replaces the pool's
+    assert_eq!(returned_u64(&out), amount_out + rebate);
+    assert_eq!(t.token_balance(&trader.token1).await, amount_out + rebate);
+    assert_eq!(t.token_balance(&trader.token0).await, 0);
+    assert_eq!(t.token_balance(&rebate_vault).await, 1_000_000 - rebate);
+}
+
+#[tokio::test]
+async fn routed_swap_matches_a_direct_swap() {
+    let (mut routed, rebate_vault) = setup(0, 0).await;
+    let trader = routed.fund_user(250_000, 0).await;
+    let ix = router_swap_ix(&routed, &trader, &rebate_vault, 250_000, 1);
+    let via_router = returned_u64(&routed.send(&[ix], &[&trader.keypair]).await.unwrap());
+
+    let mut direct = TestPool::new(PoolSetup::default()).await;
+    direct.seed_liquidity(10_000_000, 10_000_000).await;
+    let trader = direct.fund_user(250_000, 0).await;
+    direct.swap(&trader, true, 250_000, 1).await.unwrap();
+    assert_eq!(via_router, direct.token_balance(&trader.token1).await);
+}
+
+#[tokio::test]
+async fn rebate_is_capped_by_the_vault() {
+    let (mut t, rebate_vault) = setup(5_000, 10).await;
+    let trader = t.fund_user(100_000, 0).await;
+    let ix = router_swap_ix(&t, &trader, &rebate_vault, 100_000, 1);
+    let out = t.send(&[ix], &[&trader.keypair]).await.unwrap();
+    let amount_out = 10_000_000 - t.pool().await.reserve1;
+    assert_eq!(returned_u64(&out), amount_out + 10);
+    assert_eq!(t.token_balance(&rebate_vault).await, 0);
+}
+
+#[tokio::test]
+async fn pool_slippage_check_fails_through_the_router() {
+    let (mut t, rebate_vault) = setup(100, 1_000_000).await;
+    let trader = t.fund_user(100_000, 0).await;
+    let ix = router_swap_ix(&t, &trader, &rebate_vault, 100_000, 99_009);
+    assert_amm_error(t.send(&[ix], &[&trader.keypair]).await, adaptive_cpamm::AmmError::SlippageExceeded);
+}
@@ -0,0 +1,260 @@
+//! Reference keeper for `adaptive_cpamm`.
+//!
+//! Each run loads the pool, then:
+//! - cranks `snapshot` when the analytics ring exists and its interval has
+//!   passed, and
+//! - if the pool price is off a reference price by more than the fee,
+//!   sizes the most profitable swap back towards it with the program's own
+//!   math and submits it through the client builders.
+//!
+//! Configuration is read from the environment:
+//!
+//! | variable                      | default                   |
+//! |-------------------------------|---------------------------|
+//! | `KEEPER_RPC_URL`              | `http://127.0.0.1:8899`   |
+//! | `KEEPER_KEYPAIR`              | `~/.config/solana/id.json`|
+//! | `KEEPER_PROGRAM_ID`           | `adaptive_cpamm::ID`      |
+//! | `KEEPER_REFERENCE_PRICE_1E12` | unset: no arbitrage       |
+//! | `KEEPER_MIN_PROFIT`           | `0` (token1 units)        |
+//! | `KEEPER_SLIPPAGE_BPS`         | `50`                      |
+//! | `KEEPER_DRY_RUN`              | unset; set to simulate only |
+//!
+//! Profit is measured in token1 at the reference price. Quotes leave out
+//! the oracle fee term, so with a feed configured the executed fee can be
+//! higher; `min_amount_out` still bounds the result.
+
+use std::env;
+use std::error::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use adaptive_cpamm::client::{
+    build_snapshot_ix, build_swap_ix, derive_pool_address, derive_snapshots_address, PoolKeys,
+};
+use adaptive_cpamm::math::{
+    breaker_verdict, compute_dynamic_fee, isqrt, mul_div, post_trade_deviation_1e12,
+    swap_amounts, Rounding, SCALE,
+};
+use adaptive_cpamm::{Pool, Snapshots};
+use anchor_lang::AccountDeserialize;
+use anchor_spl::associated_token::get_associated_token_address;
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{read_keypair_file, Keypair, Signer};
+use solana_sdk::transaction::Transaction;
+
+type KeeperResult<T> = std::result::Result<T, Box<dyn Error>>;
+
+/// Seconds a submitted swap stays valid.
+const DEADLINE_SECONDS: i64 = 60;
+
+struct Config {
+    rpc_url: String,
+    keypair_path: String,
+    program_id: Pubkey,
+    reference_price_1e12: Option<u128>,
+    min_profit: u128,
+    slippage_bps: u64,
+    dry_run: bool,
+}
+
+impl Config {
+    fn from_env() -> KeeperResult<Self> {
+        let var = |name: &str| env::var(name).ok();
+        Ok(Config {
+            rpc_url: var("KEEPER_RPC_URL").unwrap_or_else(|| "http://127.0.0.1:8899".into()),
+            keypair_path: var("KEEPER_KEYPAIR").unwrap_or_else(|| {
+                format!("{}/.config/solana/id.json", var("HOME").unwrap_or_default())
+            }),
+            program_id: match var("KEEPER_PROGRAM_ID") {
+                Some(id) => id.parse()?,
+                None => adaptive_cpamm::ID,
+            },
+            reference_price_1e12: var("KEEPER_REFERENCE_PRICE_1E12")
+                .map(|p| p.parse())
+                .transpose()?,
+            min_profit: var("KEEPER_MIN_PROFIT").map(|p| p.parse()).transpose()?.unwrap_or(0),
+            slippage_bps: var("KEEPER_SLIPPAGE_BPS").map(|s| s.parse()).transpose()?.unwrap_or(50),
+            dry_run: var("KEEPER_DRY_RUN").is_some(),
+        })
+    }
+}
+
+/// A swap worth submitting.
+#[derive(Debug)]
+struct Arbitrage {
+    token_in_is_0: bool,
+    amount_in: u64,
+    amount_out: u64,
+    profit: u128,
+}
+
+fn main() -> KeeperResult<()> {
+    let config = Config::from_env()?;
+    let rpc = RpcClient::new(config.rpc_url.clone());
+    let payer = read_keypair_file(&config.keypair_path)?;
+
+    let pool_address = derive_pool_address(&config.program_id).0;
+    let pool = Pool::try_deserialize(&mut &rpc.get_account_data(&pool_address)?[..])?;
+    let keys = PoolKeys::from_pool(&config.program_id, &pool);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+
+    let snapshots = derive_snapshots_address(&config.program_id, &pool_address);
+    if let Ok(data) = rpc.get_account_data(&snapshots) {
+        let ring = Snapshots::decode(&data)?;
+        if ring.len == 0 || now >= ring.last_timestamp + ring.interval_seconds {
+            println!("snapshot due (last {})", ring.last_timestamp);
+            submit(&rpc, &payer, build_snapshot_ix(&config.program_id), config.dry_run)?;
+        }
+    }
+
+    let Some(reference) = config.reference_price_1e12 else {
+        return Ok(());
+    };
+    if pool.paused || pool.manually_tripped {
+        println!("pool not swappable; skipping arbitrage");
+        return Ok(());
+    }
+    match find_arbitrage(&pool, reference)? {
+        Some(arb) if arb.profit > config.min_profit => {
+            println!("{arb:?}");
+            let (mint_in, mint_out) = if arb.token_in_is_0 {
+                (keys.token0_mint, keys.token1_mint)
+            } else {
+                (keys.token1_mint, keys.token0_mint)
+            };
+            let min_amount_out = mul_div(
+                arb.amount_out as u128,
+                (10_000 - config.slippage_bps) as u128,
+                10_000,
+                Rounding::Down,
+            )
+            .ok_or("slippage overflow")? as u64;
+            let swap = build_swap_ix(
+                &config.program_id,
+                &keys,
+                &payer.pubkey(),
+                &get_associated_token_address(&payer.pubkey(), &mint_in),
+                &get_associated_token_address(&payer.pubkey(), &mint_out),
+                arb.token_in_is_0,
+                arb.amount_in,
+                min_amount_out,
+                now + DEADLINE_SECONDS,
+            );
+            submit(&rpc, &payer, swap, config.dry_run)?;
+        }
+        _ => println!("no profitable arbitrage at reference {reference}"),
+    }
+    Ok(())
+}
+
+/// Quote a swap exactly as the program prices it (oracle term excluded).
+fn quote(pool: &Pool, token_in_is_0: bool, amount_in: u64) -> Option<u64> {
+    let (r0, r1) = (pool.reserve0 as u128, pool.reserve1 as u128);
+    let (fee_ppm, vol_1e12, _, _) =
+        compute_dynamic_fee(&pool.fee_params(), token_in_is_0, amount_in as u128, r0, r1, 0)
+            .ok()?;
+    let (rin, rout) = if token_in_is_0 { (r0, r1) } else { (r1, r0) };
+    let (out, _) = swap_amounts(amount_in, rin, rout, fee_ppm, pool.fee_on_output).ok()?;
+    let post_vol_1e12 = post_trade_deviation_1e12(
+        pool.ema_price_1e18,
+        token_in_is_0,
+        r0,
+        r1,
+        amount_in as u128,
+        out as u128,
+    )
+    .ok()?;
+    let threshold = pool.breaker_threshold_1e12();
+    breaker_verdict(pool.manually_tripped, threshold, vol_1e12, post_vol_1e12)
+        .is_open()
+        .then_some(out)
+}
+
+/// Profit in token1 at the reference price; None when the trade loses.
+fn profit(
+    token_in_is_0: bool,
+    amount_in: u64,
+    amount_out: u64,
+    reference_1e12: u128,
+) -> Option<u128> {
+    let (value_in, value_out) = if token_in_is_0 {
+        let value_in = mul_div(amount_in as u128, reference_1e12, SCALE, Rounding::Up)?;
+        (value_in, amount_out as u128)
+    } else {
+        let value_out = mul_div(amount_out as u128, reference_1e12, SCALE, Rounding::Down)?;
+        (amount_in as u128, value_out)
+    };
+    value_out.checked_sub(value_in)
+}
+
+/// Size the swap that moves the pool towards `reference_1e12` for the
+/// most profit. The fee makes profit unimodal in the input, so a ternary
+/// search up to the no-fee optimum finds it.
+fn find_arbitrage(pool: &Pool, reference_1e12: u128) -> KeeperResult<Option<Arbitrage>> {
+    let (r0, r1) = (pool.reserve0 as u128, pool.reserve1 as u128);
+    if r0 == 0 || r1 == 0 {
+        return Ok(None);
+    }
+    let spot_1e12 = mul_div(r1, SCALE, r0, Rounding::Down).ok_or("spot overflow")?;
+    // token0 cheap in the pool: buy it with token1, and vice versa
+    let token_in_is_0 = spot_1e12 > reference_1e12;
+
+    // without fees the price lands on the reference once
+    // r0' = sqrt(k / P) (token0 in) or r1' = sqrt(k * P) (token1 in)
+    let k = r0.checked_mul(r1).ok_or("k overflow")?;
+    let target = if token_in_is_0 {
+        let r0_after = mul_div(k, SCALE, reference_1e12.max(1), Rounding::Down).ok_or("overflow")?;
+        isqrt(r0_after).saturating_sub(r0)
+    } else {
+        let r1_after = mul_div(k, reference_1e12, SCALE, Rounding::Down).ok_or("overflow")?;
+        isqrt(r1_after).saturating_sub(r1)
+    };
+    let (mut lo, mut hi) = (1u64, u64::try_from(target).unwrap_or(u64::MAX));
+    if hi < lo {
+        return Ok(None);
+    }
+
+    let value = |amount_in: u64| {
+        quote(pool, token_in_is_0, amount_in)
+            .and_then(|out| profit(token_in_is_0, amount_in, out, reference_1e12))
+            .unwrap_or(0)
+    };
+    while hi - lo > 2 {
+        let third = (hi - lo) / 3;
+        let (m1, m2) = (lo + third, hi - third);
+        if value(m1) < value(m2) {
+            lo = m1;
+        } else {
+            hi = m2;
+        }
+    }
+    let best = (lo..=hi).max_by_key(|&amount| value(amount)).unwrap_or(lo);
+    Ok(match quote(pool, token_in_is_0, best) {
+        Some(amount_out) => profit(token_in_is_0, best, amount_out, reference_1e12).map(|profit| {
+            Arbitrage {
+                token_in_is_0,
+                amount_in: best,
+                amount_out,
+                profit,
+            }
+        }),
+        None => None,
+    })
+}
+
+/// Send `ix`, or only simulate it and print the logs in dry-run mode.
+fn submit(rpc: &RpcClient, payer: &Keypair, ix: Instruction, dry_run: bool) -> KeeperResult<()> {
+    let blockhash = rpc.get_latest_blockhash()?;
+    let tx = Transaction::new_signed_with_payer(&[ix], Some(&payer.pubkey()), &[payer], blockhash);
+    if dry_run {
+        let result = rpc.simulate_transaction(&tx)?.value;
+        println!("simulated: err={:?}", result.err);
+        for line in result.logs.unwrap_or_default() {
+            println!("  {line}");
+        }
+    } else {
+        println!("sent {}", rpc.send_and_confirm_transaction(&tx)?);
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+//! Time-weighted price accumulators with wrapping arithmetic.
+//!
+//! `price_cumulative_1e18` and `log2_price_cumulative` grow forever; with
+//! checked math they would eventually overflow and fail every instruction
+//! that touches reserves. Instead they wrap modulo 2^128, as in Uniswap v2.
+//! Only the difference between two readings is meaningful, and that
+//! difference is exact as long as the true increase between them is below
+//! 2^128: even a price of 1e12 (1e30 on the 1e18 grid) takes about ten
+//! years to add that much.
+//!
+//! The wrapping is confined to this module; everything else keeps checked
+//! math.
+
+/// Advance `cumulative` by `value * elapsed`, wrapping modulo 2^128.
+pub fn accumulate(cumulative: u128, value: u128, elapsed: u64) -> u128 {
+    cumulative.wrapping_add(value.wrapping_mul(elapsed as u128))
+}
+
+/// Increase from `start` to `end`, correct across one wrap of the counter.
+pub fn delta(start: u128, end: u128) -> u128 {
+    end.wrapping_sub(start)
+}
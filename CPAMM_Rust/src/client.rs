@@ -0,0 +1,1650 @@
+//! Off-chain instruction builders and PDA helpers (`client` feature).
+//!
+//! Builders fill in every account of the matching context, so callers
+//! using plain `solana-client` never hand-assemble metas. Addresses are
+//! derived from the same seed constants the program uses.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{Instruction, InstructionError};
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::system_program;
+use anchor_lang::{InstructionData, ToAccountMetas};
+use anchor_spl::associated_token::{self, get_associated_token_address};
+use anchor_spl::token;
+
+use crate::{
+    accounts, instruction, AdminLog, AdminLogEntry, AmmError, OracleSource, Pool, SlipMode,
+    ADMIN_LOG_SEED, CONFIG_SEED, DENYLIST_SEED, LAUNCH_BUYS_SEED, MINT_BADGE_SEED, POOL_SEED,
+    POSITION_SEED, SIMULATE_SWAP_LOG_PREFIX, SNAPSHOTS_SEED,
+};
+
+/// The singleton pool PDA and its bump.
+pub fn derive_pool_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[POOL_SEED], program_id)
+}
+
+/// Vault ATAs of the pool for (token0, token1).
+pub fn derive_vaults(pool: &Pubkey, token0_mint: &Pubkey, token1_mint: &Pubkey) -> (Pubkey, Pubkey) {
+    (
+        get_associated_token_address(pool, token0_mint),
+        get_associated_token_address(pool, token1_mint),
+    )
+}
+
+pub fn derive_position_address(program_id: &Pubkey, pool: &Pubkey, owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[POSITION_SEED, pool.as_ref(), owner.as_ref()], program_id).0
+}
+
+pub fn derive_snapshots_address(program_id: &Pubkey, pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[SNAPSHOTS_SEED, pool.as_ref()], program_id).0
+}
+
+pub fn derive_denylist_address(program_id: &Pubkey, pool: &Pubkey, address: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[DENYLIST_SEED, pool.as_ref(), address.as_ref()], program_id).0
+}
+
+pub fn derive_launch_buys_address(program_id: &Pubkey, pool: &Pubkey, wallet: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[LAUNCH_BUYS_SEED, pool.as_ref(), wallet.as_ref()], program_id).0
+}
+
+pub fn derive_admin_log_address(program_id: &Pubkey, pool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[ADMIN_LOG_SEED, pool.as_ref()], program_id).0
+}
+
+pub fn derive_config_address(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[CONFIG_SEED], program_id).0
+}
+
+pub fn derive_mint_badge_address(program_id: &Pubkey, mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[MINT_BADGE_SEED, mint.as_ref()], program_id).0
+}
+
+#[cfg(feature = "event-cpi")]
+fn derive_event_authority(program_id: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"__event_authority"], program_id).0
+}
+
+/// Addresses every builder needs, read once from the pool account.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolKeys {
+    pub pool: Pubkey,
+    pub token0_mint: Pubkey,
+    pub token1_mint: Pubkey,
+    pub vault0: Pubkey,
+    pub vault1: Pubkey,
+    pub lp_mint: Pubkey,
+    /// appended to the remaining accounts of `swap` when set
+    pub oracle: Option<Pubkey>,
+    /// set once `init_rewards` has run
+    pub reward_mint: Option<Pubkey>,
+    /// trades and deposits carry the user's denylist marker after it
+    pub enforce_denylist: bool,
+    /// while nonzero, swaps also carry the user's LaunchBuys record; set to
+    /// 0 once the window has passed to drop it
+    pub launch_end_slot: u64,
+}
+
+impl PoolKeys {
+    pub fn from_pool(program_id: &Pubkey, pool: &Pool) -> Self {
+        PoolKeys {
+            pool: derive_pool_address(program_id).0,
+            token0_mint: pool.token0_mint,
+            token1_mint: pool.token1_mint,
+            vault0: pool.vault0,
+            vault1: pool.vault1,
+            lp_mint: pool.lp_mint,
+            oracle: match pool.oracle_source {
+                OracleSource::None => None,
+                _ => Some(pool.oracle),
+            },
+            reward_mint: (pool.reward_mint != Pubkey::default()).then_some(pool.reward_mint),
+            enforce_denylist: pool.enforce_denylist,
+            launch_end_slot: if pool.max_buy_per_wallet > 0 { pool.launch_end_slot } else { 0 },
+        }
+    }
+}
+
+/// Append the denylist markers of `holders` not passed yet when the pool
+/// enforces the denylist.
+fn push_denylist_markers(program_id: &Pubkey, keys: &PoolKeys, holders: &[Pubkey], ix: &mut Instruction) {
+    if keys.enforce_denylist {
+        for holder in holders {
+            let marker = derive_denylist_address(program_id, &keys.pool, holder);
+            if !ix.accounts.iter().any(|meta| meta.pubkey == marker) {
+                ix.accounts.push(AccountMeta::new_readonly(marker, false));
+            }
+        }
+    }
+}
+
+/// Pass the denylist markers of `holders` to a trade or liquidity
+/// instruction built by this module. The builders pass the signer's (and a
+/// swap recipient's); token accounts owned by anyone else need their
+/// owners' too.
+pub fn with_denylist_markers(
+    mut trade_ix: Instruction,
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    holders: &[Pubkey],
+) -> Instruction {
+    push_denylist_markers(program_id, keys, holders, &mut trade_ix);
+    trade_ix
+}
+
+/// Append `user`'s LaunchBuys record (writable) while the pool has a launch
+/// window.
+fn push_launch_buys(program_id: &Pubkey, keys: &PoolKeys, user: &Pubkey, ix: &mut Instruction) {
+    if keys.launch_end_slot > 0 {
+        let record = derive_launch_buys_address(program_id, &keys.pool, user);
+        ix.accounts.push(AccountMeta::new(record, false));
+    }
+}
+
+fn ix(program_id: &Pubkey, accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// `initialize_pool`; `lp_mint` must also sign (it is created here).
+/// Pass `with_badges` when the Config requires mint badges.
+#[allow(clippy::too_many_arguments)]
+pub fn build_initialize_pool_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    token0_mint: &Pubkey,
+    token1_mint: &Pubkey,
+    lp_mint: &Pubkey,
+    with_badges: bool,
+    args: instruction::InitializePool,
+) -> Instruction {
+    let pool = derive_pool_address(program_id).0;
+    let (vault0, vault1) = derive_vaults(&pool, token0_mint, token1_mint);
+    ix(
+        program_id,
+        accounts::InitializePool {
+            authority: *authority,
+            pool,
+            token0_mint: *token0_mint,
+            token1_mint: *token1_mint,
+            config: derive_config_address(program_id),
+            token0_badge: with_badges.then(|| derive_mint_badge_address(program_id, token0_mint)),
+            token1_badge: with_badges.then(|| derive_mint_badge_address(program_id, token1_mint)),
+            lp_mint: *lp_mint,
+            vault0,
+            vault1,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            rent: sysvar::rent::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        args,
+    )
+}
+
+/// `initialize_config`; `admin` must be the program's upgrade authority.
+pub fn build_initialize_config_ix(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    require_badges: bool,
+) -> Instruction {
+    let program_data = Pubkey::find_program_address(
+        &[program_id.as_ref()],
+        &anchor_lang::solana_program::bpf_loader_upgradeable::ID,
+    )
+    .0;
+    ix(
+        program_id,
+        accounts::InitializeConfig {
+            admin: *admin,
+            config: derive_config_address(program_id),
+            program: *program_id,
+            program_data,
+            system_program: system_program::ID,
+        },
+        instruction::InitializeConfig { require_badges },
+    )
+}
+
+pub fn build_set_config_ix(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    new_admin: &Pubkey,
+    require_badges: bool,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::SetConfig {
+            admin: *admin,
+            config: derive_config_address(program_id),
+        },
+        instruction::SetConfig {
+            admin: *new_admin,
+            require_badges,
+        },
+    )
+}
+
+pub fn build_approve_mint_ix(program_id: &Pubkey, admin: &Pubkey, mint: &Pubkey) -> Instruction {
+    ix(
+        program_id,
+        accounts::ApproveMint {
+            admin: *admin,
+            config: derive_config_address(program_id),
+            mint: *mint,
+            badge: derive_mint_badge_address(program_id, mint),
+            system_program: system_program::ID,
+        },
+        instruction::ApproveMint {},
+    )
+}
+
+pub fn build_revoke_mint_ix(program_id: &Pubkey, admin: &Pubkey, mint: &Pubkey) -> Instruction {
+    ix(
+        program_id,
+        accounts::RevokeMint {
+            admin: *admin,
+            config: derive_config_address(program_id),
+            badge: derive_mint_badge_address(program_id, mint),
+        },
+        instruction::RevokeMint {},
+    )
+}
+
+fn admin_accounts(program_id: &Pubkey, authority: &Pubkey) -> accounts::SetParams {
+    accounts::SetParams {
+        authority: *authority,
+        pool: derive_pool_address(program_id).0,
+        admin_log: None,
+        #[cfg(feature = "event-cpi")]
+        event_authority: derive_event_authority(program_id),
+        #[cfg(feature = "event-cpi")]
+        program: *program_id,
+    }
+}
+
+pub fn build_set_params_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    args: instruction::SetParams,
+) -> Instruction {
+    ix(program_id, admin_accounts(program_id, authority), args)
+}
+
+pub fn build_set_oracle_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    args: instruction::SetOracle,
+) -> Instruction {
+    ix(program_id, admin_accounts(program_id, authority), args)
+}
+
+pub fn build_set_paused_ix(program_id: &Pubkey, authority: &Pubkey, paused: bool) -> Instruction {
+    ix(program_id, admin_accounts(program_id, authority), instruction::SetPaused { paused })
+}
+
+pub fn build_set_treasury_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    treasury: Pubkey,
+    skim_to_treasury: bool,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetTreasury { treasury, skim_to_treasury },
+    )
+}
+
+pub fn build_set_max_price_impact_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_price_impact_bps: u16,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetMaxPriceImpact { max_price_impact_bps },
+    )
+}
+
+pub fn build_set_ema_max_age_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    ema_max_age_seconds: u32,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetEmaMaxAge { ema_max_age_seconds },
+    )
+}
+
+pub fn build_set_guardian_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    guardian: Pubkey,
+) -> Instruction {
+    ix(program_id, admin_accounts(program_id, authority), instruction::SetGuardian { guardian })
+}
+
+pub fn build_set_protocol_fee_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    protocol_fee_share_bps: u16,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetProtocolFee { protocol_fee_share_bps },
+    )
+}
+
+/// `collect_protocol_fees`; `signer` is the authority, treasurer or treasury.
+pub fn build_collect_protocol_fees_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    signer: &Pubkey,
+    treasury_token0: &Pubkey,
+    treasury_token1: &Pubkey,
+    collect_as_token_0: Option<bool>,
+    min_swap_out: u64,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::CollectProtocolFees {
+            signer: *signer,
+            pool: keys.pool,
+            admin_log: None,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            treasury_token0: *treasury_token0,
+            treasury_token1: *treasury_token1,
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::CollectProtocolFees { collect_as_token_0, min_swap_out },
+    )
+}
+
+pub fn build_set_twap_defaults_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    default_twap_window_seconds: u32,
+    max_observation_staleness_seconds: u32,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetTwapDefaults {
+            default_twap_window_seconds,
+            max_observation_staleness_seconds,
+        },
+    )
+}
+
+pub fn build_set_min_trade_amounts_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    min_trade_amount0: u64,
+    min_trade_amount1: u64,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetMinTradeAmounts {
+            min_trade_amount0,
+            min_trade_amount1,
+        },
+    )
+}
+
+pub fn build_set_max_trade_size_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_trade_bps_of_reserve: u16,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetMaxTradeSize { max_trade_bps_of_reserve },
+    )
+}
+
+pub fn build_set_roles_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    fee_manager: Pubkey,
+    pauser: Pubkey,
+    treasurer: Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetRoles { fee_manager, pauser, treasurer },
+    )
+}
+
+pub fn build_set_metadata_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    name: String,
+    uri: String,
+) -> Instruction {
+    ix(program_id, admin_accounts(program_id, authority), instruction::SetMetadata { name, uri })
+}
+
+pub fn build_set_slip_mode_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    slip_mode: SlipMode,
+) -> Instruction {
+    ix(program_id, admin_accounts(program_id, authority), instruction::SetSlipMode { slip_mode })
+}
+
+pub fn build_set_co_authority_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    co_authority: Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetCoAuthority { co_authority },
+    )
+}
+
+pub fn build_set_compliance_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    compliance: Pubkey,
+    enforce_denylist: bool,
+    denylist_allows_removal: bool,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetCompliance {
+            compliance,
+            enforce_denylist,
+            denylist_allows_removal,
+        },
+    )
+}
+
+/// `deny_address`; `payer` funds the marker's rent.
+pub fn build_deny_address_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    address: Pubkey,
+) -> Instruction {
+    let pool = derive_pool_address(program_id).0;
+    ix(
+        program_id,
+        accounts::DenyAddress {
+            authority: *authority,
+            payer: *payer,
+            pool,
+            admin_log: None,
+            marker: derive_denylist_address(program_id, &pool, &address),
+            system_program: system_program::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::DenyAddress { address },
+    )
+}
+
+/// `allow_address`; the marker's rent goes to `rent_receiver`.
+pub fn build_allow_address_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    rent_receiver: &Pubkey,
+    address: &Pubkey,
+) -> Instruction {
+    let pool = derive_pool_address(program_id).0;
+    ix(
+        program_id,
+        accounts::AllowAddress {
+            authority: *authority,
+            pool,
+            admin_log: None,
+            marker: derive_denylist_address(program_id, &pool, address),
+            rent_receiver: *rent_receiver,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::AllowAddress {},
+    )
+}
+
+pub fn build_set_reversion_penalty_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    reversion_window_slots: u64,
+    reversion_band_bps: u16,
+    reversion_penalty_bps: u16,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetReversionPenalty {
+            reversion_window_slots,
+            reversion_band_bps,
+            reversion_penalty_bps,
+        },
+    )
+}
+
+pub fn build_set_routers_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    only_via_router: bool,
+    routers: Vec<Pubkey>,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetRouters { only_via_router, routers },
+    )
+}
+
+fn guardian_accounts(program_id: &Pubkey, signer: &Pubkey) -> accounts::GuardianAction {
+    accounts::GuardianAction {
+        signer: *signer,
+        pool: derive_pool_address(program_id).0,
+        admin_log: None,
+        #[cfg(feature = "event-cpi")]
+        event_authority: derive_event_authority(program_id),
+        #[cfg(feature = "event-cpi")]
+        program: *program_id,
+    }
+}
+
+/// `trip_breaker`; `signer` is the guardian or the authority.
+pub fn build_trip_breaker_ix(program_id: &Pubkey, signer: &Pubkey) -> Instruction {
+    ix(program_id, guardian_accounts(program_id, signer), instruction::TripBreaker {})
+}
+
+pub fn build_reset_breaker_ix(program_id: &Pubkey, signer: &Pubkey) -> Instruction {
+    ix(program_id, guardian_accounts(program_id, signer), instruction::ResetBreaker {})
+}
+
+fn freeze_accounts(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    authority: &Pubkey,
+    lp_account: &Pubkey,
+) -> accounts::FreezeLpAccount {
+    accounts::FreezeLpAccount {
+        authority: *authority,
+        pool: keys.pool,
+        admin_log: None,
+        lp_mint: keys.lp_mint,
+        lp_account: *lp_account,
+        pool_signer: derive_pool_address(program_id).0,
+        token_program: token::ID,
+        #[cfg(feature = "event-cpi")]
+        event_authority: derive_event_authority(program_id),
+        #[cfg(feature = "event-cpi")]
+        program: *program_id,
+    }
+}
+
+pub fn build_freeze_lp_account_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    authority: &Pubkey,
+    lp_account: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        freeze_accounts(program_id, keys, authority, lp_account),
+        instruction::FreezeLpAccount {},
+    )
+}
+
+pub fn build_thaw_lp_account_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    authority: &Pubkey,
+    lp_account: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        freeze_accounts(program_id, keys, authority, lp_account),
+        instruction::ThawLpAccount {},
+    )
+}
+
+pub fn build_revoke_lp_freeze_authority_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    authority: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::RevokeLpFreezeAuthority {
+            authority: *authority,
+            pool: keys.pool,
+            admin_log: None,
+            lp_mint: keys.lp_mint,
+            token_program: token::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::RevokeLpFreezeAuthority {},
+    )
+}
+
+/// `rescue_tokens`; `signer` is the authority or the treasurer.
+pub fn build_rescue_tokens_ix(
+    program_id: &Pubkey,
+    signer: &Pubkey,
+    source: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let pool = derive_pool_address(program_id).0;
+    ix(
+        program_id,
+        accounts::RescueTokens {
+            signer: *signer,
+            pool,
+            admin_log: None,
+            source: *source,
+            destination: *destination,
+            pool_signer: pool,
+            token_program: token::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::RescueTokens { amount },
+    )
+}
+
+/// A per-pool record for `close_pool` to close along with the pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolRecord {
+    /// The denylist marker of this address
+    Denylisted(Pubkey),
+    /// The LaunchBuys of this wallet; its rent goes back to the wallet
+    LaunchBuys(Pubkey),
+    /// The Position of this owner; its rent goes back to the owner
+    Position(Pubkey),
+    /// A TwapCheckpoint account
+    TwapCheckpoint(Pubkey),
+}
+
+/// `close_pool`; pass `with_snapshots` when the snapshots PDA exists, and
+/// every record of the pool in `records`, or their rent stays locked. The
+/// reward vault and LP escrow go along once `keys.reward_mint` is set.
+pub fn build_close_pool_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    with_snapshots: bool,
+    records: &[PoolRecord],
+) -> Instruction {
+    let mut close = ix(
+        program_id,
+        accounts::ClosePool {
+            authority: *authority,
+            pool: keys.pool,
+            admin_log: None,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            reward_vault: keys
+                .reward_mint
+                .map(|reward_mint| get_associated_token_address(&keys.pool, &reward_mint)),
+            lp_escrow: keys
+                .reward_mint
+                .map(|_| get_associated_token_address(&keys.pool, &keys.lp_mint)),
+            snapshots: with_snapshots.then(|| derive_snapshots_address(program_id, &keys.pool)),
+            recipient: *recipient,
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::ClosePool {},
+    );
+    for record in records {
+        let (address, wallet) = match *record {
+            PoolRecord::Denylisted(address) => (derive_denylist_address(program_id, &keys.pool, &address), None),
+            PoolRecord::LaunchBuys(wallet) => {
+                (derive_launch_buys_address(program_id, &keys.pool, &wallet), Some(wallet))
+            }
+            PoolRecord::Position(owner) => (derive_position_address(program_id, &keys.pool, &owner), Some(owner)),
+            PoolRecord::TwapCheckpoint(checkpoint) => (checkpoint, None),
+        };
+        close.accounts.push(AccountMeta::new(address, false));
+        if let Some(wallet) = wallet {
+            close.accounts.push(AccountMeta::new(wallet, false));
+        }
+    }
+    close
+}
+
+/// `add_liquidity` into the user's LP ATA; pass `with_position` to update
+/// the user's IL-tracking position.
+#[allow(clippy::too_many_arguments)]
+pub fn build_add_liquidity_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token0: &Pubkey,
+    user_token1: &Pubkey,
+    amount0: u64,
+    amount1: u64,
+    with_position: bool,
+) -> Instruction {
+    let mut deposit = ix(
+        program_id,
+        accounts::AddLiquidity {
+            user: *user,
+            pool: keys.pool,
+            user_token0: *user_token0,
+            user_token1: *user_token1,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            lp_mint: keys.lp_mint,
+            user_lp: get_associated_token_address(user, &keys.lp_mint),
+            position: with_position.then(|| derive_position_address(program_id, &keys.pool, user)),
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::AddLiquidity { amount0, amount1 },
+    );
+    push_denylist_markers(program_id, keys, &[*user], &mut deposit);
+    deposit
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn build_remove_liquidity_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token0: &Pubkey,
+    user_token1: &Pubkey,
+    shares: u64,
+    with_position: bool,
+) -> Instruction {
+    let mut withdraw = ix(
+        program_id,
+        accounts::RemoveLiquidity {
+            user: *user,
+            pool: keys.pool,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            lp_mint: keys.lp_mint,
+            user_lp: get_associated_token_address(user, &keys.lp_mint),
+            user_token0: *user_token0,
+            user_token1: *user_token1,
+            position: with_position.then(|| derive_position_address(program_id, &keys.pool, user)),
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            instructions: None,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::RemoveLiquidity { shares },
+    );
+    push_denylist_markers(program_id, keys, &[*user], &mut withdraw);
+    withdraw
+}
+
+/// `remove_liquidity_and_swap`, with the pool's oracle (if any) appended
+/// as remaining account.
+#[allow(clippy::too_many_arguments)]
+pub fn build_remove_liquidity_and_swap_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token0: &Pubkey,
+    user_token1: &Pubkey,
+    with_position: bool,
+    args: instruction::RemoveLiquidityAndSwap,
+) -> Instruction {
+    let mut exit = ix(
+        program_id,
+        accounts::RemoveLiquidity {
+            user: *user,
+            pool: keys.pool,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            lp_mint: keys.lp_mint,
+            user_lp: get_associated_token_address(user, &keys.lp_mint),
+            user_token0: *user_token0,
+            user_token1: *user_token1,
+            position: with_position.then(|| derive_position_address(program_id, &keys.pool, user)),
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            instructions: None,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        args,
+    );
+    if let Some(oracle) = keys.oracle {
+        exit.accounts.push(AccountMeta::new_readonly(oracle, false));
+    }
+    push_denylist_markers(program_id, keys, &[*user], &mut exit);
+    push_launch_buys(program_id, keys, user, &mut exit);
+    exit
+}
+
+/// `close_launch_buys` for `wallet`, after the launch window.
+pub fn build_close_launch_buys_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    wallet: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::CloseLaunchBuys {
+            wallet: *wallet,
+            pool: keys.pool,
+            launch_buys: derive_launch_buys_address(program_id, &keys.pool, wallet),
+        },
+        instruction::CloseLaunchBuys {},
+    )
+}
+
+pub fn build_open_position_ix(program_id: &Pubkey, keys: &PoolKeys, user: &Pubkey) -> Instruction {
+    ix(
+        program_id,
+        accounts::OpenPosition {
+            user: *user,
+            pool: keys.pool,
+            position: derive_position_address(program_id, &keys.pool, user),
+            system_program: system_program::ID,
+        },
+        instruction::OpenPosition {},
+    )
+}
+
+/// `swap` with the pool's oracle (if any) appended as remaining account.
+#[allow(clippy::too_many_arguments)]
+pub fn build_swap_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token_in: &Pubkey,
+    user_token_out: &Pubkey,
+    token_in_is_0: bool,
+    amount_in: u64,
+    min_amount_out: u64,
+    deadline: i64,
+) -> Instruction {
+    build_swap_to_ix(
+        program_id,
+        keys,
+        user,
+        user_token_in,
+        user,
+        user_token_out,
+        token_in_is_0,
+        amount_in,
+        min_amount_out,
+        deadline,
+    )
+}
+
+/// `swap` paying out to `recipient`'s `recipient_token_out`. `user` may be
+/// a delegate of `user_token_in` rather than its owner.
+#[allow(clippy::too_many_arguments)]
+pub fn build_swap_to_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token_in: &Pubkey,
+    recipient: &Pubkey,
+    recipient_token_out: &Pubkey,
+    token_in_is_0: bool,
+    amount_in: u64,
+    min_amount_out: u64,
+    deadline: i64,
+) -> Instruction {
+    let mut swap = ix(
+        program_id,
+        accounts::Swap {
+            user: *user,
+            pool: keys.pool,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            mint_out: if token_in_is_0 { keys.token1_mint } else { keys.token0_mint },
+            user_token_in: *user_token_in,
+            recipient: *recipient,
+            user_token_out: *recipient_token_out,
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            instructions: None,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::Swap {
+            token_in_is_0,
+            amount_in,
+            min_amount_out,
+            deadline,
+        },
+    );
+    if let Some(oracle) = keys.oracle {
+        swap.accounts.push(AccountMeta::new_readonly(oracle, false));
+    }
+    push_denylist_markers(program_id, keys, &[*user, *recipient], &mut swap);
+    push_launch_buys(program_id, keys, user, &mut swap);
+    swap
+}
+
+/// `swap_and_add_liquidity` into the user's LP ATA, with the pool's oracle
+/// (if any) appended as remaining account.
+#[allow(clippy::too_many_arguments)]
+pub fn build_swap_and_add_liquidity_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token0: &Pubkey,
+    user_token1: &Pubkey,
+    with_position: bool,
+    args: instruction::SwapAndAddLiquidity,
+) -> Instruction {
+    let mut zap = ix(
+        program_id,
+        accounts::SwapAndAddLiquidity {
+            user: *user,
+            pool: keys.pool,
+            user_token0: *user_token0,
+            user_token1: *user_token1,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            lp_mint: keys.lp_mint,
+            user_lp: get_associated_token_address(user, &keys.lp_mint),
+            position: with_position.then(|| derive_position_address(program_id, &keys.pool, user)),
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            instructions: None,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        args,
+    );
+    if let Some(oracle) = keys.oracle {
+        zap.accounts.push(AccountMeta::new_readonly(oracle, false));
+    }
+    push_denylist_markers(program_id, keys, &[*user], &mut zap);
+    push_launch_buys(program_id, keys, user, &mut zap);
+    zap
+}
+
+/// `donate`; pass the donor's accounts for both tokens even if one amount
+/// is zero.
+pub fn build_donate_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    donor: &Pubkey,
+    donor_token0: &Pubkey,
+    donor_token1: &Pubkey,
+    amount0: u64,
+    amount1: u64,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::Donate {
+            donor: *donor,
+            pool: keys.pool,
+            donor_token0: *donor_token0,
+            donor_token1: *donor_token1,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            token_program: token::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::Donate { amount0, amount1 },
+    )
+}
+
+pub fn build_set_flash_fee_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    flash_fee_bps: u16,
+) -> Instruction {
+    ix(
+        program_id,
+        admin_accounts(program_id, authority),
+        instruction::SetFlashFee { flash_fee_bps },
+    )
+}
+
+/// `flash_borrow` into `borrower_token`. The transaction must also carry
+/// the matching `build_flash_repay_ix` after it.
+pub fn build_flash_borrow_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    borrower: &Pubkey,
+    borrower_token: &Pubkey,
+    token_is_0: bool,
+    amount: u64,
+) -> Instruction {
+    let mut borrow = ix(
+        program_id,
+        accounts::FlashBorrow {
+            borrower: *borrower,
+            pool: keys.pool,
+            vault: if token_is_0 { keys.vault0 } else { keys.vault1 },
+            borrower_token: *borrower_token,
+            pool_signer: keys.pool,
+            instructions: sysvar::instructions::ID,
+            token_program: token::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::FlashBorrow { token_is_0, amount },
+    );
+    push_denylist_markers(program_id, keys, &[*borrower], &mut borrow);
+    borrow
+}
+
+/// `flash_repay` of a loan of `amount`; `payer_token` pays it plus the fee.
+pub fn build_flash_repay_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    payer: &Pubkey,
+    payer_token: &Pubkey,
+    token_is_0: bool,
+    amount: u64,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::FlashRepay {
+            payer: *payer,
+            pool: keys.pool,
+            vault: if token_is_0 { keys.vault0 } else { keys.vault1 },
+            payer_token: *payer_token,
+            token_program: token::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::FlashRepay { token_is_0, amount },
+    )
+}
+
+/// `flash_swap` of `amount_out` into `user_token_out`, then the callback
+/// with `data` and `callback_accounts`; the pool's oracle (if any) goes
+/// first, as for `swap`.
+#[allow(clippy::too_many_arguments)]
+pub fn build_flash_swap_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    user_token_out: &Pubkey,
+    callback_program: &Pubkey,
+    token_out_is_0: bool,
+    amount_out: u64,
+    data: Vec<u8>,
+    callback_accounts: &[AccountMeta],
+) -> Instruction {
+    let mut flash = ix(
+        program_id,
+        accounts::FlashSwap {
+            user: *user,
+            pool: keys.pool,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            user_token_out: *user_token_out,
+            callback_program: *callback_program,
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            instructions: None,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::FlashSwap { token_out_is_0, amount_out, data },
+    );
+    if let Some(oracle) = keys.oracle {
+        flash.accounts.push(AccountMeta::new_readonly(oracle, false));
+    }
+    push_denylist_markers(program_id, keys, &[*user], &mut flash);
+    push_launch_buys(program_id, keys, user, &mut flash);
+    flash.accounts.extend_from_slice(callback_accounts);
+    flash
+}
+
+pub fn build_skim_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    recipient: &Pubkey,
+    recipient_token0: &Pubkey,
+    recipient_token1: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::Skim {
+            pool: keys.pool,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            recipient_token0: *recipient_token0,
+            recipient_token1: *recipient_token1,
+            pool_signer: keys.pool,
+            token_program: token::ID,
+        },
+        instruction::Skim { recipient: *recipient },
+    )
+}
+
+pub fn build_sync_ix(program_id: &Pubkey, keys: &PoolKeys) -> Instruction {
+    ix(
+        program_id,
+        accounts::SyncReserves {
+            pool: keys.pool,
+            vault0: keys.vault0,
+            vault1: keys.vault1,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::Sync {},
+    )
+}
+
+/// `checkpoint_twap`; `checkpoint` is a fresh keypair that must also sign.
+pub fn build_checkpoint_twap_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    checkpoint: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::CheckpointTwap {
+            payer: *payer,
+            pool: derive_pool_address(program_id).0,
+            checkpoint: *checkpoint,
+            system_program: system_program::ID,
+        },
+        instruction::CheckpointTwap {},
+    )
+}
+
+pub fn build_init_snapshots_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    interval_seconds: i64,
+) -> Instruction {
+    let pool = derive_pool_address(program_id).0;
+    ix(
+        program_id,
+        accounts::InitSnapshots {
+            authority: *authority,
+            payer: *payer,
+            pool,
+            admin_log: None,
+            snapshots: derive_snapshots_address(program_id, &pool),
+            system_program: system_program::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::InitSnapshots { interval_seconds },
+    )
+}
+
+/// `init_rewards` paying in `reward_mint`.
+pub fn build_init_rewards_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    reward_mint: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::InitRewards {
+            authority: *authority,
+            payer: *payer,
+            pool: keys.pool,
+            admin_log: None,
+            reward_mint: *reward_mint,
+            lp_mint: keys.lp_mint,
+            reward_vault: get_associated_token_address(&keys.pool, reward_mint),
+            lp_escrow: get_associated_token_address(&keys.pool, &keys.lp_mint),
+            token_program: token::ID,
+            associated_token_program: associated_token::ID,
+            system_program: system_program::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::InitRewards {},
+    )
+}
+
+fn campaign_accounts(
+    program_id: &Pubkey,
+    reward_mint: &Pubkey,
+    authority: &Pubkey,
+) -> accounts::FundCampaign {
+    let pool = derive_pool_address(program_id).0;
+    accounts::FundCampaign {
+        authority: *authority,
+        pool,
+        admin_log: None,
+        reward_vault: get_associated_token_address(&pool, reward_mint),
+        authority_token: get_associated_token_address(authority, reward_mint),
+        pool_signer: pool,
+        token_program: token::ID,
+        #[cfg(feature = "event-cpi")]
+        event_authority: derive_event_authority(program_id),
+        #[cfg(feature = "event-cpi")]
+        program: *program_id,
+    }
+}
+
+/// `fund_campaign` from the authority's reward ATA.
+pub fn build_fund_campaign_ix(
+    program_id: &Pubkey,
+    reward_mint: &Pubkey,
+    authority: &Pubkey,
+    amount: u64,
+    start_ts: i64,
+    end_ts: i64,
+) -> Instruction {
+    ix(
+        program_id,
+        campaign_accounts(program_id, reward_mint, authority),
+        instruction::FundCampaign { amount, start_ts, end_ts },
+    )
+}
+
+/// `end_campaign_early`, returning the unstreamed rewards to the
+/// authority's reward ATA.
+pub fn build_end_campaign_early_ix(
+    program_id: &Pubkey,
+    reward_mint: &Pubkey,
+    authority: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        campaign_accounts(program_id, reward_mint, authority),
+        instruction::EndCampaignEarly {},
+    )
+}
+
+fn stake_accounts(program_id: &Pubkey, keys: &PoolKeys, user: &Pubkey) -> accounts::StakeLp {
+    accounts::StakeLp {
+        user: *user,
+        pool: keys.pool,
+        position: derive_position_address(program_id, &keys.pool, user),
+        user_lp: get_associated_token_address(user, &keys.lp_mint),
+        lp_escrow: get_associated_token_address(&keys.pool, &keys.lp_mint),
+        pool_signer: keys.pool,
+        token_program: token::ID,
+    }
+}
+
+/// `stake_lp` from the user's LP ATA; the user's position must exist.
+pub fn build_stake_lp_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    ix(program_id, stake_accounts(program_id, keys, user), instruction::StakeLp { amount })
+}
+
+/// `unstake_lp` back to the user's LP ATA.
+pub fn build_unstake_lp_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    user: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    ix(program_id, stake_accounts(program_id, keys, user), instruction::UnstakeLp { amount })
+}
+
+/// `claim_rewards` into the user's reward ATA.
+pub fn build_claim_rewards_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    reward_mint: &Pubkey,
+    user: &Pubkey,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::ClaimRewards {
+            user: *user,
+            pool: keys.pool,
+            position: derive_position_address(program_id, &keys.pool, user),
+            reward_vault: get_associated_token_address(&keys.pool, reward_mint),
+            user_reward: get_associated_token_address(user, reward_mint),
+            pool_signer: keys.pool,
+            token_program: token::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::ClaimRewards {},
+    )
+}
+
+pub fn build_init_admin_log_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+) -> Instruction {
+    let pool = derive_pool_address(program_id).0;
+    ix(
+        program_id,
+        accounts::InitAdminLog {
+            authority: *authority,
+            payer: *payer,
+            pool,
+            admin_log: derive_admin_log_address(program_id, &pool),
+            system_program: system_program::ID,
+            #[cfg(feature = "event-cpi")]
+            event_authority: derive_event_authority(program_id),
+            #[cfg(feature = "event-cpi")]
+            program: *program_id,
+        },
+        instruction::InitAdminLog {},
+    )
+}
+
+/// Pass the pool's `AdminLog` to an admin instruction built by this module;
+/// required once `init_admin_log` ran. The builders leave the optional
+/// `admin_log` slot, always the first one, empty.
+pub fn with_admin_log(mut admin_ix: Instruction, program_id: &Pubkey) -> Instruction {
+    let log = derive_admin_log_address(program_id, &derive_pool_address(program_id).0);
+    if let Some(meta) = admin_ix.accounts.iter_mut().find(|meta| meta.pubkey == *program_id) {
+        *meta = AccountMeta::new(log, false);
+    }
+    admin_ix
+}
+
+/// Decode `AdminLog` account data into its entries, oldest first.
+pub fn decode_admin_log(data: &[u8]) -> Result<Vec<AdminLogEntry>> {
+    Ok(AdminLog::try_deserialize(&mut &data[..])?.chronological())
+}
+
+pub fn build_snapshot_ix(program_id: &Pubkey) -> Instruction {
+    let pool = derive_pool_address(program_id).0;
+    ix(
+        program_id,
+        accounts::TakeSnapshot {
+            pool,
+            snapshots: derive_snapshots_address(program_id, &pool),
+        },
+        instruction::Snapshot {},
+    )
+}
+
+/// Grow the snapshots ring; repeat until `cardinality_next` is reached.
+pub fn build_increase_snapshot_cardinality_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    cardinality_next: u32,
+) -> Instruction {
+    let pool = derive_pool_address(program_id).0;
+    ix(
+        program_id,
+        accounts::IncreaseSnapshotCardinality {
+            payer: *payer,
+            pool,
+            snapshots: derive_snapshots_address(program_id, &pool),
+            system_program: system_program::ID,
+        },
+        instruction::IncreaseSnapshotCardinality { cardinality_next },
+    )
+}
+
+pub fn build_sync_decimals_ix(program_id: &Pubkey, keys: &PoolKeys) -> Instruction {
+    ix(
+        program_id,
+        accounts::SyncDecimals {
+            pool: keys.pool,
+            token0_mint: keys.token0_mint,
+            token1_mint: keys.token1_mint,
+        },
+        instruction::SyncDecimals {},
+    )
+}
+
+pub fn build_migrate_pool_ix(program_id: &Pubkey, payer: &Pubkey) -> Instruction {
+    ix(
+        program_id,
+        accounts::MigratePool {
+            payer: *payer,
+            pool: derive_pool_address(program_id).0,
+            system_program: system_program::ID,
+        },
+        instruction::MigratePool {},
+    )
+}
+
+pub fn build_get_pool_state_ix(program_id: &Pubkey, keys: &PoolKeys) -> Instruction {
+    ix(
+        program_id,
+        accounts::GetPoolState {
+            pool: keys.pool,
+            lp_mint: keys.lp_mint,
+        },
+        instruction::GetPoolState {},
+    )
+}
+
+/// `simulate_swap` with the pool's oracle (if any) appended. Simulate it
+/// and pass the logs to `parse_simulate_swap_logs`.
+pub fn build_simulate_swap_ix(
+    program_id: &Pubkey,
+    keys: &PoolKeys,
+    token_in_is_0: bool,
+    amount_in: u64,
+) -> Instruction {
+    let mut simulate = ix(
+        program_id,
+        accounts::SimulateSwap { pool: keys.pool },
+        instruction::SimulateSwap { token_in_is_0, amount_in },
+    );
+    if let Some(oracle) = keys.oracle {
+        simulate.accounts.push(AccountMeta::new_readonly(oracle, false));
+    }
+    simulate
+}
+
+/// Quote logged by `simulate_swap`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimulatedSwap {
+    pub amount_out: u64,
+    pub fee_bps: u16,
+    /// 0 when logged by a program from before ppm fees
+    pub fee_ppm: u32,
+    pub vol_1e12: u128,
+    pub slip_1e12: u128,
+    pub shallow_1e12: u128,
+    pub breaker_would_trip: bool,
+}
+
+/// Recover the quote from the logs of a simulated `simulate_swap`. None if
+/// the line is missing (e.g. the pool had no liquidity) or malformed.
+pub fn parse_simulate_swap_logs(logs: &[String]) -> Option<SimulatedSwap> {
+    let fields = logs.iter().find_map(|log| log.split_once(SIMULATE_SWAP_LOG_PREFIX))?.1;
+    let mut quote = SimulatedSwap::default();
+    for field in fields.split_whitespace() {
+        let (key, value) = field.split_once('=')?;
+        match key {
+            "amount_out" => quote.amount_out = value.parse().ok()?,
+            "fee_bps" => quote.fee_bps = value.parse().ok()?,
+            "fee_ppm" => quote.fee_ppm = value.parse().ok()?,
+            "vol_1e12" => quote.vol_1e12 = value.parse().ok()?,
+            "slip_1e12" => quote.slip_1e12 = value.parse().ok()?,
+            "shallow_1e12" => quote.shallow_1e12 = value.parse().ok()?,
+            "breaker_would_trip" => quote.breaker_would_trip = value.parse().ok()?,
+            _ => {}
+        }
+    }
+    Some(quote)
+}
+
+/// `quote_marginal_fee` with the pool's oracle (if any) appended.
+pub fn build_quote_marginal_fee_ix(program_id: &Pubkey, keys: &PoolKeys) -> Instruction {
+    let mut quote = ix(
+        program_id,
+        accounts::QuoteFee { pool: keys.pool },
+        instruction::QuoteMarginalFee {},
+    );
+    if let Some(oracle) = keys.oracle {
+        quote.accounts.push(AccountMeta::new_readonly(oracle, false));
+    }
+    quote
+}
+
+/// `health_check` with the pool's oracle (if any) appended so its
+/// freshness is reported.
+pub fn build_health_check_ix(program_id: &Pubkey, keys: &PoolKeys) -> Instruction {
+    let mut health = ix(
+        program_id,
+        accounts::HealthCheck { pool: keys.pool },
+        instruction::HealthCheck {},
+    );
+    if let Some(oracle) = keys.oracle {
+        health.accounts.push(AccountMeta::new_readonly(oracle, false));
+    }
+    health
+}
+
+pub fn build_get_twap_ix(
+    program_id: &Pubkey,
+    checkpoint: &Pubkey,
+    window_seconds: u32,
+    fail_if_stale: bool,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::GetTwap {
+            pool: derive_pool_address(program_id).0,
+            checkpoint: *checkpoint,
+        },
+        instruction::GetTwap { window_seconds, fail_if_stale },
+    )
+}
+
+pub fn build_quote_add_liquidity_ix(
+    program_id: &Pubkey,
+    amount0_desired: u64,
+    amount1_desired: u64,
+) -> Instruction {
+    ix(
+        program_id,
+        accounts::QuoteLiquidity {
+            pool: derive_pool_address(program_id).0,
+        },
+        instruction::QuoteAddLiquidity { amount0_desired, amount1_desired },
+    )
+}
+
+pub fn build_quote_remove_liquidity_ix(program_id: &Pubkey, shares: u64) -> Instruction {
+    ix(
+        program_id,
+        accounts::QuoteLiquidity {
+            pool: derive_pool_address(program_id).0,
+        },
+        instruction::QuoteRemoveLiquidity { shares },
+    )
+}
+
+/// Every `AmmError` in declaration order, so the code of each is
+/// ERROR_CODE_OFFSET plus its index. Extend it with the enum.
+pub const AMM_ERRORS: [AmmError; 66] = [
+    AmmError::NotAuthorized, AmmError::BadBounds, AmmError::ZeroAmount, AmmError::ZeroShares,
+    AmmError::InsufficientLP, AmmError::NoLiquidity, AmmError::BadRatio, AmmError::MathOverflow,
+    AmmError::AmountOutZero, AmmError::VolTooHigh, AmmError::TwapWindowUnavailable,
+    AmmError::InvalidOracle, AmmError::PoolPaused, AmmError::InvalidRecipient,
+    AmmError::DonationModeMismatch, AmmError::InsufficientOutputReserve,
+    AmmError::SlippageExceeded, AmmError::DeadlineExceeded, AmmError::FeeTooHigh,
+    AmmError::PriceImpactTooHigh, AmmError::WrongTokenAccount, AmmError::RateLimited,
+    AmmError::FreezeDisabled, AmmError::RiskyMint, AmmError::FlashLoansDisabled,
+    AmmError::FlashLoanActive, AmmError::FlashLoanTooLarge, AmmError::FlashRepayMissing,
+    AmmError::FlashLoanMismatch, AmmError::FlashRepayShort, AmmError::PoolLocked,
+    AmmError::FlashSwapUnderpaid, AmmError::ProtectedAccount, AmmError::PoolNotEmpty,
+    AmmError::BreakerTripped, AmmError::StaleTwap, AmmError::OracleRequired,
+    AmmError::SimulationOnly, AmmError::StaleOracle, AmmError::RewardsAlreadyInitialized,
+    AmmError::RewardsNotInitialized, AmmError::BadRewardMint, AmmError::CampaignActive,
+    AmmError::NoActiveCampaign, AmmError::MintNotApproved, AmmError::TradeTooSmall,
+    AmmError::TradeTooLarge, AmmError::MetadataTooLong, AmmError::InvalidMetadata,
+    AmmError::InvalidTokenOwner, AmmError::TradeWouldTripBreaker, AmmError::AlphaOutOfRange,
+    AmmError::ThresholdZero, AmmError::CoefficientTooLarge, AmmError::FeeAboveDenominator,
+    AmmError::RouterRequired, AmmError::RouterNotAllowed, AmmError::InvalidRouterList,
+    AmmError::AddressDenylisted, AmmError::DenylistAccountMissing, AmmError::LaunchBuyCapExceeded,
+    AmmError::LaunchAccountMissing, AmmError::LaunchWindowOpen, AmmError::RewardAccountsMissing,
+    AmmError::AdminLogMissing, AmmError::NotPoolRecord,
+];
+
+/// The `AmmError` behind a custom program error code, such as the
+/// `InstructionError::Custom` of a failed transaction. None for the codes
+/// of Anchor itself or of other programs.
+pub fn amm_error(code: u32) -> Option<AmmError> {
+    let index = code.checked_sub(anchor_lang::error::ERROR_CODE_OFFSET)?;
+    AMM_ERRORS.get(index as usize).copied()
+}
+
+/// `amm_error` of a failed instruction, if it failed with a custom code.
+pub fn amm_error_from_instruction(error: &InstructionError) -> Option<AmmError> {
+    match error {
+        InstructionError::Custom(code) => amm_error(*code),
+        _ => None,
+    }
+}
@@ -0,0 +1,72 @@
+//! Decoding helpers for the program's events.
+//!
+//! `emit!` writes `discriminator (8) || borsh(event)` base64-encoded after a
+//! `Program data: ` log prefix. These helpers take the decoded bytes.
+
+use anchor_lang::prelude::*;
+use anchor_lang::Discriminator;
+
+use crate::{
+    AdminActionEvent, BurnEvent, Donation, FlashBorrowed, FlashRepaid, MintEvent, PoolInitialized,
+    ProtocolFeesCollected, RewardsClaimed, SwapEvent, SyncEvent, TokensRescued, EVENT_VERSION,
+};
+
+/// Any event emitted by the pool.
+pub enum AmmEvent {
+    Swap(SwapEvent),
+    Mint(MintEvent),
+    Burn(BurnEvent),
+    Sync(SyncEvent),
+    TokensRescued(TokensRescued),
+    Admin(AdminActionEvent),
+    ProtocolFeesCollected(ProtocolFeesCollected),
+    Donation(Donation),
+    RewardsClaimed(RewardsClaimed),
+    FlashBorrowed(FlashBorrowed),
+    FlashRepaid(FlashRepaid),
+    PoolInitialized(PoolInitialized),
+}
+
+/// Layout version of an encoded event (the first byte after the
+/// discriminator), or None if the payload is too short.
+pub fn event_version(data: &[u8]) -> Option<u8> {
+    data.get(8).copied()
+}
+
+/// Decode a raw event payload. Returns None for foreign discriminators and
+/// for layouts other than the current `EVENT_VERSION`.
+pub fn decode_event(data: &[u8]) -> Option<AmmEvent> {
+    if data.len() < 8 || event_version(data)? != EVENT_VERSION {
+        return None;
+    }
+    let (disc, mut body) = data.split_at(8);
+    if disc == SwapEvent::DISCRIMINATOR {
+        SwapEvent::deserialize(&mut body).ok().map(AmmEvent::Swap)
+    } else if disc == MintEvent::DISCRIMINATOR {
+        MintEvent::deserialize(&mut body).ok().map(AmmEvent::Mint)
+    } else if disc == BurnEvent::DISCRIMINATOR {
+        BurnEvent::deserialize(&mut body).ok().map(AmmEvent::Burn)
+    } else if disc == SyncEvent::DISCRIMINATOR {
+        SyncEvent::deserialize(&mut body).ok().map(AmmEvent::Sync)
+    } else if disc == TokensRescued::DISCRIMINATOR {
+        TokensRescued::deserialize(&mut body).ok().map(AmmEvent::TokensRescued)
+    } else if disc == AdminActionEvent::DISCRIMINATOR {
+        AdminActionEvent::deserialize(&mut body).ok().map(AmmEvent::Admin)
+    } else if disc == ProtocolFeesCollected::DISCRIMINATOR {
+        ProtocolFeesCollected::deserialize(&mut body)
+            .ok()
+            .map(AmmEvent::ProtocolFeesCollected)
+    } else if disc == Donation::DISCRIMINATOR {
+        Donation::deserialize(&mut body).ok().map(AmmEvent::Donation)
+    } else if disc == PoolInitialized::DISCRIMINATOR {
+        PoolInitialized::deserialize(&mut body).ok().map(AmmEvent::PoolInitialized)
+    } else if disc == RewardsClaimed::DISCRIMINATOR {
+        RewardsClaimed::deserialize(&mut body).ok().map(AmmEvent::RewardsClaimed)
+    } else if disc == FlashBorrowed::DISCRIMINATOR {
+        FlashBorrowed::deserialize(&mut body).ok().map(AmmEvent::FlashBorrowed)
+    } else if disc == FlashRepaid::DISCRIMINATOR {
+        FlashRepaid::deserialize(&mut body).ok().map(AmmEvent::FlashRepaid)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,315 @@
+//! Flat, serde-serializable view of a pool for indexers (`serde` feature).
+//!
+//! `PoolSnapshot` mirrors every `Pool` field plus a few derived values, so
+//! off-chain services can write pool state to JSON or a database without
+//! their own mapping. Keys are base58 strings and the oracle source is its
+//! variant name, which keeps the JSON readable and stable.
+//!
+//! ```ignore
+//! use adaptive_cpamm::{indexer::PoolSnapshot, Pool};
+//! use anchor_lang::AccountDeserialize;
+//! use anchor_spl::token::TokenAccount;
+//! use solana_client::rpc_client::RpcClient;
+//!
+//! let rpc = RpcClient::new("https://api.devnet.solana.com".to_string());
+//! let pool = Pool::try_deserialize(&mut &rpc.get_account_data(&pool_address)?[..])?;
+//! let amount = |key| -> anyhow::Result<u64> {
+//!     Ok(TokenAccount::try_deserialize(&mut &rpc.get_account_data(key)?[..])?.amount)
+//! };
+//! let snapshot = PoolSnapshot::with_vaults(&pool, amount(&pool.vault0)?, amount(&pool.vault1)?);
+//! println!("{}", serde_json::to_string(&snapshot)?);
+//! ```
+
+use anchor_lang::prelude::Pubkey;
+use serde::{Deserialize, Serialize};
+
+use crate::math::{price_to_1e12, spot_price_0_in_1};
+use crate::Pool;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PoolSnapshot {
+    pub bump: u8,
+    pub authority: String,
+    pub token0_mint: String,
+    pub token1_mint: String,
+    pub vault0: String,
+    pub vault1: String,
+    pub lp_mint: String,
+    pub total_lp_supply: u64,
+    pub reserve0: u64,
+    pub reserve1: u64,
+
+    pub min_fee_bps: u16,
+    pub max_fee_bps: u16,
+    /// effective fee bounds (the bps ones times 100 on pre-ppm pools)
+    pub beta_vol_bps_per1e12: u16,
+    pub gamma_slip_bps_per1e12: u16,
+    pub delta_shallow_bps_per1e12: u16,
+
+    pub ema_price_1e18: u128,
+    pub ema_alpha_1e12: u64,
+    pub breaker_vol_threshold_1e12: u64,
+
+    pub price_cumulative_1e18: u128,
+    pub log2_price_cumulative: u128,
+    pub cumulative_last_timestamp: i64,
+    pub geometric_twap_enabled: bool,
+
+    pub last_update_slot: u64,
+    pub last_update_timestamp: i64,
+
+    pub cumulative_volume0: u128,
+    pub cumulative_volume1: u128,
+    pub swap_count: u64,
+    pub cumulative_fees0: u128,
+    pub cumulative_fees1: u128,
+    pub event_seq: u64,
+
+    pub oracle_source: String,
+    pub oracle: String,
+    pub epsilon_oracle_bps_per1e12: u16,
+    pub oracle_max_age_seconds: u32,
+
+    pub paused: bool,
+    pub treasury: String,
+    pub skim_to_treasury: bool,
+    pub sync_donations: bool,
+    pub max_price_impact_bps: u16,
+    pub params_updated_slot: u64,
+    pub lp_freezable: bool,
+    pub allow_risky_mints: bool,
+    pub ema_updated_at: i64,
+    pub ema_max_age_seconds: u32,
+
+    pub flash_fee_bps: u16,
+    pub flash_token_is_0: bool,
+    pub flash_amount: u64,
+    pub flash_fee: u64,
+    pub locked: bool,
+
+    pub guardian: String,
+    pub manually_tripped: bool,
+    pub protocol_fee_share_bps: u16,
+    pub protocol_fees_owed0: u64,
+    pub protocol_fees_owed1: u64,
+    pub default_twap_window_seconds: u32,
+    pub max_observation_staleness_seconds: u32,
+
+    pub reward_mint: String,
+    pub reward_vault: String,
+    pub lp_escrow: String,
+    pub reward_rate_1e18: u128,
+    pub reward_start_ts: i64,
+    pub reward_end_ts: i64,
+    pub reward_updated_at: i64,
+    pub reward_per_share_1e18: u128,
+    pub reward_staked: u64,
+    pub flash_loan_count: u64,
+    pub flash_volume0: u128,
+    pub flash_volume1: u128,
+    pub flash_fees0: u128,
+    pub flash_fees1: u128,
+    pub lp_freeze_revoked: bool,
+    pub min_trade_amount0: u64,
+    pub min_trade_amount1: u64,
+    pub max_trade_bps_of_reserve: u16,
+    pub decimals0: u8,
+    pub decimals1: u8,
+    pub epoch: u32,
+    pub fee_manager: String,
+    pub pauser: String,
+    pub treasurer: String,
+    pub name: String,
+    pub uri: String,
+    pub slip_mode: String,
+    pub shallow_mode: String,
+    pub target_depth0: u64,
+    pub ema_depth0: u128,
+    pub depth_ema_alpha_1e12: u64,
+    pub co_authority: String,
+    pub oracle_required: bool,
+    pub fee_on_output: bool,
+    pub breaker_enabled: bool,
+    pub price_high_1e12: u64,
+    pub price_low_1e12: u64,
+    pub hl_window_start_ts: i64,
+    pub sqrt_k_last: u128,
+    pub growth_1e12: u128,
+    pub only_via_router: bool,
+    /// allowlisted router programs, unused slots dropped
+    pub routers: Vec<String>,
+    pub reversion_window_slots: u64,
+    pub reversion_band_bps: u16,
+    pub reversion_penalty_bps: u16,
+    pub last_trade_token_in_is_0: bool,
+    pub last_trade_amount_out: u64,
+    pub last_trade_slot: u64,
+    pub min_fee_ppm: u32,
+    pub max_fee_ppm: u32,
+    pub compliance: String,
+    pub enforce_denylist: bool,
+    pub denylist_allows_removal: bool,
+    pub launch_token_is_0: bool,
+    pub launch_end_slot: u64,
+    pub max_buy_per_wallet: u64,
+    pub zeta_skew_bps_per1e12: u16,
+    pub admin_log_enabled: bool,
+
+    // derived
+    /// token0 in token1 at 1e12; 0 for an empty pool
+    pub spot_price_1e12: u64,
+    pub ema_price_1e12: u64,
+    /// actual vault balances, when supplied
+    pub vault0_balance: Option<u64>,
+    pub vault1_balance: Option<u64>,
+    /// reserves in whole tokens, using the pool's stored decimals
+    pub reserve0_ui: f64,
+    pub reserve1_ui: f64,
+}
+
+impl PoolSnapshot {
+    /// Snapshot including the actual vault balances.
+    pub fn with_vaults(pool: &Pool, vault0_balance: u64, vault1_balance: u64) -> Self {
+        Self {
+            vault0_balance: Some(vault0_balance),
+            vault1_balance: Some(vault1_balance),
+            ..Self::from(pool)
+        }
+    }
+}
+
+impl From<&Pool> for PoolSnapshot {
+    fn from(pool: &Pool) -> Self {
+        Self {
+            bump: pool.bump,
+            authority: pool.authority.to_string(),
+            token0_mint: pool.token0_mint.to_string(),
+            token1_mint: pool.token1_mint.to_string(),
+            vault0: pool.vault0.to_string(),
+            vault1: pool.vault1.to_string(),
+            lp_mint: pool.lp_mint.to_string(),
+            total_lp_supply: pool.total_lp_supply,
+            reserve0: pool.reserve0,
+            reserve1: pool.reserve1,
+            min_fee_bps: pool.min_fee_bps,
+            max_fee_bps: pool.max_fee_bps,
+            beta_vol_bps_per1e12: pool.beta_vol_bps_per1e12,
+            gamma_slip_bps_per1e12: pool.gamma_slip_bps_per1e12,
+            delta_shallow_bps_per1e12: pool.delta_shallow_bps_per1e12,
+            ema_price_1e18: pool.ema_price_1e18,
+            ema_alpha_1e12: pool.ema_alpha_1e12,
+            breaker_vol_threshold_1e12: pool.breaker_vol_threshold_1e12,
+            price_cumulative_1e18: pool.price_cumulative_1e18,
+            log2_price_cumulative: pool.log2_price_cumulative,
+            cumulative_last_timestamp: pool.cumulative_last_timestamp,
+            geometric_twap_enabled: pool.geometric_twap_enabled,
+            last_update_slot: pool.last_update_slot,
+            last_update_timestamp: pool.last_update_timestamp,
+            cumulative_volume0: pool.cumulative_volume0,
+            cumulative_volume1: pool.cumulative_volume1,
+            swap_count: pool.swap_count,
+            cumulative_fees0: pool.cumulative_fees0,
+            cumulative_fees1: pool.cumulative_fees1,
+            event_seq: pool.event_seq,
+            oracle_source: format!("{:?}", pool.oracle_source),
+            oracle: pool.oracle.to_string(),
+            epsilon_oracle_bps_per1e12: pool.epsilon_oracle_bps_per1e12,
+            oracle_max_age_seconds: pool.oracle_max_age_seconds,
+            paused: pool.paused,
+            treasury: pool.treasury.to_string(),
+            skim_to_treasury: pool.skim_to_treasury,
+            sync_donations: pool.sync_donations,
+            max_price_impact_bps: pool.max_price_impact_bps,
+            params_updated_slot: pool.params_updated_slot,
+            lp_freezable: pool.lp_freezable,
+            allow_risky_mints: pool.allow_risky_mints,
+            ema_updated_at: pool.ema_updated_at,
+            ema_max_age_seconds: pool.ema_max_age_seconds,
+            flash_fee_bps: pool.flash_fee_bps,
+            flash_token_is_0: pool.flash_token_is_0,
+            flash_amount: pool.flash_amount,
+            flash_fee: pool.flash_fee,
+            locked: pool.locked,
+            guardian: pool.guardian.to_string(),
+            manually_tripped: pool.manually_tripped,
+            protocol_fee_share_bps: pool.protocol_fee_share_bps,
+            protocol_fees_owed0: pool.protocol_fees_owed0,
+            protocol_fees_owed1: pool.protocol_fees_owed1,
+            default_twap_window_seconds: pool.default_twap_window_seconds,
+            max_observation_staleness_seconds: pool.max_observation_staleness_seconds,
+            reward_mint: pool.reward_mint.to_string(),
+            reward_vault: pool.reward_vault.to_string(),
+            lp_escrow: pool.lp_escrow.to_string(),
+            reward_rate_1e18: pool.reward_rate_1e18,
+            reward_start_ts: pool.reward_start_ts,
+            reward_end_ts: pool.reward_end_ts,
+            reward_updated_at: pool.reward_updated_at,
+            reward_per_share_1e18: pool.reward_per_share_1e18,
+            reward_staked: pool.reward_staked,
+            flash_loan_count: pool.flash_loan_count,
+            flash_volume0: pool.flash_volume0,
+            flash_volume1: pool.flash_volume1,
+            flash_fees0: pool.flash_fees0,
+            flash_fees1: pool.flash_fees1,
+            lp_freeze_revoked: pool.lp_freeze_revoked,
+            min_trade_amount0: pool.min_trade_amount0,
+            min_trade_amount1: pool.min_trade_amount1,
+            max_trade_bps_of_reserve: pool.max_trade_bps_of_reserve,
+            decimals0: pool.decimals0,
+            decimals1: pool.decimals1,
+            epoch: pool.epoch,
+            fee_manager: pool.fee_manager.to_string(),
+            pauser: pool.pauser.to_string(),
+            treasurer: pool.treasurer.to_string(),
+            name: pool.name(),
+            uri: pool.uri(),
+            slip_mode: format!("{:?}", pool.slip_mode),
+            shallow_mode: format!("{:?}", pool.shallow_mode),
+            target_depth0: pool.target_depth0,
+            ema_depth0: pool.ema_depth0,
+            depth_ema_alpha_1e12: pool.depth_ema_alpha_1e12,
+            co_authority: pool.co_authority.to_string(),
+            oracle_required: pool.oracle_required,
+            fee_on_output: pool.fee_on_output,
+            breaker_enabled: pool.breaker_enabled,
+            price_high_1e12: pool.price_high_1e12,
+            price_low_1e12: pool.price_low_1e12,
+            hl_window_start_ts: pool.hl_window_start_ts,
+            sqrt_k_last: pool.sqrt_k_last,
+            growth_1e12: pool.growth_1e12,
+            only_via_router: pool.only_via_router,
+            routers: pool
+                .routers
+                .iter()
+                .filter(|r| **r != Pubkey::default())
+                .map(Pubkey::to_string)
+                .collect(),
+            reversion_window_slots: pool.reversion_window_slots,
+            reversion_band_bps: pool.reversion_band_bps,
+            reversion_penalty_bps: pool.reversion_penalty_bps,
+            last_trade_token_in_is_0: pool.last_trade_token_in_is_0,
+            last_trade_amount_out: pool.last_trade_amount_out,
+            last_trade_slot: pool.last_trade_slot,
+            min_fee_ppm: pool.fee_bounds_ppm().0,
+            max_fee_ppm: pool.fee_bounds_ppm().1,
+            compliance: pool.compliance.to_string(),
+            enforce_denylist: pool.enforce_denylist,
+            denylist_allows_removal: pool.denylist_allows_removal,
+            launch_token_is_0: pool.launch_token_is_0,
+            launch_end_slot: pool.launch_end_slot,
+            max_buy_per_wallet: pool.max_buy_per_wallet,
+            zeta_skew_bps_per1e12: pool.zeta_skew_bps_per1e12,
+            admin_log_enabled: pool.admin_log_enabled,
+            spot_price_1e12: spot_price_0_in_1(pool.reserve0, pool.reserve1).unwrap_or(0),
+            ema_price_1e12: price_to_1e12(pool.ema_price_1e18),
+            vault0_balance: None,
+            vault1_balance: None,
+            reserve0_ui: to_ui(pool.reserve0, pool.decimals0),
+            reserve1_ui: to_ui(pool.reserve1, pool.decimals1),
+        }
+    }
+}
+
+fn to_ui(amount: u64, decimals: u8) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
@@ -0,0 +1,143 @@
+//! Post-instruction state assertions, compiled in with the `invariants`
+//! feature (test and devnet builds). Every check panics with the offending
+//! values; without the feature the calls are left out entirely, so
+//! production builds pay no compute for them.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token::{Mint, TokenAccount};
+
+use crate::math::{bps_to_ppm, expected_k_growth, PRICE_SCALE, REVERSION_FEE_CAP_BPS, SCALE};
+use crate::{split_swap_fee, Pool, SwapEvent};
+
+/// Upper bound on the EMA: u64::MAX / 2 at the external 1e12 precision.
+const EMA_MAX_1E18: u128 = (u64::MAX / 2) as u128 * (PRICE_SCALE / SCALE);
+
+/// Constant-product k over the recorded reserves.
+pub fn k(pool: &Pool) -> u128 {
+    pool.reserve0 as u128 * pool.reserve1 as u128
+}
+
+/// Vaults hold at least the recorded reserves plus owed protocol fees,
+/// counting an outstanding flash loan as held; anything above is an
+/// unreconciled donation.
+pub fn check_reserves(
+    pool: &Pool,
+    vault0: &mut Account<'_, TokenAccount>,
+    vault1: &mut Account<'_, TokenAccount>,
+) {
+    vault0.reload().expect("invariant: reload vault0");
+    vault1.reload().expect("invariant: reload vault1");
+    let lent0 = if pool.flash_token_is_0 { pool.flash_amount } else { 0 };
+    let lent1 = if pool.flash_token_is_0 { 0 } else { pool.flash_amount };
+    let floor0 = pool.reserve0 as u128 + pool.protocol_fees_owed0 as u128;
+    let floor1 = pool.reserve1 as u128 + pool.protocol_fees_owed1 as u128;
+    assert!(
+        vault0.amount as u128 + lent0 as u128 >= floor0,
+        "invariant: vault0 {} (+{} lent) below reserve0 + owed {}",
+        vault0.amount,
+        lent0,
+        floor0
+    );
+    assert!(
+        vault1.amount as u128 + lent1 as u128 >= floor1,
+        "invariant: vault1 {} (+{} lent) below reserve1 + owed {}",
+        vault1.amount,
+        lent1,
+        floor1
+    );
+}
+
+/// The pool's LP accounting matches the mint.
+pub fn check_lp_supply(pool: &Pool, lp_mint: &mut Account<'_, Mint>) {
+    lp_mint.reload().expect("invariant: reload lp mint");
+    assert_eq!(
+        pool.total_lp_supply, lp_mint.supply,
+        "invariant: total_lp_supply {} != mint supply {}",
+        pool.total_lp_supply, lp_mint.supply
+    );
+}
+
+/// (reserve0, reserve1, total_lp_supply): what one LP share is backed by.
+pub fn share_value(pool: &Pool) -> (u64, u64, u64) {
+    (pool.reserve0, pool.reserve1, pool.total_lp_supply)
+}
+
+/// Adding or removing liquidity never lowers the reserves backing one
+/// share (the rounding policy in `math`). Bootstrap and full exits have no
+/// per-share value on one side and are skipped.
+pub fn check_share_value_non_decreasing(before: (u64, u64, u64), pool: &Pool) {
+    let (r0, r1, supply) = before;
+    let (r0_after, r1_after, supply_after) = share_value(pool);
+    if supply == 0 || supply_after == 0 {
+        return;
+    }
+    for (side, r, r_after) in [(0, r0, r0_after), (1, r1, r1_after)] {
+        // r_after / supply_after >= r / supply
+        assert!(
+            r_after as u128 * supply as u128 >= r as u128 * supply_after as u128,
+            "invariant: reserve{} per share fell from {}/{} to {}/{}",
+            side,
+            r,
+            supply,
+            r_after,
+            supply_after
+        );
+    }
+}
+
+/// Swaps may only grow k (fees stay in the pool).
+pub fn check_k_non_decreasing(k_before: u128, pool: &Pool) {
+    let k_after = k(pool);
+    assert!(
+        k_after >= k_before,
+        "invariant: k decreased from {} to {}",
+        k_before,
+        k_after
+    );
+}
+
+/// A swap grows k by at least the LP part of its realized fee (see
+/// `math::expected_k_growth`), so pricing, the fee amount and the reserve
+/// update must agree.
+pub fn check_k_fee_growth(k_before: u128, pool: &Pool, event: &SwapEvent) {
+    let (protocol_fee, _, _) =
+        split_swap_fee(pool, event.amount_in, event.amount_out, event.fee_amount)
+            .expect("invariant: split swap fee");
+    let lp_fee = (event.fee_amount - protocol_fee) as u128;
+    let fee_side_after = if event.fee_token_is_0 { pool.reserve0 } else { pool.reserve1 };
+    let k_min = expected_k_growth(k_before, fee_side_after as u128, lp_fee)
+        .expect("invariant: expected k growth");
+    let k_after = k(pool);
+    assert!(
+        k_after >= k_min,
+        "invariant: k {} -> {} below {} for lp fee {}",
+        k_before,
+        k_after,
+        k_min,
+        lp_fee
+    );
+}
+
+pub fn check_ema(pool: &Pool) {
+    assert!(
+        pool.ema_price_1e18 <= EMA_MAX_1E18,
+        "invariant: ema {} above {}",
+        pool.ema_price_1e18,
+        EMA_MAX_1E18
+    );
+}
+
+pub fn check_fee(pool: &Pool, fee_ppm: u32) {
+    let (min_fee_ppm, mut max_fee_ppm) = pool.fee_bounds_ppm();
+    // a round-trip penalty may lift the fee up to its own cap
+    if pool.reversion_penalty_bps > 0 {
+        max_fee_ppm = max_fee_ppm.max(bps_to_ppm(REVERSION_FEE_CAP_BPS));
+    }
+    assert!(
+        fee_ppm >= min_fee_ppm && fee_ppm <= max_fee_ppm,
+        "invariant: fee {} ppm outside [{}, {}]",
+        fee_ppm,
+        min_fee_ppm,
+        max_fee_ppm
+    );
+}
@@ -1,13 +1,95 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::entrypoint::MAX_PERMITTED_DATA_INCREASE;
+use anchor_lang::solana_program::hash::hash;
+use anchor_lang::system_program;
+use anchor_lang::Discriminator;
+use anchor_lang::solana_program::instruction::{
+    get_stack_height, Instruction, TRANSACTION_LEVEL_STACK_HEIGHT,
+};
+use anchor_lang::solana_program::program::invoke;
+use anchor_lang::solana_program::sysvar::instructions::{
+    self as instructions_sysvar, get_instruction_relative, load_current_index_checked,
+    load_instruction_at_checked,
+};
 use anchor_spl::associated_token::AssociatedToken;
-use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount, Transfer, Burn};
+use anchor_spl::token::spl_token::instruction::AuthorityType;
+use anchor_spl::token::{
+    self, Burn, CloseAccount, FreezeAccount, Mint, MintTo, SetAuthority, ThawAccount, Token,
+    TokenAccount, Transfer,
+};
 
-declare_id!("Adap1veCpAMM_Rust");
+pub mod accumulator;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod event_parser;
+#[cfg(feature = "serde")]
+pub mod indexer;
+pub mod math;
+#[cfg(feature = "simulator")]
+pub mod simulator;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "invariants")]
+pub mod invariants;
 
-/// Fixed-point scale for prices/EMA/slippage signals.
-const SCALE: u128 = 1_000_000_000_000; // 1e12
-/// Basis points denominator
-const BPS_DENOM: u64 = 10_000;
+declare_id!("3d1AdjA472EKqTswCoGnmHk3osGxnV9CQi9r3NEbeySo");
+
+pub use math::{
+    bps_to_ppm, compute_dynamic_fee, compute_marginal_fee, get_amount_out, isqrt, mul_div,
+    ppm_to_bps, spot_price_0_in_1, spot_price_1_in_0, swap_amounts, MathError, PoolParams, Rounding,
+};
+use math::{
+    accrue_k_growth, amounts_for_shares, breaker_verdict, ema_update, impermanent_loss,
+    k_with_fee_holds,
+    post_trade_deviation_1e12, price_to_1e12, ratio_matched_amounts, ratio_matches,
+    reversion_penalty_bps, shallow_depth_k, shares_for_deposit, spot_price_1e18, sqrt_k,
+    with_reversion_penalty, BreakerVerdict, LastTrade, ReversionParams, BPS_DENOM, PPM_DENOM,
+    PRICE_SCALE, REVERSION_FEE_CAP_BPS, SCALE,
+};
+/// Layout version carried by every event; bump on any field change.
+/// Version 0 is the original layout without this field.
+pub const EVENT_VERSION: u8 = 16;
+
+/// Emit through `emit_cpi!` when built with `event-cpi` (event bytes land in
+/// inner-instruction data, which RPCs never truncate), otherwise as a log.
+/// The event must be built before the call: `emit_cpi!` borrows `ctx`.
+macro_rules! emit_event {
+    ($ctx:ident, $event:expr) => {{
+        #[cfg(feature = "event-cpi")]
+        {
+            let ctx = &$ctx;
+            emit_cpi!($event);
+        }
+        #[cfg(not(feature = "event-cpi"))]
+        emit!($event);
+    }};
+}
+
+/// PDA seeds, shared with the off-chain address derivation in `client`.
+pub const POOL_SEED: &[u8] = b"pool";
+pub const POSITION_SEED: &[u8] = b"position";
+pub const SNAPSHOTS_SEED: &[u8] = b"snapshots";
+pub const CONFIG_SEED: &[u8] = b"config";
+pub const MINT_BADGE_SEED: &[u8] = b"mint_badge";
+pub const ADMIN_LOG_SEED: &[u8] = b"admin_log";
+pub const DENYLIST_SEED: &[u8] = b"denylist";
+pub const LAUNCH_BUYS_SEED: &[u8] = b"launch_buys";
+
+/// Fractional bits of the fixed-point log2 used by the geometric TWAP accumulator.
+pub const LOG2_FRAC_BITS: u32 = 32;
+
+/// Shortest default TWAP window the authority may configure.
+pub const MIN_TWAP_WINDOW_SECONDS: u32 = 60;
+
+/// Depth EMA alpha of new pools (0.1).
+pub const DEFAULT_DEPTH_EMA_ALPHA_1E12: u64 = 100_000_000_000;
+
+/// Marker of the `simulate_swap` log line, followed by `key=value` fields.
+pub const SIMULATE_SWAP_LOG_PREFIX: &str = "simulate_swap:";
+
+/// Byte sizes of the zero-padded `Pool::name` / `Pool::uri` fields.
+pub const POOL_NAME_LEN: usize = 32;
+pub const POOL_URI_LEN: usize = 128;
 
 #[program]
 pub mod adaptive_cpamm {
@@ -20,17 +102,33 @@ pub mod adaptive_cpamm {
     /// - Vault token accounts owned by pool PDA
     pub fn initialize_pool(
         ctx: Context<InitializePool>,
-        min_fee_bps: u16,
-        max_fee_bps: u16,
-        beta_vol_bps_per1e12: u16,
-        gamma_slip_bps_per1e12: u16,
-        delta_shallow_bps_per1e12: u16,
-        ema_alpha_1e12: u64,       // e.g., 0.05 * 1e12
-        breaker_vol_threshold_1e12: u64, // e.g., 0.20 * 1e12
+        fee_curve: FeeCurve,
+        flags: PoolFlags,
+        launch: LaunchConfig,
+        name: String, // optional label, "" = none
+        uri: String,
     ) -> Result<()> {
-        require!(min_fee_bps <= max_fee_bps, AmmError::BadBounds);
+        let PoolFlags {
+            geometric_twap_enabled,
+            sync_donations,
+            lp_freezable,
+            allow_risky_mints,
+            fee_on_output,
+        } = flags;
+        if !allow_risky_mints {
+            check_mint_risk(&ctx.accounts.token0_mint)?;
+            check_mint_risk(&ctx.accounts.token1_mint)?;
+        }
+        // Badges are only checked here: revoking one later leaves pools
+        // already created with that mint untouched
+        if load_config(&ctx.accounts.config)?.is_some_and(|config| config.require_badges) {
+            require!(
+                ctx.accounts.token0_badge.is_some() && ctx.accounts.token1_badge.is_some(),
+                AmmError::MintNotApproved
+            );
+        }
         let pool = &mut ctx.accounts.pool;
-        pool.bump = *ctx.bumps.get("pool").unwrap();
+        pool.bump = ctx.bumps.pool;
         pool.authority = ctx.accounts.authority.key();
         pool.token0_mint = ctx.accounts.token0_mint.key();
         pool.token1_mint = ctx.accounts.token1_mint.key();
@@ -41,545 +139,5645 @@ pub mod adaptive_cpamm {
         pool.reserve0 = 0;
         pool.reserve1 = 0;
 
-        pool.min_fee_bps = min_fee_bps;
-        pool.max_fee_bps = max_fee_bps;
-        pool.beta_vol_bps_per1e12 = beta_vol_bps_per1e12;
-        pool.gamma_slip_bps_per1e12 = gamma_slip_bps_per1e12;
-        pool.delta_shallow_bps_per1e12 = delta_shallow_bps_per1e12;
+        pool.set_fee_bounds_ppm(fee_curve.min_fee_ppm, fee_curve.max_fee_ppm);
+        pool.beta_vol_bps_per1e12 = fee_curve.beta_vol_bps_per1e12;
+        pool.gamma_slip_bps_per1e12 = fee_curve.gamma_slip_bps_per1e12;
+        pool.delta_shallow_bps_per1e12 = fee_curve.delta_shallow_bps_per1e12;
+        pool.zeta_skew_bps_per1e12 = fee_curve.zeta_skew_bps_per1e12;
+
+        pool.ema_price_1e18 = 0; // initialize on first liquidity
+        pool.ema_alpha_1e12 = fee_curve.ema_alpha_1e12;
+        pool.breaker_vol_threshold_1e12 = fee_curve.breaker_vol_threshold_1e12;
+        pool.breaker_enabled = fee_curve.breaker_enabled;
+
+        // TWAP accumulators start accruing from pool creation
+        pool.price_cumulative_1e18 = 0;
+        pool.log2_price_cumulative = 0;
+        let clock = Clock::get()?;
+        pool.cumulative_last_timestamp = clock.unix_timestamp;
+        pool.geometric_twap_enabled = geometric_twap_enabled;
+        pool.sync_donations = sync_donations;
+        pool.lp_freezable = lp_freezable;
+        pool.lp_freeze_revoked = !lp_freezable;
+        pool.allow_risky_mints = allow_risky_mints;
+        pool.decimals0 = ctx.accounts.token0_mint.decimals;
+        pool.decimals1 = ctx.accounts.token1_mint.decimals;
+        pool.min_trade_amount0 = default_min_trade_amount(pool.decimals0);
+        pool.min_trade_amount1 = default_min_trade_amount(pool.decimals1);
+        pool.fee_manager = pool.authority;
+        pool.pauser = pool.authority;
+        pool.treasurer = pool.authority;
+        pool.name = pack_metadata(&name)?;
+        pool.uri = pack_metadata(&uri)?;
+        pool.slip_mode = SlipMode::InputFraction;
+        pool.shallow_mode = ShallowMode::MinReserve;
+        pool.depth_ema_alpha_1e12 = DEFAULT_DEPTH_EMA_ALPHA_1E12;
+        pool.fee_on_output = fee_on_output;
+        pool.growth_1e12 = SCALE;
+        pool.launch_token_is_0 = launch.launch_token_is_0;
+        pool.launch_end_slot = launch.launch_end_slot;
+        pool.max_buy_per_wallet = launch.max_buy_per_wallet;
+        validate_params(pool)?;
+        record_update(pool, &clock);
+
+        // Pools that opt out of LP freezing drop the authority for good
+        if !lp_freezable {
+            revoke_lp_freeze(
+                &ctx.accounts.pool,
+                &ctx.accounts.lp_mint,
+                &ctx.accounts.token_program,
+            )?;
+        }
+
+        let pool = &mut ctx.accounts.pool;
+        let event = PoolInitialized {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            creator: pool.authority,
+            token0_mint: pool.token0_mint,
+            token1_mint: pool.token1_mint,
+            vault0: pool.vault0,
+            vault1: pool.vault1,
+            lp_mint: pool.lp_mint,
+            fee_config: pool.fee_config(),
+            geometric_twap_enabled,
+            sync_donations,
+            lp_freezable,
+            allow_risky_mints,
+            name,
+            uri,
+            fee_on_output,
+            launch_token_is_0: launch.launch_token_is_0,
+            launch_end_slot: launch.launch_end_slot,
+            max_buy_per_wallet: launch.max_buy_per_wallet,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Program upgrade authority: create the program-wide Config whose
+    /// admin manages the mint allowlist.
+    pub fn initialize_config(ctx: Context<InitializeConfig>, require_badges: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.require_badges = require_badges;
+        config.bump = ctx.bumps.config;
+        Ok(())
+    }
 
-        pool.ema_price_1e12 = 0; // initialize on first liquidity
-        pool.ema_alpha_1e12 = ema_alpha_1e12;
-        pool.breaker_vol_threshold_1e12 = breaker_vol_threshold_1e12;
+    /// Config admin: turn the mint allowlist on or off, or hand over admin.
+    pub fn set_config(ctx: Context<SetConfig>, admin: Pubkey, require_badges: bool) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = admin;
+        config.require_badges = require_badges;
+        Ok(())
+    }
 
+    /// Config admin: allowlist a mint for pool creation.
+    pub fn approve_mint(ctx: Context<ApproveMint>) -> Result<()> {
+        let badge = &mut ctx.accounts.badge;
+        badge.mint = ctx.accounts.mint.key();
+        badge.bump = ctx.bumps.badge;
         Ok(())
     }
 
-    /// Admin: update parameters
-    pub fn set_params(
-        ctx: Context<SetParams>,
-        min_fee_bps: u16,
-        max_fee_bps: u16,
-        beta_vol_bps_per1e12: u16,
-        gamma_slip_bps_per1e12: u16,
-        delta_shallow_bps_per1e12: u16,
-        ema_alpha_1e12: u64,
-        breaker_vol_threshold_1e12: u64,
-    ) -> Result<()> {
-        require!(min_fee_bps <= max_fee_bps, AmmError::BadBounds);
+    /// Config admin: remove a mint from the allowlist (refunds the badge
+    /// rent). Pools that already hold the mint keep working.
+    pub fn revoke_mint(_ctx: Context<RevokeMint>) -> Result<()> {
+        Ok(())
+    }
+
+    /// Authority or fee manager: update parameters
+    pub fn set_params(ctx: Context<SetParams>, fee_config: FeeConfig) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), AmmError::NotAuthorized);
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::FeeManager)?;
+
+        // dates the fee curve for indexers; nothing on chain reads it
+        let clock = Clock::get()?;
+        pool.params_updated_slot = clock.slot;
 
-        pool.min_fee_bps = min_fee_bps;
-        pool.max_fee_bps = max_fee_bps;
-        pool.beta_vol_bps_per1e12 = beta_vol_bps_per1e12;
-        pool.gamma_slip_bps_per1e12 = gamma_slip_bps_per1e12;
-        pool.delta_shallow_bps_per1e12 = delta_shallow_bps_per1e12;
-        pool.ema_alpha_1e12 = ema_alpha_1e12;
-        pool.breaker_vol_threshold_1e12 = breaker_vol_threshold_1e12;
+        let old = pool.fee_config();
+        pool.set_fee_config(&fee_config);
+        validate_params(pool)?;
+        record_update(pool, &clock);
+
+        let new = pool.fee_config();
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetParams { old, new },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
         Ok(())
     }
 
-    /// Add liquidity (must match current price ratio when pool has liquidity).
-    /// Mints LP shares to provider.
-    pub fn add_liquidity(
-        ctx: Context<AddLiquidity>,
-        amount0: u64,
-        amount1: u64,
+    /// Admin: attach (or detach with `OracleSource::None`) a price feed
+    /// whose confidence interval feeds an extra volatility term. Unless
+    /// `oracle_required`, swaps without a fresh feed skip that term.
+    pub fn set_oracle(
+        ctx: Context<SetParams>,
+        oracle_source: OracleSource,
+        oracle: Pubkey,
+        epsilon_oracle_bps_per1e12: u16,
+        oracle_max_age_seconds: u32,
+        oracle_required: bool,
     ) -> Result<()> {
-        require!(amount0 > 0 && amount1 > 0, AmmError::ZeroAmount);
-
+        require!(
+            !oracle_required || oracle_source != OracleSource::None,
+            AmmError::BadBounds
+        );
         let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
 
-        // Enforce price invariance when reserves > 0
-        if pool.reserve0 > 0 && pool.reserve1 > 0 {
-            // reserve0 * amount1 == reserve1 * amount0
-            let lhs = (pool.reserve0 as u128) * (amount1 as u128);
-            let rhs = (pool.reserve1 as u128) * (amount0 as u128);
-            require!(lhs == rhs, AmmError::BadRatio);
-        }
+        let old = pool.oracle_config();
+        pool.oracle_source = oracle_source;
+        pool.oracle = oracle;
+        pool.epsilon_oracle_bps_per1e12 = epsilon_oracle_bps_per1e12;
+        pool.oracle_max_age_seconds = oracle_max_age_seconds;
+        pool.oracle_required = oracle_required;
+        validate_params(pool)?;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-        // Pull tokens into vaults
-        transfer_into_vault(
-            &ctx.accounts.user,
-            &ctx.accounts.user_token0,
-            &ctx.accounts.vault0,
-            &ctx.accounts.token_program,
-            amount0,
-        )?;
-        transfer_into_vault(
-            &ctx.accounts.user,
-            &ctx.accounts.user_token1,
-            &ctx.accounts.vault1,
-            &ctx.accounts.token_program,
-            amount1,
-        )?;
+        let new = pool.oracle_config();
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetOracle { old, new },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Authority or pauser: pause/unpause swaps and deposits. Withdrawals
+    /// stay open so LPs can always exit.
+    pub fn set_paused(ctx: Context<SetParams>, paused: bool) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Pauser)?;
 
-        // Update reserves from vault balances
-        let new_bal0 = ctx.accounts.vault0.amount;
-        let new_bal1 = ctx.accounts.vault1.amount;
+        let old = pool.paused;
+        pool.paused = paused;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-        let (shares_to_mint, new_reserve0, new_reserve1) = if pool.total_lp_supply == 0 {
-            // L0 = sqrt(x*y)
-            let k = (new_bal0 as u128)
-                .checked_mul(new_bal1 as u128)
-                .ok_or(AmmError::MathOverflow)?;
-            let shares = isqrt(k) as u64;
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetPaused { old, new: paused },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-            // init EMA with first spot price
-            if pool.ema_price_1e12 == 0 {
-                let price = spot_price_1e12(new_bal0, new_bal1)?;
-                pool.ema_price_1e12 = price;
-            }
+    /// Admin: cap on a swap's price impact in bps of the pre-trade spot
+    /// (fee included). 0 disables the check.
+    pub fn set_max_price_impact(ctx: Context<SetParams>, max_price_impact_bps: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+        require!(max_price_impact_bps as u64 <= BPS_DENOM, AmmError::BadBounds);
 
-            (shares, new_bal0, new_bal1)
-        } else {
-            // shares = min( dx/x * T, dy/y * T )
-            let t = pool.total_lp_supply as u128;
-            let dx = (amount0 as u128)
-                .checked_mul(t)
-                .ok_or(AmmError::MathOverflow)?
-                / (pool.reserve0 as u128);
-            let dy = (amount1 as u128)
-                .checked_mul(t)
-                .ok_or(AmmError::MathOverflow)?
-                / (pool.reserve1 as u128);
-            let shares = u128::min(dx, dy) as u64;
-            (shares, new_bal0, new_bal1)
-        };
-
-        require!(shares_to_mint > 0, AmmError::ZeroShares);
-
-        // Mint LP shares to user
-        mint_lp_shares(
-            &ctx.accounts.pool,
-            &ctx.accounts.lp_mint,
-            &ctx.accounts.user_lp,
-            &ctx.accounts.token_program,
-            shares_to_mint,
-            &ctx.accounts.pool_signer,
-        )?;
+        let old = pool.max_price_impact_bps;
+        pool.max_price_impact_bps = max_price_impact_bps;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-        // Save reserves & total supply
-        pool.reserve0 = new_reserve0;
-        pool.reserve1 = new_reserve1;
-        pool.total_lp_supply = pool
-            .total_lp_supply
-            .checked_add(shares_to_mint)
-            .ok_or(AmmError::MathOverflow)?;
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetMaxPriceImpact { old, new: max_price_impact_bps },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-        // Optional EMA update after add
-        if pool.reserve0 > 0 && pool.reserve1 > 0 {
-            let price = spot_price_1e12(pool.reserve0, pool.reserve1)?;
-            ema_update(&mut pool.ema_price_1e12, pool.ema_alpha_1e12, price);
-        }
+    /// Admin: maximum EMA age before a swap resets it to spot. 0 disables.
+    pub fn set_ema_max_age(ctx: Context<SetParams>, ema_max_age_seconds: u32) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
 
-        emit!(MintEvent {
-            sender: ctx.accounts.user.key(),
-            amount0,
-            amount1,
-            shares: shares_to_mint
-        });
+        let old = pool.ema_max_age_seconds;
+        pool.ema_max_age_seconds = ema_max_age_seconds;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetEmaMaxAge { old, new: ema_max_age_seconds },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
         Ok(())
     }
 
-    /// Remove liquidity: burns LP and returns tokens pro-rata.
-    pub fn remove_liquidity(ctx: Context<RemoveLiquidity>, shares: u64) -> Result<()> {
+    /// Authority or fee manager: fee on flash loans in bps of the amount
+    /// borrowed. 0 disables `flash_borrow`.
+    pub fn set_flash_fee(ctx: Context<SetParams>, flash_fee_bps: u16) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
-        require!(shares > 0, AmmError::ZeroShares);
-        require!(pool.total_lp_supply >= shares, AmmError::InsufficientLP);
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::FeeManager)?;
+        require!((flash_fee_bps as u64) < BPS_DENOM, AmmError::BadBounds);
 
-        // Burn LP from user
-        burn_lp_shares(
-            &ctx.accounts.user,
-            &ctx.accounts.user_lp,
-            &ctx.accounts.lp_mint,
-            &ctx.accounts.token_program,
-            shares,
+        let old = pool.flash_fee_bps;
+        pool.flash_fee_bps = flash_fee_bps;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetFlashFee { old, new: flash_fee_bps },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: freeze an LP token account (compliance). Frozen LPs cannot
+    /// transfer but can still exit through `remove_liquidity`.
+    pub fn freeze_lp_account(ctx: Context<FreezeLpAccount>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let role = authorize(&accounts.pool, accounts.authority.key(), Role::Authority)?;
+        require!(accounts.pool.lp_freezable, AmmError::FreezeDisabled);
+        set_lp_frozen(
+            &accounts.pool,
+            &accounts.lp_mint,
+            &accounts.lp_account,
+            &accounts.token_program,
+            &accounts.pool_signer,
+            true,
         )?;
 
-        // Compute pro-rata amounts
-        let bal0 = ctx.accounts.vault0.amount;
-        let bal1 = ctx.accounts.vault1.amount;
-
-        let amount0 = (shares as u128)
-            .checked_mul(bal0 as u128)
-            .ok_or(AmmError::MathOverflow)?
-            / (pool.total_lp_supply as u128);
-        let amount1 = (shares as u128)
-            .checked_mul(bal1 as u128)
-            .ok_or(AmmError::MathOverflow)?
-            / (pool.total_lp_supply as u128);
-
-        // Update pool supply before transfer out
-        pool.total_lp_supply = pool
-            .total_lp_supply
-            .checked_sub(shares)
-            .ok_or(AmmError::MathOverflow)?;
+        let actor = ctx.accounts.authority.key();
+        let account = ctx.accounts.lp_account.key();
+        let event = admin_event(
+            &mut ctx.accounts.pool,
+            actor,
+            role,
+            AdminAction::FreezeLpAccount { account },
+            &Clock::get()?,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-        // Transfer out to user
-        transfer_from_vault(
-            &ctx.accounts.pool,
-            &ctx.accounts.vault0,
-            &ctx.accounts.user_token0,
-            &ctx.accounts.token_program,
-            amount0 as u64,
-            &ctx.accounts.pool_signer,
+    /// Admin: thaw a previously frozen LP token account.
+    pub fn thaw_lp_account(ctx: Context<FreezeLpAccount>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let role = authorize(&accounts.pool, accounts.authority.key(), Role::Authority)?;
+        require!(accounts.pool.lp_freezable, AmmError::FreezeDisabled);
+        set_lp_frozen(
+            &accounts.pool,
+            &accounts.lp_mint,
+            &accounts.lp_account,
+            &accounts.token_program,
+            &accounts.pool_signer,
+            false,
         )?;
+
+        let actor = ctx.accounts.authority.key();
+        let account = ctx.accounts.lp_account.key();
+        let event = admin_event(
+            &mut ctx.accounts.pool,
+            actor,
+            role,
+            AdminAction::ThawLpAccount { account },
+            &Clock::get()?,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: drop the LP mint freeze authority for good, so LP tokens can
+    /// never be frozen. Irreversible; freeze/thaw fail with FreezeDisabled.
+    pub fn revoke_lp_freeze_authority(ctx: Context<RevokeLpFreezeAuthority>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let role = authorize(&accounts.pool, accounts.authority.key(), Role::Authority)?;
+        require!(accounts.pool.lp_freezable, AmmError::FreezeDisabled);
+        revoke_lp_freeze(&accounts.pool, &accounts.lp_mint, &accounts.token_program)?;
+
+        let pool = &mut ctx.accounts.pool;
+        pool.lp_freezable = false;
+        pool.lp_freeze_revoked = true;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::RevokeLpFreezeAuthority,
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Authority or treasurer: move `amount` of a stray token out of an
+    /// account owned by the pool PDA. The two vaults, the reward vault and
+    /// LP-mint accounts can never be a source.
+    pub fn rescue_tokens(ctx: Context<RescueTokens>, amount: u64) -> Result<()> {
+        require!(amount > 0, AmmError::ZeroAmount);
+        let pool = &mut ctx.accounts.pool;
+        let actor = ctx.accounts.signer.key();
+        let role = authorize(pool, actor, Role::Treasurer)?;
+        let source = &ctx.accounts.source;
+        require_keys_neq!(source.key(), pool.vault0, AmmError::ProtectedAccount);
+        require_keys_neq!(source.key(), pool.vault1, AmmError::ProtectedAccount);
+        require_keys_neq!(source.key(), pool.reward_vault, AmmError::ProtectedAccount);
+        require_keys_neq!(source.mint, pool.lp_mint, AmmError::ProtectedAccount);
+
         transfer_from_vault(
-            &ctx.accounts.pool,
-            &ctx.accounts.vault1,
-            &ctx.accounts.user_token1,
+            pool,
+            source,
+            &ctx.accounts.destination,
             &ctx.accounts.token_program,
-            amount1 as u64,
+            amount,
             &ctx.accounts.pool_signer,
         )?;
 
-        // Update reserves from vault balances
-        pool.reserve0 = ctx.accounts.vault0.amount;
-        pool.reserve1 = ctx.accounts.vault1.amount;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+        let action = AdminAction::RescueTokens {
+            mint: source.mint,
+            source: source.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+        };
+        let admin = admin_event(pool, actor, role, action, &clock);
+        append_admin_log(pool, ctx.accounts.admin_log.as_ref(), &admin)?;
+        let event = TokensRescued {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            actor,
+            role,
+            mint: source.mint,
+            source: source.key(),
+            destination: ctx.accounts.destination.key(),
+            amount,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        emit_event!(ctx, admin);
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-        // Optional EMA update
-        if pool.reserve0 > 0 && pool.reserve1 > 0 {
-            let price = spot_price_1e12(pool.reserve0, pool.reserve1)?;
-            ema_update(&mut pool.ema_price_1e12, pool.ema_alpha_1e12, price);
+    /// Admin: tear down an empty pool. Closes both vault ATAs, the reward
+    /// vault and LP escrow once rewards exist (both must be empty, so end
+    /// any campaign first), the snapshots PDA and admin log when passed,
+    /// and the pool itself, sending their rent to `recipient`. The pool's
+    /// Denylisted, LaunchBuys, Position and TwapCheckpoint records passed
+    /// as remaining accounts are closed too; see `close_pool_record`.
+    /// Everything happens in this one instruction, so the pool is either
+    /// fully usable or gone. The LP mint cannot be closed by the classic
+    /// token program and is left behind with zero supply.
+    pub fn close_pool<'info>(ctx: Context<'_, '_, '_, 'info, ClosePool<'info>>) -> Result<()> {
+        let accounts = &ctx.accounts;
+        let pool = &accounts.pool;
+        let role = authorize(pool, accounts.authority.key(), Role::Authority)?;
+        if pool.total_lp_supply > 0 || accounts.vault0.amount > 0 || accounts.vault1.amount > 0 {
+            msg!(
+                "lp supply {} vaults {}/{}",
+                pool.total_lp_supply,
+                accounts.vault0.amount,
+                accounts.vault1.amount
+            );
+            return err!(AmmError::PoolNotEmpty);
+        }
+        let mut vaults = vec![&accounts.vault0, &accounts.vault1];
+        if pool.reward_mint != Pubkey::default() {
+            let (Some(reward_vault), Some(lp_escrow)) = (&accounts.reward_vault, &accounts.lp_escrow)
+            else {
+                return err!(AmmError::RewardAccountsMissing);
+            };
+            if reward_vault.amount > 0 || lp_escrow.amount > 0 {
+                msg!("reward vault {} lp escrow {}", reward_vault.amount, lp_escrow.amount);
+                return err!(AmmError::PoolNotEmpty);
+            }
+            vaults.extend([reward_vault, lp_escrow]);
         }
 
-        emit!(BurnEvent {
-            sender: ctx.accounts.user.key(),
-            shares,
-            amount0: amount0 as u64,
-            amount1: amount1 as u64
-        });
+        for vault in vaults {
+            close_vault(
+                pool,
+                vault,
+                &accounts.recipient,
+                &accounts.token_program,
+                &accounts.pool_signer,
+            )?;
+        }
+        let pool_key = pool.key();
+        let recipient = accounts.recipient.to_account_info();
+        let mut records = ctx.remaining_accounts.iter();
+        while let Some(record) = records.next() {
+            close_pool_record(&pool_key, record, &mut records, &recipient)?;
+        }
 
+        let actor = accounts.authority.key();
+        let event = admin_event(
+            &mut ctx.accounts.pool,
+            actor,
+            role,
+            AdminAction::ClosePool,
+            &Clock::get()?,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        // pool, snapshots and admin log are closed to `recipient` by their
+        // constraints
         Ok(())
     }
 
-    /// Swap with adaptive fee and a circuit breaker on excessive volatility.
-    pub fn swap(ctx: Context<Swap>, token_in_is_0: bool, amount_in: u64) -> Result<()> {
-        require!(amount_in > 0, AmmError::ZeroAmount);
+    /// Primary authority only: set or clear (default pubkey) the
+    /// co-authority, which can act wherever the authority can except here.
+    pub fn set_co_authority(ctx: Context<SetParams>, co_authority: Pubkey) -> Result<()> {
         let pool = &mut ctx.accounts.pool;
+        require_keys_eq!(pool.authority, ctx.accounts.authority.key(), AmmError::NotAuthorized);
 
-        // Pull token_in from user → vault
-        if token_in_is_0 {
-            transfer_into_vault(
-                &ctx.accounts.user,
-                &ctx.accounts.user_token_in,
-                &ctx.accounts.vault0,
-                &ctx.accounts.token_program,
-                amount_in,
-            )?;
-        } else {
-            transfer_into_vault(
-                &ctx.accounts.user,
-                &ctx.accounts.user_token_in,
-                &ctx.accounts.vault1,
-                &ctx.accounts.token_program,
-                amount_in,
-            )?;
-        }
+        let old = pool.co_authority;
+        pool.co_authority = co_authority;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-        // Refresh reserves from vault balances
-        let r0 = ctx.accounts.vault0.amount as u128;
-        let r1 = ctx.accounts.vault1.amount as u128;
-        require!(r0 > 0 && r1 > 0, AmmError::NoLiquidity);
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            Role::Authority,
+            AdminAction::SetCoAuthority { old, new: co_authority },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: appoint the fee manager (set_params, set_protocol_fee,
+    /// set_flash_fee), pauser (set_paused) and treasurer
+    /// (collect_protocol_fees, rescue_tokens). Pass the default pubkey to
+    /// leave a role to the authority alone.
+    pub fn set_roles(
+        ctx: Context<SetParams>,
+        fee_manager: Pubkey,
+        pauser: Pubkey,
+        treasurer: Pubkey,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
 
-        // Compute dynamic fee & components
-        let (fee_bps, vol_1e12, _slip_1e12, _shallow_1e12) =
-            compute_dynamic_fee(pool, token_in_is_0, amount_in as u128, r0, r1)?;
+        let old = pool.role_config();
+        pool.fee_manager = fee_manager;
+        pool.pauser = pauser;
+        pool.treasurer = treasurer;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-        // Circuit breaker
-        require!(
-            vol_1e12 <= pool.breaker_vol_threshold_1e12 as u128,
-            AmmError::VolTooHigh
+        let new = pool.role_config();
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetRoles { old, new },
+            &clock,
         );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-        // x*y=k pricing with fee on amountIn
-        let (rin, rout) = if token_in_is_0 { (r0, r1) } else { (r1, r0) };
+    /// Authority or fee manager: choose the slippage signal of the fee.
+    pub fn set_slip_mode(ctx: Context<SetParams>, slip_mode: SlipMode) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::FeeManager)?;
 
-        let fee_num = (BPS_DENOM - fee_bps as u64) as u128;
-        let dx_fee = (amount_in as u128)
-            .checked_mul(fee_num)
-            .ok_or(AmmError::MathOverflow)?
-            / (BPS_DENOM as u128);
+        let old = pool.slip_mode;
+        pool.slip_mode = slip_mode;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-        let amount_out = (rout
-            .checked_mul(dx_fee)
-            .ok_or(AmmError::MathOverflow)?)
-            / (rin
-            .checked_add(dx_fee)
-            .ok_or(AmmError::MathOverflow)?);
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetSlipMode { old, new: slip_mode },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-        require!(amount_out > 0, AmmError::AmountOutZero);
+    /// Admin: replace the pool's display name and documentation URI
+    /// ("" clears a field).
+    pub fn set_metadata(ctx: Context<SetParams>, name: String, uri: String) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
 
-        // Send token_out to user from vault
-        if token_in_is_0 {
-            transfer_from_vault(
-                &ctx.accounts.pool,
-                &ctx.accounts.vault1,
-                &ctx.accounts.user_token_out,
-                &ctx.accounts.token_program,
-                amount_out as u64,
-                &ctx.accounts.pool_signer,
-            )?;
-        } else {
-            transfer_from_vault(
-                &ctx.accounts.pool,
-                &ctx.accounts.vault0,
-                &ctx.accounts.user_token_out,
-                &ctx.accounts.token_program,
-                amount_out as u64,
-                &ctx.accounts.pool_signer,
-            )?;
-        }
+        let (old_name, old_uri) = (pool.name(), pool.uri());
+        pool.name = pack_metadata(&name)?;
+        pool.uri = pack_metadata(&uri)?;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-        // Update reserves
-        pool.reserve0 = ctx.accounts.vault0.amount;
-        pool.reserve1 = ctx.accounts.vault1.amount;
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetMetadata { old_name, old_uri, new_name: name, new_uri: uri },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-        // Update EMA
-        let price = spot_price_1e12(pool.reserve0, pool.reserve1)?;
-        ema_update(&mut pool.ema_price_1e12, pool.ema_alpha_1e12, price);
+    /// Admin: appoint the guardian allowed to trip and reset the breaker.
+    /// `Pubkey::default()` removes it.
+    pub fn set_guardian(ctx: Context<SetParams>, guardian: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
 
-        emit!(SwapEvent {
-            trader: ctx.accounts.user.key(),
-            token_in_is_0,
-            amount_in,
-            amount_out: amount_out as u64,
-            fee_bps
-        });
+        let old = pool.guardian;
+        pool.guardian = guardian;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetGuardian { old, new: guardian },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
         Ok(())
     }
-}
 
-/* ------------------------------- State ---------------------------------- */
+    /// Guardian or authority: halt swaps now, ahead of the volatility
+    /// signal. Stays tripped until `reset_breaker`; withdrawals stay open.
+    pub fn trip_breaker(ctx: Context<GuardianAction>) -> Result<()> {
+        set_manual_breaker(ctx, true)
+    }
 
-#[account]
-pub struct Pool {
-    pub bump: u8,
-    pub authority: Pubkey,
+    /// Guardian or authority: clear a manual trip.
+    pub fn reset_breaker(ctx: Context<GuardianAction>) -> Result<()> {
+        set_manual_breaker(ctx, false)
+    }
 
-    pub token0_mint: Pubkey,
-    pub token1_mint: Pubkey,
-    pub vault0: Pubkey,
-    pub vault1: Pubkey,
+    /// Authority or fee manager: protocol share of every swap fee, in bps
+    /// of the fee. Accrues as owed balances for `collect_protocol_fees`.
+    pub fn set_protocol_fee(ctx: Context<SetParams>, protocol_fee_share_bps: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::FeeManager)?;
+        require!(protocol_fee_share_bps as u64 <= BPS_DENOM, AmmError::BadBounds);
 
-    pub lp_mint: Pubkey,
-    pub total_lp_supply: u64,
+        let old = pool.protocol_fee_share_bps;
+        pool.protocol_fee_share_bps = protocol_fee_share_bps;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-    // reserves mirrored from vault balances
-    pub reserve0: u64,
-    pub reserve1: u64,
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetProtocolFee { old, new: protocol_fee_share_bps },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-    // fee params (bps and per-1e12 coefficients)
-    pub min_fee_bps: u16,
-    pub max_fee_bps: u16,
-    pub beta_vol_bps_per1e12: u16,
-    pub gamma_slip_bps_per1e12: u16,
-    pub delta_shallow_bps_per1e12: u16,
+    /// Admin: smallest accepted swap input per token (0 = no minimum).
+    /// Dust trades pay no fee after rounding and steer the EMA for free.
+    pub fn set_min_trade_amounts(
+        ctx: Context<SetParams>,
+        min_trade_amount0: u64,
+        min_trade_amount1: u64,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
 
-    // EMA config and circuit breaker
-    pub ema_price_1e12: u64,
-    pub ema_alpha_1e12: u64,
-    pub breaker_vol_threshold_1e12: u64,
-}
+        let (old0, old1) = (pool.min_trade_amount0, pool.min_trade_amount1);
+        pool.min_trade_amount0 = min_trade_amount0;
+        pool.min_trade_amount1 = min_trade_amount1;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-impl Pool {
-    pub fn seeds(&self) -> [&[u8]; 2] {
-        [b"pool", &[self.bump]]
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetMinTradeAmounts {
+                old0,
+                old1,
+                new0: min_trade_amount0,
+                new1: min_trade_amount1,
+            },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
     }
-}
 
-/* ------------------------------- Events --------------------------------- */
+    /// Admin: cap any single swap input at `max_trade_bps_of_reserve` of
+    /// the pre-trade input-side reserve (0 = no cap).
+    pub fn set_max_trade_size(ctx: Context<SetParams>, max_trade_bps_of_reserve: u16) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+        require!(max_trade_bps_of_reserve as u64 <= BPS_DENOM, AmmError::BadBounds);
 
-#[event]
-pub struct SwapEvent {
-    pub trader: Pubkey,
-    pub token_in_is_0: bool,
-    pub amount_in: u64,
-    pub amount_out: u64,
-    pub fee_bps: u16,
-}
+        let old = pool.max_trade_bps_of_reserve;
+        pool.max_trade_bps_of_reserve = max_trade_bps_of_reserve;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
 
-#[event]
-pub struct MintEvent {
-    pub sender: Pubkey,
-    pub amount0: u64,
-    pub amount1: u64,
-    pub shares: u64,
-}
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetMaxTradeSize { old, new: max_trade_bps_of_reserve },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
 
-#[event]
-pub struct BurnEvent {
-    pub sender: Pubkey,
+    /// Admin: with `only_via_router` set, swaps are only accepted by CPI
+    /// from one of `routers` (at most MAX_ROUTERS); direct calls fail.
+    pub fn set_routers(
+        ctx: Context<SetParams>,
+        only_via_router: bool,
+        routers: Vec<Pubkey>,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+        require!(
+            routers.len() <= MAX_ROUTERS && !(only_via_router && routers.is_empty()),
+            AmmError::InvalidRouterList
+        );
+
+        let (old_only_via_router, old_routers) = (pool.only_via_router, pool.routers);
+        pool.only_via_router = only_via_router;
+        pool.routers = [Pubkey::default(); MAX_ROUTERS];
+        pool.routers[..routers.len()].copy_from_slice(&routers);
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetRouters {
+                old_only_via_router,
+                old_routers,
+                new_only_via_router: only_via_router,
+                new_routers: pool.routers,
+            },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Authority or fee manager: a swap in the opposite direction of one of
+    /// the last RECENT_TRADES in that direction, within
+    /// `reversion_window_slots` of it and with an input within
+    /// `reversion_band_bps` of that swap's output, pays
+    /// `reversion_penalty_bps` on top of the dynamic fee (the sum capped at
+    /// REVERSION_FEE_CAP_BPS). Any zero turns the penalty off.
+    pub fn set_reversion_penalty(
+        ctx: Context<SetParams>,
+        reversion_window_slots: u64,
+        reversion_band_bps: u16,
+        reversion_penalty_bps: u16,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::FeeManager)?;
+        require!(
+            reversion_band_bps as u64 <= BPS_DENOM
+                && reversion_penalty_bps <= REVERSION_FEE_CAP_BPS,
+            AmmError::BadBounds
+        );
+
+        let old = pool.reversion_params();
+        pool.reversion_window_slots = reversion_window_slots;
+        pool.reversion_band_bps = reversion_band_bps;
+        pool.reversion_penalty_bps = reversion_penalty_bps;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetReversionPenalty {
+                old_window_slots: old.window_slots,
+                old_band_bps: old.band_bps,
+                old_penalty_bps: old.penalty_bps,
+                new_window_slots: reversion_window_slots,
+                new_band_bps: reversion_band_bps,
+                new_penalty_bps: reversion_penalty_bps,
+            },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: set the compliance key that manages the denylist, and turn
+    /// its enforcement on or off. With `denylist_allows_removal`, listed
+    /// addresses can still `remove_liquidity` so their funds are not
+    /// trapped.
+    pub fn set_compliance(
+        ctx: Context<SetParams>,
+        compliance: Pubkey,
+        enforce_denylist: bool,
+        denylist_allows_removal: bool,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+
+        let old = (pool.compliance, pool.enforce_denylist, pool.denylist_allows_removal);
+        pool.compliance = compliance;
+        pool.enforce_denylist = enforce_denylist;
+        pool.denylist_allows_removal = denylist_allows_removal;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetCompliance {
+                old_compliance: old.0,
+                old_enforce_denylist: old.1,
+                old_denylist_allows_removal: old.2,
+                new_compliance: compliance,
+                new_enforce_denylist: enforce_denylist,
+                new_denylist_allows_removal: denylist_allows_removal,
+            },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Authority or compliance: list `address` by creating its `Denylisted`
+    /// marker. Takes effect while `enforce_denylist` is on.
+    pub fn deny_address(ctx: Context<DenyAddress>, address: Pubkey) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Compliance)?;
+        let marker = &mut ctx.accounts.marker;
+        marker.address = address;
+        marker.bump = ctx.bumps.marker;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::DenyAddress { address },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Authority or compliance: delist an address, closing its marker and
+    /// refunding the rent to `rent_receiver`.
+    pub fn allow_address(ctx: Context<AllowAddress>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Compliance)?;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::AllowAddress { address: ctx.accounts.marker.address },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Authority, treasurer or treasury: pay the owed protocol fees out of
+    /// the vaults to the treasury's token accounts and zero the counters.
+    /// With `collect_as_token_0` set, the owed amount of the other token is
+    /// first sold through the pool like any swap (LP fee, breaker, at least
+    /// `min_swap_out`) and only the chosen token is paid out; that swap's
+    /// own protocol fee is owed as usual, so with an input-side fee a
+    /// little of the other token stays behind. Pass the pool's oracle among
+    /// the remaining accounts, as for `swap`.
+    pub fn collect_protocol_fees(
+        ctx: Context<CollectProtocolFees>,
+        collect_as_token_0: Option<bool>,
+        min_swap_out: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let pool = &mut ctx.accounts.pool;
+        let signer = ctx.accounts.signer.key();
+        let role = match authorize(pool, signer, Role::Treasurer) {
+            Ok(role) => role,
+            Err(_) => authorize(pool, signer, Role::Treasury)?,
+        };
+
+        let mut swap_event = None;
+        if let Some(to0) = collect_as_token_0 {
+            // the owed leg already sits in its vault: handing it to the
+            // reserves is the swap's input transfer
+            let leg = if to0 { pool.protocol_fees_owed1 } else { pool.protocol_fees_owed0 };
+            if leg > 0 {
+                #[cfg(feature = "invariants")]
+                let k_before = invariants::k(pool);
+                if to0 {
+                    pool.protocol_fees_owed1 = 0;
+                } else {
+                    pool.protocol_fees_owed0 = 0;
+                }
+                let pool_key = pool.key();
+                let event = apply_swap(
+                    pool,
+                    pool_key,
+                    signer,
+                    |pool| oracle_confidence_1e12(pool, ctx.remaining_accounts, clock.unix_timestamp),
+                    !to0,
+                    leg,
+                    min_swap_out,
+                    &clock,
+                )?;
+                #[cfg(feature = "invariants")]
+                invariants::check_k_fee_growth(k_before, pool, &event);
+                // the output stays in the vault and is paid out below
+                if to0 {
+                    pool.protocol_fees_owed0 = pool
+                        .protocol_fees_owed0
+                        .checked_add(event.amount_out)
+                        .ok_or(AmmError::MathOverflow)?;
+                } else {
+                    pool.protocol_fees_owed1 = pool
+                        .protocol_fees_owed1
+                        .checked_add(event.amount_out)
+                        .ok_or(AmmError::MathOverflow)?;
+                }
+                swap_event = Some(event);
+            }
+        }
+
+        let (amount0, amount1) = match collect_as_token_0 {
+            Some(true) => (pool.protocol_fees_owed0, 0),
+            Some(false) => (0, pool.protocol_fees_owed1),
+            None => (pool.protocol_fees_owed0, pool.protocol_fees_owed1),
+        };
+        if amount0 > 0 {
+            transfer_from_vault(
+                pool,
+                &ctx.accounts.vault0,
+                &ctx.accounts.treasury_token0,
+                &ctx.accounts.token_program,
+                amount0,
+                &ctx.accounts.pool_signer,
+            )?;
+        }
+        if amount1 > 0 {
+            transfer_from_vault(
+                pool,
+                &ctx.accounts.vault1,
+                &ctx.accounts.treasury_token1,
+                &ctx.accounts.token_program,
+                amount1,
+                &ctx.accounts.pool_signer,
+            )?;
+        }
+        pool.protocol_fees_owed0 -= amount0;
+        pool.protocol_fees_owed1 -= amount1;
+        record_update(pool, &clock);
+        let action = AdminAction::CollectProtocolFees { amount0, amount1 };
+        let admin = admin_event(pool, signer, role, action, &clock);
+        append_admin_log(pool, ctx.accounts.admin_log.as_ref(), &admin)?;
+
+        #[cfg(feature = "invariants")]
+        invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+
+        let event = ProtocolFeesCollected {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            collector: signer,
+            role,
+            amount0,
+            amount1,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        if let Some(swap_event) = swap_event {
+            emit_event!(ctx, swap_event);
+        }
+        emit_event!(ctx, admin);
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: defaults for `get_twap` so consumers share one risk window.
+    /// Requires window >= MIN_TWAP_WINDOW_SECONDS and staleness >= window.
+    pub fn set_twap_defaults(
+        ctx: Context<SetParams>,
+        default_twap_window_seconds: u32,
+        max_observation_staleness_seconds: u32,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+        require!(
+            default_twap_window_seconds >= MIN_TWAP_WINDOW_SECONDS
+                && max_observation_staleness_seconds >= default_twap_window_seconds,
+            AmmError::BadBounds
+        );
+
+        let old_window = pool.default_twap_window_seconds;
+        let old_staleness = pool.max_observation_staleness_seconds;
+        pool.default_twap_window_seconds = default_twap_window_seconds;
+        pool.max_observation_staleness_seconds = max_observation_staleness_seconds;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetTwapDefaults {
+                old_window,
+                old_staleness,
+                new_window: default_twap_window_seconds,
+                new_staleness: max_observation_staleness_seconds,
+            },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: set the protocol treasury and whether `skim` must pay it
+    /// instead of a caller-chosen recipient.
+    pub fn set_treasury(
+        ctx: Context<SetParams>,
+        treasury: Pubkey,
+        skim_to_treasury: bool,
+    ) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+
+        let (old_treasury, old_skim_to_treasury) = (pool.treasury, pool.skim_to_treasury);
+        pool.treasury = treasury;
+        pool.skim_to_treasury = skim_to_treasury;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::SetTreasury {
+                old_treasury,
+                old_skim_to_treasury,
+                new_treasury: treasury,
+                new_skim_to_treasury: skim_to_treasury,
+            },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Add liquidity (must match current price ratio when pool has liquidity).
+    /// Mints LP shares to provider.
+    pub fn add_liquidity(
+        ctx: Context<AddLiquidity>,
+        amount0: u64,
+        amount1: u64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        let user = ctx.accounts.user.key();
+        check_denylist(&ctx.accounts.pool, &[user], ctx.remaining_accounts, false)?;
+        let legs = PoolLegs {
+            user: &ctx.accounts.user,
+            user_token0: &ctx.accounts.user_token0,
+            user_token1: &ctx.accounts.user_token1,
+            vault0: &ctx.accounts.vault0,
+            vault1: &ctx.accounts.vault1,
+            pool_signer: &ctx.accounts.pool_signer,
+            token_program: &ctx.accounts.token_program,
+        };
+        #[cfg(feature = "invariants")]
+        let share_value_before = invariants::share_value(&ctx.accounts.pool);
+        let event = add_liquidity_core(
+            &mut ctx.accounts.pool,
+            &legs,
+            &ctx.accounts.lp_mint,
+            &ctx.accounts.user_lp,
+            ctx.accounts.position.as_mut(),
+            amount0,
+            amount1,
+            &clock,
+        )?;
+
+        #[cfg(feature = "invariants")]
+        {
+            let pool = &ctx.accounts.pool;
+            invariants::check_share_value_non_decreasing(share_value_before, pool);
+            invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+            invariants::check_lp_supply(pool, &mut ctx.accounts.lp_mint);
+            invariants::check_ema(pool);
+        }
+
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// Remove liquidity: burns LP and returns tokens pro-rata.
+    pub fn remove_liquidity(ctx: Context<RemoveLiquidity>, shares: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        let user = ctx.accounts.user.key();
+        let holders = [
+            user,
+            ctx.accounts.user_lp.owner,
+            ctx.accounts.user_token0.owner,
+            ctx.accounts.user_token1.owner,
+        ];
+        check_denylist(&ctx.accounts.pool, &holders, ctx.remaining_accounts, true)?;
+        let legs = PoolLegs {
+            user: &ctx.accounts.user,
+            user_token0: &ctx.accounts.user_token0,
+            user_token1: &ctx.accounts.user_token1,
+            vault0: &ctx.accounts.vault0,
+            vault1: &ctx.accounts.vault1,
+            pool_signer: &ctx.accounts.pool_signer,
+            token_program: &ctx.accounts.token_program,
+        };
+        #[cfg(feature = "invariants")]
+        let share_value_before = invariants::share_value(&ctx.accounts.pool);
+        let event = remove_liquidity_core(
+            &mut ctx.accounts.pool,
+            &legs,
+            &ctx.accounts.lp_mint,
+            &ctx.accounts.user_lp,
+            ctx.accounts.position.as_mut(),
+            shares,
+            &clock,
+        )?;
+
+        #[cfg(feature = "invariants")]
+        {
+            let pool = &ctx.accounts.pool;
+            invariants::check_share_value_non_decreasing(share_value_before, pool);
+            invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+            invariants::check_lp_supply(pool, &mut ctx.accounts.lp_mint);
+            invariants::check_ema(pool);
+        }
+
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// Burn `shares`, then swap the other leg into the `exit_to_0` side so
+    /// the user leaves holding a single token. The swap runs the full
+    /// adaptive-fee and breaker path; the whole exit reverts unless at least
+    /// `min_total_out` of the exit token is received. Returns that total.
+    /// An exit buying the launched token during the launch window counts
+    /// against the cap; the exit cannot create the LaunchBuys record, so it
+    /// must already exist from an earlier buy.
+    pub fn remove_liquidity_and_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, RemoveLiquidity<'info>>,
+        shares: u64,
+        exit_to_0: bool,
+        min_total_out: u64,
+        deadline: i64,
+    ) -> Result<u64> {
+        let clock = Clock::get()?;
+        check_deadline(&clock, deadline)?;
+        check_router(&ctx.accounts.pool, ctx.accounts.instructions.as_ref())?;
+        // an exit that also swaps is a trade, not just a withdrawal
+        let user = ctx.accounts.user.key();
+        let holders = [
+            user,
+            ctx.accounts.user_lp.owner,
+            ctx.accounts.user_token0.owner,
+            ctx.accounts.user_token1.owner,
+        ];
+        check_denylist(&ctx.accounts.pool, &holders, ctx.remaining_accounts, false)?;
+
+        let legs = PoolLegs {
+            user: &ctx.accounts.user,
+            user_token0: &ctx.accounts.user_token0,
+            user_token1: &ctx.accounts.user_token1,
+            vault0: &ctx.accounts.vault0,
+            vault1: &ctx.accounts.vault1,
+            pool_signer: &ctx.accounts.pool_signer,
+            token_program: &ctx.accounts.token_program,
+        };
+        let burn_event = remove_liquidity_core(
+            &mut ctx.accounts.pool,
+            &legs,
+            &ctx.accounts.lp_mint,
+            &ctx.accounts.user_lp,
+            ctx.accounts.position.as_mut(),
+            shares,
+            &clock,
+        )?;
+        #[cfg(feature = "invariants")]
+        let k_before = invariants::k(&ctx.accounts.pool);
+
+        // Sell the leg that is not the exit token; the bound covers the total
+        let (kept, leg) = if exit_to_0 {
+            (burn_event.amount0, burn_event.amount1)
+        } else {
+            (burn_event.amount1, burn_event.amount0)
+        };
+        let swap_event = if leg > 0 {
+            Some(swap_core(
+                &mut ctx.accounts.pool,
+                &legs,
+                ctx.remaining_accounts,
+                !exit_to_0,
+                leg,
+                0,
+                &clock,
+            )?)
+        } else {
+            None
+        };
+        if let Some(event) = swap_event.as_ref() {
+            track_launch_buy(
+                &ctx.accounts.pool,
+                &ctx.accounts.user,
+                ctx.remaining_accounts,
+                None,
+                event,
+            )?;
+        }
+        let total_out = kept
+            .checked_add(swap_event.as_ref().map_or(0, |e| e.amount_out))
+            .ok_or(AmmError::MathOverflow)?;
+        if total_out < min_total_out {
+            msg!("slippage: total_out {} < min_total_out {}", total_out, min_total_out);
+            return err!(AmmError::SlippageExceeded);
+        }
+
+        #[cfg(feature = "invariants")]
+        {
+            let pool = &ctx.accounts.pool;
+            if let Some(event) = swap_event.as_ref() {
+                invariants::check_k_fee_growth(k_before, pool, event);
+                invariants::check_fee(pool, event.fee_ppm);
+            } else {
+                invariants::check_k_non_decreasing(k_before, pool);
+            }
+            invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+            invariants::check_lp_supply(pool, &mut ctx.accounts.lp_mint);
+            invariants::check_ema(pool);
+        }
+
+        emit_event!(ctx, burn_event);
+        if let Some(event) = swap_event {
+            emit_event!(ctx, event);
+        }
+
+        Ok(total_out)
+    }
+
+    /// Open a per-user position used to report impermanent loss on exit.
+    /// Optional: add/remove work without it, and then report zero IL.
+    pub fn open_position(ctx: Context<OpenPosition>) -> Result<()> {
+        let position = &mut ctx.accounts.position;
+        position.pool = ctx.accounts.pool.key();
+        position.owner = ctx.accounts.user.key();
+        position.bump = ctx.bumps.position;
+        Ok(())
+    }
+
+    /// Close the signer's LaunchBuys record once the launch window is over,
+    /// refunding its rent.
+    pub fn close_launch_buys(ctx: Context<CloseLaunchBuys>) -> Result<()> {
+        let slot = Clock::get()?.slot;
+        require!(slot >= ctx.accounts.pool.launch_end_slot, AmmError::LaunchWindowOpen);
+        Ok(())
+    }
+
+    /// Swap with adaptive fee and a circuit breaker on excessive volatility.
+    /// Reverts if fewer than `min_amount_out` tokens would be received or
+    /// the transaction lands after `deadline` (unix seconds). Returns
+    /// `amount_out` via return data for CPI callers.
+    pub fn swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, Swap<'info>>,
+        token_in_is_0: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        deadline: i64,
+    ) -> Result<u64> {
+        let clock = Clock::get()?;
+        check_deadline(&clock, deadline)?;
+        check_router(&ctx.accounts.pool, ctx.accounts.instructions.as_ref())?;
+        let user = ctx.accounts.user.key();
+        let holders = [user, ctx.accounts.user_token_in.owner, ctx.accounts.recipient.key()];
+        check_denylist(&ctx.accounts.pool, &holders, ctx.remaining_accounts, false)?;
+        #[cfg(feature = "invariants")]
+        let k_before = invariants::k(&ctx.accounts.pool);
+
+        let (user_token0, user_token1) = if token_in_is_0 {
+            (&ctx.accounts.user_token_in, &ctx.accounts.user_token_out)
+        } else {
+            (&ctx.accounts.user_token_out, &ctx.accounts.user_token_in)
+        };
+        let legs = PoolLegs {
+            user: &ctx.accounts.user,
+            user_token0,
+            user_token1,
+            vault0: &ctx.accounts.vault0,
+            vault1: &ctx.accounts.vault1,
+            pool_signer: &ctx.accounts.pool_signer,
+            token_program: &ctx.accounts.token_program,
+        };
+        let event = swap_core(
+            &mut ctx.accounts.pool,
+            &legs,
+            ctx.remaining_accounts,
+            token_in_is_0,
+            amount_in,
+            min_amount_out,
+            &clock,
+        )?;
+        track_launch_buy(
+            &ctx.accounts.pool,
+            &ctx.accounts.user,
+            ctx.remaining_accounts,
+            Some(&ctx.accounts.system_program),
+            &event,
+        )?;
+
+        #[cfg(feature = "invariants")]
+        {
+            let pool = &ctx.accounts.pool;
+            invariants::check_k_fee_growth(k_before, pool, &event);
+            invariants::check_fee(pool, event.fee_ppm);
+            invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+            invariants::check_ema(pool);
+        }
+
+        let amount_out = event.amount_out;
+        emit_event!(ctx, event);
+
+        Ok(amount_out)
+    }
+
+    /// Swap `amount_in`, then deposit the output together with up to
+    /// `amount_other_desired` of the input token, trimmed to the post-swap
+    /// reserve ratio. Both legs run against the same pool state in one
+    /// instruction; reverts if fewer than `min_shares` are minted.
+    pub fn swap_and_add_liquidity<'info>(
+        ctx: Context<'_, '_, '_, 'info, SwapAndAddLiquidity<'info>>,
+        token_in_is_0: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        amount_other_desired: u64,
+        min_shares: u64,
+        deadline: i64,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        check_deadline(&clock, deadline)?;
+        check_router(&ctx.accounts.pool, ctx.accounts.instructions.as_ref())?;
+        let user = ctx.accounts.user.key();
+        check_denylist(&ctx.accounts.pool, &[user], ctx.remaining_accounts, false)?;
+        #[cfg(feature = "invariants")]
+        let k_before = invariants::k(&ctx.accounts.pool);
+
+        let legs = PoolLegs {
+            user: &ctx.accounts.user,
+            user_token0: &ctx.accounts.user_token0,
+            user_token1: &ctx.accounts.user_token1,
+            vault0: &ctx.accounts.vault0,
+            vault1: &ctx.accounts.vault1,
+            pool_signer: &ctx.accounts.pool_signer,
+            token_program: &ctx.accounts.token_program,
+        };
+        let swap_event = swap_core(
+            &mut ctx.accounts.pool,
+            &legs,
+            ctx.remaining_accounts,
+            token_in_is_0,
+            amount_in,
+            min_amount_out,
+            &clock,
+        )?;
+        track_launch_buy(
+            &ctx.accounts.pool,
+            &ctx.accounts.user,
+            ctx.remaining_accounts,
+            Some(&ctx.accounts.system_program),
+            &swap_event,
+        )?;
+        #[cfg(feature = "invariants")]
+        invariants::check_k_fee_growth(k_before, &ctx.accounts.pool, &swap_event);
+
+        let (desired0, desired1) = if token_in_is_0 {
+            (amount_other_desired, swap_event.amount_out)
+        } else {
+            (swap_event.amount_out, amount_other_desired)
+        };
+        let pool = &ctx.accounts.pool;
+        let (amount0, amount1) =
+            ratio_matched_amounts(desired0, desired1, pool.reserve0, pool.reserve1);
+        let mint_event = add_liquidity_core(
+            &mut ctx.accounts.pool,
+            &legs,
+            &ctx.accounts.lp_mint,
+            &ctx.accounts.user_lp,
+            ctx.accounts.position.as_mut(),
+            amount0,
+            amount1,
+            &clock,
+        )?;
+        if mint_event.shares < min_shares {
+            msg!("shares {} < min_shares {}", mint_event.shares, min_shares);
+            return err!(AmmError::SlippageExceeded);
+        }
+
+        #[cfg(feature = "invariants")]
+        {
+            let pool = &ctx.accounts.pool;
+            invariants::check_fee(pool, swap_event.fee_ppm);
+            invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+            invariants::check_lp_supply(pool, &mut ctx.accounts.lp_mint);
+            invariants::check_ema(pool);
+        }
+
+        emit_event!(ctx, swap_event);
+        emit_event!(ctx, mint_event);
+
+        Ok(())
+    }
+
+    /// Lend `amount` of one vault to the borrower within this transaction.
+    /// A later top-level `flash_repay` of the same token and amount must
+    /// follow, or the borrow fails; it returns the amount plus
+    /// `flash_fee_bps`, which goes to the reserves. Until then swaps and
+    /// liquidity changes keep pricing against the recorded reserves, which
+    /// still count the lent tokens, while `sync` and `skim` refuse to run.
+    pub fn flash_borrow(ctx: Context<FlashBorrow>, token_is_0: bool, amount: u64) -> Result<()> {
+        require!(amount > 0, AmmError::ZeroAmount);
+        let borrower = ctx.accounts.borrower.key();
+        let holders = [borrower, ctx.accounts.borrower_token.owner];
+        check_denylist(&ctx.accounts.pool, &holders, ctx.remaining_accounts, false)?;
+        let pool = &mut ctx.accounts.pool;
+        require!(!pool.paused, AmmError::PoolPaused);
+        require!(pool.flash_fee_bps > 0, AmmError::FlashLoansDisabled);
+        require!(pool.flash_amount == 0, AmmError::FlashLoanActive);
+        let reserve = if token_is_0 { pool.reserve0 } else { pool.reserve1 };
+        if amount > reserve {
+            msg!("flash amount {} > reserve {}", amount, reserve);
+            return err!(AmmError::FlashLoanTooLarge);
+        }
+        require_flash_repay(&ctx.accounts.instructions, token_is_0, amount)?;
+
+        let fee = mul_div(
+            amount as u128,
+            pool.flash_fee_bps as u128,
+            BPS_DENOM as u128,
+            Rounding::Up,
+        )
+        .ok_or(AmmError::MathOverflow)?;
+        let fee = u64::try_from(fee).map_err(|_| AmmError::MathOverflow)?;
+        pool.flash_token_is_0 = token_is_0;
+        pool.flash_amount = amount;
+        pool.flash_fee = fee;
+        let clock = Clock::get()?;
+        record_update(pool, &clock);
+
+        transfer_from_vault(
+            pool,
+            &ctx.accounts.vault,
+            &ctx.accounts.borrower_token,
+            &ctx.accounts.token_program,
+            amount,
+            &ctx.accounts.pool_signer,
+        )?;
+
+        let event = FlashBorrowed {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            borrower: ctx.accounts.borrower.key(),
+            token_is_0,
+            amount,
+            fee,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Close the outstanding flash loan: pull the borrowed amount plus the
+    /// fee from `payer_token`. The fee is split like a swap fee: the
+    /// protocol share is owed, the rest joins the reserves.
+    pub fn flash_repay(ctx: Context<FlashRepay>, token_is_0: bool, amount: u64) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let open = pool.flash_amount != 0;
+        if !open || pool.flash_token_is_0 != token_is_0 || pool.flash_amount != amount {
+            msg!("no flash loan of {} (token0: {}) outstanding", amount, token_is_0);
+            return err!(AmmError::FlashLoanMismatch);
+        }
+        let fee = pool.flash_fee;
+        let owed = amount.checked_add(fee).ok_or(AmmError::MathOverflow)?;
+        transfer_into_vault(
+            &ctx.accounts.payer,
+            &ctx.accounts.payer_token,
+            &ctx.accounts.vault,
+            &ctx.accounts.token_program,
+            owed,
+        )?;
+
+        let clock = Clock::get()?;
+        update_price_accumulators(pool, clock.unix_timestamp)?;
+        let protocol_fee = mul_div(
+            fee as u128,
+            pool.protocol_fee_share_bps as u128,
+            BPS_DENOM as u128,
+            Rounding::Down,
+        )
+        .ok_or(AmmError::MathOverflow)? as u64;
+        let lp_fee = fee - protocol_fee;
+        if token_is_0 {
+            pool.reserve0 = pool.reserve0.checked_add(lp_fee).ok_or(AmmError::MathOverflow)?;
+            pool.protocol_fees_owed0 = pool
+                .protocol_fees_owed0
+                .checked_add(protocol_fee)
+                .ok_or(AmmError::MathOverflow)?;
+            pool.cumulative_fees0 = pool
+                .cumulative_fees0
+                .checked_add(fee as u128)
+                .ok_or(AmmError::MathOverflow)?;
+            pool.flash_volume0 = pool
+                .flash_volume0
+                .checked_add(amount as u128)
+                .ok_or(AmmError::MathOverflow)?;
+            pool.flash_fees0 =
+                pool.flash_fees0.checked_add(fee as u128).ok_or(AmmError::MathOverflow)?;
+        } else {
+            pool.reserve1 = pool.reserve1.checked_add(lp_fee).ok_or(AmmError::MathOverflow)?;
+            pool.protocol_fees_owed1 = pool
+                .protocol_fees_owed1
+                .checked_add(protocol_fee)
+                .ok_or(AmmError::MathOverflow)?;
+            pool.cumulative_fees1 = pool
+                .cumulative_fees1
+                .checked_add(fee as u128)
+                .ok_or(AmmError::MathOverflow)?;
+            pool.flash_volume1 = pool
+                .flash_volume1
+                .checked_add(amount as u128)
+                .ok_or(AmmError::MathOverflow)?;
+            pool.flash_fees1 =
+                pool.flash_fees1.checked_add(fee as u128).ok_or(AmmError::MathOverflow)?;
+        }
+        pool.flash_loan_count = pool.flash_loan_count.saturating_add(1);
+        pool.flash_amount = 0;
+        pool.flash_fee = 0;
+
+        // the vault covers its reserve and owed protocol fees again
+        ctx.accounts.vault.reload()?;
+        let floor = if token_is_0 {
+            pool.reserve0 as u128 + pool.protocol_fees_owed0 as u128
+        } else {
+            pool.reserve1 as u128 + pool.protocol_fees_owed1 as u128
+        };
+        if (ctx.accounts.vault.amount as u128) < floor {
+            msg!("vault {} below reserve + owed {} after repay", ctx.accounts.vault.amount, floor);
+            return err!(AmmError::FlashRepayShort);
+        }
+
+        if pool.reserve0 > 0 && pool.reserve1 > 0 {
+            let price = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+            let alpha = pool.ema_alpha_1e12;
+            ema_update(&mut pool.ema_price_1e18, alpha, price);
+            pool.ema_updated_at = clock.unix_timestamp;
+        }
+        update_depth_ema(pool);
+        update_high_low(pool, clock.unix_timestamp);
+        track_k_growth(pool)?;
+        record_update(pool, &clock);
+
+        #[cfg(feature = "invariants")]
+        invariants::check_ema(pool);
+
+        let event = FlashRepaid {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            payer: ctx.accounts.payer.key(),
+            token_is_0,
+            amount,
+            fee,
+            protocol_fee,
+            reserve0_after: pool.reserve0,
+            reserve1_after: pool.reserve1,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Uniswap-v2 style flash swap: send `amount_out` first, then call
+    /// `callback_program` with `data` and the remaining accounts (including
+    /// the oracle feed when the pool has one, as for `swap`). The pool
+    /// signer is never a signer of the callback. On return the vault
+    /// balances, with the dynamic fee taken off whatever came back on either
+    /// side, must keep k at least where it was. The pool is locked until
+    /// then, so the callback cannot reenter it. During the launch window the
+    /// whole `amount_out` counts against the cap, and the LaunchBuys record
+    /// must already exist from an earlier swap.
+    pub fn flash_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, FlashSwap<'info>>,
+        token_out_is_0: bool,
+        amount_out: u64,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        require!(amount_out > 0, AmmError::ZeroAmount);
+        check_router(&ctx.accounts.pool, ctx.accounts.instructions.as_ref())?;
+        let user = ctx.accounts.user.key();
+        let holders = [user, ctx.accounts.user_token_out.owner];
+        check_denylist(&ctx.accounts.pool, &holders, ctx.remaining_accounts, false)?;
+        let pool = &mut ctx.accounts.pool;
+        require!(!pool.paused, AmmError::PoolPaused);
+        // a loan already leaves a vault short of its reserve
+        require!(pool.flash_amount == 0, AmmError::FlashLoanActive);
+        let clock = Clock::get()?;
+        update_price_accumulators(pool, clock.unix_timestamp)?;
+        #[cfg(feature = "invariants")]
+        let k_before = invariants::k(pool);
+
+        let token_in_is_0 = !token_out_is_0;
+        let r0 = pool.reserve0 as u128;
+        let r1 = pool.reserve1 as u128;
+        require!(r0 > 0 && r1 > 0, AmmError::NoLiquidity);
+        let (rin, rout) = if token_in_is_0 { (r0, r1) } else { (r1, r0) };
+        if amount_out as u128 >= rout {
+            msg!("amount_out {} >= reserve {}", amount_out, rout);
+            return err!(AmmError::InsufficientOutputReserve);
+        }
+
+        // Lock before anything leaves the vaults. A nested call reads the
+        // pool from account data, so the flag is written out right away
+        pool.locked = true;
+        pool.exit(&crate::ID)?;
+
+        let vault_out = if token_out_is_0 { &ctx.accounts.vault0 } else { &ctx.accounts.vault1 };
+        transfer_from_vault(
+            pool,
+            vault_out,
+            &ctx.accounts.user_token_out,
+            &ctx.accounts.token_program,
+            amount_out,
+            &ctx.accounts.pool_signer,
+        )?;
+
+        // Plain `invoke`: the callback only gets the signatures of the
+        // transaction itself, never the pool's
+        let callback = &ctx.accounts.callback_program;
+        let accounts = ctx
+            .remaining_accounts
+            .iter()
+            .map(|info| {
+                if info.is_writable {
+                    AccountMeta::new(info.key(), info.is_signer)
+                } else {
+                    AccountMeta::new_readonly(info.key(), info.is_signer)
+                }
+            })
+            .collect();
+        let mut infos = ctx.remaining_accounts.to_vec();
+        infos.push(callback.to_account_info());
+        invoke(&Instruction { program_id: callback.key(), accounts, data }, &infos)?;
+
+        // Whatever arrived on either side counts as paid; owed protocol
+        // fees sit in the vaults too and do not
+        ctx.accounts.vault0.reload()?;
+        ctx.accounts.vault1.reload()?;
+        let pool = &mut ctx.accounts.pool;
+        let held0 = ctx.accounts.vault0.amount.saturating_sub(pool.protocol_fees_owed0);
+        let held1 = ctx.accounts.vault1.amount.saturating_sub(pool.protocol_fees_owed1);
+        let (balance_in, balance_out) = if token_in_is_0 { (held0, held1) } else { (held1, held0) };
+        let paid_in = (balance_in as u128).saturating_sub(rin);
+        let paid_out = (balance_out as u128).saturating_sub(rout - amount_out as u128);
+
+        // Fee signals as for a swap of the input actually paid; a repayment
+        // made only on the output side prices as the smallest trade
+        let oracle_conf_1e12 =
+            oracle_confidence_1e12(pool, ctx.remaining_accounts, clock.unix_timestamp)?;
+        let (fee_ppm, vol_1e12, _slip_1e12, _shallow_1e12) = compute_dynamic_fee(
+            &pool.fee_params(),
+            token_in_is_0,
+            paid_in.max(1),
+            r0,
+            r1,
+            oracle_conf_1e12,
+        )?;
+        let paid_in_u64 = u64::try_from(paid_in).map_err(|_| AmmError::MathOverflow)?;
+        let fee_ppm = reversion_fee_ppm(pool, fee_ppm, token_in_is_0, paid_in_u64, clock.slot);
+        // breaker on the deviation before and after, as for a swap; output
+        // paid back nets against what left
+        let post_vol_1e12 = post_trade_deviation_1e12(
+            pool.ema_price_1e18,
+            token_in_is_0,
+            r0,
+            r1,
+            paid_in,
+            (amount_out as u128).saturating_sub(paid_out),
+        )?;
+        check_breaker(pool, vol_1e12, post_vol_1e12)?;
+        if fee_ppm as u64 >= PPM_DENOM {
+            msg!("fee {} ppm >= cap {}", fee_ppm, PPM_DENOM);
+            return err!(AmmError::FeeTooHigh);
+        }
+        let balances = (balance_in as u128, balance_out as u128);
+        if !k_with_fee_holds((rin, rout), balances, (paid_in, paid_out), fee_ppm)? {
+            msg!(
+                "flash swap of {} underpaid: in {} out {} at {} ppm",
+                amount_out,
+                paid_in,
+                paid_out,
+                fee_ppm
+            );
+            return err!(AmmError::FlashSwapUnderpaid);
+        }
+
+        // Reserves follow the balances less the protocol part of the fee,
+        // which is owed outside them as for a swap; stats as for a swap,
+        // plus the fee on any output paid back
+        let fee_in = mul_div(paid_in, fee_ppm as u128, PPM_DENOM as u128, Rounding::Down)
+            .ok_or(AmmError::MathOverflow)?;
+        let fee_out = mul_div(paid_out, fee_ppm as u128, PPM_DENOM as u128, Rounding::Down)
+            .ok_or(AmmError::MathOverflow)?;
+        let share = pool.protocol_fee_share_bps as u128;
+        let protocol = |fee: u128| {
+            mul_div(fee, share, BPS_DENOM as u128, Rounding::Down)
+                .and_then(|p| u64::try_from(p).ok())
+                .ok_or(AmmError::MathOverflow)
+        };
+        let (protocol_in, protocol_out) = (protocol(fee_in)?, protocol(fee_out)?);
+        let (new_in, new_out) = (balance_in - protocol_in, balance_out - protocol_out);
+        let (fee0, fee1, protocol0, protocol1) = if token_in_is_0 {
+            (fee_in, fee_out, protocol_in, protocol_out)
+        } else {
+            (fee_out, fee_in, protocol_out, protocol_in)
+        };
+        if token_in_is_0 {
+            pool.reserve0 = new_in;
+            pool.reserve1 = new_out;
+            pool.cumulative_volume0 =
+                pool.cumulative_volume0.checked_add(paid_in).ok_or(AmmError::MathOverflow)?;
+        } else {
+            pool.reserve0 = new_out;
+            pool.reserve1 = new_in;
+            pool.cumulative_volume1 =
+                pool.cumulative_volume1.checked_add(paid_in).ok_or(AmmError::MathOverflow)?;
+        }
+        pool.cumulative_fees0 =
+            pool.cumulative_fees0.checked_add(fee0).ok_or(AmmError::MathOverflow)?;
+        pool.cumulative_fees1 =
+            pool.cumulative_fees1.checked_add(fee1).ok_or(AmmError::MathOverflow)?;
+        pool.protocol_fees_owed0 =
+            pool.protocol_fees_owed0.checked_add(protocol0).ok_or(AmmError::MathOverflow)?;
+        pool.protocol_fees_owed1 =
+            pool.protocol_fees_owed1.checked_add(protocol1).ok_or(AmmError::MathOverflow)?;
+
+        let price = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+        let alpha = pool.ema_alpha_1e12;
+        ema_update(&mut pool.ema_price_1e18, alpha, price);
+        pool.ema_updated_at = clock.unix_timestamp;
+        update_depth_ema(pool);
+        update_high_low(pool, clock.unix_timestamp);
+        track_k_growth(pool)?;
+        pool.swap_count = pool.swap_count.saturating_add(1);
+        let net_out = amount_out.saturating_sub(u64::try_from(paid_out).unwrap_or(u64::MAX));
+        record_last_trade(pool, token_in_is_0, net_out, clock.slot);
+        pool.locked = false;
+        record_update(pool, &clock);
+
+        #[cfg(feature = "invariants")]
+        {
+            invariants::check_k_non_decreasing(k_before, pool);
+            invariants::check_fee(pool, fee_ppm);
+            invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+            invariants::check_ema(pool);
+        }
+
+        let event = SwapEvent {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            epoch: pool.epoch,
+            trader: ctx.accounts.user.key(),
+            token_in_is_0,
+            amount_in: paid_in_u64,
+            amount_out,
+            fee_bps: ppm_to_bps(fee_ppm),
+            fee_ppm,
+            fee_amount: u64::try_from(fee_in).map_err(|_| AmmError::MathOverflow)?,
+            fee_token_is_0: token_in_is_0,
+            reserve0_after: pool.reserve0,
+            reserve1_after: pool.reserve1,
+            total_lp_supply_after: pool.total_lp_supply,
+            price_0_in_1_1e12: price_to_1e12(price),
+            price_1_in_0_1e12: spot_price_1_in_0(pool.reserve0, pool.reserve1)?,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        track_launch_buy(
+            &ctx.accounts.pool,
+            &ctx.accounts.user,
+            ctx.remaining_accounts,
+            None,
+            &event,
+        )?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Gift tokens to the LPs: pulled from the donor straight into the
+    /// recorded reserves, minting no shares. Unlike a raw vault transfer
+    /// this is never skimmable.
+    pub fn donate(ctx: Context<Donate>, amount0: u64, amount1: u64) -> Result<()> {
+        require!(amount0 > 0 || amount1 > 0, AmmError::ZeroAmount);
+        let pool = &mut ctx.accounts.pool;
+        require!(!pool.paused, AmmError::PoolPaused);
+        // with no LPs the first depositor would simply capture the gift
+        require!(pool.total_lp_supply > 0, AmmError::NoLiquidity);
+        let clock = Clock::get()?;
+        update_price_accumulators(pool, clock.unix_timestamp)?;
+
+        if amount0 > 0 {
+            transfer_into_vault(
+                &ctx.accounts.donor,
+                &ctx.accounts.donor_token0,
+                &ctx.accounts.vault0,
+                &ctx.accounts.token_program,
+                amount0,
+            )?;
+        }
+        if amount1 > 0 {
+            transfer_into_vault(
+                &ctx.accounts.donor,
+                &ctx.accounts.donor_token1,
+                &ctx.accounts.vault1,
+                &ctx.accounts.token_program,
+                amount1,
+            )?;
+        }
+        pool.reserve0 = pool.reserve0.checked_add(amount0).ok_or(AmmError::MathOverflow)?;
+        pool.reserve1 = pool.reserve1.checked_add(amount1).ok_or(AmmError::MathOverflow)?;
+
+        let price = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+        let alpha = pool.ema_alpha_1e12;
+        ema_update(&mut pool.ema_price_1e18, alpha, price);
+        pool.ema_updated_at = clock.unix_timestamp;
+        update_depth_ema(pool);
+        update_high_low(pool, clock.unix_timestamp);
+        track_k_growth(pool)?;
+        record_update(pool, &clock);
+
+        #[cfg(feature = "invariants")]
+        {
+            invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+            invariants::check_ema(pool);
+        }
+
+        let event = Donation {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            donor: ctx.accounts.donor.key(),
+            amount0,
+            amount1,
+            reserve0_after: pool.reserve0,
+            reserve1_after: pool.reserve1,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: set up liquidity mining paid in `reward_mint`. Creates the
+    /// reward vault and the escrow holding staked LP shares, both owned by
+    /// the pool. One reward mint per pool, fixed once set.
+    pub fn init_rewards(ctx: Context<InitRewards>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+        require!(pool.reward_mint == Pubkey::default(), AmmError::RewardsAlreadyInitialized);
+        let reward_mint = ctx.accounts.reward_mint.key();
+        // the pool's ATA for any of these already exists
+        if reward_mint == pool.token0_mint
+            || reward_mint == pool.token1_mint
+            || reward_mint == pool.lp_mint
+        {
+            msg!("reward mint {} is one of the pool's own mints", reward_mint);
+            return err!(AmmError::BadRewardMint);
+        }
+
+        pool.reward_mint = reward_mint;
+        pool.reward_vault = ctx.accounts.reward_vault.key();
+        pool.lp_escrow = ctx.accounts.lp_escrow.key();
+        let clock = Clock::get()?;
+        pool.reward_updated_at = clock.unix_timestamp;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::InitRewards { reward_mint },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: deposit `amount` reward tokens streamed to stakers at
+    /// amount/(end_ts - start_ts) per second over [start_ts, end_ts].
+    /// Campaigns never overlap: the next one can be funded once the
+    /// current one has ended, and starts no earlier than that.
+    pub fn fund_campaign(
+        ctx: Context<FundCampaign>,
+        amount: u64,
+        start_ts: i64,
+        end_ts: i64,
+    ) -> Result<()> {
+        require!(amount > 0, AmmError::ZeroAmount);
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+        require!(pool.reward_mint != Pubkey::default(), AmmError::RewardsNotInitialized);
+        let clock = Clock::get()?;
+        require!(start_ts >= clock.unix_timestamp && end_ts > start_ts, AmmError::BadBounds);
+        if clock.unix_timestamp < pool.reward_end_ts {
+            msg!("campaign running until {}", pool.reward_end_ts);
+            return err!(AmmError::CampaignActive);
+        }
+
+        // close out the previous campaign before the rate changes
+        accrue_rewards(pool, clock.unix_timestamp)?;
+        transfer_into_vault(
+            &ctx.accounts.authority,
+            &ctx.accounts.authority_token,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        pool.reward_rate_1e18 = mul_div(
+            amount as u128,
+            PRICE_SCALE,
+            (end_ts - start_ts) as u128,
+            Rounding::Down,
+        )
+        .ok_or(AmmError::MathOverflow)?;
+        pool.reward_start_ts = start_ts;
+        pool.reward_end_ts = end_ts;
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::FundCampaign { amount, start_ts, end_ts },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: stop the current campaign now and take back what has not
+    /// been streamed yet. Rewards already streamed stay claimable.
+    pub fn end_campaign_early(ctx: Context<FundCampaign>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let role = authorize(pool, ctx.accounts.authority.key(), Role::Authority)?;
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        require!(now < pool.reward_end_ts, AmmError::NoActiveCampaign);
+
+        accrue_rewards(pool, now)?;
+        let remaining = pool.reward_end_ts - now.max(pool.reward_start_ts);
+        let unstreamed =
+            mul_div(pool.reward_rate_1e18, remaining as u128, PRICE_SCALE, Rounding::Down)
+                .and_then(|u| u64::try_from(u).ok())
+                .ok_or(AmmError::MathOverflow)?;
+        pool.reward_start_ts = now;
+        pool.reward_end_ts = now;
+        if unstreamed > 0 {
+            transfer_from_vault(
+                pool,
+                &ctx.accounts.reward_vault,
+                &ctx.accounts.authority_token,
+                &ctx.accounts.token_program,
+                unstreamed,
+                &ctx.accounts.pool_signer,
+            )?;
+        }
+        record_update(pool, &clock);
+
+        let event = admin_event(
+            pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::EndCampaignEarly { returned: unstreamed },
+            &clock,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Move `amount` LP shares from the user into the pool's escrow, where
+    /// they earn the running campaign's rewards through the user's
+    /// position.
+    pub fn stake_lp(ctx: Context<StakeLp>, amount: u64) -> Result<()> {
+        require!(amount > 0, AmmError::ZeroAmount);
+        let pool = &mut ctx.accounts.pool;
+        require!(!pool.paused, AmmError::PoolPaused);
+        let clock = Clock::get()?;
+        accrue_rewards(pool, clock.unix_timestamp)?;
+        settle_rewards(pool, &mut ctx.accounts.position)?;
+
+        transfer_into_vault(
+            &ctx.accounts.user,
+            &ctx.accounts.user_lp,
+            &ctx.accounts.lp_escrow,
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        let position = &mut ctx.accounts.position;
+        position.staked = position.staked.checked_add(amount).ok_or(AmmError::MathOverflow)?;
+        pool.reward_staked = pool.reward_staked.checked_add(amount).ok_or(AmmError::MathOverflow)?;
+        record_update(pool, &clock);
+        Ok(())
+    }
+
+    /// Return `amount` staked LP shares to the user. Earned rewards stay
+    /// on the position for `claim_rewards`.
+    pub fn unstake_lp(ctx: Context<StakeLp>, amount: u64) -> Result<()> {
+        require!(amount > 0, AmmError::ZeroAmount);
+        let pool = &mut ctx.accounts.pool;
+        if ctx.accounts.position.staked < amount {
+            msg!("unstake {} > staked {}", amount, ctx.accounts.position.staked);
+            return err!(AmmError::InsufficientLP);
+        }
+        let clock = Clock::get()?;
+        accrue_rewards(pool, clock.unix_timestamp)?;
+        settle_rewards(pool, &mut ctx.accounts.position)?;
+
+        ctx.accounts.position.staked -= amount;
+        pool.reward_staked -= amount;
+        transfer_from_vault(
+            pool,
+            &ctx.accounts.lp_escrow,
+            &ctx.accounts.user_lp,
+            &ctx.accounts.token_program,
+            amount,
+            &ctx.accounts.pool_signer,
+        )?;
+        record_update(pool, &clock);
+        Ok(())
+    }
+
+    /// Pay out the rewards the user's position has earned so far.
+    pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        let clock = Clock::get()?;
+        accrue_rewards(pool, clock.unix_timestamp)?;
+        settle_rewards(pool, &mut ctx.accounts.position)?;
+
+        let amount = ctx.accounts.position.rewards_owed;
+        require!(amount > 0, AmmError::ZeroAmount);
+        ctx.accounts.position.rewards_owed = 0;
+        transfer_from_vault(
+            pool,
+            &ctx.accounts.reward_vault,
+            &ctx.accounts.user_reward,
+            &ctx.accounts.token_program,
+            amount,
+            &ctx.accounts.pool_signer,
+        )?;
+        record_update(pool, &clock);
+
+        let event = RewardsClaimed {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            owner: ctx.accounts.user.key(),
+            amount,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Permissionless: copy the mint decimals onto the pool. Backfills
+    /// pools created before the fields existed, once `migrate_pool` has
+    /// grown them; mint decimals never change, so repeating it is harmless.
+    pub fn sync_decimals(ctx: Context<SyncDecimals>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        pool.decimals0 = ctx.accounts.token0_mint.decimals;
+        pool.decimals1 = ctx.accounts.token1_mint.decimals;
+        record_update(pool, &Clock::get()?);
+        Ok(())
+    }
+
+    /// Permissionless: grow a pool account written by an older version of
+    /// the program to the current layout, the payer funding the extra rent.
+    /// Until then the account does not deserialize and every instruction on
+    /// it fails. The appended fields read as zero, which is what the
+    /// fallbacks for older pools expect: the fee bounds come from the bps
+    /// fields times 100 and the decimals wait for `sync_decimals`. A pool
+    /// already at the current size is left untouched.
+    pub fn migrate_pool(ctx: Context<MigratePool>) -> Result<()> {
+        let info = ctx.accounts.pool.to_account_info();
+        if info.data_len() >= Pool::SPACE {
+            return Ok(());
+        }
+        if !info.try_borrow_data()?.starts_with(&Pool::DISCRIMINATOR) {
+            return err!(anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch);
+        }
+        let top_up = Rent::get()?
+            .minimum_balance(Pool::SPACE)
+            .saturating_sub(info.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: info.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        info.realloc(Pool::SPACE, true)?;
+        Ok(())
+    }
+
+    /// Permissionless: send vault balances above the recorded reserves
+    /// (direct donations) to `recipient`, or to the treasury when the pool
+    /// is configured so. Reserves are left untouched.
+    pub fn skim(ctx: Context<Skim>, recipient: Pubkey) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        require!(!pool.sync_donations, AmmError::DonationModeMismatch);
+        // a vault with tokens out on loan would read as short
+        require!(pool.flash_amount == 0, AmmError::FlashLoanActive);
+        let to = if pool.skim_to_treasury { pool.treasury } else { recipient };
+        require_keys_eq!(ctx.accounts.recipient_token0.owner, to, AmmError::InvalidRecipient);
+        require_keys_eq!(ctx.accounts.recipient_token1.owner, to, AmmError::InvalidRecipient);
+
+        // owed protocol fees sit in the vaults too and are not donations
+        let floor0 = pool.reserve0.saturating_add(pool.protocol_fees_owed0);
+        let floor1 = pool.reserve1.saturating_add(pool.protocol_fees_owed1);
+        let excess0 = ctx.accounts.vault0.amount.saturating_sub(floor0);
+        let excess1 = ctx.accounts.vault1.amount.saturating_sub(floor1);
+        if excess0 > 0 {
+            transfer_from_vault(
+                pool,
+                &ctx.accounts.vault0,
+                &ctx.accounts.recipient_token0,
+                &ctx.accounts.token_program,
+                excess0,
+                &ctx.accounts.pool_signer,
+            )?;
+        }
+        if excess1 > 0 {
+            transfer_from_vault(
+                pool,
+                &ctx.accounts.vault1,
+                &ctx.accounts.recipient_token1,
+                &ctx.accounts.token_program,
+                excess1,
+                &ctx.accounts.pool_signer,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Permissionless: adopt the vault balances as reserves so donations
+    /// accrue to LPs. Only on pools created with `sync_donations`.
+    pub fn sync(ctx: Context<SyncReserves>) -> Result<()> {
+        let pool = &mut ctx.accounts.pool;
+        require!(pool.sync_donations, AmmError::DonationModeMismatch);
+        require!(!pool.paused, AmmError::PoolPaused);
+        // as with `donate`: with no LPs the balances would only skew the
+        // bootstrap, so they wait in the vault
+        require!(pool.total_lp_supply > 0, AmmError::NoLiquidity);
+        require!(pool.flash_amount == 0, AmmError::FlashLoanActive);
+
+        // owed protocol fees never become LP reserves
+        let bal0 = ctx.accounts.vault0.amount.saturating_sub(pool.protocol_fees_owed0);
+        let bal1 = ctx.accounts.vault1.amount.saturating_sub(pool.protocol_fees_owed1);
+        if bal0 == pool.reserve0 && bal1 == pool.reserve1 {
+            return Ok(());
+        }
+
+        let clock = Clock::get()?;
+        update_price_accumulators(pool, clock.unix_timestamp)?;
+        pool.reserve0 = bal0;
+        pool.reserve1 = bal1;
+        if bal0 > 0 && bal1 > 0 {
+            let price = spot_price_1e18(bal0, bal1)?;
+            let alpha = pool.ema_alpha_1e12;
+            ema_update(&mut pool.ema_price_1e18, alpha, price);
+            pool.ema_updated_at = clock.unix_timestamp;
+        }
+        update_depth_ema(pool);
+        update_high_low(pool, clock.unix_timestamp);
+        track_k_growth(pool)?;
+        record_update(pool, &clock);
+
+        #[cfg(feature = "invariants")]
+        {
+            invariants::check_reserves(pool, &mut ctx.accounts.vault0, &mut ctx.accounts.vault1);
+            invariants::check_ema(pool);
+        }
+
+        let event = SyncEvent {
+            version: EVENT_VERSION,
+            pool: pool.key(),
+            seq: next_event_seq(pool),
+            reserve0: pool.reserve0,
+            reserve1: pool.reserve1,
+            slot: clock.slot,
+            timestamp: clock.unix_timestamp,
+        };
+        emit_event!(ctx, event);
+
+        Ok(())
+    }
+
+    /// Record the pool's current TWAP accumulators into a new checkpoint
+    /// account, to be measured against later by `get_twap`.
+    pub fn checkpoint_twap(ctx: Context<CheckpointTwap>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        let (price_cumulative, log2_price_cumulative) = current_cumulatives(pool, now)?;
+
+        let checkpoint = &mut ctx.accounts.checkpoint;
+        checkpoint.pool = pool.key();
+        checkpoint.timestamp = now;
+        checkpoint.price_cumulative_1e18 = price_cumulative;
+        checkpoint.log2_price_cumulative = log2_price_cumulative;
+        Ok(())
+    }
+
+    /// Admin: create the analytics ring buffer written by `snapshot`.
+    pub fn init_snapshots(ctx: Context<InitSnapshots>, interval_seconds: i64) -> Result<()> {
+        let role = authorize(&ctx.accounts.pool, ctx.accounts.authority.key(), Role::Authority)?;
+        require!(interval_seconds > 0, AmmError::BadBounds);
+
+        {
+            let mut snapshots = ctx.accounts.snapshots.load_init()?;
+            snapshots.pool = ctx.accounts.pool.key();
+            snapshots.interval_seconds = interval_seconds;
+            snapshots.cardinality = SNAPSHOT_CAPACITY as u32;
+            snapshots.cardinality_next = SNAPSHOT_CAPACITY as u32;
+        }
+
+        let event = admin_event(
+            &mut ctx.accounts.pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::InitSnapshots { interval_seconds },
+            &Clock::get()?,
+        );
+        append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Admin: create the pool's `AdminLog`. From then on every admin
+    /// instruction must be passed the log and appends to it; its own
+    /// creation is the first entry.
+    pub fn init_admin_log(ctx: Context<InitAdminLog>) -> Result<()> {
+        let role = authorize(&ctx.accounts.pool, ctx.accounts.authority.key(), Role::Authority)?;
+        ctx.accounts.pool.admin_log_enabled = true;
+
+        let event = admin_event(
+            &mut ctx.accounts.pool,
+            ctx.accounts.authority.key(),
+            role,
+            AdminAction::InitAdminLog,
+            &Clock::get()?,
+        );
+        {
+            let mut log = ctx.accounts.admin_log.load_init()?;
+            log.pool = ctx.accounts.pool.key();
+            log.push(AdminLogEntry::new(&event)?);
+        }
+        emit_event!(ctx, event);
+        Ok(())
+    }
+
+    /// Permissionless: append an analytics checkpoint, at most once per
+    /// interval. Calls inside the interval are no-ops so crankers can be dumb.
+    pub fn snapshot(ctx: Context<TakeSnapshot>) -> Result<()> {
+        let pool = &ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+
+        let spot_price_1e12 = if pool.reserve0 > 0 && pool.reserve1 > 0 {
+            spot_price_1e12(pool.reserve0, pool.reserve1)?
+        } else {
+            0
+        };
+        let info = ctx.accounts.snapshots.to_account_info();
+        let mut data = info.try_borrow_mut_data()?;
+        let (snapshots, slots) = Snapshots::split_mut(&mut data);
+        if snapshots.len > 0 && now < snapshots.last_timestamp.saturating_add(snapshots.interval_seconds) {
+            return Ok(());
+        }
+        snapshots.push(slots, SnapshotEntry {
+            cumulative_volume0: pool.cumulative_volume0,
+            cumulative_volume1: pool.cumulative_volume1,
+            cumulative_fees0: pool.cumulative_fees0,
+            cumulative_fees1: pool.cumulative_fees1,
+            timestamp: now,
+            spot_price_1e12,
+            ema_price_1e12: price_to_1e12(pool.ema_price_1e18),
+            reserve0: pool.reserve0,
+            reserve1: pool.reserve1,
+            _padding: 0,
+        });
+        Ok(())
+    }
+
+    /// Permissionless: grow the snapshots ring towards `cardinality_next`,
+    /// the payer funding the extra rent. Each call adds at most
+    /// MAX_SNAPSHOT_GROWTH slots (realloc limit), so large targets take
+    /// several calls. New slots join the ring the next time the head wraps.
+    pub fn increase_snapshot_cardinality(
+        ctx: Context<IncreaseSnapshotCardinality>,
+        cardinality_next: u32,
+    ) -> Result<()> {
+        let current = ctx.accounts.snapshots.load()?.cardinality_next;
+        require!(cardinality_next > current, AmmError::BadBounds);
+        let target = cardinality_next.min(current.saturating_add(MAX_SNAPSHOT_GROWTH));
+
+        let info = ctx.accounts.snapshots.to_account_info();
+        let new_len = Snapshots::space(target);
+        let top_up = Rent::get()?
+            .minimum_balance(new_len)
+            .saturating_sub(info.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: info.clone(),
+                    },
+                ),
+                top_up,
+            )?;
+        }
+        // zero-filled slots have timestamp 0, which readers treat as unwritten
+        info.realloc(new_len, true)?;
+
+        ctx.accounts.snapshots.load_mut()?.cardinality_next = target;
+        Ok(())
+    }
+
+    /// View: typed pool snapshot via return data. Reserves are the recorded
+    /// pricing reserves; unsynced donations are not included.
+    pub fn get_pool_state(ctx: Context<GetPoolState>) -> Result<PoolState> {
+        let pool = &ctx.accounts.pool;
+        let reserve0 = pool.reserve0;
+        let reserve1 = pool.reserve1;
+
+        let (spot_price_1e12, spot_price_1_in_0_1e12, current_min_fee_ppm, breaker_active) =
+            if reserve0 > 0 && reserve1 > 0 {
+                // fee and breaker verdict for a minimal (1 unit) trade
+                let (fee_ppm, vol_1e12, _, _) = compute_dynamic_fee(
+                    &pool.fee_params(),
+                    true,
+                    1,
+                    reserve0 as u128,
+                    reserve1 as u128,
+                    0,
+                )?;
+                let threshold = pool.breaker_threshold_1e12();
+                (
+                    spot_price_0_in_1(reserve0, reserve1)?,
+                    spot_price_1_in_0(reserve0, reserve1)?,
+                    fee_ppm,
+                    !breaker_verdict(pool.manually_tripped, threshold, vol_1e12, vol_1e12)
+                        .is_open(),
+                )
+            } else {
+                (0, 0, pool.fee_bounds_ppm().0, pool.manually_tripped)
+            };
+
+        Ok(PoolState {
+            reserve0,
+            reserve1,
+            total_lp_supply: ctx.accounts.lp_mint.supply,
+            spot_price_1e12,
+            spot_price_1_in_0_1e12,
+            ema_price_1e12: price_to_1e12(pool.ema_price_1e18),
+            current_min_fee_bps: ppm_to_bps(current_min_fee_ppm),
+            current_min_fee_ppm,
+            breaker_active,
+            paused: pool.paused,
+            cumulative_volume0: pool.cumulative_volume0,
+            cumulative_volume1: pool.cumulative_volume1,
+            swap_count: pool.swap_count,
+            allow_risky_mints: pool.allow_risky_mints,
+            min_trade_amount0: pool.min_trade_amount0,
+            min_trade_amount1: pool.min_trade_amount1,
+            max_trade_bps_of_reserve: pool.max_trade_bps_of_reserve,
+            name: pool.name(),
+            uri: pool.uri(),
+            fee_on_output: pool.fee_on_output,
+            breaker_enabled: pool.breaker_enabled,
+            price_high_1e12: pool.price_high_1e12,
+            price_low_1e12: pool.price_low_1e12,
+            hl_window_start_ts: pool.hl_window_start_ts,
+            growth_1e12: pool.growth_1e12,
+            only_via_router: pool.only_via_router,
+            routers: pool.routers,
+            reversion_window_slots: pool.reversion_window_slots,
+            reversion_band_bps: pool.reversion_band_bps,
+            reversion_penalty_bps: pool.reversion_penalty_bps,
+            enforce_denylist: pool.enforce_denylist,
+            denylist_allows_removal: pool.denylist_allows_removal,
+            launch_token_is_0: pool.launch_token_is_0,
+            launch_end_slot: pool.launch_end_slot,
+            max_buy_per_wallet: pool.max_buy_per_wallet,
+        })
+    }
+
+    /// Never succeeds: prices a swap the way `swap` would, logs the quote
+    /// as one `SIMULATE_SWAP_LOG_PREFIX` line and fails with SimulationOnly,
+    /// so it is harmless even if signed. For clients that only see the logs
+    /// of a simulation (see `client::parse_simulate_swap_logs`). Pass the
+    /// pool's oracle among the remaining accounts, as for `swap`.
+    pub fn simulate_swap<'info>(
+        ctx: Context<'_, '_, '_, 'info, SimulateSwap<'info>>,
+        token_in_is_0: bool,
+        amount_in: u64,
+    ) -> Result<()> {
+        require!(amount_in > 0, AmmError::ZeroAmount);
+        let mut pool: Pool = (*ctx.accounts.pool).clone();
+        let r0 = pool.reserve0 as u128;
+        let r1 = pool.reserve1 as u128;
+        require!(r0 > 0 && r1 > 0, AmmError::NoLiquidity);
+
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        if ema_is_stale(&pool, now) {
+            pool.ema_price_1e18 = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+        }
+        let oracle_conf_1e12 = oracle_confidence_1e12(&pool, ctx.remaining_accounts, now)?;
+        let (fee_ppm, vol_1e12, slip_1e12, shallow_1e12) = compute_dynamic_fee(
+            &pool.fee_params(),
+            token_in_is_0,
+            amount_in as u128,
+            r0,
+            r1,
+            oracle_conf_1e12,
+        )?;
+        let fee_ppm = reversion_fee_ppm(&pool, fee_ppm, token_in_is_0, amount_in, clock.slot);
+
+        // 0 when the fee hits the cap and `swap` would reject the trade
+        let (rin, rout) = if token_in_is_0 { (r0, r1) } else { (r1, r0) };
+        let (amount_out, fee_amount) = if (fee_ppm as u64) < PPM_DENOM {
+            swap_amounts(amount_in, rin, rout, fee_ppm, pool.fee_on_output)?
+        } else {
+            (0, 0)
+        };
+        // the post-trade deviation, as `swap` checks it (reserves net of the
+        // protocol's share of the fee); an emptied out side counts as a trip
+        let (_, kept_in, taken_out) = split_swap_fee(&pool, amount_in, amount_out, fee_amount)?;
+        let post_vol_1e12 = post_trade_deviation_1e12(
+            pool.ema_price_1e18,
+            token_in_is_0,
+            r0,
+            r1,
+            kept_in as u128,
+            taken_out as u128,
+        )
+        .unwrap_or(u128::MAX);
+        let breaker_would_trip = !breaker_verdict(
+            pool.manually_tripped,
+            pool.breaker_threshold_1e12(),
+            vol_1e12,
+            post_vol_1e12,
+        )
+        .is_open();
+
+        msg!(
+            "{} amount_out={} fee_bps={} fee_ppm={} vol_1e12={} slip_1e12={} \
+             shallow_1e12={} breaker_would_trip={}",
+            SIMULATE_SWAP_LOG_PREFIX,
+            amount_out,
+            ppm_to_bps(fee_ppm),
+            fee_ppm,
+            vol_1e12,
+            slip_1e12,
+            shallow_1e12,
+            breaker_would_trip
+        );
+        err!(AmmError::SimulationOnly)
+    }
+
+    /// View: the pool's current fee, i.e. the dynamic fee as amount_in -> 0
+    /// (no slip term), with its components, via return data. Priced like a
+    /// swap right now: a stale EMA counts as restarted at spot, and the
+    /// pool's oracle goes among the remaining accounts, as for `swap`.
+    pub fn quote_marginal_fee<'info>(
+        ctx: Context<'_, '_, '_, 'info, QuoteFee<'info>>,
+    ) -> Result<MarginalFee> {
+        let mut pool: Pool = (*ctx.accounts.pool).clone();
+        require!(pool.reserve0 > 0 && pool.reserve1 > 0, AmmError::NoLiquidity);
+
+        let now = Clock::get()?.unix_timestamp;
+        if ema_is_stale(&pool, now) {
+            pool.ema_price_1e18 = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+        }
+        let oracle_conf_1e12 = oracle_confidence_1e12(&pool, ctx.remaining_accounts, now)?;
+        let (fee_ppm, vol_1e12, shallow_1e12) = compute_marginal_fee(
+            &pool.fee_params(),
+            pool.reserve0 as u128,
+            pool.reserve1 as u128,
+            oracle_conf_1e12,
+        )?;
+        let threshold = pool.breaker_threshold_1e12();
+        Ok(MarginalFee {
+            fee_bps: ppm_to_bps(fee_ppm),
+            fee_ppm,
+            vol_1e12,
+            shallow_1e12,
+            oracle_conf_1e12,
+            breaker_would_trip: !breaker_verdict(
+                pool.manually_tripped,
+                threshold,
+                vol_1e12,
+                vol_1e12,
+            )
+            .is_open(),
+        })
+    }
+
+    /// View: can a router use this pool right now? Pure read of current
+    /// state via return data. Pass the pool's oracle among the remaining
+    /// accounts to have its freshness checked.
+    pub fn health_check<'info>(
+        ctx: Context<'_, '_, '_, 'info, HealthCheck<'info>>,
+    ) -> Result<PoolHealth> {
+        let pool = &ctx.accounts.pool;
+        let now = Clock::get()?.unix_timestamp;
+        let reserves_below_min = pool.reserve0 == 0 || pool.reserve1 == 0;
+
+        // the volatility term is the pre-trade price vs EMA deviation, so
+        // it does not depend on the size passed here
+        let breaker_would_trip_at_zero_size = pool.manually_tripped
+            || (!reserves_below_min && {
+                let (_, vol_1e12, _, _) = compute_dynamic_fee(
+                    &pool.fee_params(),
+                    true,
+                    1,
+                    pool.reserve0 as u128,
+                    pool.reserve1 as u128,
+                    0,
+                )?;
+                let threshold = pool.breaker_threshold_1e12();
+                !breaker_verdict(false, threshold, vol_1e12, vol_1e12).is_open()
+            });
+
+        let ema_stale = pool.ema_max_age_seconds > 0
+            && now.saturating_sub(pool.ema_updated_at) > pool.ema_max_age_seconds as i64;
+
+        // a configured feed that was not supplied counts as stale
+        let oracle_stale = match (pool.oracle_source, oracle_account(pool, ctx.remaining_accounts)) {
+            (OracleSource::None, _) => false,
+            (_, None) => true,
+            (source, Some(info)) => {
+                let oracle = read_oracle_price(info, source)?;
+                now.saturating_sub(oracle.publish_time) > pool.oracle_max_age_seconds as i64
+            }
+        };
+
+        Ok(PoolHealth {
+            paused: pool.paused,
+            breaker_enabled: pool.breaker_enabled,
+            breaker_would_trip_at_zero_size,
+            ema_stale,
+            reserves_below_min,
+            oracle_stale,
+            last_update_slot: pool.last_update_slot,
+            max_trade_bps_of_reserve: pool.max_trade_bps_of_reserve,
+        })
+    }
+
+    /// View: TWAP between a previously recorded checkpoint and now.
+    /// Returned via return data; fails if the checkpoint is younger than
+    /// the requested window. `window_seconds = 0` uses the pool default.
+    /// A stale result is flagged, or rejected with `fail_if_stale`.
+    pub fn get_twap(
+        ctx: Context<GetTwap>,
+        window_seconds: u32,
+        fail_if_stale: bool,
+    ) -> Result<TwapResult> {
+        let pool = &ctx.accounts.pool;
+        let checkpoint = &ctx.accounts.checkpoint;
+        let now = Clock::get()?.unix_timestamp;
+        let window_seconds = if window_seconds == 0 {
+            pool.default_twap_window_seconds
+        } else {
+            window_seconds
+        };
+
+        let elapsed = now.saturating_sub(checkpoint.timestamp);
+        require!(
+            elapsed > 0 && elapsed >= window_seconds as i64,
+            AmmError::TwapWindowUnavailable
+        );
+
+        let (price_cumulative, log2_price_cumulative) = current_cumulatives(pool, now)?;
+        let twap_1e12 = compute_twap(
+            checkpoint.price_cumulative_1e18,
+            price_cumulative,
+            elapsed as u64,
+        )?;
+        let geometric_twap_1e12 = if pool.geometric_twap_enabled {
+            compute_geometric_twap(
+                checkpoint.log2_price_cumulative,
+                log2_price_cumulative,
+                elapsed as u64,
+            )?
+        } else {
+            0
+        };
+
+        // no reserve change for that long: the TWAP is just the last spot
+        let max_staleness = if pool.max_observation_staleness_seconds > 0 {
+            pool.max_observation_staleness_seconds
+        } else {
+            window_seconds
+        };
+        let stale = now.saturating_sub(pool.cumulative_last_timestamp) >= max_staleness as i64;
+        if stale && fail_if_stale {
+            msg!("last update {} older than {}s", pool.cumulative_last_timestamp, max_staleness);
+            return err!(AmmError::StaleTwap);
+        }
+
+        Ok(TwapResult {
+            twap_1e12,
+            geometric_twap_1e12,
+            window_actual_seconds: elapsed as u64,
+            stale,
+        })
+    }
+
+    /// View: what `add_liquidity` would pull and mint for the desired
+    /// amounts. Amounts are trimmed to the largest pair that matches the
+    /// pool ratio exactly, so they can be passed straight to `add_liquidity`.
+    pub fn quote_add_liquidity(
+        ctx: Context<QuoteLiquidity>,
+        amount0_desired: u64,
+        amount1_desired: u64,
+    ) -> Result<AddLiquidityQuote> {
+        let pool = &ctx.accounts.pool;
+        require!(amount0_desired > 0 && amount1_desired > 0, AmmError::ZeroAmount);
+
+        let (amount0, amount1) =
+            ratio_matched_amounts(amount0_desired, amount1_desired, pool.reserve0, pool.reserve1);
+        require!(amount0 > 0 && amount1 > 0, AmmError::BadRatio);
+
+        let shares = shares_for_deposit(
+            amount0,
+            amount1,
+            pool.reserve0,
+            pool.reserve1,
+            pool.total_lp_supply,
+        )?;
+        require!(shares > 0, AmmError::ZeroShares);
+
+        Ok(AddLiquidityQuote { amount0, amount1, shares })
+    }
+
+    /// View: token amounts `remove_liquidity` would pay out for `shares`.
+    pub fn quote_remove_liquidity(
+        ctx: Context<QuoteLiquidity>,
+        shares: u64,
+    ) -> Result<RemoveLiquidityQuote> {
+        let pool = &ctx.accounts.pool;
+        require!(shares > 0, AmmError::ZeroShares);
+        require!(pool.total_lp_supply >= shares, AmmError::InsufficientLP);
+
+        let (amount0, amount1) =
+            amounts_for_shares(shares, pool.reserve0, pool.reserve1, pool.total_lp_supply)?;
+        Ok(RemoveLiquidityQuote { amount0, amount1 })
+    }
+}
+
+/* ------------------------------- State ---------------------------------- */
+
+#[account]
+pub struct Pool {
+    pub bump: u8,
+    pub authority: Pubkey,
+
+    pub token0_mint: Pubkey,
+    pub token1_mint: Pubkey,
+    pub vault0: Pubkey,
+    pub vault1: Pubkey,
+
+    pub lp_mint: Pubkey,
+    pub total_lp_supply: u64,
+
+    // canonical pricing reserves; vault balances above them are donations
+    // that only `skim`/`sync` reconcile
+    pub reserve0: u64,
+    pub reserve1: u64,
+
+    // fee params (per-1e12 coefficients in bps). The fee bounds moved to
+    // min/max_fee_ppm; these bps fields mirror them rounded up and are
+    // only read for pools created before the move (max_fee_ppm == 0)
+    pub min_fee_bps: u16,
+    pub max_fee_bps: u16,
+    pub beta_vol_bps_per1e12: u16,
+    pub gamma_slip_bps_per1e12: u16,
+    pub delta_shallow_bps_per1e12: u16,
+
+    // EMA config and circuit breaker (EMA tracks token0 priced in token1)
+    pub ema_price_1e18: u128,
+    pub ema_alpha_1e12: u64,
+    pub breaker_vol_threshold_1e12: u64,
+
+    // TWAP accumulators: sum of price (1e18) * seconds (arithmetic) and
+    // log2(price 1e18) * seconds in Q32.32 (geometric, only when enabled)
+    pub price_cumulative_1e18: u128,
+    pub log2_price_cumulative: u128,
+    pub cumulative_last_timestamp: i64,
+    pub geometric_twap_enabled: bool,
+
+    // last instruction that mutated pool state
+    pub last_update_slot: u64,
+    pub last_update_timestamp: i64,
+
+    // lifetime stats
+    pub cumulative_volume0: u128,
+    pub cumulative_volume1: u128,
+    pub swap_count: u64,
+    pub cumulative_fees0: u128,
+    pub cumulative_fees1: u128,
+
+    // sequence number of the last emitted event
+    pub event_seq: u64,
+
+    // optional price feed; its conf/price adds a manipulation-resistant
+    // volatility term
+    pub oracle_source: OracleSource,
+    pub oracle: Pubkey,
+    pub epsilon_oracle_bps_per1e12: u16,
+    pub oracle_max_age_seconds: u32,
+
+    // swaps and deposits are rejected while paused
+    pub paused: bool,
+
+    // destination of skimmed donations when skim_to_treasury is set
+    pub treasury: Pubkey,
+    pub skim_to_treasury: bool,
+
+    // fixed at init: donations are folded in by `sync` (true) or
+    // removed by `skim` (false)
+    pub sync_donations: bool,
+
+    // 0 disables the per-swap price impact cap
+    pub max_price_impact_bps: u16,
+    // slot of the last set_params, i.e. since when the fee curve applies;
+    // informational (the indexer's PoolRow), not a rate limit
+    pub params_updated_slot: u64,
+
+    // pool PDA keeps the LP mint freeze authority (chosen at init)
+    pub lp_freezable: bool,
+
+    // creator accepted mints that an issuer could use to freeze the vaults
+    pub allow_risky_mints: bool,
+
+    // last EMA refresh; older than ema_max_age_seconds (0 = off) resets it
+    pub ema_updated_at: i64,
+    pub ema_max_age_seconds: u32,
+
+    // flash loans (flash_fee_bps 0 = off) and the one outstanding loan,
+    // open from flash_borrow until the flash_repay later in the same
+    // transaction (flash_amount 0 = none)
+    pub flash_fee_bps: u16,
+    pub flash_token_is_0: bool,
+    pub flash_amount: u64,
+    pub flash_fee: u64,
+
+    // set while a flash_swap callback runs; every mutating instruction
+    // refuses a locked pool
+    pub locked: bool,
+
+    // optional operator key that may trip/reset the breaker (default = none)
+    pub guardian: Pubkey,
+    // set by trip_breaker; blocks swaps until reset_breaker
+    pub manually_tripped: bool,
+
+    // protocol share of each swap fee (bps of the fee), accrued in the
+    // vaults but outside the reserves until collect_protocol_fees
+    pub protocol_fee_share_bps: u16,
+    pub protocol_fees_owed0: u64,
+    pub protocol_fees_owed1: u64,
+
+    // get_twap defaults: window used when the caller passes 0, and the
+    // maximum age of the last accumulator update (0 = use the window)
+    pub default_twap_window_seconds: u32,
+    pub max_observation_staleness_seconds: u32,
+
+    // liquidity mining (reward_mint default = not set up): LP shares staked
+    // in lp_escrow earn reward_rate_1e18 per second over [reward_start_ts,
+    // reward_end_ts], accumulated per staked share
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    pub lp_escrow: Pubkey,
+    pub reward_rate_1e18: u128,
+    pub reward_start_ts: i64,
+    pub reward_end_ts: i64,
+    pub reward_updated_at: i64,
+    pub reward_per_share_1e18: u128,
+    pub reward_staked: u64,
+
+    // lifetime flash-loan stats (volume and fees in the borrowed token)
+    pub flash_loan_count: u64,
+    pub flash_volume0: u128,
+    pub flash_volume1: u128,
+    pub flash_fees0: u128,
+    pub flash_fees1: u128,
+
+    // the LP mint has no freeze authority (dropped at init or by
+    // revoke_lp_freeze_authority); can never be undone
+    pub lp_freeze_revoked: bool,
+
+    // smallest swap input per token; 0 disables the check
+    pub min_trade_amount0: u64,
+    pub min_trade_amount1: u64,
+
+    // largest swap input in bps of the pre-trade in-side reserve; 0 = off
+    pub max_trade_bps_of_reserve: u16,
+
+    // mint decimals, copied at init (or by sync_decimals for older pools)
+    pub decimals0: u8,
+    pub decimals1: u8,
+
+    // incremented whenever total_lp_supply returns to 0
+    pub epoch: u32,
+
+    // operator keys (the authority at init); the admin keys can always act
+    // in their place, and default = nobody else
+    pub fee_manager: Pubkey,
+    pub pauser: Pubkey,
+    pub treasurer: Pubkey,
+
+    // UTF-8 label and documentation link, zero-padded ("" = unset)
+    pub name: [u8; POOL_NAME_LEN],
+    pub uri: [u8; POOL_URI_LEN],
+
+    // slippage signal of the fee (InputFraction for older pools)
+    pub slip_mode: SlipMode,
+
+    // shallow-depth signal of the fee, and the pool value in token0 base
+    // units at which it reaches 0 in TargetDepth mode
+    pub shallow_mode: ShallowMode,
+    pub target_depth0: u64,
+
+    // pool value in token0 (2 * reserve0) smoothed over reserve changes,
+    // read by the shallow signal; alpha 0 follows the reserves exactly
+    pub ema_depth0: u128,
+    pub depth_ema_alpha_1e12: u64,
+
+    // second admin key with the authority's powers except managing the
+    // co-authority itself (default = none)
+    pub co_authority: Pubkey,
+
+    // swaps revert without a fresh oracle reading instead of dropping the
+    // oracle term
+    pub oracle_required: bool,
+
+    // the swap fee is withheld from the output token instead of the input;
+    // fixed at init
+    pub fee_on_output: bool,
+
+    // false skips the volatility breaker (vol is still computed and
+    // reported); the guardian's manual trip still applies
+    pub breaker_enabled: bool,
+
+    // high/low spot (token0 in token1) since hl_window_start_ts, restarted
+    // once HL_WINDOW_SECONDS have passed
+    pub price_high_1e12: u64,
+    pub price_low_1e12: u64,
+    pub hl_window_start_ts: i64,
+
+    // LP yield proxy: sqrt(k) after the last reserve change, and the
+    // cumulative growth of sqrt(k) from swaps and donations (1e12, starts at
+    // 1.0); liquidity adds/removes rebase sqrt_k_last without growth
+    pub sqrt_k_last: u128,
+    pub growth_1e12: u128,
+
+    // swaps only by CPI from a program in `routers` (unused slots are
+    // Pubkey::default())
+    pub only_via_router: bool,
+    pub routers: [Pubkey; MAX_ROUTERS],
+
+    // round-trip penalty (any zero = off) and the previous swap
+    // (last_trade_slot 0 = none yet); see recent_trades_in0/in1
+    pub reversion_window_slots: u64,
+    pub reversion_band_bps: u16,
+    pub reversion_penalty_bps: u16,
+    pub last_trade_token_in_is_0: bool,
+    pub last_trade_amount_out: u64,
+    pub last_trade_slot: u64,
+
+    // fee bounds in ppm (see min_fee_bps)
+    pub min_fee_ppm: u32,
+    pub max_fee_ppm: u32,
+
+    // denylist: managed by `compliance`; while enforced, an address with a
+    // Denylisted marker can neither sign nor own the token accounts of a
+    // trade or deposit (nor of a withdrawal unless denylist_allows_removal)
+    pub compliance: Pubkey,
+    pub enforce_denylist: bool,
+    pub denylist_allows_removal: bool,
+
+    // fair launch, fixed at init: until launch_end_slot each wallet may buy
+    // at most max_buy_per_wallet of the launched token (0 = no cap)
+    pub launch_token_is_0: bool,
+    pub launch_end_slot: u64,
+    pub max_buy_per_wallet: u64,
+
+    // inventory-skew fee coefficient: trades that deepen the imbalance
+    // (valued at the EMA) pay up to this much more, ones that ease it less
+    pub zeta_skew_bps_per1e12: u16,
+
+    // set by init_admin_log; admin instructions then fail without the log
+    pub admin_log_enabled: bool,
+
+    // the last RECENT_TRADES swaps in each direction, newest first (slot 0
+    // = empty): a reversal is checked against all of them, so a trade in
+    // between, like a sandwich's victim, does not hide it
+    pub recent_trades_in0: [RecentTrade; RECENT_TRADES],
+    pub recent_trades_in1: [RecentTrade; RECENT_TRADES],
+}
+
+/// Output and slot of a recent swap, for the round-trip penalty.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct RecentTrade {
+    pub amount_out: u64,
+    pub slot: u64,
+}
+
+/// Length of the high/low price window.
+pub const HL_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+
+/// Size of the pool's router allowlist.
+pub const MAX_ROUTERS: usize = 4;
+
+/// Swaps per direction the round-trip penalty checks a reversal against.
+pub const RECENT_TRADES: usize = 4;
+
+/// Entries the `Snapshots` ring buffer is created with.
+pub const SNAPSHOT_CAPACITY: usize = 64;
+
+/// Slots one `increase_snapshot_cardinality` call can add, bounded by the
+/// per-instruction realloc limit.
+pub const MAX_SNAPSHOT_GROWTH: u32 =
+    (MAX_PERMITTED_DATA_INCREASE / std::mem::size_of::<SnapshotEntry>()) as u32;
+
+/// One analytics checkpoint (prices at 1e12).
+#[zero_copy]
+pub struct SnapshotEntry {
+    pub cumulative_volume0: u128,
+    pub cumulative_volume1: u128,
+    pub cumulative_fees0: u128,
+    pub cumulative_fees1: u128,
+    pub timestamp: i64,
+    pub spot_price_1e12: u64,
+    pub ema_price_1e12: u64,
+    pub reserve0: u64,
+    pub reserve1: u64,
+    pub _padding: u64,
+}
+
+/// Growable ring buffer of analytics checkpoints, PDA of [b"snapshots", pool].
+/// The header is followed by `cardinality_next` raw `SnapshotEntry` slots;
+/// like Uniswap v3 observations, the ring only widens to `cardinality_next`
+/// when the head wraps, so entries stay in order across a resize.
+#[account(zero_copy)]
+pub struct Snapshots {
+    pub pool: Pubkey,
+    pub interval_seconds: i64,
+    pub last_timestamp: i64,
+    /// slot the next entry is written to
+    pub head: u32,
+    /// number of valid entries (<= cardinality)
+    pub len: u32,
+    /// slots currently in the ring
+    pub cardinality: u32,
+    /// slots allocated; the ring grows to this on the next wrap
+    pub cardinality_next: u32,
+}
+
+impl Snapshots {
+    const HEADER_LEN: usize = 8 + std::mem::size_of::<Self>();
+    const ENTRY_LEN: usize = std::mem::size_of::<SnapshotEntry>();
+
+    /// Account size for `cardinality` slots.
+    pub fn space(cardinality: u32) -> usize {
+        Self::HEADER_LEN + cardinality as usize * Self::ENTRY_LEN
+    }
+
+    /// Split raw account data (already type-checked) into header and slots.
+    fn split_mut(data: &mut [u8]) -> (&mut Self, &mut [u8]) {
+        let (header, slots) = data.split_at_mut(Self::HEADER_LEN);
+        (bytemuck::from_bytes_mut(&mut header[8..]), slots)
+    }
+
+    fn read_slot(slots: &[u8], index: u32) -> SnapshotEntry {
+        let offset = index as usize * Self::ENTRY_LEN;
+        bytemuck::pod_read_unaligned(&slots[offset..offset + Self::ENTRY_LEN])
+    }
+
+    fn push(&mut self, slots: &mut [u8], entry: SnapshotEntry) {
+        self.last_timestamp = entry.timestamp;
+        let offset = self.head as usize * Self::ENTRY_LEN;
+        slots[offset..offset + Self::ENTRY_LEN].copy_from_slice(bytemuck::bytes_of(&entry));
+        if self.head + 1 == self.cardinality && self.cardinality_next > self.cardinality {
+            self.cardinality = self.cardinality_next;
+        }
+        self.head = (self.head + 1) % self.cardinality;
+        self.len = u32::min(self.len + 1, self.cardinality);
+    }
+
+    /// Valid entries, oldest first. Unwritten (zeroed) slots are skipped.
+    pub fn chronological(&self, slots: &[u8]) -> Vec<SnapshotEntry> {
+        let start = (self.head + self.cardinality - self.len) % self.cardinality;
+        (0..self.len)
+            .map(|i| Self::read_slot(slots, (start + i) % self.cardinality))
+            .filter(|entry| entry.timestamp != 0)
+            .collect()
+    }
+
+    /// Client helper: decode raw account data into its valid entries.
+    pub fn decode_entries(data: &[u8]) -> Result<Vec<SnapshotEntry>> {
+        let header = Self::decode(data)?;
+        require!(
+            data.len() >= Self::space(header.cardinality),
+            ErrorCode::AccountDidNotDeserialize
+        );
+        Ok(header.chronological(&data[Self::HEADER_LEN..]))
+    }
+
+    /// Client helper: decode raw account data (discriminator included).
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        require!(
+            data.len() >= 8 + std::mem::size_of::<Self>(),
+            ErrorCode::AccountDidNotDeserialize
+        );
+        require!(
+            data[..8] == <Self as anchor_lang::Discriminator>::DISCRIMINATOR,
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+        Ok(bytemuck::pod_read_unaligned(
+            &data[8..8 + std::mem::size_of::<Self>()],
+        ))
+    }
+}
+
+/// Entries the `AdminLog` ring buffer holds.
+pub const ADMIN_LOG_CAPACITY: usize = 64;
+
+/// One privileged action. `action_code` is the `AdminAction` variant index
+/// and `payload_hash` the SHA-256 of the Borsh-encoded `AdminAction`, so an
+/// entry can be matched against its `AdminActionEvent`.
+#[zero_copy]
+pub struct AdminLogEntry {
+    pub slot: u64,
+    pub actor: Pubkey,
+    pub payload_hash: [u8; 32],
+    pub action_code: u8,
+    pub _padding: [u8; 7],
+}
+
+impl AdminLogEntry {
+    fn new(event: &AdminActionEvent) -> Result<Self> {
+        let payload = event.action.try_to_vec()?;
+        Ok(Self {
+            slot: event.slot,
+            actor: event.actor,
+            payload_hash: hash(&payload).to_bytes(),
+            action_code: payload[0],
+            _padding: [0; 7],
+        })
+    }
+}
+
+/// Append-only record of admin actions, PDA of [b"admin_log", pool]. Once
+/// full, each new entry overwrites the oldest.
+#[account(zero_copy)]
+pub struct AdminLog {
+    pub pool: Pubkey,
+    /// slot the next entry is written to
+    pub head: u32,
+    /// number of valid entries (<= ADMIN_LOG_CAPACITY)
+    pub len: u32,
+    pub entries: [AdminLogEntry; ADMIN_LOG_CAPACITY],
+}
+
+impl AdminLog {
+    fn push(&mut self, entry: AdminLogEntry) {
+        self.entries[self.head as usize] = entry;
+        self.head = (self.head + 1) % ADMIN_LOG_CAPACITY as u32;
+        self.len = u32::min(self.len + 1, ADMIN_LOG_CAPACITY as u32);
+    }
+
+    /// Valid entries, oldest first.
+    pub fn chronological(&self) -> Vec<AdminLogEntry> {
+        let capacity = ADMIN_LOG_CAPACITY as u32;
+        let start = (self.head + capacity - self.len) % capacity;
+        (0..self.len)
+            .map(|i| self.entries[((start + i) % capacity) as usize])
+            .collect()
+    }
+}
+
+/// Snapshot of the pool's TWAP accumulators at a point in time.
+#[account]
+pub struct TwapCheckpoint {
+    pub pool: Pubkey,
+    pub timestamp: i64,
+    pub price_cumulative_1e18: u128,
+    pub log2_price_cumulative: u128,
+}
+
+/// Program-wide settings, PDA of [b"config"].
+#[account]
+pub struct Config {
+    pub admin: Pubkey,
+    // initialize_pool requires a MintBadge for both mints
+    pub require_badges: bool,
+    pub bump: u8,
+}
+
+/// Denylist entry for one address, PDA of [b"denylist", pool, address].
+#[account]
+pub struct Denylisted {
+    pub address: Pubkey,
+    pub bump: u8,
+}
+
+/// Launch-window purchases of one wallet, PDA of [b"launch_buys", pool, wallet].
+#[account]
+pub struct LaunchBuys {
+    pub wallet: Pubkey,
+    // launched-token output bought so far
+    pub bought: u64,
+    pub bump: u8,
+}
+
+impl LaunchBuys {
+    pub const SPACE: usize = 8 + 32 + 8 + 1; // wallet + bought + bump
+}
+
+/// Allowlist entry for one mint, PDA of [b"mint_badge", mint].
+#[account]
+pub struct MintBadge {
+    pub mint: Pubkey,
+    pub bump: u8,
+}
+
+/// Per-LP entry bookkeeping, PDA of [b"position", pool, owner].
+#[account]
+pub struct Position {
+    pub pool: Pubkey,
+    pub owner: Pubkey,
+    pub bump: u8,
+
+    // shares minted while this position was attached and their entry basis
+    pub shares: u64,
+    pub entry_amount0: u64,
+    pub entry_amount1: u64,
+    pub entry_price_1e12: u64,
+
+    // LP shares staked for rewards, the reward per share already credited
+    // to them, and rewards credited but not yet claimed
+    pub staked: u64,
+    pub reward_per_share_paid_1e18: u128,
+    pub rewards_owed: u64,
+}
+
+/// Which price-feed format `Pool::oracle` points at.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OracleSource {
+    None,
+    Pyth,
+    Switchboard,
+}
+
+/// Signal behind the fee's slippage term.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SlipMode {
+    /// amountIn / (rin + amountIn)
+    InputFraction,
+    /// realized price impact of the fill, see `math::price_impact_1e12`
+    PriceImpact,
+}
+
+/// Signal behind the fee's shallow-depth term.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShallowMode {
+    /// 1 - minRes / (minRes + K), K from the mint decimals
+    MinReserve,
+    /// max(0, 1 - depth / target_depth0), see `math::target_depth_shallow_1e12`
+    TargetDepth,
+}
+
+impl Pool {
+    /// Account size of the current layout, discriminator included.
+    pub const SPACE: usize = 8 + // discriminator
+        1 + 32 + // bump + authority
+        32 + 32 + 32 + 32 + // mints/vaults
+        32 + 8 + // lp_mint + total_lp_supply
+        8 + 8 +  // reserves
+        2 + 2 + 2 + 2 + 2 + // fee params
+        16 + 8 + 8 + // ema + alpha + breaker
+        16 + 16 + 8 + 1 + // twap accumulators + last ts + geometric flag
+        8 + 8 + // last update slot + timestamp
+        16 + 16 + 8 + // cumulative volumes + swap count
+        16 + 16 + // cumulative fees
+        8 + // event seq
+        1 + 32 + 2 + 4 + // oracle source + oracle + epsilon + max age
+        1 + // paused
+        32 + 1 + // treasury + skim flag
+        1 + // sync donations
+        2 + 8 + // price impact cap + params updated slot
+        1 + // lp freezable
+        1 + // allow risky mints
+        8 + 4 + // ema updated at + max age
+        2 + 1 + 8 + 8 + // flash fee + outstanding loan
+        1 + // locked
+        32 + 1 + // guardian + manually tripped
+        2 + 8 + 8 + // protocol fee share + owed0 + owed1
+        4 + 4 + // default twap window + max observation staleness
+        32 * 3 + 16 + 8 * 3 + 16 + 8 + // rewards
+        8 + 16 * 4 + // flash loan count + volumes + fees
+        1 + // lp freeze revoked
+        8 + 8 + // min trade amounts
+        2 + // max trade bps of reserve
+        1 + 1 + // decimals
+        4 + // epoch
+        32 + 32 + 32 + // fee manager + pauser + treasurer
+        POOL_NAME_LEN + POOL_URI_LEN + // name + uri
+        1 + // slip mode
+        1 + 8 + // shallow mode + target depth
+        16 + 8 + // depth ema + alpha
+        32 + // co-authority
+        1 + // oracle required
+        1 + // fee on output
+        1 + // breaker enabled
+        8 + 8 + 8 + // price high + low + window start
+        16 + 16 + // sqrt k last + growth
+        1 + 32 * MAX_ROUTERS + // router allowlist
+        8 + 2 + 2 + // reversion window + band + penalty
+        1 + 8 + 8 + // last trade direction + output + slot
+        4 + 4 + // fee bounds in ppm
+        32 + 1 + 1 + // compliance + denylist flags
+        1 + 8 + 8 + // launch side + end slot + per-wallet cap
+        2 + // skew coefficient
+        1 + // admin log enabled
+        (8 + 8) * 2 * RECENT_TRADES; // recent trades per direction
+
+    pub fn seeds(&self) -> [&[u8]; 2] {
+        [POOL_SEED, std::slice::from_ref(&self.bump)]
+    }
+
+    pub fn fee_config(&self) -> FeeConfig {
+        let (min_fee_ppm, max_fee_ppm) = self.fee_bounds_ppm();
+        FeeConfig {
+            min_fee_ppm,
+            max_fee_ppm,
+            beta_vol_bps_per1e12: self.beta_vol_bps_per1e12,
+            gamma_slip_bps_per1e12: self.gamma_slip_bps_per1e12,
+            delta_shallow_bps_per1e12: self.delta_shallow_bps_per1e12,
+            ema_alpha_1e12: self.ema_alpha_1e12,
+            breaker_vol_threshold_1e12: self.breaker_vol_threshold_1e12,
+            breaker_enabled: self.breaker_enabled,
+            shallow_mode: self.shallow_mode,
+            target_depth0: self.target_depth0,
+            depth_ema_alpha_1e12: self.depth_ema_alpha_1e12,
+            zeta_skew_bps_per1e12: self.zeta_skew_bps_per1e12,
+        }
+    }
+
+    pub fn set_fee_config(&mut self, config: &FeeConfig) {
+        self.set_fee_bounds_ppm(config.min_fee_ppm, config.max_fee_ppm);
+        self.beta_vol_bps_per1e12 = config.beta_vol_bps_per1e12;
+        self.gamma_slip_bps_per1e12 = config.gamma_slip_bps_per1e12;
+        self.delta_shallow_bps_per1e12 = config.delta_shallow_bps_per1e12;
+        self.ema_alpha_1e12 = config.ema_alpha_1e12;
+        self.breaker_vol_threshold_1e12 = config.breaker_vol_threshold_1e12;
+        self.breaker_enabled = config.breaker_enabled;
+        self.shallow_mode = config.shallow_mode;
+        self.target_depth0 = config.target_depth0;
+        self.depth_ema_alpha_1e12 = config.depth_ema_alpha_1e12;
+        self.zeta_skew_bps_per1e12 = config.zeta_skew_bps_per1e12;
+    }
+
+    /// Volatility threshold the breaker enforces; None when it is disabled.
+    pub fn breaker_threshold_1e12(&self) -> Option<u64> {
+        self.breaker_enabled.then_some(self.breaker_vol_threshold_1e12)
+    }
+
+    pub fn name(&self) -> String {
+        unpack_metadata(&self.name)
+    }
+
+    pub fn uri(&self) -> String {
+        unpack_metadata(&self.uri)
+    }
+
+    pub fn role_config(&self) -> RoleConfig {
+        RoleConfig {
+            fee_manager: self.fee_manager,
+            pauser: self.pauser,
+            treasurer: self.treasurer,
+        }
+    }
+
+    pub fn oracle_config(&self) -> OracleConfig {
+        OracleConfig {
+            oracle_source: self.oracle_source,
+            oracle: self.oracle,
+            epsilon_oracle_bps_per1e12: self.epsilon_oracle_bps_per1e12,
+            oracle_max_age_seconds: self.oracle_max_age_seconds,
+            oracle_required: self.oracle_required,
+        }
+    }
+
+    /// Round-trip penalty inputs for `math::reversion_penalty_bps`.
+    pub fn reversion_params(&self) -> ReversionParams {
+        ReversionParams {
+            window_slots: self.reversion_window_slots,
+            band_bps: self.reversion_band_bps,
+            penalty_bps: self.reversion_penalty_bps,
+        }
+    }
+
+    pub fn last_trade(&self) -> LastTrade {
+        LastTrade {
+            token_in_is_0: self.last_trade_token_in_is_0,
+            amount_out: self.last_trade_amount_out,
+            slot: self.last_trade_slot,
+        }
+    }
+
+    /// The recent swaps of both directions, for `math::reversion_penalty_bps`.
+    pub fn recent_trades(&self) -> [LastTrade; 2 * RECENT_TRADES] {
+        std::array::from_fn(|i| {
+            let token_in_is_0 = i < RECENT_TRADES;
+            let trade = if token_in_is_0 {
+                self.recent_trades_in0[i]
+            } else {
+                self.recent_trades_in1[i - RECENT_TRADES]
+            };
+            LastTrade { token_in_is_0, amount_out: trade.amount_out, slot: trade.slot }
+        })
+    }
+
+    /// (min, max) fee in ppm. A pool from before ppm fees has no ppm
+    /// bounds yet (zero once `migrate_pool` grew its account) and reads
+    /// its bps bounds times 100.
+    pub fn fee_bounds_ppm(&self) -> (u32, u32) {
+        if self.max_fee_ppm == 0 {
+            (bps_to_ppm(self.min_fee_bps), bps_to_ppm(self.max_fee_bps))
+        } else {
+            (self.min_fee_ppm, self.max_fee_ppm)
+        }
+    }
+
+    /// Store the fee bounds, keeping the legacy bps mirror in step.
+    pub fn set_fee_bounds_ppm(&mut self, min_fee_ppm: u32, max_fee_ppm: u32) {
+        self.min_fee_ppm = min_fee_ppm;
+        self.max_fee_ppm = max_fee_ppm;
+        self.min_fee_bps = ppm_to_bps(min_fee_ppm);
+        self.max_fee_bps = ppm_to_bps(max_fee_ppm);
+    }
+
+    /// Fee curve inputs for `math::compute_dynamic_fee`.
+    pub fn fee_params(&self) -> PoolParams {
+        let (min_fee_ppm, max_fee_ppm) = self.fee_bounds_ppm();
+        PoolParams {
+            min_fee_ppm,
+            max_fee_ppm,
+            beta_vol_bps_per1e12: self.beta_vol_bps_per1e12,
+            gamma_slip_bps_per1e12: self.gamma_slip_bps_per1e12,
+            delta_shallow_bps_per1e12: self.delta_shallow_bps_per1e12,
+            epsilon_oracle_bps_per1e12: self.epsilon_oracle_bps_per1e12,
+            ema_price_1e18: self.ema_price_1e18,
+            shallow_depth_k: shallow_depth_k(self.decimals0, self.decimals1),
+            slip_price_impact: self.slip_mode == SlipMode::PriceImpact,
+            shallow_target_depth0: match self.shallow_mode {
+                ShallowMode::MinReserve => 0,
+                ShallowMode::TargetDepth => self.target_depth0 as u128,
+            },
+            ema_depth0: self.ema_depth0,
+            zeta_skew_bps_per1e12: self.zeta_skew_bps_per1e12,
+        }
+    }
+}
+
+/* ------------------------------- Events --------------------------------- */
+
+#[event]
+pub struct SwapEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    /// pool life; bumped each time the last LP exits
+    pub epoch: u32,
+    pub trader: Pubkey,
+    pub token_in_is_0: bool,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// fee_ppm rounded up to whole bps, for consumers of the bps layout
+    pub fee_bps: u16,
+    pub fee_ppm: u32,
+    // fee withheld, in units of the input token, or of the output token
+    // on fee_on_output pools (see fee_token_is_0)
+    pub fee_amount: u64,
+    pub fee_token_is_0: bool,
+    pub reserve0_after: u64,
+    pub reserve1_after: u64,
+    pub total_lp_supply_after: u64,
+    // post-trade spot in both directions
+    pub price_0_in_1_1e12: u64,
+    pub price_1_in_0_1e12: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MintEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    /// pool life; bumped each time the last LP exits
+    pub epoch: u32,
+    pub sender: Pubkey,
+    pub amount0: u64,
+    pub amount1: u64,
+    pub shares: u64,
+    pub reserve0_after: u64,
+    pub reserve1_after: u64,
+    pub total_lp_supply_after: u64,
+    /// depositor's LP balance over the new supply, 1e12 = the whole pool
+    pub pool_share_1e12_after: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BurnEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    /// pool life; bumped each time the last LP exits
+    pub epoch: u32,
+    pub sender: Pubkey,
+    pub shares: u64,
+    pub amount0: u64,
+    pub amount1: u64,
+    pub reserve0_after: u64,
+    pub reserve1_after: u64,
+    pub total_lp_supply_after: u64,
+    /// value of the entry amounts at exit price (0 without a position)
+    pub hodl_value_in_token1: u64,
+    /// value actually withdrawn for those shares
+    pub lp_value_in_token1: u64,
+    /// (lp - hodl) / hodl in bps; negative is a loss versus holding
+    pub il_bps: i64,
+    pub slot: u64,
+    pub timestamp: i64,
+    /// share-weighted entry price of the position (token0 in token1, 1e12);
+    /// exit price over it is the move the IL was taken on (0 without one)
+    pub entry_price_1e12: u64,
+}
+
+#[event]
+pub struct SyncEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub reserve0: u64,
+    pub reserve1: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+/// Fee curve `initialize_pool` starts with; the shallow and depth-EMA
+/// settings start at their defaults and change through `set_params`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeeCurve {
+    pub min_fee_ppm: u32,
+    pub max_fee_ppm: u32,
+    pub beta_vol_bps_per1e12: u16,
+    pub gamma_slip_bps_per1e12: u16,
+    pub delta_shallow_bps_per1e12: u16,
+    pub zeta_skew_bps_per1e12: u16,
+    pub ema_alpha_1e12: u64,             // e.g., 0.05 * 1e12
+    pub breaker_vol_threshold_1e12: u64, // e.g., 0.20 * 1e12
+    pub breaker_enabled: bool,
+}
+
+/// Behaviour switches chosen at `initialize_pool`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PoolFlags {
+    pub geometric_twap_enabled: bool,
+    pub sync_donations: bool,
+    pub lp_freezable: bool,
+    pub allow_risky_mints: bool,
+    pub fee_on_output: bool,
+}
+
+/// Fair-launch window, fixed at `initialize_pool`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct LaunchConfig {
+    pub launch_token_is_0: bool, // side sold by the launch
+    pub launch_end_slot: u64,
+    pub max_buy_per_wallet: u64, // 0 = no fair-launch cap
+}
+
+/// Fee-curve settings written by `set_params`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FeeConfig {
+    pub min_fee_ppm: u32,
+    pub max_fee_ppm: u32,
+    pub beta_vol_bps_per1e12: u16,
+    pub gamma_slip_bps_per1e12: u16,
+    pub delta_shallow_bps_per1e12: u16,
+    pub ema_alpha_1e12: u64,
+    pub breaker_vol_threshold_1e12: u64,
+    pub breaker_enabled: bool,
+    pub shallow_mode: ShallowMode,
+    pub target_depth0: u64,
+    pub depth_ema_alpha_1e12: u64,
+    pub zeta_skew_bps_per1e12: u16,
+}
+
+/// Oracle settings written by `set_oracle`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct OracleConfig {
+    pub oracle_source: OracleSource,
+    pub oracle: Pubkey,
+    pub epsilon_oracle_bps_per1e12: u16,
+    pub oracle_max_age_seconds: u32,
+    pub oracle_required: bool,
+}
+
+/// Operator keys written by `set_roles`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RoleConfig {
+    pub fee_manager: Pubkey,
+    pub pauser: Pubkey,
+    pub treasurer: Pubkey,
+}
+
+/// Capacity a signer acted in; both admin keys act in every role.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Role {
+    Authority,
+    FeeManager,
+    Pauser,
+    Treasurer,
+    Guardian,
+    Treasury,
+    CoAuthority,
+    Compliance,
+}
+
+/// What an admin instruction changed, with old and new values.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq, Debug)]
+pub enum AdminAction {
+    SetParams { old: FeeConfig, new: FeeConfig },
+    SetOracle { old: OracleConfig, new: OracleConfig },
+    SetPaused { old: bool, new: bool },
+    SetMaxPriceImpact { old: u16, new: u16 },
+    SetEmaMaxAge { old: u32, new: u32 },
+    SetTreasury {
+        old_treasury: Pubkey,
+        old_skim_to_treasury: bool,
+        new_treasury: Pubkey,
+        new_skim_to_treasury: bool,
+    },
+    FreezeLpAccount { account: Pubkey },
+    ThawLpAccount { account: Pubkey },
+    InitSnapshots { interval_seconds: i64 },
+    ClosePool,
+    SetFlashFee { old: u16, new: u16 },
+    SetGuardian { old: Pubkey, new: Pubkey },
+    TripBreaker,
+    ResetBreaker,
+    SetProtocolFee { old: u16, new: u16 },
+    SetTwapDefaults {
+        old_window: u32,
+        old_staleness: u32,
+        new_window: u32,
+        new_staleness: u32,
+    },
+    InitRewards { reward_mint: Pubkey },
+    FundCampaign { amount: u64, start_ts: i64, end_ts: i64 },
+    EndCampaignEarly { returned: u64 },
+    RevokeLpFreezeAuthority,
+    SetMinTradeAmounts { old0: u64, old1: u64, new0: u64, new1: u64 },
+    SetMaxTradeSize { old: u16, new: u16 },
+    SetRoles { old: RoleConfig, new: RoleConfig },
+    SetMetadata { old_name: String, old_uri: String, new_name: String, new_uri: String },
+    SetSlipMode { old: SlipMode, new: SlipMode },
+    InitAdminLog,
+    SetCoAuthority { old: Pubkey, new: Pubkey },
+    SetRouters {
+        old_only_via_router: bool,
+        old_routers: [Pubkey; MAX_ROUTERS],
+        new_only_via_router: bool,
+        new_routers: [Pubkey; MAX_ROUTERS],
+    },
+    SetReversionPenalty {
+        old_window_slots: u64,
+        old_band_bps: u16,
+        old_penalty_bps: u16,
+        new_window_slots: u64,
+        new_band_bps: u16,
+        new_penalty_bps: u16,
+    },
+    SetCompliance {
+        old_compliance: Pubkey,
+        old_enforce_denylist: bool,
+        old_denylist_allows_removal: bool,
+        new_compliance: Pubkey,
+        new_enforce_denylist: bool,
+        new_denylist_allows_removal: bool,
+    },
+    DenyAddress { address: Pubkey },
+    AllowAddress { address: Pubkey },
+    RescueTokens { mint: Pubkey, source: Pubkey, destination: Pubkey, amount: u64 },
+    CollectProtocolFees { amount0: u64, amount1: u64 },
+}
+
+/// One per admin instruction; replaying them in `seq` order rebuilds the
+/// pool's configuration history.
+#[event]
+pub struct AdminActionEvent {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub actor: Pubkey,
+    pub role: Role,
+    pub action: AdminAction,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct PoolInitialized {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub creator: Pubkey,
+    pub token0_mint: Pubkey,
+    pub token1_mint: Pubkey,
+    pub vault0: Pubkey,
+    pub vault1: Pubkey,
+    pub lp_mint: Pubkey,
+    pub fee_config: FeeConfig,
+    pub geometric_twap_enabled: bool,
+    pub sync_donations: bool,
+    pub lp_freezable: bool,
+    pub allow_risky_mints: bool,
+    pub name: String,
+    pub uri: String,
+    pub fee_on_output: bool,
+    pub launch_token_is_0: bool,
+    pub launch_end_slot: u64,
+    pub max_buy_per_wallet: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct Donation {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub donor: Pubkey,
+    pub amount0: u64,
+    pub amount1: u64,
+    pub reserve0_after: u64,
+    pub reserve1_after: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ProtocolFeesCollected {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub collector: Pubkey,
+    pub role: Role,
+    pub amount0: u64,
+    pub amount1: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct TokensRescued {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub actor: Pubkey,
+    pub role: Role,
+    pub mint: Pubkey,
+    pub source: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FlashBorrowed {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub borrower: Pubkey,
+    pub token_is_0: bool,
+    pub amount: u64,
+    // due with the repayment, in the borrowed token
+    pub fee: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct FlashRepaid {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub payer: Pubkey,
+    pub token_is_0: bool,
+    pub amount: u64,
+    pub fee: u64,
+    // part of `fee` owed to the protocol rather than added to the reserves
+    pub protocol_fee: u64,
+    pub reserve0_after: u64,
+    pub reserve1_after: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsClaimed {
+    pub version: u8,
+    pub pool: Pubkey,
+    pub seq: u64,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub slot: u64,
+    pub timestamp: i64,
+}
+
+/* ------------------------------ View types ------------------------------ */
+
+/// Return data of `get_twap`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TwapResult {
+    pub twap_1e12: u64,
+    /// 0 unless the pool tracks the geometric accumulator
+    pub geometric_twap_1e12: u64,
+    pub window_actual_seconds: u64,
+    pub stale: bool,
+}
+
+/// Return data of `get_pool_state` (prices at 1e12, token0 in token1
+/// unless noted).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PoolState {
+    pub reserve0: u64,
+    pub reserve1: u64,
+    pub total_lp_supply: u64,
+    pub spot_price_1e12: u64,
+    pub spot_price_1_in_0_1e12: u64,
+    pub ema_price_1e12: u64,
+    /// dynamic fee of a minimal trade (bps rounded up, and exact ppm)
+    pub current_min_fee_bps: u16,
+    pub current_min_fee_ppm: u32,
+    /// even a minimal trade would trip the breaker
+    pub breaker_active: bool,
+    pub paused: bool,
+    pub cumulative_volume0: u128,
+    pub cumulative_volume1: u128,
+    pub swap_count: u64,
+    /// created with `allow_risky_mints`; UIs should warn LPs
+    pub allow_risky_mints: bool,
+    /// smaller swap inputs fail with TradeTooSmall (0 = no minimum)
+    pub min_trade_amount0: u64,
+    pub min_trade_amount1: u64,
+    /// larger inputs (bps of the in-side reserve) fail with TradeTooLarge
+    pub max_trade_bps_of_reserve: u16,
+    pub name: String,
+    pub uri: String,
+    /// swap fees are withheld from the output token
+    pub fee_on_output: bool,
+    /// false: the volatility breaker is off, only a manual trip blocks swaps
+    pub breaker_enabled: bool,
+    /// high/low spot of the current HL_WINDOW_SECONDS window, which started
+    /// at hl_window_start_ts (0 = no price yet)
+    pub price_high_1e12: u64,
+    pub price_low_1e12: u64,
+    pub hl_window_start_ts: i64,
+    /// cumulative sqrt(k) growth per LP share (1e12); compare two readings
+    /// with `math::growth_between_1e12` for the fee APR
+    pub growth_1e12: u128,
+    /// swaps must come by CPI from one of `routers` (default keys unused)
+    pub only_via_router: bool,
+    pub routers: [Pubkey; MAX_ROUTERS],
+    /// round-trip penalty settings (see `set_reversion_penalty`)
+    pub reversion_window_slots: u64,
+    pub reversion_band_bps: u16,
+    pub reversion_penalty_bps: u16,
+    /// signers with a Denylisted marker are refused (see `set_compliance`)
+    pub enforce_denylist: bool,
+    pub denylist_allows_removal: bool,
+    /// fair launch: per-wallet cap on buys of the launched token until
+    /// launch_end_slot (cap 0 = none)
+    pub launch_token_is_0: bool,
+    pub launch_end_slot: u64,
+    pub max_buy_per_wallet: u64,
+}
+
+/// Return data of `quote_marginal_fee`; signals at 1e12.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MarginalFee {
+    /// fee_ppm rounded up to whole bps
+    pub fee_bps: u16,
+    pub fee_ppm: u32,
+    pub vol_1e12: u128,
+    pub shallow_1e12: u128,
+    pub oracle_conf_1e12: u128,
+    /// even a minimal trade would be blocked by the breaker
+    pub breaker_would_trip: bool,
+}
+
+/// Return data of `health_check`. Any flag set means routers should skip
+/// the pool (or expect swaps to fail or be priced without the oracle).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PoolHealth {
+    pub paused: bool,
+    /// false: only a manual trip blocks swaps
+    pub breaker_enabled: bool,
+    /// manually tripped, or the pre-trade EMA deviation is over the threshold
+    pub breaker_would_trip_at_zero_size: bool,
+    /// the next swap resets the EMA to spot
+    pub ema_stale: bool,
+    /// a reserve is empty, so swaps fail with NoLiquidity
+    pub reserves_below_min: bool,
+    /// configured feed missing or older than oracle_max_age_seconds
+    pub oracle_stale: bool,
+    pub last_update_slot: u64,
+    /// per-swap input cap in bps of the in-side reserve (0 = none)
+    pub max_trade_bps_of_reserve: u16,
+}
+
+/// Return data of `quote_add_liquidity`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct AddLiquidityQuote {
+    pub amount0: u64,
+    pub amount1: u64,
     pub shares: u64,
+}
+
+/// Return data of `quote_remove_liquidity`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct RemoveLiquidityQuote {
     pub amount0: u64,
     pub amount1: u64,
 }
 
-/* ------------------------------- Contexts -------------------------------- */
+/* ------------------------------- Contexts -------------------------------- */
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct InitializePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    /// Pool PDA
+    #[account(
+        init,
+        payer = authority,
+        space = Pool::SPACE,
+        seeds = [POOL_SEED],
+        bump
+    )]
+    pub pool: Account<'info, Pool>,
+
+    // Token mints
+    pub token0_mint: Account<'info, Mint>,
+    pub token1_mint: Account<'info, Mint>,
+
+    /// CHECK: Config PDA; may not exist, in which case no badges are needed
+    #[account(seeds=[CONFIG_SEED], bump)]
+    pub config: UncheckedAccount<'info>,
+    #[account(seeds=[MINT_BADGE_SEED, token0_mint.key().as_ref()], bump=token0_badge.bump)]
+    pub token0_badge: Option<Account<'info, MintBadge>>,
+    #[account(seeds=[MINT_BADGE_SEED, token1_mint.key().as_ref()], bump=token1_badge.bump)]
+    pub token1_badge: Option<Account<'info, MintBadge>>,
+
+    /// LP mint (authority = pool PDA)
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = 9,
+        mint::authority = pool,
+        mint::freeze_authority = pool
+    )]
+    pub lp_mint: Account<'info, Mint>,
+
+    /// Vaults (owned by pool PDA)
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = token0_mint,
+        associated_token::authority = pool
+    )]
+    pub vault0: Account<'info, TokenAccount>,
+
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = token1_mint,
+        associated_token::authority = pool
+    )]
+    pub vault1: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Admin contexts take the authority as a bare `Signer`: never `mut`, never
+/// a payer, never the source of a lamport transfer. That keeps every admin
+/// instruction executable by a PDA signing through CPI (e.g. an SPL
+/// Governance native treasury). Rent for anything created goes through a
+/// separate `payer` account.
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct SetParams<'info> {
+    /// Authority, or the role key the instruction accepts
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    /// Liquidity provider
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    // User token accounts. They must belong to the signer: deposits are
+    // never funded through a delegate approval on someone else's account
+    #[account(
+        mut,
+        constraint = user_token0.mint == pool.token0_mint,
+        constraint = user_token0.owner == user.key() @ AmmError::InvalidTokenOwner
+    )]
+    pub user_token0: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_token1.mint == pool.token1_mint,
+        constraint = user_token1.owner == user.key() @ AmmError::InvalidTokenOwner
+    )]
+    pub user_token1: Account<'info, TokenAccount>,
+
+    // Vaults
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    // LP mint and recipient (created as the user's ATA if missing)
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[cfg_attr(
+        feature = "init-if-needed",
+        account(
+            init_if_needed,
+            payer = user,
+            associated_token::mint = lp_mint,
+            associated_token::authority = user
+        )
+    )]
+    #[cfg_attr(
+        not(feature = "init-if-needed"),
+        account(
+            mut,
+            token::mint = lp_mint,
+            constraint = user_lp.owner == user.key() @ AmmError::InvalidTokenOwner
+        )
+    )]
+    pub user_lp: Account<'info, TokenAccount>,
+
+    /// Optional IL-tracking position of the user
+    #[account(
+        mut,
+        seeds=[POSITION_SEED, pool.key().as_ref(), user.key().as_ref()],
+        bump=position.bump
+    )]
+    pub position: Option<Account<'info, Position>>,
+
+    /// CHECK: pool signer PDA for CPIs
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct SwapAndAddLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    // Both sides: one pays the swap, the other receives it; both fund the
+    // deposit. Owned by the signer, as in AddLiquidity
+    #[account(
+        mut,
+        constraint = user_token0.mint == pool.token0_mint,
+        constraint = user_token0.owner == user.key() @ AmmError::InvalidTokenOwner
+    )]
+    pub user_token0: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = user_token1.mint == pool.token1_mint,
+        constraint = user_token1.owner == user.key() @ AmmError::InvalidTokenOwner
+    )]
+    pub user_token1: Account<'info, TokenAccount>,
+
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[cfg_attr(
+        feature = "init-if-needed",
+        account(
+            init_if_needed,
+            payer = user,
+            associated_token::mint = lp_mint,
+            associated_token::authority = user
+        )
+    )]
+    #[cfg_attr(
+        not(feature = "init-if-needed"),
+        account(
+            mut,
+            token::mint = lp_mint,
+            constraint = user_lp.owner == user.key() @ AmmError::InvalidTokenOwner
+        )
+    )]
+    pub user_lp: Account<'info, TokenAccount>,
+
+    /// Optional IL-tracking position of the user
+    #[account(
+        mut,
+        seeds=[POSITION_SEED, pool.key().as_ref(), user.key().as_ref()],
+        bump=position.bump
+    )]
+    pub position: Option<Account<'info, Position>>,
+
+    /// CHECK: pool signer PDA for CPIs
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: instructions sysvar; needed when the pool only accepts routed
+    /// swaps
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    // Vaults
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    // LP
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, constraint = user_lp.mint == pool.lp_mint)]
+    pub user_lp: Account<'info, TokenAccount>,
+
+    // user token outs
+    #[account(mut, constraint = user_token0.mint == pool.token0_mint)]
+    pub user_token0: Account<'info, TokenAccount>,
+    #[account(mut, constraint = user_token1.mint == pool.token1_mint)]
+    pub user_token1: Account<'info, TokenAccount>,
+
+    /// Optional IL-tracking position of the user
+    #[account(
+        mut,
+        seeds=[POSITION_SEED, pool.key().as_ref(), user.key().as_ref()],
+        bump=position.bump
+    )]
+    pub position: Option<Account<'info, Position>>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: instructions sysvar; needed when the pool only accepts routed
+    /// swaps
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct CollectProtocolFees<'info> {
+    /// Authority, treasurer or treasury
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token0_mint, token::authority = pool.treasury)]
+    pub treasury_token0: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.token1_mint, token::authority = pool.treasury)]
+    pub treasury_token1: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct GuardianAction<'info> {
+    /// Guardian or authority; bare signer like `SetParams`
+    pub signer: Signer<'info>,
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct FreezeLpAccount<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, token::mint = pool.lp_mint)]
+    pub lp_account: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct RevokeLpFreezeAuthority<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+
+    #[account(mut, address = pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct RescueTokens<'info> {
+    /// Authority or treasurer
+    pub signer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+
+    /// Any token account the pool PDA owns; vaults are rejected in the handler
+    #[account(mut, token::authority = pool)]
+    pub source: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = source.mint)]
+    pub destination: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct ClosePool<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut, seeds=[POOL_SEED], bump=pool.bump, close = recipient)]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed. The
+    /// closing is its last entry, kept in the `AdminActionEvent`.
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump, close = recipient)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    /// Required once `init_rewards` has run
+    #[account(mut, address = pool.reward_vault)]
+    pub reward_vault: Option<Account<'info, TokenAccount>>,
+    #[account(mut, address = pool.lp_escrow)]
+    pub lp_escrow: Option<Account<'info, TokenAccount>>,
+
+    /// Pass when `init_snapshots` was run, or its rent leaks
+    #[account(mut, seeds = [SNAPSHOTS_SEED, pool.key().as_ref()], bump, close = recipient)]
+    pub snapshots: Option<AccountLoader<'info, Snapshots>>,
+
+    /// Receives every closed account's lamports
+    #[account(mut)]
+    pub recipient: SystemAccount<'info>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 1 + 1, // admin + require badges + bump
+        seeds = [CONFIG_SEED],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
+    // only the upgrade authority may create the Config, so nobody can
+    // front-run the deployment and claim the allowlist
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::AdaptiveCpamm>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ AmmError::NotAuthorized
+    )]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [CONFIG_SEED],
+        bump = config.bump,
+        has_one = admin @ AmmError::NotAuthorized
+    )]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(seeds=[CONFIG_SEED], bump=config.bump, has_one = admin @ AmmError::NotAuthorized)]
+    pub config: Account<'info, Config>,
+
+    pub mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 32 + 1, // mint + bump
+        seeds = [MINT_BADGE_SEED, mint.key().as_ref()],
+        bump
+    )]
+    pub badge: Account<'info, MintBadge>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeMint<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(seeds=[CONFIG_SEED], bump=config.bump, has_one = admin @ AmmError::NotAuthorized)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [MINT_BADGE_SEED, badge.mint.as_ref()],
+        bump = badge.bump,
+        close = admin
+    )]
+    pub badge: Account<'info, MintBadge>,
+}
+
+#[derive(Accounts)]
+pub struct OpenPosition<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = user,
+        space = 8 + 32 + 32 + 1 + // pool + owner + bump
+            8 + 8 + 8 + 8 + // shares + entry amounts + entry price
+            8 + 16 + 8, // staked + reward checkpoint + owed
+        seeds = [POSITION_SEED, pool.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+#[instruction(token_in_is_0: bool)]
+pub struct Swap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    /// Mint of the output side
+    #[account(
+        address = if token_in_is_0 { pool.token1_mint } else { pool.token0_mint }
+            @ AmmError::WrongTokenAccount
+    )]
+    pub mint_out: Account<'info, Mint>,
+
+    // For convenience we pass generic "in/out" ATAs bound to the chosen side.
+    // `user` may be the owner of the input account or only its delegate
+    // (e.g. a session key); the token program enforces the allowance
+    #[account(mut)]
+    pub user_token_in: Account<'info, TokenAccount>,
+
+    /// CHECK: owner of the output account; `user` on the default path
+    pub recipient: UncheckedAccount<'info>,
+    // the recipient's output ATA is created if missing
+    #[cfg_attr(
+        feature = "init-if-needed",
+        account(
+            init_if_needed,
+            payer = user,
+            associated_token::mint = mint_out,
+            associated_token::authority = recipient
+        )
+    )]
+    #[cfg_attr(
+        not(feature = "init-if-needed"),
+        account(
+            mut,
+            constraint = user_token_out.owner == recipient.key() @ AmmError::InvalidRecipient
+        )
+    )]
+    pub user_token_out: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: instructions sysvar; needed when the pool only accepts routed
+    /// swaps
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+#[instruction(token_is_0: bool)]
+pub struct FlashBorrow<'info> {
+    pub borrower: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    /// Vault of the borrowed side
+    #[account(
+        mut,
+        address = if token_is_0 { pool.vault0 } else { pool.vault1 }
+            @ AmmError::WrongTokenAccount
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = vault.mint)]
+    pub borrower_token: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    /// CHECK: instructions sysvar, searched for the matching flash_repay
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+#[instruction(token_is_0: bool)]
+pub struct FlashRepay<'info> {
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        address = if token_is_0 { pool.vault0 } else { pool.vault1 }
+            @ AmmError::WrongTokenAccount
+    )]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = vault.mint)]
+    pub payer_token: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+#[instruction(token_out_is_0: bool)]
+pub struct FlashSwap<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = user_token_out.mint
+            == if token_out_is_0 { pool.token0_mint } else { pool.token1_mint }
+            @ AmmError::WrongTokenAccount
+    )]
+    pub user_token_out: Account<'info, TokenAccount>,
+
+    /// CHECK: any program; called with the remaining accounts only
+    #[account(executable)]
+    pub callback_program: UncheckedAccount<'info>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: instructions sysvar; needed when the pool only accepts routed
+    /// swaps
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct Donate<'info> {
+    pub donor: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut, token::mint = pool.token0_mint)]
+    pub donor_token0: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.token1_mint)]
+    pub donor_token1: Account<'info, TokenAccount>,
+
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct InitRewards<'info> {
+    /// Bare signer so a governance PDA can act (see `SetParams`)
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+
+    pub reward_mint: Account<'info, Mint>,
+    #[account(address = pool.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = reward_mint,
+        associated_token::authority = pool
+    )]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = lp_mint,
+        associated_token::authority = pool
+    )]
+    pub lp_escrow: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Shared by `fund_campaign` (tokens in) and `end_campaign_early`
+/// (unstreamed tokens back).
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct FundCampaign<'info> {
+    /// Bare signer so a governance PDA can act (see `SetParams`)
+    pub authority: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+
+    #[account(mut, address = pool.reward_vault)]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.reward_mint)]
+    pub authority_token: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+/// Shared by `stake_lp` and `unstake_lp`.
+#[derive(Accounts)]
+pub struct StakeLp<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds=[POSITION_SEED, pool.key().as_ref(), user.key().as_ref()],
+        bump=position.bump
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(mut, token::mint = pool.lp_mint)]
+    pub user_lp: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.lp_escrow)]
+    pub lp_escrow: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct ClaimRewards<'info> {
+    pub user: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        mut,
+        seeds=[POSITION_SEED, pool.key().as_ref(), user.key().as_ref()],
+        bump=position.bump
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(mut, address = pool.reward_vault)]
+    pub reward_vault: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.reward_mint)]
+    pub user_reward: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Skim<'info> {
+    #[account(seeds=[POOL_SEED], bump=pool.bump, constraint = !pool.locked @ AmmError::PoolLocked)]
+    pub pool: Account<'info, Pool>,
+
+    #[account(mut, address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(mut, address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = pool.token0_mint)]
+    pub recipient_token0: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = pool.token1_mint)]
+    pub recipient_token1: Account<'info, TokenAccount>,
+
+    /// CHECK: pool signer PDA
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool_signer: UncheckedAccount<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct SyncReserves<'info> {
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+
+    #[account(address = pool.vault0)]
+    pub vault0: Account<'info, TokenAccount>,
+    #[account(address = pool.vault1)]
+    pub vault1: Account<'info, TokenAccount>,
+}
 
 #[derive(Accounts)]
-pub struct InitializePool<'info> {
+pub struct CheckpointTwap<'info> {
     #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(seeds=[POOL_SEED], bump=pool.bump, constraint = !pool.locked @ AmmError::PoolLocked)]
+    pub pool: Account<'info, Pool>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 32 + 8 + 16 + 16 // pool + timestamp + cumulatives
+    )]
+    pub checkpoint: Account<'info, TwapCheckpoint>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct InitSnapshots<'info> {
+    /// Bare signer so a governance PDA can act (see `SetParams`)
     pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    /// Optional admin log; every admin action is appended when passed
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
 
-    /// Pool PDA
     #[account(
         init,
-        payer = authority,
-        space = 8 +  // discriminator
-            1 + 32 + // bump + authority
-            32 + 32 + 32 + 32 + // mints/vaults
-            32 + 8 + // lp_mint + total_lp_supply
-            8 + 8 +  // reserves
-            2 + 2 + 2 + 2 + 2 + // fee params
-            8 + 8 + 8, // ema + alpha + breaker
-        seeds = [b"pool"],
+        payer = payer,
+        space = Snapshots::space(SNAPSHOT_CAPACITY as u32),
+        seeds = [SNAPSHOTS_SEED, pool.key().as_ref()],
         bump
     )]
-    pub pool: Account<'info, Pool>,
+    pub snapshots: AccountLoader<'info, Snapshots>,
 
-    // Token mints
-    pub token0_mint: Account<'info, Mint>,
-    pub token1_mint: Account<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+pub struct InitAdminLog<'info> {
+    /// Bare signer so a governance PDA can act (see `SetParams`)
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
 
-    /// LP mint (authority = pool PDA)
     #[account(
-        init,
-        payer = authority,
-        mint::decimals = 9,
-        mint::authority = pool,
-        mint::freeze_authority = pool
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
     )]
-    pub lp_mint: Account<'info, Mint>,
+    pub pool: Account<'info, Pool>,
 
-    /// Vaults (owned by pool PDA)
     #[account(
         init,
-        payer = authority,
-        associated_token::mint = token0_mint,
-        associated_token::authority = pool
+        payer = payer,
+        space = 8 + std::mem::size_of::<AdminLog>(),
+        seeds = [ADMIN_LOG_SEED, pool.key().as_ref()],
+        bump
     )]
-    pub vault0: Account<'info, TokenAccount>,
+    pub admin_log: AccountLoader<'info, AdminLog>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[cfg_attr(feature = "event-cpi", event_cpi)]
+#[derive(Accounts)]
+#[instruction(address: Pubkey)]
+pub struct DenyAddress<'info> {
+    /// Authority, co-authority or compliance; bare signer (see `SetParams`)
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
+    pub pool: Account<'info, Pool>,
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
 
     #[account(
         init,
-        payer = authority,
-        associated_token::mint = token1_mint,
-        associated_token::authority = pool
+        payer = payer,
+        space = 8 + 32 + 1, // address + bump
+        seeds = [DENYLIST_SEED, pool.key().as_ref(), address.as_ref()],
+        bump
     )]
-    pub vault1: Account<'info, TokenAccount>,
+    pub marker: Account<'info, Denylisted>,
 
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
-    pub rent: Sysvar<'info, Rent>,
 }
 
+#[cfg_attr(feature = "event-cpi", event_cpi)]
 #[derive(Accounts)]
-pub struct SetParams<'info> {
+pub struct AllowAddress<'info> {
+    /// Authority, co-authority or compliance; bare signer (see `SetParams`)
     pub authority: Signer<'info>,
-    #[account(mut, seeds=[b"pool"], bump=pool.bump)]
+
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
     pub pool: Account<'info, Pool>,
+    #[account(mut, seeds=[ADMIN_LOG_SEED, pool.key().as_ref()], bump)]
+    pub admin_log: Option<AccountLoader<'info, AdminLog>>,
+
+    #[account(
+        mut,
+        seeds = [DENYLIST_SEED, pool.key().as_ref(), marker.address.as_ref()],
+        bump = marker.bump,
+        close = rent_receiver
+    )]
+    pub marker: Account<'info, Denylisted>,
+    /// CHECK: receives the marker's rent
+    #[account(mut)]
+    pub rent_receiver: UncheckedAccount<'info>,
 }
 
 #[derive(Accounts)]
-pub struct AddLiquidity<'info> {
-    /// Liquidity provider
+pub struct CloseLaunchBuys<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub wallet: Signer<'info>,
 
-    #[account(mut, seeds=[b"pool"], bump=pool.bump)]
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
     pub pool: Account<'info, Pool>,
 
-    // User token accounts
-    #[account(mut, constraint = user_token0.mint == pool.token0_mint)]
-    pub user_token0: Account<'info, TokenAccount>,
-    #[account(mut, constraint = user_token1.mint == pool.token1_mint)]
-    pub user_token1: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [LAUNCH_BUYS_SEED, pool.key().as_ref(), wallet.key().as_ref()],
+        bump = launch_buys.bump,
+        close = wallet
+    )]
+    pub launch_buys: Account<'info, LaunchBuys>,
+}
 
-    // Vaults
-    #[account(mut, address = pool.vault0)]
-    pub vault0: Account<'info, TokenAccount>,
-    #[account(mut, address = pool.vault1)]
-    pub vault1: Account<'info, TokenAccount>,
+#[derive(Accounts)]
+pub struct TakeSnapshot<'info> {
+    #[account(seeds=[POOL_SEED], bump=pool.bump, constraint = !pool.locked @ AmmError::PoolLocked)]
+    pub pool: Account<'info, Pool>,
 
-    // LP mint and recipient
-    #[account(mut, address = pool.lp_mint)]
-    pub lp_mint: Account<'info, Mint>,
+    #[account(mut, seeds=[SNAPSHOTS_SEED, pool.key().as_ref()], bump)]
+    pub snapshots: AccountLoader<'info, Snapshots>,
+}
+
+#[derive(Accounts)]
+pub struct IncreaseSnapshotCardinality<'info> {
     #[account(mut)]
-    pub user_lp: Account<'info, TokenAccount>,
+    pub payer: Signer<'info>,
 
-    /// CHECK: pool signer PDA for CPIs
-    #[account(seeds=[b"pool"], bump=pool.bump)]
-    pub pool_signer: UncheckedAccount<'info>,
+    #[account(seeds=[POOL_SEED], bump=pool.bump, constraint = !pool.locked @ AmmError::PoolLocked)]
+    pub pool: Account<'info, Pool>,
 
-    pub token_program: Program<'info, Token>,
+    #[account(mut, seeds=[SNAPSHOTS_SEED, pool.key().as_ref()], bump)]
+    pub snapshots: AccountLoader<'info, Snapshots>,
+
+    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct RemoveLiquidity<'info> {
+pub struct MigratePool<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub payer: Signer<'info>,
+
+    /// CHECK: the pool PDA, possibly too short for `Pool` to deserialize;
+    /// seeds, owner and discriminator are checked instead
+    #[account(mut, seeds=[POOL_SEED], bump, owner = crate::ID)]
+    pub pool: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
 
-    #[account(mut, seeds=[b"pool"], bump=pool.bump)]
+#[derive(Accounts)]
+pub struct SyncDecimals<'info> {
+    #[account(
+        mut,
+        seeds=[POOL_SEED],
+        bump=pool.bump,
+        constraint = !pool.locked @ AmmError::PoolLocked
+    )]
     pub pool: Account<'info, Pool>,
 
-    // Vaults
-    #[account(mut, address = pool.vault0)]
-    pub vault0: Account<'info, TokenAccount>,
-    #[account(mut, address = pool.vault1)]
-    pub vault1: Account<'info, TokenAccount>,
+    #[account(address = pool.token0_mint)]
+    pub token0_mint: Account<'info, Mint>,
+    #[account(address = pool.token1_mint)]
+    pub token1_mint: Account<'info, Mint>,
+}
 
-    // LP
-    #[account(mut, address = pool.lp_mint)]
+#[derive(Accounts)]
+pub struct GetPoolState<'info> {
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool: Account<'info, Pool>,
+
+    #[account(address = pool.lp_mint)]
     pub lp_mint: Account<'info, Mint>,
-    #[account(mut, constraint = user_lp.mint == pool.lp_mint)]
-    pub user_lp: Account<'info, TokenAccount>,
+}
 
-    // user token outs
-    #[account(mut, constraint = user_token0.mint == pool.token0_mint)]
-    pub user_token0: Account<'info, TokenAccount>,
-    #[account(mut, constraint = user_token1.mint == pool.token1_mint)]
-    pub user_token1: Account<'info, TokenAccount>,
+#[derive(Accounts)]
+pub struct SimulateSwap<'info> {
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool: Account<'info, Pool>,
+}
 
-    /// CHECK: pool signer PDA
-    #[account(seeds=[b"pool"], bump=pool.bump)]
-    pub pool_signer: UncheckedAccount<'info>,
+#[derive(Accounts)]
+pub struct QuoteFee<'info> {
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool: Account<'info, Pool>,
+}
 
-    pub token_program: Program<'info, Token>,
+#[derive(Accounts)]
+pub struct HealthCheck<'info> {
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool: Account<'info, Pool>,
 }
 
 #[derive(Accounts)]
-pub struct Swap<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+pub struct GetTwap<'info> {
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
+    pub pool: Account<'info, Pool>,
 
-    #[account(mut, seeds=[b"pool"], bump=pool.bump)]
+    #[account(has_one = pool)]
+    pub checkpoint: Account<'info, TwapCheckpoint>,
+}
+
+#[derive(Accounts)]
+pub struct QuoteLiquidity<'info> {
+    #[account(seeds=[POOL_SEED], bump=pool.bump)]
     pub pool: Account<'info, Pool>,
+}
 
-    #[account(mut, address = pool.vault0)]
-    pub vault0: Account<'info, TokenAccount>,
-    #[account(mut, address = pool.vault1)]
-    pub vault1: Account<'info, TokenAccount>,
+/* ------------------------------- Helpers -------------------------------- */
+
+/// Token accounts a swap or deposit moves funds between, borrowed from
+/// whichever context drives the shared cores below.
+struct PoolLegs<'a, 'info> {
+    user: &'a Signer<'info>,
+    user_token0: &'a Account<'info, TokenAccount>,
+    user_token1: &'a Account<'info, TokenAccount>,
+    vault0: &'a Account<'info, TokenAccount>,
+    vault1: &'a Account<'info, TokenAccount>,
+    pool_signer: &'a UncheckedAccount<'info>,
+    token_program: &'a Program<'info, Token>,
+}
+
+fn check_deadline(clock: &Clock, deadline: i64) -> Result<()> {
+    if clock.unix_timestamp > deadline {
+        msg!("deadline {} passed at {}", deadline, clock.unix_timestamp);
+        return err!(AmmError::DeadlineExceeded);
+    }
+    Ok(())
+}
+
+/// Body of `add_liquidity`: transfers and mint around `apply_add_liquidity`,
+/// then the position update. Returns the event for the caller to emit.
+#[allow(clippy::too_many_arguments)]
+fn add_liquidity_core<'info>(
+    pool: &mut Account<'info, Pool>,
+    legs: &PoolLegs<'_, 'info>,
+    lp_mint: &Account<'info, Mint>,
+    user_lp: &Account<'info, TokenAccount>,
+    position: Option<&mut Account<'info, Position>>,
+    amount0: u64,
+    amount1: u64,
+    clock: &Clock,
+) -> Result<MintEvent> {
+    let shares_to_mint = apply_add_liquidity(pool, amount0, amount1, clock)?;
+
+    // Pull tokens into vaults
+    transfer_into_vault(
+        legs.user,
+        legs.user_token0,
+        legs.vault0,
+        legs.token_program,
+        amount0,
+    )?;
+    transfer_into_vault(
+        legs.user,
+        legs.user_token1,
+        legs.vault1,
+        legs.token_program,
+        amount1,
+    )?;
+
+    // Mint LP shares to user
+    mint_lp_shares(
+        pool,
+        lp_mint,
+        user_lp,
+        legs.token_program,
+        shares_to_mint,
+        legs.pool_signer,
+    )?;
+
+    // Track entry for IL reporting (share-weighted average entry price)
+    if let Some(position) = position {
+        let price = spot_price_1e12(pool.reserve0, pool.reserve1)? as u128;
+        let old_shares = position.shares as u128;
+        let total_shares = old_shares + shares_to_mint as u128;
+        let weighted = (position.entry_price_1e12 as u128 * old_shares)
+            .checked_add(price * shares_to_mint as u128)
+            .ok_or(AmmError::MathOverflow)?;
+        position.entry_price_1e12 =
+            u64::try_from(weighted / total_shares).map_err(|_| AmmError::MathOverflow)?;
+        position.shares = u64::try_from(total_shares).map_err(|_| AmmError::MathOverflow)?;
+        position.entry_amount0 = position
+            .entry_amount0
+            .checked_add(amount0)
+            .ok_or(AmmError::MathOverflow)?;
+        position.entry_amount1 = position
+            .entry_amount1
+            .checked_add(amount1)
+            .ok_or(AmmError::MathOverflow)?;
+    }
+
+    // `user_lp` still holds the pre-mint balance; no reload needed. The
+    // first depositor owns the whole pool (nothing is locked at bootstrap).
+    let user_lp_after = user_lp
+        .amount
+        .checked_add(shares_to_mint)
+        .ok_or(AmmError::MathOverflow)?;
+    let pool_share_1e12_after = mul_div(
+        user_lp_after as u128,
+        SCALE,
+        pool.total_lp_supply as u128,
+        Rounding::Down,
+    )
+    .ok_or(AmmError::MathOverflow)?;
+
+    let event = MintEvent {
+        version: EVENT_VERSION,
+        pool: pool.key(),
+        seq: next_event_seq(pool),
+        epoch: pool.epoch,
+        sender: legs.user.key(),
+        amount0,
+        amount1,
+        shares: shares_to_mint,
+        reserve0_after: pool.reserve0,
+        reserve1_after: pool.reserve1,
+        total_lp_supply_after: pool.total_lp_supply,
+        pool_share_1e12_after: u64::try_from(pool_share_1e12_after)
+            .map_err(|_| AmmError::MathOverflow)?,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    };
+    Ok(event)
+}
+
+/// Checks and state update of a deposit, without any token movement: the
+/// caller moves both amounts into the vaults and mints the returned shares.
+/// Shared with the simulator.
+fn apply_add_liquidity(pool: &mut Pool, amount0: u64, amount1: u64, clock: &Clock) -> Result<u64> {
+    require!(amount0 > 0 && amount1 > 0, AmmError::ZeroAmount);
+
+    require!(!pool.paused, AmmError::PoolPaused);
+    update_price_accumulators(pool, clock.unix_timestamp)?;
+    drop_orphaned_reserves(pool);
+
+    let shares_to_mint = shares_for_deposit(
+        amount0,
+        amount1,
+        pool.reserve0,
+        pool.reserve1,
+        pool.total_lp_supply,
+    )?;
+
+    // Enforce price invariance when reserves > 0
+    if pool.reserve0 > 0
+        && pool.reserve1 > 0
+        && !ratio_matches(pool.reserve0, pool.reserve1, amount0, amount1)
+    {
+        msg!(
+            "ratio: deposit {}:{} vs reserves {}:{}",
+            amount0,
+            amount1,
+            pool.reserve0,
+            pool.reserve1
+        );
+        return err!(AmmError::BadRatio);
+    }
+
+    // Reserves move by exactly the deposit; donations never enter pricing
+    let new_reserve0 = pool
+        .reserve0
+        .checked_add(amount0)
+        .ok_or(AmmError::MathOverflow)?;
+    let new_reserve1 = pool
+        .reserve1
+        .checked_add(amount1)
+        .ok_or(AmmError::MathOverflow)?;
+
+    // init EMA with first spot price
+    if pool.total_lp_supply == 0 && pool.ema_price_1e18 == 0 {
+        pool.ema_price_1e18 = spot_price_1e18(new_reserve0, new_reserve1)?;
+        pool.ema_updated_at = clock.unix_timestamp;
+    }
+
+    require!(shares_to_mint > 0, AmmError::ZeroShares);
+
+    // Save reserves & total supply
+    pool.reserve0 = new_reserve0;
+    pool.reserve1 = new_reserve1;
+    pool.total_lp_supply = pool
+        .total_lp_supply
+        .checked_add(shares_to_mint)
+        .ok_or(AmmError::MathOverflow)?;
+
+    // Optional EMA update after add
+    if pool.reserve0 > 0 && pool.reserve1 > 0 {
+        let price = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+        let alpha = pool.ema_alpha_1e12;
+        ema_update(&mut pool.ema_price_1e18, alpha, price);
+        pool.ema_updated_at = clock.unix_timestamp;
+    }
+    update_depth_ema(pool);
+    update_high_low(pool, clock.unix_timestamp);
+    rebase_sqrt_k(pool);
+    record_update(pool, clock);
+    Ok(shares_to_mint)
+}
+
+/// Body of `remove_liquidity`: burn, pro-rata payout, IL accounting and
+/// state update. Returns the event for the caller to emit.
+fn remove_liquidity_core<'info>(
+    pool: &mut Account<'info, Pool>,
+    legs: &PoolLegs<'_, 'info>,
+    lp_mint: &Account<'info, Mint>,
+    user_lp: &Account<'info, TokenAccount>,
+    position: Option<&mut Account<'info, Position>>,
+    shares: u64,
+    clock: &Clock,
+) -> Result<BurnEvent> {
+    require!(shares > 0, AmmError::ZeroShares);
+    if pool.total_lp_supply < shares {
+        msg!("shares {} > lp supply {}", shares, pool.total_lp_supply);
+        return err!(AmmError::InsufficientLP);
+    }
+    update_price_accumulators(pool, clock.unix_timestamp)?;
+
+    // Burn LP from user; a frozen account is thawed for the burn only
+    let frozen = user_lp.is_frozen();
+    if frozen {
+        set_lp_frozen(
+            pool,
+            lp_mint,
+            user_lp,
+            legs.token_program,
+            legs.pool_signer,
+            false,
+        )?;
+    }
+    burn_lp_shares(
+        legs.user,
+        user_lp,
+        lp_mint,
+        legs.token_program,
+        shares,
+    )?;
+    if frozen {
+        set_lp_frozen(
+            pool,
+            lp_mint,
+            user_lp,
+            legs.token_program,
+            legs.pool_signer,
+            true,
+        )?;
+    }
+
+    // Compute pro-rata amounts of the recorded reserves
+    let (amount0, amount1) =
+        amounts_for_shares(shares, pool.reserve0, pool.reserve1, pool.total_lp_supply)?;
+
+    // IL versus holding the entry amounts, for the shares this position backs
+    let (entry_price_1e12, (hodl_value_in_token1, lp_value_in_token1, il_bps)) =
+        match position {
+            Some(position) if position.shares > 0 => {
+                let price = spot_price_1e12(pool.reserve0, pool.reserve1)?;
+                let covered = u64::min(shares, position.shares);
+                let pro_rata = |amount: u64, of: u64| {
+                    mul_div(amount as u128, covered as u128, of as u128, Rounding::Down)
+                        .ok_or(AmmError::MathOverflow)
+                };
+                let hodl0 = pro_rata(position.entry_amount0, position.shares)?;
+                let hodl1 = pro_rata(position.entry_amount1, position.shares)?;
+                let out0 = pro_rata(amount0, shares)?;
+                let out1 = pro_rata(amount1, shares)?;
+                let result = impermanent_loss(hodl0, hodl1, out0, out1, price)?;
+                let entry_price_1e12 = position.entry_price_1e12;
+
+                position.shares -= covered;
+                position.entry_amount0 -=
+                    u64::try_from(hodl0).map_err(|_| AmmError::MathOverflow)?;
+                position.entry_amount1 -=
+                    u64::try_from(hodl1).map_err(|_| AmmError::MathOverflow)?;
+                (entry_price_1e12, result)
+            }
+            _ => (0, (0, 0, 0)),
+        };
+
+    // Update pool supply before transfer out
+    pool.total_lp_supply = pool
+        .total_lp_supply
+        .checked_sub(shares)
+        .ok_or(AmmError::MathOverflow)?;
+
+    // Transfer out to user
+    transfer_from_vault(
+        pool,
+        legs.vault0,
+        legs.user_token0,
+        legs.token_program,
+        amount0,
+        legs.pool_signer,
+    )?;
+    transfer_from_vault(
+        pool,
+        legs.vault1,
+        legs.user_token1,
+        legs.token_program,
+        amount1,
+        legs.pool_signer,
+    )?;
+
+    // Update reserves by the withdrawn amounts
+    pool.reserve0 -= amount0;
+    pool.reserve1 -= amount1;
+
+    // Optional EMA update
+    if pool.reserve0 > 0 && pool.reserve1 > 0 {
+        let price = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+        let alpha = pool.ema_alpha_1e12;
+        ema_update(&mut pool.ema_price_1e18, alpha, price);
+        pool.ema_updated_at = clock.unix_timestamp;
+    }
+    update_depth_ema(pool);
+    update_high_low(pool, clock.unix_timestamp);
+    rebase_sqrt_k(pool);
+    reset_if_drained(pool);
+    record_update(pool, clock);
+
+    let event = BurnEvent {
+        version: EVENT_VERSION,
+        pool: pool.key(),
+        seq: next_event_seq(pool),
+        epoch: pool.epoch,
+        sender: legs.user.key(),
+        shares,
+        amount0,
+        amount1,
+        reserve0_after: pool.reserve0,
+        reserve1_after: pool.reserve1,
+        total_lp_supply_after: pool.total_lp_supply,
+        hodl_value_in_token1,
+        lp_value_in_token1,
+        il_bps,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+        entry_price_1e12,
+    };
+    Ok(event)
+}
+
+/// Body of `swap`: token checks and transfers around `apply_swap`.
+/// Returns the event for the caller to emit.
+fn swap_core<'info>(
+    pool: &mut Account<'info, Pool>,
+    legs: &PoolLegs<'_, 'info>,
+    remaining_accounts: &[AccountInfo],
+    token_in_is_0: bool,
+    amount_in: u64,
+    min_amount_out: u64,
+    clock: &Clock,
+) -> Result<SwapEvent> {
+    let (mint_in, mint_out) = if token_in_is_0 {
+        (pool.token0_mint, pool.token1_mint)
+    } else {
+        (pool.token1_mint, pool.token0_mint)
+    };
+    let (user_token_in, user_token_out) = if token_in_is_0 {
+        (legs.user_token0, legs.user_token1)
+    } else {
+        (legs.user_token1, legs.user_token0)
+    };
+    if user_token_in.mint != mint_in || user_token_out.mint != mint_out {
+        msg!(
+            "token accounts: in {} out {}, expected in {} out {}",
+            user_token_in.mint,
+            user_token_out.mint,
+            mint_in,
+            mint_out
+        );
+        return err!(AmmError::WrongTokenAccount);
+    }
+    let pool_key = pool.key();
+    let event = apply_swap(
+        pool,
+        pool_key,
+        legs.user.key(),
+        |pool| oracle_confidence_1e12(pool, remaining_accounts, clock.unix_timestamp),
+        token_in_is_0,
+        amount_in,
+        min_amount_out,
+        clock,
+    )?;
+
+    // Pull token_in from user → vault, send token_out back
+    let (vault_in, vault_out) = if token_in_is_0 {
+        (legs.vault0, legs.vault1)
+    } else {
+        (legs.vault1, legs.vault0)
+    };
+    transfer_into_vault(
+        legs.user,
+        user_token_in,
+        vault_in,
+        legs.token_program,
+        amount_in,
+    )?;
+    transfer_from_vault(
+        pool,
+        vault_out,
+        user_token_out,
+        legs.token_program,
+        event.amount_out,
+        legs.pool_signer,
+    )?;
+    Ok(event)
+}
+
+/// Adaptive fee, breaker, pricing and state update of a swap, without any
+/// token movement: the caller moves `amount_in` into the input vault (or
+/// already holds it there) and pays `amount_out` from the output vault.
+/// `oracle_conf_1e12` reads the oracle signal once the pre-trade checks
+/// pass. Shared with the simulator; returns the event for the caller to emit.
+#[allow(clippy::too_many_arguments)]
+fn apply_swap(
+    pool: &mut Pool,
+    pool_key: Pubkey,
+    trader: Pubkey,
+    oracle_conf_1e12: impl FnOnce(&Pool) -> Result<u128>,
+    token_in_is_0: bool,
+    amount_in: u64,
+    min_amount_out: u64,
+    clock: &Clock,
+) -> Result<SwapEvent> {
+    require!(amount_in > 0, AmmError::ZeroAmount);
+    require!(!pool.paused, AmmError::PoolPaused);
+    check_min_trade(pool, token_in_is_0, amount_in)?;
+    update_price_accumulators(pool, clock.unix_timestamp)?;
+
+    // Price against the recorded reserves, never the raw vault balances:
+    // a donation must not move the fee signals or the curve
+    let r0 = pool.reserve0 as u128;
+    let r1 = pool.reserve1 as u128;
+    require!(r0 > 0 && r1 > 0, AmmError::NoLiquidity);
+
+    // An EMA nobody has refreshed for too long says nothing about current
+    // volatility: restart it from the pre-trade spot
+    if ema_is_stale(pool, clock.unix_timestamp) {
+        let spot = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+        msg!(
+            "ema stale since {}: reset {} -> {}",
+            pool.ema_updated_at,
+            pool.ema_price_1e18,
+            spot
+        );
+        pool.ema_price_1e18 = spot;
+        pool.ema_updated_at = clock.unix_timestamp;
+    }
+
+    // Oracle confidence (0 when no feed is configured or it is stale)
+    let oracle_conf_1e12 = oracle_conf_1e12(pool)?;
+
+    // Compute dynamic fee & components
+    let (fee_ppm, vol_1e12, _slip_1e12, _shallow_1e12) = compute_dynamic_fee(
+        &pool.fee_params(),
+        token_in_is_0,
+        amount_in as u128,
+        r0,
+        r1,
+        oracle_conf_1e12,
+    )?;
+    let fee_ppm = reversion_fee_ppm(pool, fee_ppm, token_in_is_0, amount_in, clock.slot);
+
+    if fee_ppm as u64 >= PPM_DENOM {
+        msg!("fee {} ppm >= cap {}", fee_ppm, PPM_DENOM);
+        return err!(AmmError::FeeTooHigh);
+    }
+
+    // x*y=k pricing with the fee on amountIn, or on the output
+    let (rin, rout) = if token_in_is_0 { (r0, r1) } else { (r1, r0) };
+    check_max_trade(pool, amount_in, rin)?;
+
+    let (amount_out, fee_amount) =
+        swap_amounts(amount_in, rin, rout, fee_ppm, pool.fee_on_output)?;
+    // The fee exactly as pricing withheld it, in the input token or (with
+    // fee_on_output) the output token. Stats, the event and any protocol
+    // share must all read this one value.
+    let fee_token_is_0 = token_in_is_0 != pool.fee_on_output;
+    let (protocol_fee, kept_in, taken_out) =
+        split_swap_fee(pool, amount_in, amount_out, fee_amount)?;
+
+    require!(amount_out > 0, AmmError::AmountOutZero);
+    // never empty the out side: a zero reserve leaves the price undefined
+    if taken_out as u128 >= rout {
+        msg!("amount_out {} >= reserve {}", taken_out, rout);
+        return err!(AmmError::InsufficientOutputReserve);
+    }
+    // Circuit breaker: manual, on the pre-trade deviation, or on the one
+    // this trade would leave behind, so a single huge swap from a calm
+    // state cannot carry the pool past the threshold
+    let post_vol_1e12 = post_trade_deviation_1e12(
+        pool.ema_price_1e18,
+        token_in_is_0,
+        r0,
+        r1,
+        kept_in as u128,
+        taken_out as u128,
+    )?;
+    check_breaker(pool, vol_1e12, post_vol_1e12)?;
+    if amount_out < min_amount_out {
+        msg!("slippage: amount_out {} < min_amount_out {}", amount_out, min_amount_out);
+        return err!(AmmError::SlippageExceeded);
+    }
+    if pool.max_price_impact_bps > 0 {
+        let impact_bps = price_impact_bps(amount_in, amount_out, rin, rout)?;
+        if impact_bps > pool.max_price_impact_bps as u128 {
+            msg!("price impact {} bps > cap {}", impact_bps, pool.max_price_impact_bps);
+            return err!(AmmError::PriceImpactTooHigh);
+        }
+    }
 
-    // For convenience we pass generic "in/out" ATAs bound to the chosen side
-    #[account(mut)]
-    pub user_token_in: Account<'info, TokenAccount>,
-    #[account(mut)]
-    pub user_token_out: Account<'info, TokenAccount>,
+    // Update reserves by the trade deltas and the lifetime stats (volume
+    // attributed to the input token, fees to the token they were taken in)
+    let new_rin =
+        u64::try_from(rin + kept_in as u128).map_err(|_| AmmError::MathOverflow)?;
+    let new_rout =
+        u64::try_from(rout - taken_out as u128).map_err(|_| AmmError::MathOverflow)?;
+    if token_in_is_0 {
+        pool.reserve0 = new_rin;
+        pool.reserve1 = new_rout;
+        pool.cumulative_volume0 = pool
+            .cumulative_volume0
+            .checked_add(amount_in as u128)
+            .ok_or(AmmError::MathOverflow)?;
+    } else {
+        pool.reserve0 = new_rout;
+        pool.reserve1 = new_rin;
+        pool.cumulative_volume1 = pool
+            .cumulative_volume1
+            .checked_add(amount_in as u128)
+            .ok_or(AmmError::MathOverflow)?;
+    }
+    if fee_token_is_0 {
+        pool.protocol_fees_owed0 = pool
+            .protocol_fees_owed0
+            .checked_add(protocol_fee)
+            .ok_or(AmmError::MathOverflow)?;
+        pool.cumulative_fees0 = pool
+            .cumulative_fees0
+            .checked_add(fee_amount as u128)
+            .ok_or(AmmError::MathOverflow)?;
+    } else {
+        pool.protocol_fees_owed1 = pool
+            .protocol_fees_owed1
+            .checked_add(protocol_fee)
+            .ok_or(AmmError::MathOverflow)?;
+        pool.cumulative_fees1 = pool
+            .cumulative_fees1
+            .checked_add(fee_amount as u128)
+            .ok_or(AmmError::MathOverflow)?;
+    }
 
-    /// CHECK: pool signer PDA
-    #[account(seeds=[b"pool"], bump=pool.bump)]
-    pub pool_signer: UncheckedAccount<'info>,
+    // Update EMA
+    let price = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+    let alpha = pool.ema_alpha_1e12;
+    ema_update(&mut pool.ema_price_1e18, alpha, price);
+    pool.ema_updated_at = clock.unix_timestamp;
+    update_depth_ema(pool);
+    update_high_low(pool, clock.unix_timestamp);
+    track_k_growth(pool)?;
+    record_last_trade(pool, token_in_is_0, amount_out, clock.slot);
+    pool.swap_count = pool.swap_count.saturating_add(1);
+    record_update(pool, clock);
 
-    pub token_program: Program<'info, Token>,
+    let event = SwapEvent {
+        version: EVENT_VERSION,
+        pool: pool_key,
+        seq: next_event_seq(pool),
+        epoch: pool.epoch,
+        trader,
+        token_in_is_0,
+        amount_in,
+        amount_out,
+        fee_bps: ppm_to_bps(fee_ppm),
+        fee_ppm,
+        fee_amount,
+        fee_token_is_0,
+        reserve0_after: pool.reserve0,
+        reserve1_after: pool.reserve1,
+        total_lp_supply_after: pool.total_lp_supply,
+        price_0_in_1_1e12: price_to_1e12(price),
+        price_1_in_0_1e12: spot_price_1_in_0(pool.reserve0, pool.reserve1)?,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    };
+    Ok(event)
 }
 
-/* ------------------------------- Helpers -------------------------------- */
-
 fn transfer_into_vault<'info>(
     user: &Signer<'info>,
     user_ata: &Account<'info, TokenAccount>,
@@ -603,7 +5801,7 @@ fn transfer_from_vault<'info>(
     amount: u64,
     pool_signer: &UncheckedAccount<'info>,
 ) -> Result<()> {
-    let seeds = &[b"pool".as_ref(), &[pool.bump]];
+    let seeds = &[POOL_SEED, &[pool.bump]];
     let signer = &[&seeds[..]];
     let cpi_accounts = Transfer {
         from: vault.to_account_info(),
@@ -624,7 +5822,7 @@ fn mint_lp_shares<'info>(
     amount: u64,
     pool_signer: &UncheckedAccount<'info>,
 ) -> Result<()> {
-    let seeds = &[b"pool".as_ref(), &[pool.bump]];
+    let seeds = &[POOL_SEED, &[pool.bump]];
     let signer = &[&seeds[..]];
     let cpi_accounts = MintTo {
         mint: lp_mint.to_account_info(),
@@ -637,6 +5835,83 @@ fn mint_lp_shares<'info>(
     )
 }
 
+/// Close an empty vault ATA with the pool signer, refunding its rent.
+fn close_vault<'info>(
+    pool: &Account<'info, Pool>,
+    vault: &Account<'info, TokenAccount>,
+    destination: &SystemAccount<'info>,
+    token_program: &Program<'info, Token>,
+    pool_signer: &UncheckedAccount<'info>,
+) -> Result<()> {
+    let seeds = &[POOL_SEED, &[pool.bump]];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = CloseAccount {
+        account: vault.to_account_info(),
+        destination: destination.to_account_info(),
+        authority: pool_signer.to_account_info(),
+    };
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        cpi_accounts,
+        signer,
+    ))
+}
+
+/// Set the LP mint freeze authority (held by the pool PDA) to None.
+fn revoke_lp_freeze<'info>(
+    pool: &Account<'info, Pool>,
+    lp_mint: &Account<'info, Mint>,
+    token_program: &Program<'info, Token>,
+) -> Result<()> {
+    let seeds = &[POOL_SEED, &[pool.bump]];
+    let signer = &[&seeds[..]];
+    let cpi_accounts = SetAuthority {
+        current_authority: pool.to_account_info(),
+        account_or_mint: lp_mint.to_account_info(),
+    };
+    token::set_authority(
+        CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer),
+        AuthorityType::FreezeAccount,
+        None,
+    )
+}
+
+/// Freeze or thaw an LP token account with the pool PDA's freeze authority.
+fn set_lp_frozen<'info>(
+    pool: &Account<'info, Pool>,
+    lp_mint: &Account<'info, Mint>,
+    lp_account: &Account<'info, TokenAccount>,
+    token_program: &Program<'info, Token>,
+    pool_signer: &UncheckedAccount<'info>,
+    frozen: bool,
+) -> Result<()> {
+    let seeds = &[POOL_SEED, &[pool.bump]];
+    let signer = &[&seeds[..]];
+    if frozen {
+        let cpi_accounts = FreezeAccount {
+            account: lp_account.to_account_info(),
+            mint: lp_mint.to_account_info(),
+            authority: pool_signer.to_account_info(),
+        };
+        token::freeze_account(CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        ))
+    } else {
+        let cpi_accounts = ThawAccount {
+            account: lp_account.to_account_info(),
+            mint: lp_mint.to_account_info(),
+            authority: pool_signer.to_account_info(),
+        };
+        token::thaw_account(CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        ))
+    }
+}
+
 fn burn_lp_shares<'info>(
     user: &Signer<'info>,
     user_lp: &Account<'info, TokenAccount>,
@@ -652,123 +5927,944 @@ fn burn_lp_shares<'info>(
     token::burn(CpiContext::new(token_program.to_account_info(), cpi_accounts), amount)
 }
 
+/// A flash loan must close in the same transaction: find a later top-level
+/// `flash_repay` of this program for the same token and amount. A borrow
+/// reached by CPI is judged by the instructions after the enclosing one.
+fn require_flash_repay(
+    instructions: &UncheckedAccount,
+    token_is_0: bool,
+    amount: u64,
+) -> Result<()> {
+    let sysvar = instructions.to_account_info();
+    let mut index = load_current_index_checked(&sysvar)? as usize + 1;
+    while let Ok(ix) = load_instruction_at_checked(index, &sysvar) {
+        let discriminator = <instruction::FlashRepay as anchor_lang::Discriminator>::DISCRIMINATOR;
+        if ix.program_id == crate::ID && ix.data.starts_with(&discriminator) {
+            if let Ok(repay) = instruction::FlashRepay::try_from_slice(&ix.data[8..]) {
+                if repay.token_is_0 == token_is_0 && repay.amount == amount {
+                    return Ok(());
+                }
+            }
+        }
+        index += 1;
+    }
+    msg!("no flash_repay of {} (token0: {}) follows", amount, token_is_0);
+    err!(AmmError::FlashRepayMissing)
+}
+
 /// Spot price token0 in token1 (scaled by 1e12).
 fn spot_price_1e12(reserve0: u64, reserve1: u64) -> Result<u64> {
-    require!(reserve0 > 0 && reserve1 > 0, AmmError::NoLiquidity);
-    let p = (reserve1 as u128)
-        .checked_mul(SCALE)
-        .ok_or(AmmError::MathOverflow)?
-        / (reserve0 as u128);
-    Ok(p as u64)
+    Ok(spot_price_0_in_1(reserve0, reserve1)?)
 }
 
-/// Simple integer sqrt (Babylonian)
-fn isqrt(y: u128) -> u128 {
-    if y == 0 {
-        return 0;
+/// The Config, if one has been created at `info` (already seed-checked).
+fn load_config(info: &AccountInfo) -> Result<Option<Config>> {
+    if info.data_is_empty() {
+        return Ok(None);
+    }
+    require_keys_eq!(*info.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+    Ok(Some(Config::try_deserialize(&mut &info.try_borrow_data()?[..])?))
+}
+
+/// Reject mints whose issuer can freeze the pool's vaults. Close-authority
+/// and permanent-delegate risks are Token-2022 extensions, which a classic
+/// `Account<Mint>` cannot carry.
+fn check_mint_risk(mint: &Account<Mint>) -> Result<()> {
+    if let Some(freeze_authority) = Option::<Pubkey>::from(mint.freeze_authority) {
+        msg!("mint {} has freeze authority {}", mint.key(), freeze_authority);
+        return err!(AmmError::RiskyMint);
+    }
+    Ok(())
+}
+
+/// Initial dust threshold: a millionth of a whole token, at least 1 unit.
+fn default_min_trade_amount(decimals: u8) -> u64 {
+    10u64.pow(decimals.saturating_sub(6) as u32)
+}
+
+/// Bootstrap is priced from the first deposit alone. Recorded reserves
+/// with no LPs behind them (e.g. a one-sided `sync` on an empty pool) are
+/// taken off the books: the tokens stay in the vault as a donation that
+/// `skim`/`sync` handle once the pool has LPs, so they can neither skew
+/// the opening price nor be credited to the first depositor.
+pub(crate) fn drop_orphaned_reserves(pool: &mut Pool) {
+    if pool.total_lp_supply == 0 && (pool.reserve0 > 0 || pool.reserve1 > 0) {
+        msg!("dropping orphaned reserves {}:{}", pool.reserve0, pool.reserve1);
+        pool.reserve0 = 0;
+        pool.reserve1 = 0;
+    }
+}
+
+/// Fold the current pool value in token0 (2 * reserve0) into ema_depth0
+/// after a reserve change. The first reading seeds it, and alpha 0 makes
+/// it track the reserves exactly.
+pub(crate) fn update_depth_ema(pool: &mut Pool) {
+    let depth0 = 2 * pool.reserve0 as u128;
+    if pool.ema_depth0 == 0 || pool.depth_ema_alpha_1e12 == 0 {
+        pool.ema_depth0 = depth0;
+    } else {
+        ema_update(&mut pool.ema_depth0, pool.depth_ema_alpha_1e12, depth0);
+    }
+}
+
+/// Fold the post-change spot into the daily high/low. The window is a
+/// fixed HL_WINDOW_SECONDS from its first price; the first update after it
+/// expires, however long the pool sat idle, restarts it at the current
+/// price. Empty reserves have no price and leave it untouched.
+pub(crate) fn update_high_low(pool: &mut Pool, now: i64) {
+    if pool.reserve0 == 0 || pool.reserve1 == 0 {
+        return;
+    }
+    let Ok(price) = spot_price_0_in_1(pool.reserve0, pool.reserve1) else {
+        return;
+    };
+    if pool.hl_window_start_ts == 0
+        || now.saturating_sub(pool.hl_window_start_ts) >= HL_WINDOW_SECONDS
+    {
+        pool.hl_window_start_ts = now;
+        pool.price_high_1e12 = price;
+        pool.price_low_1e12 = price;
+    } else {
+        pool.price_high_1e12 = pool.price_high_1e12.max(price);
+        pool.price_low_1e12 = pool.price_low_1e12.min(price);
+    }
+}
+
+/// Count the change of sqrt(k) since the last reserve change as LP yield
+/// (swaps, donations) in growth_1e12.
+pub(crate) fn track_k_growth(pool: &mut Pool) -> Result<()> {
+    let sqrt_k_now = sqrt_k(pool.reserve0, pool.reserve1);
+    pool.growth_1e12 = accrue_k_growth(pool.growth_1e12, pool.sqrt_k_last, sqrt_k_now)?;
+    pool.sqrt_k_last = sqrt_k_now;
+    Ok(())
+}
+
+/// With `enforce_denylist` on, refuse the instruction if any of `addresses`
+/// (the signer, and the owners of the token accounts paying in or out) has
+/// a Denylisted marker. Each distinct address's marker PDA must be among
+/// the remaining accounts whether or not it was ever created; only an
+/// account owned by this program with data counts as a listing, so lamports
+/// sent to the bare address change nothing. A `removal` passes regardless
+/// when `denylist_allows_removal` is set.
+pub(crate) fn check_denylist(
+    pool: &Account<Pool>,
+    addresses: &[Pubkey],
+    remaining_accounts: &[AccountInfo],
+    removal: bool,
+) -> Result<()> {
+    if !pool.enforce_denylist || (removal && pool.denylist_allows_removal) {
+        return Ok(());
     }
-    let mut z = y;
-    let mut x = y / 2 + 1;
-    while x < z {
-        z = x;
-        x = (y / x + x) / 2;
-    }
-    z
-}
-
-/// EMA <- EMA + alpha * (price - EMA), all scaled by 1e12.
-fn ema_update(ema: &mut u64, alpha_1e12: u64, price_1e12: u64) {
-    let ema_u = *ema as u128;
-    let price_u = price_1e12 as u128;
-    if price_u >= ema_u {
-        let diff = price_u - ema_u;
-        let delta = diff
-            .saturating_mul(alpha_1e12 as u128)
-            / SCALE;
-        *ema = ema_u.saturating_add(delta) as u64;
+    let pool_key = pool.key();
+    for (i, address) in addresses.iter().enumerate() {
+        if addresses[..i].contains(address) {
+            continue;
+        }
+        let (marker, _) = Pubkey::find_program_address(
+            &[DENYLIST_SEED, pool_key.as_ref(), address.as_ref()],
+            &crate::ID,
+        );
+        let info = remaining_accounts
+            .iter()
+            .find(|info| info.key() == marker)
+            .ok_or(AmmError::DenylistAccountMissing)?;
+        if info.owner == &crate::ID && !info.data_is_empty() {
+            msg!("address {} is denylisted", address);
+            return err!(AmmError::AddressDenylisted);
+        }
+    }
+    Ok(())
+}
+
+/// Close one of the pool's records passed to `close_pool`: a Denylisted
+/// marker, LaunchBuys, Position or TwapCheckpoint of this pool. A LaunchBuys
+/// or Position was paid for by its wallet, which must be the next account
+/// in `rest` (writable) and gets the rent back; the other records' rent
+/// goes to `recipient`.
+fn close_pool_record<'info>(
+    pool_key: &Pubkey,
+    record: &AccountInfo<'info>,
+    rest: &mut std::slice::Iter<'_, AccountInfo<'info>>,
+    recipient: &AccountInfo<'info>,
+) -> Result<()> {
+    require_keys_eq!(*record.owner, crate::ID, AmmError::NotPoolRecord);
+    let is_pda = |seeds: &[&[u8]]| {
+        Pubkey::create_program_address(seeds, &crate::ID).is_ok_and(|address| address == record.key())
+    };
+    let data = record.try_borrow_data()?;
+    let disc = data.get(..8).ok_or(AmmError::NotPoolRecord)?;
+    let mut bytes: &[u8] = &data;
+    let (belongs, wallet) = if disc == Denylisted::DISCRIMINATOR {
+        let marker = Denylisted::try_deserialize(&mut bytes)?;
+        let seeds: &[&[u8]] = &[DENYLIST_SEED, pool_key.as_ref(), marker.address.as_ref(), &[marker.bump]];
+        (is_pda(seeds), None)
+    } else if disc == LaunchBuys::DISCRIMINATOR {
+        let buys = LaunchBuys::try_deserialize(&mut bytes)?;
+        let seeds: &[&[u8]] = &[LAUNCH_BUYS_SEED, pool_key.as_ref(), buys.wallet.as_ref(), &[buys.bump]];
+        (is_pda(seeds), Some(buys.wallet))
+    } else if disc == Position::DISCRIMINATOR {
+        let position = Position::try_deserialize(&mut bytes)?;
+        let seeds: &[&[u8]] = &[POSITION_SEED, pool_key.as_ref(), position.owner.as_ref(), &[position.bump]];
+        (position.pool == *pool_key && is_pda(seeds), Some(position.owner))
+    } else if disc == TwapCheckpoint::DISCRIMINATOR {
+        let checkpoint = TwapCheckpoint::try_deserialize(&mut bytes)?;
+        (checkpoint.pool == *pool_key, None)
     } else {
-        let diff = ema_u - price_u;
-        let delta = diff
-            .saturating_mul(alpha_1e12 as u128)
-            / SCALE;
-        *ema = ema_u.saturating_sub(delta) as u64;
+        (false, None)
+    };
+    drop(data);
+    if !belongs {
+        msg!("{} is not a record of this pool", record.key());
+        return err!(AmmError::NotPoolRecord);
+    }
+    let destination = match wallet {
+        Some(wallet) => {
+            let owner = rest.next().ok_or(AmmError::NotPoolRecord)?;
+            require_keys_eq!(owner.key(), wallet, AmmError::NotPoolRecord);
+            owner
+        }
+        None => recipient,
+    };
+    let refunded = destination
+        .lamports()
+        .checked_add(record.lamports())
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    **destination.try_borrow_mut_lamports()? = refunded;
+    **record.try_borrow_mut_lamports()? = 0;
+    record.assign(&system_program::ID);
+    record.realloc(0, false)?;
+    Ok(())
+}
+
+/// During the launch window, add a purchase of the launched token to the
+/// signer's LaunchBuys and refuse it beyond `max_buy_per_wallet`; sells and
+/// trades from `launch_end_slot` on pass untouched. The LaunchBuys PDA must
+/// be among the remaining accounts. On the wallet's first buy it is created
+/// here at the wallet's expense, which needs `system_program`; without it
+/// the record must already exist.
+pub(crate) fn track_launch_buy<'info>(
+    pool: &Account<'info, Pool>,
+    wallet: &Signer<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    system_program: Option<&Program<'info, System>>,
+    event: &SwapEvent,
+) -> Result<()> {
+    let buys_launched = event.token_in_is_0 != pool.launch_token_is_0;
+    if pool.max_buy_per_wallet == 0 || event.slot >= pool.launch_end_slot || !buys_launched {
+        return Ok(());
+    }
+    let pool_key = pool.key();
+    let wallet_key = wallet.key();
+    let (address, bump) = Pubkey::find_program_address(
+        &[LAUNCH_BUYS_SEED, pool_key.as_ref(), wallet_key.as_ref()],
+        &crate::ID,
+    );
+    let info = remaining_accounts
+        .iter()
+        .find(|info| info.key() == address)
+        .ok_or(AmmError::LaunchAccountMissing)?;
+    require!(info.is_writable, AmmError::LaunchAccountMissing);
+
+    if info.owner != &crate::ID {
+        let system_program = system_program.ok_or(AmmError::LaunchAccountMissing)?;
+        let program = system_program.to_account_info();
+        let signer_seeds: &[&[u8]] =
+            &[LAUNCH_BUYS_SEED, pool_key.as_ref(), wallet_key.as_ref(), &[bump]];
+        // transfer + allocate + assign, so lamports sent to the address
+        // beforehand cannot block the creation
+        let top_up = Rent::get()?
+            .minimum_balance(LaunchBuys::SPACE)
+            .saturating_sub(info.lamports());
+        if top_up > 0 {
+            system_program::transfer(
+                CpiContext::new(
+                    program.clone(),
+                    system_program::Transfer { from: wallet.to_account_info(), to: info.clone() },
+                ),
+                top_up,
+            )?;
+        }
+        system_program::allocate(
+            CpiContext::new_with_signer(
+                program.clone(),
+                system_program::Allocate { account_to_allocate: info.clone() },
+                &[signer_seeds],
+            ),
+            LaunchBuys::SPACE as u64,
+        )?;
+        system_program::assign(
+            CpiContext::new_with_signer(
+                program,
+                system_program::Assign { account_to_assign: info.clone() },
+                &[signer_seeds],
+            ),
+            &crate::ID,
+        )?;
+        let record = LaunchBuys { wallet: wallet_key, bought: 0, bump };
+        record.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    }
+
+    let mut record = LaunchBuys::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    record.bought = record.bought.checked_add(event.amount_out).ok_or(AmmError::MathOverflow)?;
+    if record.bought > pool.max_buy_per_wallet {
+        msg!(
+            "launch cap: {} bought > max_buy_per_wallet {}",
+            record.bought,
+            pool.max_buy_per_wallet
+        );
+        return err!(AmmError::LaunchBuyCapExceeded);
+    }
+    record.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+/// With `only_via_router` set, accept a swap only when it runs inside a CPI
+/// whose top-level instruction belongs to an allowlisted router. The
+/// outermost program is the one judged, so a router must be invoked by the
+/// transaction itself rather than nested under another program.
+pub(crate) fn check_router(pool: &Pool, instructions: Option<&UncheckedAccount>) -> Result<()> {
+    if !pool.only_via_router {
+        return Ok(());
     }
+    require!(get_stack_height() > TRANSACTION_LEVEL_STACK_HEIGHT, AmmError::RouterRequired);
+    let instructions = instructions.ok_or(AmmError::RouterRequired)?;
+    let caller = get_instruction_relative(0, &instructions.to_account_info())?.program_id;
+    if caller == Pubkey::default() || !pool.routers.contains(&caller) {
+        msg!("caller program {} is not an allowed router", caller);
+        return err!(AmmError::RouterNotAllowed);
+    }
+    Ok(())
 }
 
-/// Compute dynamic fee and its components (vol/slip/shallow).
-/// Returns (fee_bps, vol_1e12, slip_1e12, shallow_1e12).
-fn compute_dynamic_fee(
+/// `fee_ppm` plus the round-trip penalty this swap owes, if any.
+pub(crate) fn reversion_fee_ppm(
     pool: &Pool,
+    fee_ppm: u32,
     token_in_is_0: bool,
-    amount_in: u128,
-    r0: u128,
-    r1: u128,
-) -> Result<(u16, u128, u128, u128)> {
-    require!(amount_in > 0, AmmError::ZeroAmount);
+    amount_in: u64,
+    slot: u64,
+) -> u32 {
+    let penalty_bps = reversion_penalty_bps(
+        &pool.reversion_params(),
+        &pool.recent_trades(),
+        token_in_is_0,
+        amount_in,
+        slot,
+    );
+    if penalty_bps == 0 {
+        return fee_ppm;
+    }
+    let total = with_reversion_penalty(fee_ppm, penalty_bps);
+    msg!("round trip: fee {} -> {} ppm", fee_ppm, total);
+    total
+}
 
-    let (rin, _rout) = if token_in_is_0 { (r0, r1) } else { (r1, r0) };
-
-    // --- volatility proxy: |price - ema| / ema ---
-    let price_now = (r1)
-        .checked_mul(SCALE)
-        .ok_or(AmmError::MathOverflow)?
-        / r0;
-    let ema = pool.ema_price_1e12 as u128;
-    let vol_1e12 = if ema == 0 {
-        0
-    } else if price_now >= ema {
-        (price_now - ema)
-            .checked_mul(SCALE)
-            .ok_or(AmmError::MathOverflow)?
-            / ema
+/// Remember this swap for the reversion checks of the next ones.
+pub(crate) fn record_last_trade(pool: &mut Pool, token_in_is_0: bool, amount_out: u64, slot: u64) {
+    pool.last_trade_token_in_is_0 = token_in_is_0;
+    pool.last_trade_amount_out = amount_out;
+    pool.last_trade_slot = slot;
+    let recent = if token_in_is_0 {
+        &mut pool.recent_trades_in0
     } else {
-        (ema - price_now)
-            .checked_mul(SCALE)
-            .ok_or(AmmError::MathOverflow)?
-            / ema
+        &mut pool.recent_trades_in1
     };
+    recent.rotate_right(1);
+    recent[0] = RecentTrade { amount_out, slot };
+}
+
+/// Restart the growth tracking from the current sqrt(k) without counting
+/// the change: liquidity adds and removes are not yield.
+pub(crate) fn rebase_sqrt_k(pool: &mut Pool) {
+    pool.sqrt_k_last = sqrt_k(pool.reserve0, pool.reserve1);
+}
+
+/// After the last LP exits, forget the old price so the next bootstrap
+/// deposit seeds a fresh EMA, and start a new epoch. Lifetime stats and
+/// the TWAP accumulators carry on; indexers split them by epoch.
+pub(crate) fn reset_if_drained(pool: &mut Pool) {
+    if pool.total_lp_supply == 0 {
+        pool.ema_price_1e18 = 0;
+        pool.ema_updated_at = 0;
+        pool.ema_depth0 = 0;
+        pool.epoch = pool.epoch.wrapping_add(1);
+    }
+}
+
+/// Reject swap inputs below the pool's minimum for that token.
+pub(crate) fn check_min_trade(pool: &Pool, token_in_is_0: bool, amount_in: u64) -> Result<()> {
+    let min = if token_in_is_0 { pool.min_trade_amount0 } else { pool.min_trade_amount1 };
+    if amount_in < min {
+        msg!("amount_in {} < min trade {}", amount_in, min);
+        return err!(AmmError::TradeTooSmall);
+    }
+    Ok(())
+}
+
+/// Zero-pad `value` into a fixed metadata field. NUL bytes are rejected
+/// because the padding marks the end of the text.
+fn pack_metadata<const N: usize>(value: &str) -> Result<[u8; N]> {
+    let bytes = value.as_bytes();
+    if bytes.len() > N {
+        msg!("metadata is {} bytes, max {}", bytes.len(), N);
+        return err!(AmmError::MetadataTooLong);
+    }
+    require!(!bytes.contains(&0), AmmError::InvalidMetadata);
+    let mut field = [0u8; N];
+    field[..bytes.len()].copy_from_slice(bytes);
+    Ok(field)
+}
+
+/// Text of a zero-padded metadata field; "" if it is not valid UTF-8.
+pub fn unpack_metadata(field: &[u8]) -> String {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    std::str::from_utf8(&field[..len]).unwrap_or_default().to_string()
+}
+
+/// Protocol share of a swap fee and the reserve deltas the swap leaves:
+/// (protocol_fee, kept_in, taken_out). The LP part of the fee stays in the
+/// reserves; the protocol part is owed, outside, on whichever side the fee
+/// was charged.
+pub(crate) fn split_swap_fee(
+    pool: &Pool,
+    amount_in: u64,
+    amount_out: u64,
+    fee_amount: u64,
+) -> Result<(u64, u64, u64)> {
+    let protocol_fee = mul_div(
+        fee_amount as u128,
+        pool.protocol_fee_share_bps as u128,
+        BPS_DENOM as u128,
+        Rounding::Down,
+    )
+    .ok_or(AmmError::MathOverflow)? as u64;
+    if pool.fee_on_output {
+        let taken_out = amount_out.checked_add(protocol_fee).ok_or(AmmError::MathOverflow)?;
+        Ok((protocol_fee, amount_in, taken_out))
+    } else {
+        Ok((protocol_fee, amount_in - protocol_fee, amount_out))
+    }
+}
+
+/// Fail a trade the breaker blocks; see `math::breaker_verdict`.
+pub(crate) fn check_breaker(pool: &Pool, pre_vol_1e12: u128, post_vol_1e12: u128) -> Result<()> {
+    let threshold = pool.breaker_vol_threshold_1e12;
+    let enabled = pool.breaker_threshold_1e12();
+    match breaker_verdict(pool.manually_tripped, enabled, pre_vol_1e12, post_vol_1e12) {
+        BreakerVerdict::Open => Ok(()),
+        BreakerVerdict::ManualTrip => {
+            msg!("breaker tripped manually");
+            err!(AmmError::BreakerTripped)
+        }
+        BreakerVerdict::PreTradeDeviation => {
+            msg!("vol {} > threshold {}", pre_vol_1e12, threshold);
+            err!(AmmError::VolTooHigh)
+        }
+        BreakerVerdict::PostTradeDeviation => {
+            msg!("post-trade vol {} > threshold {}", post_vol_1e12, threshold);
+            err!(AmmError::TradeWouldTripBreaker)
+        }
+    }
+}
+
+/// Reject swap inputs above max_trade_bps_of_reserve of the in-side
+/// reserve; an input exactly at the cap passes.
+pub(crate) fn check_max_trade(pool: &Pool, amount_in: u64, rin: u128) -> Result<()> {
+    if pool.max_trade_bps_of_reserve == 0 {
+        return Ok(());
+    }
+    let cap = mul_div(
+        rin,
+        pool.max_trade_bps_of_reserve as u128,
+        BPS_DENOM as u128,
+        Rounding::Down,
+    )
+    .ok_or(AmmError::MathOverflow)?;
+    if amount_in as u128 > cap {
+        msg!("amount_in {} > max trade {}", amount_in, cap);
+        return err!(AmmError::TradeTooLarge);
+    }
+    Ok(())
+}
+
+/// Shared validator for the fee curve and breaker settings, run on the
+/// pool as written by `initialize_pool`, `set_params` and `set_oracle`
+/// (the instruction reverts, so nothing invalid is ever stored):
+/// - min_fee_ppm <= max_fee_ppm, and a TargetDepth pool has a target
+///   (BadBounds)
+/// - max_fee_ppm leaves something to trade: a 100% fee is rejected
+///   (FeeAboveDenominator)
+/// - both EMA alphas are at most 1.0 (AlphaOutOfRange)
+/// - an enabled breaker has a non-zero threshold (ThresholdZero)
+///
+/// No coefficient is bounded: `math::fee_from_signals` sums the terms in
+/// u128 at 1e12 and clamps the result to max_fee_ppm, which no u16
+/// coefficient can overflow. CoefficientTooLarge is therefore never
+/// raised.
+fn validate_params(pool: &Pool) -> Result<()> {
+    let (min_fee_ppm, max_fee_ppm) = pool.fee_bounds_ppm();
+    require!(min_fee_ppm <= max_fee_ppm, AmmError::BadBounds);
+    require!(
+        pool.shallow_mode != ShallowMode::TargetDepth || pool.target_depth0 > 0,
+        AmmError::BadBounds
+    );
+    if max_fee_ppm as u64 >= PPM_DENOM {
+        msg!("max fee {} ppm >= cap {}", max_fee_ppm, PPM_DENOM);
+        return err!(AmmError::FeeAboveDenominator);
+    }
+    for alpha in [pool.ema_alpha_1e12, pool.depth_ema_alpha_1e12] {
+        if alpha as u128 > SCALE {
+            msg!("alpha {} > {}", alpha, SCALE);
+            return err!(AmmError::AlphaOutOfRange);
+        }
+    }
+    require!(
+        !pool.breaker_enabled || pool.breaker_vol_threshold_1e12 > 0,
+        AmmError::ThresholdZero
+    );
+    Ok(())
+}
+
+/// Shortfall of the execution price against the pre-trade spot, in bps
+/// (fee included): 1 - (out / in) / (rout / rin).
+fn price_impact_bps(amount_in: u64, amount_out: u64, rin: u128, rout: u128) -> Result<u128> {
+    let spot_out = (amount_in as u128)
+        .checked_mul(rout)
+        .ok_or(AmmError::MathOverflow)?;
+    let exec_bps = mul_div(
+        (amount_out as u128) * (BPS_DENOM as u128),
+        rin,
+        spot_out,
+        Rounding::Up,
+    )
+    .ok_or(AmmError::MathOverflow)?;
+    Ok((BPS_DENOM as u128).saturating_sub(exec_bps))
+}
 
-    // --- slippage proxy: amountIn / (rin + amountIn) ---
-    let slip_1e12 = amount_in
-        .checked_mul(SCALE)
-        .ok_or(AmmError::MathOverflow)?
-        / (rin
-        .checked_add(amount_in)
-        .ok_or(AmmError::MathOverflow)?);
-
-    // --- shallow-depth proxy: 1 - minRes / (minRes + K) ---
-    let min_res = u128::min(r0, r1);
-    let k: u128 = 1_000 * 1_000_000; // scale-less depth factor ~1e9, OK for demo
-    let shallow_1e12 = SCALE
-        - (min_res
-        .checked_mul(SCALE)
-        .ok_or(AmmError::MathOverflow)?
-        / (min_res.saturating_add(k)));
-
-    // Linear combo (bps) + clamp
-    let dyn_part_bps = (pool.beta_vol_bps_per1e12 as u128)
-        .checked_mul(vol_1e12)
-        .ok_or(AmmError::MathOverflow)?
-        / SCALE
-        + (pool.gamma_slip_bps_per1e12 as u128)
-        .checked_mul(slip_1e12)
-        .ok_or(AmmError::MathOverflow)?
-        / SCALE
-        + (pool.delta_shallow_bps_per1e12 as u128)
-        .checked_mul(shallow_1e12)
-        .ok_or(AmmError::MathOverflow)?
-        / SCALE;
-
-    let mut raw_bps = (pool.min_fee_bps as u128)
-        .checked_add(dyn_part_bps)
+/// Bring the reward accumulator up to `now`. Emission only runs inside
+/// [reward_start_ts, reward_end_ts], so the elapsed window is clipped to
+/// it: time before the start or after the end adds nothing, however late
+/// the next update comes. Emission while nothing is staked stays in the
+/// reward vault.
+fn accrue_rewards(pool: &mut Pool, now: i64) -> Result<()> {
+    let from = pool.reward_updated_at.max(pool.reward_start_ts);
+    let to = now.min(pool.reward_end_ts);
+    if to > from && pool.reward_staked > 0 {
+        let emitted_1e18 = pool
+            .reward_rate_1e18
+            .checked_mul((to - from) as u128)
+            .ok_or(AmmError::MathOverflow)?;
+        pool.reward_per_share_1e18 = pool
+            .reward_per_share_1e18
+            .checked_add(emitted_1e18 / pool.reward_staked as u128)
+            .ok_or(AmmError::MathOverflow)?;
+    }
+    pool.reward_updated_at = pool.reward_updated_at.max(now);
+    Ok(())
+}
+
+/// Credit `position` with its stake's rewards since its last checkpoint.
+/// Call after `accrue_rewards` and before the stake changes.
+fn settle_rewards(pool: &Pool, position: &mut Position) -> Result<()> {
+    let delta = pool.reward_per_share_1e18 - position.reward_per_share_paid_1e18;
+    let earned = mul_div(position.staked as u128, delta, PRICE_SCALE, Rounding::Down)
+        .and_then(|e| u64::try_from(e).ok())
         .ok_or(AmmError::MathOverflow)?;
-    if raw_bps < pool.min_fee_bps as u128 {
-        raw_bps = pool.min_fee_bps as u128;
+    position.rewards_owed =
+        position.rewards_owed.checked_add(earned).ok_or(AmmError::MathOverflow)?;
+    position.reward_per_share_paid_1e18 = pool.reward_per_share_1e18;
+    Ok(())
+}
+
+/// Accrue the pre-instruction spot price into the TWAP accumulators.
+/// Must run before reserves change so each interval is weighted by the
+/// price that actually prevailed during it.
+fn update_price_accumulators(pool: &mut Pool, now: i64) -> Result<()> {
+    let elapsed = now.saturating_sub(pool.cumulative_last_timestamp);
+    if elapsed > 0 && pool.reserve0 > 0 && pool.reserve1 > 0 {
+        // the accumulators wrap by design; see `accumulator`
+        // at internal precision, so extreme reserve ratios neither floor
+        // to 0 nor saturate the sum; the TWAPs convert back to 1e12
+        let price = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+        pool.price_cumulative_1e18 =
+            accumulator::accumulate(pool.price_cumulative_1e18, price, elapsed as u64);
+
+        if pool.geometric_twap_enabled {
+            // log2 is undefined at 0; a sub-grid price is floored to 1
+            let log_price = log2_q32(u128::max(price, 1));
+            pool.log2_price_cumulative =
+                accumulator::accumulate(pool.log2_price_cumulative, log_price, elapsed as u64);
+        }
+    }
+    pool.cumulative_last_timestamp = now;
+    Ok(())
+}
+
+/// Stamp the pool with the slot/time of the current mutation.
+fn record_update(pool: &mut Pool, clock: &Clock) {
+    pool.last_update_slot = clock.slot;
+    pool.last_update_timestamp = clock.unix_timestamp;
+}
+
+/// Shared body of `trip_breaker` / `reset_breaker`.
+fn set_manual_breaker(ctx: Context<GuardianAction>, tripped: bool) -> Result<()> {
+    let pool = &mut ctx.accounts.pool;
+    let signer = ctx.accounts.signer.key();
+    let role = authorize(pool, signer, Role::Guardian)?;
+
+    pool.manually_tripped = tripped;
+    let clock = Clock::get()?;
+    record_update(pool, &clock);
+
+    let action = if tripped { AdminAction::TripBreaker } else { AdminAction::ResetBreaker };
+    let event = admin_event(pool, signer, role, action, &clock);
+    append_admin_log(&ctx.accounts.pool, ctx.accounts.admin_log.as_ref(), &event)?;
+    emit_event!(ctx, event);
+    Ok(())
+}
+
+/// The primary authority or, when one is set, the co-authority.
+fn is_admin(pool: &Pool, signer: Pubkey) -> bool {
+    signer == pool.authority
+        || (pool.co_authority != Pubkey::default() && signer == pool.co_authority)
+}
+
+/// Role `signer` acts in. Both admin keys hold every role; anyone else
+/// must be the (non-default) key assigned to `role`.
+fn authorize(pool: &Pool, signer: Pubkey, role: Role) -> Result<Role> {
+    if signer == pool.authority {
+        return Ok(Role::Authority);
+    }
+    if is_admin(pool, signer) {
+        return Ok(Role::CoAuthority);
+    }
+    let assigned = match role {
+        Role::Authority => pool.authority,
+        Role::FeeManager => pool.fee_manager,
+        Role::Pauser => pool.pauser,
+        Role::Treasurer => pool.treasurer,
+        Role::Guardian => pool.guardian,
+        Role::Treasury => pool.treasury,
+        Role::CoAuthority => pool.co_authority,
+        Role::Compliance => pool.compliance,
+    };
+    require!(
+        assigned != Pubkey::default() && signer == assigned,
+        AmmError::NotAuthorized
+    );
+    Ok(role)
+}
+
+/// Record an admin action in the pool's `AdminLog`, if one was passed.
+fn append_admin_log(
+    pool: &Pool,
+    log: Option<&AccountLoader<AdminLog>>,
+    event: &AdminActionEvent,
+) -> Result<()> {
+    match log {
+        Some(log) => log.load_mut()?.push(AdminLogEntry::new(event)?),
+        None => require!(!pool.admin_log_enabled, AmmError::AdminLogMissing),
+    }
+    Ok(())
+}
+
+/// Build the event for an admin action, consuming one sequence number.
+fn admin_event(
+    pool: &mut Account<Pool>,
+    actor: Pubkey,
+    role: Role,
+    action: AdminAction,
+    clock: &Clock,
+) -> AdminActionEvent {
+    AdminActionEvent {
+        version: EVENT_VERSION,
+        pool: pool.key(),
+        seq: next_event_seq(pool),
+        actor,
+        role,
+        action,
+        slot: clock.slot,
+        timestamp: clock.unix_timestamp,
+    }
+}
+
+/// Next per-pool event sequence number. Call exactly once per emitted
+/// event so indexers can detect gaps; wraps at u64::MAX.
+fn next_event_seq(pool: &mut Pool) -> u64 {
+    pool.event_seq = pool.event_seq.wrapping_add(1);
+    pool.event_seq
+}
+
+/// Accumulator values extrapolated to `now` without mutating the pool.
+fn current_cumulatives(pool: &Pool, now: i64) -> Result<(u128, u128)> {
+    let mut pool = pool.clone();
+    update_price_accumulators(&mut pool, now)?;
+    Ok((pool.price_cumulative_1e18, pool.log2_price_cumulative))
+}
+
+/// Arithmetic-mean TWAP (1e12, saturating like `price_to_1e12`) between
+/// two `price_cumulative_1e18` readings. The readings may straddle a wrap
+/// of the accumulator.
+pub fn compute_twap(cumulative_start: u128, cumulative_end: u128, elapsed: u64) -> Result<u64> {
+    require!(elapsed > 0, AmmError::ZeroAmount);
+    let diff = accumulator::delta(cumulative_start, cumulative_end);
+    Ok(price_to_1e12(diff / elapsed as u128))
+}
+
+/// Geometric-mean TWAP (1e12) between two `log2_price_cumulative` readings.
+/// Unlike the arithmetic mean this is consistent across quote directions:
+/// TWAP(1/p) == 1/TWAP(p) up to fixed-point rounding. Wrap-aware and
+/// saturating like `compute_twap`.
+pub fn compute_geometric_twap(
+    log_cumulative_start: u128,
+    log_cumulative_end: u128,
+    elapsed: u64,
+) -> Result<u64> {
+    require!(elapsed > 0, AmmError::ZeroAmount);
+    let diff = accumulator::delta(log_cumulative_start, log_cumulative_end);
+    let twap_1e18 = exp2_q32(diff / elapsed as u128).ok_or(AmmError::MathOverflow)?;
+    Ok(price_to_1e12(twap_1e18))
+}
+
+/// 2^(2^-i) in Q1.63 for i = 1..=32, used by `exp2_q32`.
+const EXP2_FRAC_FACTORS: [u128; LOG2_FRAC_BITS as usize] = [
+    0xb504f333f9de6484,
+    0x9837f0518db8a96f,
+    0x8b95c1e3ea8bd6e6,
+    0x85aac367cc487b14,
+    0x82cd8698ac2ba1d7,
+    0x8164d1f3bc030773,
+    0x80b1ed4fd999ab6c,
+    0x8058d7d2d5e5f6b0,
+    0x802c6436d0e04f50,
+    0x8016302f17467628,
+    0x800b179c82028fd0,
+    0x80058baf7fee3b5d,
+    0x8002c5d00fdcfcb6,
+    0x800162e61bed4a48,
+    0x8000b17292f702a3,
+    0x800058b92abbae02,
+    0x80002c5c8dade4d7,
+    0x8000162e44eaf636,
+    0x80000b1721fa7c18,
+    0x8000058b90de7e4c,
+    0x800002c5c8678f36,
+    0x80000162e431db9f,
+    0x800000b1721872d0,
+    0x80000058b90c1aa8,
+    0x8000002c5c8605a4,
+    0x800000162e4300e6,
+    0x8000000b17217ff8,
+    0x800000058b90bfdd,
+    0x80000002c5c85fe6,
+    0x8000000162e42ff1,
+    0x80000000b17217f8,
+    0x8000000058b90bfc,
+];
+
+/// log2(x) in Q32.32 fixed point (floor), for x >= 1.
+/// Integer part from the MSB, fraction by repeated squaring of the
+/// mantissa normalized to [1, 2).
+pub fn log2_q32(x: u128) -> u128 {
+    if x == 0 {
+        return 0;
+    }
+    let msb = 127 - x.leading_zeros();
+    let mut result = (msb as u128) << LOG2_FRAC_BITS;
+
+    // mantissa in Q1.63, so squaring stays below 2^128
+    let mut y = if msb >= 63 { x >> (msb - 63) } else { x << (63 - msb) };
+    let mut bit = 1u128 << (LOG2_FRAC_BITS - 1);
+    while bit > 0 {
+        y = (y * y) >> 63;
+        if y >= 1u128 << 64 {
+            y >>= 1;
+            result |= bit;
+        }
+        bit >>= 1;
+    }
+    result
+}
+
+/// 2^(x / 2^32) for a Q32.32 exponent (floor); inverse of `log2_q32`.
+/// Returns None if the result does not fit in u128.
+pub fn exp2_q32(x: u128) -> Option<u128> {
+    let int = x >> LOG2_FRAC_BITS;
+    if int > 127 {
+        return None;
+    }
+    let frac = x & ((1u128 << LOG2_FRAC_BITS) - 1);
+
+    // product of 2^(2^-i) for each set fractional bit, in Q1.63
+    let mut acc: u128 = 1 << 63;
+    for (i, factor) in EXP2_FRAC_FACTORS.iter().enumerate() {
+        if frac & (1u128 << (LOG2_FRAC_BITS - 1 - i as u32)) != 0 {
+            acc = (acc * factor) >> 63;
+        }
+    }
+
+    // acc < 2^64, so shifting left by at most 64 cannot overflow
+    let int = int as u32;
+    if int >= 63 {
+        Some(acc << (int - 63))
+    } else {
+        Some(acc >> (63 - int))
+    }
+}
+
+/// Oracle price normalized to the 1e12 grid.
+pub struct OraclePrice {
+    pub price_1e12: u128,
+    pub conf_1e12: u128,
+    pub publish_time: i64,
+}
+
+// Pyth legacy price account layout (aggregate fields)
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_ATYPE_PRICE: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+const PYTH_OFFSET_EXPO: usize = 20;
+const PYTH_OFFSET_TIMESTAMP: usize = 96;
+const PYTH_OFFSET_AGG_PRICE: usize = 208;
+const PYTH_OFFSET_AGG_CONF: usize = 216;
+const PYTH_OFFSET_AGG_STATUS: usize = 224;
+const PYTH_PRICE_ACCOUNT_MIN_LEN: usize = 240;
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+/// Scale `value * 10^expo` to the 1e12 grid.
+fn normalize_to_1e12(value: u128, expo: i32) -> Option<u128> {
+    let shift = 12 + expo;
+    if shift >= 0 {
+        value.checked_mul(10u128.checked_pow(shift as u32)?)
+    } else {
+        Some(value / 10u128.checked_pow((-shift) as u32)?)
+    }
+}
+
+/// Parse the aggregate price of a Pyth price account. Fails on a malformed
+/// account or a non-trading / non-positive aggregate.
+fn read_pyth_price(info: &AccountInfo) -> Result<OraclePrice> {
+    let data = info.try_borrow_data()?;
+    require!(data.len() >= PYTH_PRICE_ACCOUNT_MIN_LEN, AmmError::InvalidOracle);
+    require!(
+        read_u32(&data, 0) == PYTH_MAGIC && read_u32(&data, 8) == PYTH_ATYPE_PRICE,
+        AmmError::InvalidOracle
+    );
+    require!(
+        read_u32(&data, PYTH_OFFSET_AGG_STATUS) == PYTH_STATUS_TRADING,
+        AmmError::InvalidOracle
+    );
+
+    let expo = read_u32(&data, PYTH_OFFSET_EXPO) as i32;
+    let price = read_u64(&data, PYTH_OFFSET_AGG_PRICE) as i64;
+    require!(price > 0, AmmError::InvalidOracle);
+    let conf = read_u64(&data, PYTH_OFFSET_AGG_CONF);
+
+    Ok(OraclePrice {
+        price_1e12: normalize_to_1e12(price as u128, expo).ok_or(AmmError::MathOverflow)?,
+        conf_1e12: normalize_to_1e12(conf as u128, expo).ok_or(AmmError::MathOverflow)?,
+        publish_time: read_u64(&data, PYTH_OFFSET_TIMESTAMP) as i64,
+    })
+}
+
+// Switchboard v2 AggregatorAccountData layout (packed, after the 8-byte
+// discriminator): latest_confirmed_round.{round_open_timestamp, result,
+// std_deviation}, decimals as (i128 mantissa, u32 scale)
+const SWITCHBOARD_AGGREGATOR_DISCRIMINATOR: [u8; 8] = [217, 230, 65, 101, 201, 162, 27, 125];
+const SWITCHBOARD_OFFSET_ROUND_OPEN_TIMESTAMP: usize = 358;
+const SWITCHBOARD_OFFSET_RESULT: usize = 366;
+const SWITCHBOARD_OFFSET_STD_DEVIATION: usize = 386;
+const SWITCHBOARD_AGGREGATOR_MIN_LEN: usize = 406;
+
+/// Read a SwitchboardDecimal at `offset` as (mantissa, scale).
+fn read_switchboard_decimal(data: &[u8], offset: usize) -> (i128, u32) {
+    let mantissa = i128::from_le_bytes(data[offset..offset + 16].try_into().unwrap());
+    (mantissa, read_u32(data, offset + 16))
+}
+
+/// Parse the latest confirmed round of a Switchboard v2 aggregator; the
+/// round's standard deviation plays the role of Pyth's confidence.
+fn read_switchboard_price(info: &AccountInfo) -> Result<OraclePrice> {
+    let data = info.try_borrow_data()?;
+    require!(data.len() >= SWITCHBOARD_AGGREGATOR_MIN_LEN, AmmError::InvalidOracle);
+    require!(
+        data[..8] == SWITCHBOARD_AGGREGATOR_DISCRIMINATOR,
+        AmmError::InvalidOracle
+    );
+
+    let (price, price_scale) = read_switchboard_decimal(&data, SWITCHBOARD_OFFSET_RESULT);
+    let (std_dev, std_scale) = read_switchboard_decimal(&data, SWITCHBOARD_OFFSET_STD_DEVIATION);
+    require!(price > 0 && std_dev >= 0, AmmError::InvalidOracle);
+
+    Ok(OraclePrice {
+        price_1e12: normalize_to_1e12(price as u128, -(price_scale as i32))
+            .ok_or(AmmError::MathOverflow)?,
+        conf_1e12: normalize_to_1e12(std_dev as u128, -(std_scale as i32))
+            .ok_or(AmmError::MathOverflow)?,
+        publish_time: read_u64(&data, SWITCHBOARD_OFFSET_ROUND_OPEN_TIMESTAMP) as i64,
+    })
+}
+
+/// Read a feed of the given format; every caller sees the same normalized
+/// price/confidence/publish-time triple regardless of source.
+pub fn read_oracle_price(info: &AccountInfo, source: OracleSource) -> Result<OraclePrice> {
+    match source {
+        OracleSource::Pyth => read_pyth_price(info),
+        OracleSource::Switchboard => read_switchboard_price(info),
+        OracleSource::None => err!(AmmError::InvalidOracle),
     }
-    if raw_bps > pool.max_fee_bps as u128 {
-        raw_bps = pool.max_fee_bps as u128;
+}
+
+/// The EMA has not been refreshed for longer than ema_max_age_seconds
+/// (0 = never stale); swaps restart it from the pre-trade spot.
+fn ema_is_stale(pool: &Pool, now: i64) -> bool {
+    pool.ema_max_age_seconds > 0
+        && pool.ema_price_1e18 > 0
+        && now.saturating_sub(pool.ema_updated_at) > pool.ema_max_age_seconds as i64
+}
+
+/// The pool's oracle feed, wherever it sits among the remaining accounts.
+fn oracle_account<'a, 'info>(
+    pool: &Pool,
+    remaining_accounts: &'a [AccountInfo<'info>],
+) -> Option<&'a AccountInfo<'info>> {
+    remaining_accounts.iter().find(|info| info.key() == pool.oracle)
+}
+
+/// conf / price of the pool's oracle (1e12), looked up among the remaining
+/// accounts by key. Falls back to 0 (internal EMA signal only) when no feed is
+/// configured, or when the account is not supplied or the price is stale
+/// and the pool does not set `oracle_required`, in which case both revert.
+fn oracle_confidence_1e12(
+    pool: &Pool,
+    remaining_accounts: &[AccountInfo],
+    now: i64,
+) -> Result<u128> {
+    if pool.oracle_source == OracleSource::None {
+        return Ok(0);
     }
-    Ok((raw_bps as u16, vol_1e12, slip_1e12, shallow_1e12))
+    let Some(info) = oracle_account(pool, remaining_accounts) else {
+        require!(!pool.oracle_required, AmmError::OracleRequired);
+        return Ok(0);
+    };
+
+    let oracle = read_oracle_price(info, pool.oracle_source)?;
+    if now.saturating_sub(oracle.publish_time) > pool.oracle_max_age_seconds as i64 {
+        msg!("oracle stale: published {} now {}", oracle.publish_time, now);
+        require!(!pool.oracle_required, AmmError::StaleOracle);
+        return Ok(0);
+    }
+    Ok(
+        mul_div(oracle.conf_1e12, SCALE, oracle.price_1e12.max(1), Rounding::Down)
+            .ok_or(AmmError::MathOverflow)?,
+    )
 }
 
 /* -------------------------------- Errors -------------------------------- */
@@ -795,4 +6891,128 @@ pub enum AmmError {
     AmountOutZero,
     #[msg("Volatility too high (circuit breaker)")]
     VolTooHigh,
+    #[msg("Requested TWAP window exceeds available history")]
+    TwapWindowUnavailable,
+    #[msg("Invalid oracle account")]
+    InvalidOracle,
+    #[msg("Pool is paused")]
+    PoolPaused,
+    #[msg("Recipient token account has the wrong owner")]
+    InvalidRecipient,
+    #[msg("Not available in this pool's donation mode")]
+    DonationModeMismatch,
+    #[msg("Swap would drain the output reserve")]
+    InsufficientOutputReserve,
+    #[msg("Output below the minimum accepted")]
+    SlippageExceeded,
+    #[msg("Transaction deadline passed")]
+    DeadlineExceeded,
+    #[msg("Fee exceeds 100%")]
+    FeeTooHigh,
+    #[msg("Price impact above the pool cap")]
+    PriceImpactTooHigh,
+    #[msg("Token account has the wrong mint")]
+    WrongTokenAccount,
+    // no longer returned; kept so the codes after it do not move
+    #[msg("Deprecated: set_params is no longer rate limited")]
+    RateLimited,
+    #[msg("LP freezing is disabled for this pool")]
+    FreezeDisabled,
+    #[msg("Mint has a freeze authority; pass allow_risky_mints to accept it")]
+    RiskyMint,
+    #[msg("Flash loans are disabled for this pool")]
+    FlashLoansDisabled,
+    #[msg("A flash loan is outstanding")]
+    FlashLoanActive,
+    #[msg("Flash loan exceeds the reserve")]
+    FlashLoanTooLarge,
+    #[msg("No matching flash_repay later in the transaction")]
+    FlashRepayMissing,
+    #[msg("Repayment does not match the outstanding flash loan")]
+    FlashLoanMismatch,
+    #[msg("Vault below its reserve after the flash repayment")]
+    FlashRepayShort,
+    #[msg("Pool is locked by a flash swap in progress")]
+    PoolLocked,
+    #[msg("Flash swap not paid back with the fee")]
+    FlashSwapUnderpaid,
+    #[msg("Account holds pool funds and cannot be rescued from")]
+    ProtectedAccount,
+    #[msg("Pool still holds liquidity or vault balances")]
+    PoolNotEmpty,
+    #[msg("Circuit breaker tripped by the guardian")]
+    BreakerTripped,
+    #[msg("TWAP observations are older than the staleness bound")]
+    StaleTwap,
+    #[msg("Pool requires its oracle account")]
+    OracleRequired,
+    #[msg("simulate_swap always fails; read the quote from the logs")]
+    SimulationOnly,
+    #[msg("Oracle price is stale")]
+    StaleOracle,
+    #[msg("Liquidity mining is already set up for this pool")]
+    RewardsAlreadyInitialized,
+    #[msg("Liquidity mining is not set up for this pool")]
+    RewardsNotInitialized,
+    #[msg("Reward mint must differ from the pool's token and LP mints")]
+    BadRewardMint,
+    #[msg("A reward campaign is still running or scheduled")]
+    CampaignActive,
+    #[msg("No reward campaign to end")]
+    NoActiveCampaign,
+    #[msg("Mint is not on the allowlist (missing MintBadge)")]
+    MintNotApproved,
+    #[msg("Trade is below the pool's minimum size")]
+    TradeTooSmall,
+    #[msg("Trade exceeds the pool's maximum size relative to reserves")]
+    TradeTooLarge,
+    #[msg("Pool name or URI is too long")]
+    MetadataTooLong,
+    #[msg("Pool name or URI contains a NUL byte")]
+    InvalidMetadata,
+    #[msg("Token account is not owned by the signer")]
+    InvalidTokenOwner,
+    #[msg("Trade would push the price past the circuit breaker threshold")]
+    TradeWouldTripBreaker,
+    #[msg("EMA alpha above 1.0")]
+    AlphaOutOfRange,
+    #[msg("Breaker threshold is zero")]
+    ThresholdZero,
+    // no longer returned; kept so the codes after it do not move
+    #[msg("Deprecated: fee coefficients are no longer bounded")]
+    CoefficientTooLarge,
+    #[msg("Maximum fee must be below 100%")]
+    FeeAboveDenominator,
+    #[msg("Pool only accepts swaps through an allowlisted router")]
+    RouterRequired,
+    #[msg("Calling program is not on the pool's router allowlist")]
+    RouterNotAllowed,
+    #[msg("Router allowlist is too long, or empty while required")]
+    InvalidRouterList,
+    #[msg("Address is on the pool's denylist")]
+    AddressDenylisted,
+    #[msg("Pool enforces a denylist: pass the denylist accounts of the signer and token owners")]
+    DenylistAccountMissing,
+    #[msg("Buy exceeds the per-wallet cap of the launch window")]
+    LaunchBuyCapExceeded,
+    #[msg("Launch window is open: pass the signer's writable launch account")]
+    LaunchAccountMissing,
+    #[msg("Launch window has not ended yet")]
+    LaunchWindowOpen,
+    #[msg("Reward vault and LP escrow must be passed")]
+    RewardAccountsMissing,
+    #[msg("The pool keeps an admin log; pass it")]
+    AdminLogMissing,
+    #[msg("Account is not a record of this pool, or its owner does not follow it")]
+    NotPoolRecord,
+}
+
+impl From<MathError> for anchor_lang::error::Error {
+    fn from(e: MathError) -> Self {
+        match e {
+            MathError::Overflow => AmmError::MathOverflow.into(),
+            MathError::ZeroAmount => AmmError::ZeroAmount.into(),
+            MathError::NoLiquidity => AmmError::NoLiquidity.into(),
+        }
+    }
 }
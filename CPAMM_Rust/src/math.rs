@@ -0,0 +1,1438 @@
+//! Pure pricing and fee math shared by the program and off-chain tools.
+//!
+//! Only `core` is used (no Anchor, no Solana runtime), so routers,
+//! simulators and front-ends can compile the exact on-chain formulas and
+//! the file drops unchanged into a `#![no_std]` crate. Everything works on
+//! primitive integers and `PoolParams`; failures are `MathError`, which
+//! the program maps onto `AmmError`.
+
+use core::convert::TryFrom;
+
+/// Fixed-point scale for external prices and slippage/vol signals.
+pub const SCALE: u128 = 1_000_000_000_000; // 1e12
+/// Internal price precision. Prices are kept at 1e18 in u128 so extreme
+/// reserve ratios (e.g. 0 vs 9 decimals) neither floor to 0 nor saturate;
+/// events and views convert back to 1e12 at the boundary.
+pub const PRICE_SCALE: u128 = 1_000_000_000_000_000_000; // 1e18
+/// Basis points denominator
+pub const BPS_DENOM: u64 = 10_000;
+/// Parts-per-million denominator: the precision of swap fees
+pub const PPM_DENOM: u64 = 1_000_000;
+/// ppm in one basis point
+pub const PPM_PER_BPS: u32 = 100;
+
+/// Whole-bps value in ppm.
+pub fn bps_to_ppm(bps: u16) -> u32 {
+    bps as u32 * PPM_PER_BPS
+}
+
+/// A ppm fee in whole bps, rounded up so it never understates the fee;
+/// saturates at u16::MAX.
+pub fn ppm_to_bps(ppm: u32) -> u16 {
+    u16::try_from(ppm.div_ceil(PPM_PER_BPS)).unwrap_or(u16::MAX)
+}
+
+/// Failure of a math routine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathError {
+    Overflow,
+    ZeroAmount,
+    NoLiquidity,
+}
+
+pub type MathResult<T> = core::result::Result<T, MathError>;
+
+/// Fee curve inputs: the `Pool` fields `compute_dynamic_fee` reads.
+/// Fee bounds are in ppm; the coefficients stay in bps, each being what a
+/// 100% signal adds (coefficient * PPM_PER_BPS ppm).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolParams {
+    pub min_fee_ppm: u32,
+    pub max_fee_ppm: u32,
+    pub beta_vol_bps_per1e12: u16,
+    pub gamma_slip_bps_per1e12: u16,
+    pub delta_shallow_bps_per1e12: u16,
+    pub epsilon_oracle_bps_per1e12: u16,
+    pub ema_price_1e18: u128,
+    /// reserve level at which the shallow-depth signal is 50%, in base
+    /// units (see `shallow_depth_k`); 0 falls back to `DEFAULT_SHALLOW_DEPTH_K`
+    pub shallow_depth_k: u128,
+    /// slip signal is `price_impact_1e12` instead of amountIn / (rin + amountIn)
+    pub slip_price_impact: bool,
+    /// nonzero: shallow signal is `target_depth_shallow_1e12` against this
+    /// target (token0 base units) instead of the `shallow_depth_k` formula
+    pub shallow_target_depth0: u128,
+    /// smoothed pool value in token0 (2 * r0) the shallow signal reads
+    /// instead of the current reserves; 0 = use the current reserves
+    pub ema_depth0: u128,
+    /// inventory-skew coefficient: charged on top for trades that deepen
+    /// the imbalance, rebated (down to min_fee_ppm) for ones that ease it
+    pub zeta_skew_bps_per1e12: u16,
+}
+
+/// Depth factor for pools without known decimals: 1000 tokens at 6 decimals.
+pub const DEFAULT_SHALLOW_DEPTH_K: u128 = 1_000 * 1_000_000;
+
+/// Depth factor of 1000 whole tokens of the less precise side, so the
+/// shallow-depth term means the same thing whatever the decimals.
+pub fn shallow_depth_k(decimals0: u8, decimals1: u8) -> u128 {
+    1_000 * 10u128.pow(u8::min(decimals0, decimals1) as u32)
+}
+
+/// Rounding direction of `mul_div`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    Down,
+    Up,
+}
+
+/// a * b / denom with a full 256-bit intermediate. None only when denom is
+/// 0 or the result does not fit in u128.
+pub fn mul_div(a: u128, b: u128, denom: u128, rounding: Rounding) -> Option<u128> {
+    if denom == 0 {
+        return None;
+    }
+    let (q, r) = U256::mul(a, b).div_rem(denom)?;
+    match rounding {
+        Rounding::Up if r > 0 => q.checked_add(1),
+        _ => Some(q),
+    }
+}
+
+/// Minimal 256-bit unsigned integer, just enough for `mul_div`.
+// hi before lo, so the derived ordering is numeric
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct U256 {
+    hi: u128,
+    lo: u128,
+}
+
+impl U256 {
+    const LO_MASK: u128 = u64::MAX as u128;
+
+    /// Full product of two u128s (schoolbook on 64-bit limbs).
+    pub fn mul(a: u128, b: u128) -> Self {
+        let (a1, a0) = (a >> 64, a & Self::LO_MASK);
+        let (b1, b0) = (b >> 64, b & Self::LO_MASK);
+        let p00 = a0 * b0;
+        let p01 = a0 * b1;
+        let p10 = a1 * b0;
+        let p11 = a1 * b1;
+        // < 3 * 2^64, cannot overflow
+        let mid = (p00 >> 64) + (p01 & Self::LO_MASK) + (p10 & Self::LO_MASK);
+        U256 {
+            hi: p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64),
+            lo: (p00 & Self::LO_MASK) | (mid << 64),
+        }
+    }
+
+    /// (self / d, self % d), or None if the quotient needs more than 128
+    /// bits. `d` must be non-zero.
+    pub fn div_rem(self, d: u128) -> Option<(u128, u128)> {
+        if self.hi == 0 {
+            return Some((self.lo / d, self.lo % d));
+        }
+        if self.hi >= d {
+            return None;
+        }
+        // restoring long division over the low limb; rem < d throughout,
+        // with the bit shifted out of rem carried explicitly
+        let mut rem = self.hi;
+        let mut q = 0u128;
+        for i in (0..128).rev() {
+            let carry = rem >> 127;
+            rem = (rem << 1) | ((self.lo >> i) & 1);
+            q <<= 1;
+            if carry == 1 || rem >= d {
+                rem = rem.wrapping_sub(d);
+                q |= 1;
+            }
+        }
+        Some((q, rem))
+    }
+}
+
+/// floor(sqrt(y)), i.e. the r with r^2 <= y < (r+1)^2. Digit-by-digit in
+/// base 4 starting from the highest set bit pair: at most 64 iterations of
+/// shifts and compares, no division.
+pub fn isqrt(y: u128) -> u128 {
+    if y == 0 {
+        return 0;
+    }
+    let mut rem = y;
+    let mut res = 0u128;
+    // largest power of four <= y
+    let mut bit = 1u128 << ((127 - y.leading_zeros()) & !1);
+    while bit != 0 {
+        if rem >= res + bit {
+            rem -= res + bit;
+            res = (res >> 1) + bit;
+        } else {
+            res >>= 1;
+        }
+        bit >>= 2;
+    }
+    res
+}
+
+/// EMA <- EMA + alpha * (price - EMA); prices at 1e18, alpha at 1e12.
+/// Alpha is capped at 1.0 so the result stays between the old EMA and price.
+pub fn ema_update(ema: &mut u128, alpha_1e12: u64, price_1e18: u128) {
+    let alpha_1e12 = u64::min(alpha_1e12, SCALE as u64);
+    let ema_u = *ema;
+    if price_1e18 >= ema_u {
+        let diff = price_1e18 - ema_u;
+        let delta =
+            mul_div(diff, alpha_1e12 as u128, SCALE, Rounding::Down).unwrap_or(u128::MAX);
+        *ema = ema_u.saturating_add(delta);
+    } else {
+        let diff = ema_u - price_1e18;
+        let delta =
+            mul_div(diff, alpha_1e12 as u128, SCALE, Rounding::Down).unwrap_or(u128::MAX);
+        *ema = ema_u.saturating_sub(delta);
+    }
+}
+
+/// Spot price token0 in token1 at internal precision (1e18). This is the
+/// direction the EMA, TWAP accumulators and breaker are defined in.
+pub fn spot_price_1e18(reserve0: u64, reserve1: u64) -> MathResult<u128> {
+    if reserve0 == 0 || reserve1 == 0 {
+        return Err(MathError::NoLiquidity);
+    }
+    mul_div(reserve1 as u128, PRICE_SCALE, reserve0 as u128, Rounding::Down)
+        .ok_or(MathError::Overflow)
+}
+
+/// Convert an internal 1e18 price to the external 1e12 representation,
+/// saturating at u64::MAX.
+pub fn price_to_1e12(price_1e18: u128) -> u64 {
+    u128::min(price_1e18 / (PRICE_SCALE / SCALE), u64::MAX as u128) as u64
+}
+
+/// Price of one unit of token0 in token1 (1e12), rounded down.
+pub fn spot_price_0_in_1(reserve0: u64, reserve1: u64) -> MathResult<u64> {
+    Ok(price_to_1e12(spot_price_1e18(reserve0, reserve1)?))
+}
+
+/// Price of one unit of token1 in token0 (1e12), rounded down. Computed
+/// from the reserves rather than by inverting `spot_price_0_in_1`, so both
+/// directions carry at most one unit of error on the 1e12 grid.
+pub fn spot_price_1_in_0(reserve0: u64, reserve1: u64) -> MathResult<u64> {
+    spot_price_0_in_1(reserve1, reserve0)
+}
+
+// Share rounding policy, so that no add/remove sequence can raise its own
+// per-share value at the other LPs' expense: shares minted for a deposit
+// round down, shares required for a given payout round up, and payouts for
+// burned shares round down. Every share computation goes through the three
+// helpers below.
+
+/// sqrt(reserve0 * reserve1), rounded down: the pool's liquidity, whose
+/// growth per LP share is the fee yield.
+pub fn sqrt_k(reserve0: u64, reserve1: u64) -> u128 {
+    isqrt(reserve0 as u128 * reserve1 as u128)
+}
+
+/// Fold a change of sqrt(k) that belongs to the LPs (swap fees,
+/// donations) into the cumulative growth factor: growth * after / before,
+/// rounded down. Between liquidity changes the factors telescope, so the
+/// floor in `sqrt_k` does not accumulate. An empty pool (before = 0)
+/// leaves the factor unchanged.
+pub fn accrue_k_growth(
+    growth_1e12: u128,
+    sqrt_k_before: u128,
+    sqrt_k_after: u128,
+) -> MathResult<u128> {
+    if sqrt_k_before == 0 {
+        return Ok(growth_1e12);
+    }
+    mul_div(growth_1e12, sqrt_k_after, sqrt_k_before, Rounding::Down).ok_or(MathError::Overflow)
+}
+
+/// Liquidity growth per LP share between two readings of the pool's
+/// growth_1e12, at 1e12 (SCALE = none). (ratio - 1) over the time between
+/// the readings is the fee APR.
+pub fn growth_between_1e12(earlier_1e12: u128, later_1e12: u128) -> MathResult<u128> {
+    if earlier_1e12 == 0 {
+        return Err(MathError::ZeroAmount);
+    }
+    mul_div(later_1e12, SCALE, earlier_1e12, Rounding::Down).ok_or(MathError::Overflow)
+}
+
+/// LP shares minted for depositing (amount0, amount1) against the pricing
+/// reserves, rounded down. Bootstrap: sqrt of the post-deposit product;
+/// otherwise min(dx/x * T, dy/y * T). Shared by `add_liquidity` and its
+/// quote.
+pub fn shares_for_deposit(
+    amount0: u64,
+    amount1: u64,
+    reserve0: u64,
+    reserve1: u64,
+    total_supply: u64,
+) -> MathResult<u64> {
+    if total_supply == 0 {
+        // L0 = sqrt(x*y) of the deposit alone: reserves without LPs are
+        // dropped at bootstrap (see `add_liquidity`), never credited
+        let k = (amount0 as u128)
+            .checked_mul(amount1 as u128)
+            .ok_or(MathError::Overflow)?;
+        return u64::try_from(isqrt(k)).map_err(|_| MathError::Overflow);
+    }
+    let t = total_supply as u128;
+    let dx = mul_div(amount0 as u128, t, reserve0 as u128, Rounding::Down)
+        .ok_or(MathError::Overflow)?;
+    let dy = mul_div(amount1 as u128, t, reserve1 as u128, Rounding::Down)
+        .ok_or(MathError::Overflow)?;
+    u64::try_from(u128::min(dx, dy)).map_err(|_| MathError::Overflow)
+}
+
+/// Pro-rata token amounts paid out for burning `shares` of `total_supply`,
+/// rounded down. Shared by `remove_liquidity` and its quote.
+pub fn amounts_for_shares(
+    shares: u64,
+    balance0: u64,
+    balance1: u64,
+    total_supply: u64,
+) -> MathResult<(u64, u64)> {
+    let t = total_supply as u128;
+    let amount0 = mul_div(shares as u128, balance0 as u128, t, Rounding::Down)
+        .ok_or(MathError::Overflow)?;
+    let amount1 = mul_div(shares as u128, balance1 as u128, t, Rounding::Down)
+        .ok_or(MathError::Overflow)?;
+    Ok((
+        u64::try_from(amount0).map_err(|_| MathError::Overflow)?,
+        u64::try_from(amount1).map_err(|_| MathError::Overflow)?,
+    ))
+}
+
+/// Hold-vs-LP comparison at `price_1e12` (token0 in token1): the entry
+/// amounts (hodl0, hodl1) against the withdrawn ones (out0, out1).
+/// Returns (hodl_value_in_token1, lp_value_in_token1, il_bps), the bps
+/// truncated towards zero.
+pub fn impermanent_loss(
+    hodl0: u128,
+    hodl1: u128,
+    out0: u128,
+    out1: u128,
+    price_1e12: u64,
+) -> MathResult<(u64, u64, i64)> {
+    let price = price_1e12 as u128;
+    let hodl_value = mul_div(hodl0, price, SCALE, Rounding::Down)
+        .and_then(|v| v.checked_add(hodl1))
+        .ok_or(MathError::Overflow)?;
+    let lp_value = mul_div(out0, price, SCALE, Rounding::Down)
+        .and_then(|v| v.checked_add(out1))
+        .ok_or(MathError::Overflow)?;
+    let il_bps = if hodl_value == 0 {
+        0
+    } else {
+        let ratio =
+            (lp_value as i128 - hodl_value as i128) * BPS_DENOM as i128 / hodl_value as i128;
+        i64::try_from(ratio).map_err(|_| MathError::Overflow)?
+    };
+    Ok((
+        u64::try_from(hodl_value).map_err(|_| MathError::Overflow)?,
+        u64::try_from(lp_value).map_err(|_| MathError::Overflow)?,
+        il_bps,
+    ))
+}
+
+/// Fewest LP shares whose `amounts_for_shares` payout covers (amount0,
+/// amount1): max(ceil(a0 * T / x), ceil(a1 * T / y)). Asking for more than
+/// the reserves hold is NoLiquidity.
+pub fn shares_for_withdrawal(
+    amount0: u64,
+    amount1: u64,
+    reserve0: u64,
+    reserve1: u64,
+    total_supply: u64,
+) -> MathResult<u64> {
+    if amount0 > reserve0 || amount1 > reserve1 {
+        return Err(MathError::NoLiquidity);
+    }
+    let t = total_supply as u128;
+    let side = |amount: u64, reserve: u64| {
+        if amount == 0 {
+            return Ok(0);
+        }
+        mul_div(amount as u128, t, reserve as u128, Rounding::Up).ok_or(MathError::Overflow)
+    };
+    let shares = u128::max(side(amount0, reserve0)?, side(amount1, reserve1)?);
+    u64::try_from(shares).map_err(|_| MathError::Overflow)
+}
+
+/// reserve0 * amount1 == reserve1 * amount0, compared on full 256-bit
+/// products so no operand size can overflow the check.
+pub fn ratio_matches(reserve0: u64, reserve1: u64, amount0: u64, amount1: u64) -> bool {
+    U256::mul(reserve0 as u128, amount1 as u128) == U256::mul(reserve1 as u128, amount0 as u128)
+}
+
+/// Largest (a0, a1) <= desired with reserve0 * a1 == reserve1 * a0, i.e. a
+/// whole multiple of the reduced reserve ratio.
+pub fn ratio_matched_amounts(
+    amount0_desired: u64,
+    amount1_desired: u64,
+    reserve0: u64,
+    reserve1: u64,
+) -> (u64, u64) {
+    if reserve0 == 0 || reserve1 == 0 {
+        // add_liquidity skips the ratio check on an empty or one-sided pool
+        return (amount0_desired, amount1_desired);
+    }
+    let g = gcd(reserve0, reserve1);
+    let unit0 = reserve0 / g;
+    let unit1 = reserve1 / g;
+    let k = u64::min(amount0_desired / unit0, amount1_desired / unit1);
+    (k * unit0, k * unit1)
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Constant-product output for `amount_in` against (rin, rout) with the
+/// fee taken on the input. Returns (amount_out, amount_in_after_fee); both
+/// round down, in the pool's favour.
+pub fn get_amount_out(
+    amount_in: u64,
+    rin: u128,
+    rout: u128,
+    fee_ppm: u32,
+) -> MathResult<(u64, u128)> {
+    let fee_num = PPM_DENOM
+        .checked_sub(fee_ppm as u64)
+        .ok_or(MathError::Overflow)? as u128;
+    let dx_fee = mul_div(amount_in as u128, fee_num, PPM_DENOM as u128, Rounding::Down)
+        .ok_or(MathError::Overflow)?;
+
+    let amount_out = mul_div(
+        rout,
+        dx_fee,
+        rin.checked_add(dx_fee).ok_or(MathError::Overflow)?,
+        Rounding::Down,
+    )
+    .ok_or(MathError::Overflow)?;
+    Ok((u64::try_from(amount_out).map_err(|_| MathError::Overflow)?, dx_fee))
+}
+
+/// Constant-product output with the fee withheld from the output instead:
+/// the gross x*y=k output for the whole `amount_in` rounds down and the fee
+/// on it rounds up, both in the pool's favour. Returns (amount_out,
+/// fee_amount), the fee in output-token units.
+pub fn get_amount_out_fee_on_output(
+    amount_in: u64,
+    rin: u128,
+    rout: u128,
+    fee_ppm: u32,
+) -> MathResult<(u64, u64)> {
+    let (gross, _) = get_amount_out(amount_in, rin, rout, 0)?;
+    let fee = mul_div(gross as u128, fee_ppm as u128, PPM_DENOM as u128, Rounding::Up)
+        .ok_or(MathError::Overflow)?;
+    let fee = u64::try_from(fee).map_err(|_| MathError::Overflow)?;
+    Ok((gross.checked_sub(fee).ok_or(MathError::Overflow)?, fee))
+}
+
+/// Output and fee of a swap for the pool's fee side: (amount_out,
+/// fee_amount), the fee in the input token, or in the output token with
+/// `fee_on_output`. For trades small against the reserves the two modes
+/// pay out the same up to rounding; for large ones an input-side fee also
+/// shrinks the curve step, so it pays out slightly more.
+pub fn swap_amounts(
+    amount_in: u64,
+    rin: u128,
+    rout: u128,
+    fee_ppm: u32,
+    fee_on_output: bool,
+) -> MathResult<(u64, u64)> {
+    if fee_on_output {
+        return get_amount_out_fee_on_output(amount_in, rin, rout, fee_ppm);
+    }
+    let (amount_out, dx_fee) = get_amount_out(amount_in, rin, rout, fee_ppm)?;
+    let dx_fee = u64::try_from(dx_fee).map_err(|_| MathError::Overflow)?;
+    Ok((amount_out, amount_in - dx_fee))
+}
+
+/// Least k a swap may leave behind. The fee-less curve step keeps k (its
+/// rounding only raises it), then the `lp_fee` left in the pool scales k by
+/// side_after / (side_after - lp_fee), `side_after` being the post-swap
+/// reserve of the token the fee was charged in. Rounds down, so
+/// `k_after >= expected_k_growth(..)` holds exactly, with no tolerance.
+pub fn expected_k_growth(k_before: u128, fee_side_after: u128, lp_fee: u128) -> MathResult<u128> {
+    let side_without_fee = fee_side_after.checked_sub(lp_fee).ok_or(MathError::Overflow)?;
+    if side_without_fee == 0 {
+        return Err(MathError::NoLiquidity);
+    }
+    mul_div(k_before, fee_side_after, side_without_fee, Rounding::Down).ok_or(MathError::Overflow)
+}
+
+/// Flash-swap settlement check: with `fee_ppm` taken off the amounts paid
+/// back on each side, the balances keep k at least at the reserves' level.
+/// All pairs are (in side, out side).
+pub fn k_with_fee_holds(
+    reserves: (u128, u128),
+    balances: (u128, u128),
+    paid: (u128, u128),
+    fee_ppm: u32,
+) -> MathResult<bool> {
+    let ppm = PPM_DENOM as u128;
+    let adjusted = |balance: u128, paid: u128| {
+        balance
+            .checked_mul(ppm)
+            .and_then(|b| b.checked_sub(paid.checked_mul(fee_ppm as u128)?))
+            .ok_or(MathError::Overflow)
+    };
+    let adj_in = adjusted(balances.0, paid.0)?;
+    let adj_out = adjusted(balances.1, paid.1)?;
+    let k = reserves.0.checked_mul(reserves.1).ok_or(MathError::Overflow)?;
+    Ok(U256::mul(adj_in, adj_out) >= U256::mul(k, ppm * ppm))
+}
+
+/// Realized price impact (1e12) of a fee-less fill of `amount_in` against
+/// (rin, rout): (spot - exec) / spot with spot = rout / rin and exec the
+/// exact price of the fill, rout / (rin + amountIn), taken before the
+/// payout is floored to whole units so a 1-unit trade is not charged the
+/// rounding. On this curve that is amountIn / (rin + amountIn), the proxy's
+/// ratio, rounded up instead of down; an empty side is 100%.
+pub fn price_impact_1e12(amount_in: u128, rin: u128, rout: u128) -> MathResult<u128> {
+    if amount_in == 0 {
+        return Err(MathError::ZeroAmount);
+    }
+    if rin == 0 || rout == 0 {
+        return Ok(SCALE);
+    }
+    // exec / spot = rin / (rin + amountIn)
+    mul_div(
+        amount_in,
+        SCALE,
+        rin.checked_add(amount_in).ok_or(MathError::Overflow)?,
+        Rounding::Up,
+    )
+    .ok_or(MathError::Overflow)
+}
+
+/// Shallow signal against an operator target: max(0, 1 - depth / target),
+/// where `depth0` is the pool value in token0 (2 * r0 on a constant-product
+/// curve, or its EMA). Ramps linearly from 100% when empty to 0 at the target.
+pub fn target_depth_shallow_1e12(depth0: u128, target_depth0: u128) -> MathResult<u128> {
+    if depth0 >= target_depth0 {
+        return Ok(0);
+    }
+    Ok(SCALE
+        - mul_div(depth0, SCALE, target_depth0, Rounding::Down).ok_or(MathError::Overflow)?)
+}
+
+/// Inverse of `get_amount_out` (input-side fee): the smallest input for
+/// which it returns at least `amount_out` at this `fee_ppm`. Rounds up, so
+/// `get_amount_in(get_amount_out(x).0) <= x` and
+/// `get_amount_out(get_amount_in(y)).0 >= y`. Asking for the whole out
+/// reserve or more is NoLiquidity.
+pub fn get_amount_in(amount_out: u64, rin: u128, rout: u128, fee_ppm: u32) -> MathResult<u64> {
+    if amount_out == 0 {
+        return Err(MathError::ZeroAmount);
+    }
+    if amount_out as u128 >= rout {
+        return Err(MathError::NoLiquidity);
+    }
+    let fee_num = PPM_DENOM
+        .checked_sub(fee_ppm as u64)
+        .ok_or(MathError::Overflow)? as u128;
+    // smallest after-fee input with rout * dx / (rin + dx) >= amount_out
+    let dx_fee = mul_div(amount_out as u128, rin, rout - amount_out as u128, Rounding::Up)
+        .ok_or(MathError::Overflow)?;
+    let amount_in = mul_div(dx_fee, PPM_DENOM as u128, fee_num, Rounding::Up)
+        .ok_or(MathError::Overflow)?;
+    u64::try_from(amount_in).map_err(|_| MathError::Overflow)
+}
+
+/// Distance of `price_1e18` from `ema_1e18` at 1e12: |price - ema| /
+/// min(price, ema). Dividing by the smaller of the two makes the signal
+/// identical for both quote directions: it equals max(p/ema, ema/p) - 1.
+/// 0 while there is no EMA; saturates at u128::MAX when one price is
+/// vanishingly small next to the other.
+pub fn ema_deviation_1e12(price_1e18: u128, ema_1e18: u128) -> MathResult<u128> {
+    if ema_1e18 == 0 {
+        return Ok(0);
+    }
+    let diff = price_1e18.abs_diff(ema_1e18);
+    Ok(mul_div(diff, SCALE, u128::max(u128::min(price_1e18, ema_1e18), 1), Rounding::Down)
+        .unwrap_or(u128::MAX))
+}
+
+/// Signed inventory skew at 1e12: (value0 - value1) / (value0 + value1),
+/// both sides valued in token1 at the EMA price. At spot the two values
+/// are always equal, so the EMA is the reference that exposes a pool left
+/// holding too much of one side. 0 while there is no EMA or no liquidity.
+pub fn inventory_skew_1e12(r0: u128, r1: u128, ema_1e18: u128) -> MathResult<i128> {
+    if ema_1e18 == 0 {
+        return Ok(0);
+    }
+    let value0 = mul_div(r0, ema_1e18, PRICE_SCALE, Rounding::Down).ok_or(MathError::Overflow)?;
+    let total = value0.checked_add(r1).ok_or(MathError::Overflow)?;
+    if total == 0 {
+        return Ok(0);
+    }
+    // |skew| <= SCALE, so the cast cannot wrap
+    let skew = mul_div(value0.abs_diff(r1), SCALE, total, Rounding::Down)
+        .ok_or(MathError::Overflow)? as i128;
+    Ok(if value0 >= r1 { skew } else { -skew })
+}
+
+/// Deviation from `ema_1e18` of the spot a trade leaves behind: `added_in`
+/// lands in the in-side reserve and `amount_out` leaves the other one.
+pub fn post_trade_deviation_1e12(
+    ema_1e18: u128,
+    token_in_is_0: bool,
+    r0: u128,
+    r1: u128,
+    added_in: u128,
+    amount_out: u128,
+) -> MathResult<u128> {
+    let (r0, r1) = if token_in_is_0 {
+        (r0.checked_add(added_in), r1.checked_sub(amount_out))
+    } else {
+        (r0.checked_sub(amount_out), r1.checked_add(added_in))
+    };
+    let (r0, r1) = (r0.ok_or(MathError::Overflow)?, r1.ok_or(MathError::NoLiquidity)?);
+    if r0 == 0 || r1 == 0 {
+        return Err(MathError::NoLiquidity);
+    }
+    let price = mul_div(r1, PRICE_SCALE, r0, Rounding::Down).ok_or(MathError::Overflow)?;
+    ema_deviation_1e12(price, ema_1e18)
+}
+
+/// Outcome of the circuit breaker for one trade.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakerVerdict {
+    Open,
+    /// the guardian tripped it
+    ManualTrip,
+    /// the pool is already too far from its EMA
+    PreTradeDeviation,
+    /// the pool is calm but this trade would leave it too far away
+    PostTradeDeviation,
+}
+
+impl BreakerVerdict {
+    pub fn is_open(self) -> bool {
+        self == BreakerVerdict::Open
+    }
+}
+
+/// The breaker's decision, shared by the program and every quoter. Both
+/// deviations are against the same pre-trade EMA, and a deviation exactly
+/// at the threshold passes. A `None` threshold (breaker disabled) skips
+/// both deviation checks; a manual trip still applies:
+///
+/// | manual | pre > threshold | post > threshold | verdict            |
+/// |--------|-----------------|------------------|--------------------|
+/// | yes    | any             | any              | ManualTrip         |
+/// | no     | yes             | any              | PreTradeDeviation  |
+/// | no     | no              | yes              | PostTradeDeviation |
+/// | no     | no              | no               | Open               |
+pub fn breaker_verdict(
+    manually_tripped: bool,
+    threshold_1e12: Option<u64>,
+    pre_vol_1e12: u128,
+    post_vol_1e12: u128,
+) -> BreakerVerdict {
+    if manually_tripped {
+        return BreakerVerdict::ManualTrip;
+    }
+    let Some(threshold) = threshold_1e12.map(u128::from) else {
+        return BreakerVerdict::Open;
+    };
+    if pre_vol_1e12 > threshold {
+        BreakerVerdict::PreTradeDeviation
+    } else if post_vol_1e12 > threshold {
+        BreakerVerdict::PostTradeDeviation
+    } else {
+        BreakerVerdict::Open
+    }
+}
+
+/// Dynamic fee and its components (vol/slip/shallow).
+/// `oracle_conf_1e12` (conf/price of an external feed, 0 if none) adds an
+/// `epsilon` term on top of the internal volatility proxy.
+/// Each signal is clamped to SCALE (100%) before it is weighted, so a
+/// coefficient is the most that term can ever add, and the fee is then
+/// clamped to [min_fee_ppm, max_fee_ppm]. It never errors on large
+/// signals, and in either slip mode it is non-decreasing in amount_in for
+/// a fixed pool state. The returned components are
+/// unclamped, for the breaker and for reporting.
+/// Returns (fee_ppm, vol_1e12, slip_1e12, shallow_1e12).
+pub fn compute_dynamic_fee(
+    params: &PoolParams,
+    token_in_is_0: bool,
+    amount_in: u128,
+    r0: u128,
+    r1: u128,
+    oracle_conf_1e12: u128,
+) -> MathResult<(u32, u128, u128, u128)> {
+    if amount_in == 0 {
+        return Err(MathError::ZeroAmount);
+    }
+
+    let (rin, rout) = if token_in_is_0 { (r0, r1) } else { (r1, r0) };
+    let (vol_1e12, shallow_1e12) = pool_signals(params, r0, r1)?;
+
+    // --- slippage: realized price impact, or the proxy
+    // amountIn / (rin + amountIn) ---
+    let slip_1e12 = if params.slip_price_impact {
+        price_impact_1e12(amount_in, rin, rout)?
+    } else {
+        mul_div(
+            amount_in,
+            SCALE,
+            rin.checked_add(amount_in).ok_or(MathError::Overflow)?,
+            Rounding::Down,
+        )
+        .ok_or(MathError::Overflow)?
+    };
+
+    // --- inventory skew, signed by direction: positive when the input
+    // lands on the side the pool already holds too much of ---
+    let skew_1e12 = inventory_skew_1e12(r0, r1, params.ema_price_1e18)?;
+    let trade_skew_1e12 = if token_in_is_0 { skew_1e12 } else { -skew_1e12 };
+
+    let fee_ppm = fee_from_signals(
+        params,
+        vol_1e12,
+        slip_1e12,
+        shallow_1e12,
+        oracle_conf_1e12,
+        trade_skew_1e12,
+    )?;
+    Ok((fee_ppm, vol_1e12, slip_1e12, shallow_1e12))
+}
+
+/// The fee as amount_in -> 0: `compute_dynamic_fee` with a zero slip
+/// signal, i.e. the pool's current fee before any price impact. The skew
+/// term depends on the direction and is left out. Returns
+/// (fee_ppm, vol_1e12, shallow_1e12).
+pub fn compute_marginal_fee(
+    params: &PoolParams,
+    r0: u128,
+    r1: u128,
+    oracle_conf_1e12: u128,
+) -> MathResult<(u32, u128, u128)> {
+    let (vol_1e12, shallow_1e12) = pool_signals(params, r0, r1)?;
+    let fee_ppm = fee_from_signals(params, vol_1e12, 0, shallow_1e12, oracle_conf_1e12, 0)?;
+    Ok((fee_ppm, vol_1e12, shallow_1e12))
+}
+
+/// Absolute cap the round-trip penalty can raise a fee to.
+pub const REVERSION_FEE_CAP_BPS: u16 = 1_000;
+
+/// Round-trip penalty inputs: the `Pool` reversion fields.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReversionParams {
+    pub window_slots: u64,
+    pub band_bps: u16,
+    pub penalty_bps: u16,
+}
+
+/// A recent swap as the reversion check sees it; slot 0 = none yet.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LastTrade {
+    pub token_in_is_0: bool,
+    pub amount_out: u64,
+    pub slot: u64,
+}
+
+/// Penalty owed by a swap that reverses any of the `recent` ones: the
+/// opposite direction, at most `window_slots` later, and an input within
+/// `band_bps` of that swap's output (the same token). Checking every recent
+/// swap, not just the last, lets a sandwich's back-run be caught with the
+/// victim's trade in between. 0 when any parameter is zero.
+pub fn reversion_penalty_bps(
+    params: &ReversionParams,
+    recent: &[LastTrade],
+    token_in_is_0: bool,
+    amount_in: u64,
+    slot: u64,
+) -> u16 {
+    if params.window_slots == 0 || params.band_bps == 0 || params.penalty_bps == 0 {
+        return 0;
+    }
+    let reverses = |last: &LastTrade| {
+        let diff = (amount_in as u128).abs_diff(last.amount_out as u128);
+        last.slot != 0
+            && token_in_is_0 != last.token_in_is_0
+            && slot.saturating_sub(last.slot) <= params.window_slots
+            && diff * BPS_DENOM as u128 <= last.amount_out as u128 * params.band_bps as u128
+    };
+    if recent.iter().any(reverses) {
+        params.penalty_bps
+    } else {
+        0
+    }
+}
+
+/// `fee_ppm` plus `penalty_bps`, clamped at REVERSION_FEE_CAP_BPS; the
+/// penalty never lowers a dynamic fee that is already above the cap.
+pub fn with_reversion_penalty(fee_ppm: u32, penalty_bps: u16) -> u32 {
+    fee_ppm
+        .saturating_add(bps_to_ppm(penalty_bps))
+        .min(bps_to_ppm(REVERSION_FEE_CAP_BPS))
+        .max(fee_ppm)
+}
+
+/// The trade-size independent signals: (vol_1e12, shallow_1e12).
+fn pool_signals(params: &PoolParams, r0: u128, r1: u128) -> MathResult<(u128, u128)> {
+    // --- volatility proxy: pre-trade spot vs EMA ---
+    let price_now = mul_div(r1, PRICE_SCALE, r0, Rounding::Down).ok_or(MathError::Overflow)?;
+    let vol_1e12 = ema_deviation_1e12(price_now, params.ema_price_1e18)?;
+
+    // --- shallow-depth: below the target depth, or the proxy
+    // 1 - minRes / (minRes + K). Both read the smoothed depth when there
+    // is one, so a liquidity dip right before a swap barely moves it ---
+    let depth0 = r0.saturating_mul(2);
+    let smoothed = params.ema_depth0 > 0 && depth0 > 0;
+    let shallow_1e12 = if params.shallow_target_depth0 > 0 {
+        let depth0 = if smoothed { params.ema_depth0 } else { depth0 };
+        target_depth_shallow_1e12(depth0, params.shallow_target_depth0)?
+    } else {
+        // reserves at the current price scaled to the smoothed depth
+        let min_res = if smoothed {
+            mul_div(u128::min(r0, r1), params.ema_depth0, depth0, Rounding::Down)
+                .ok_or(MathError::Overflow)?
+        } else {
+            u128::min(r0, r1)
+        };
+        let k = if params.shallow_depth_k == 0 {
+            DEFAULT_SHALLOW_DEPTH_K
+        } else {
+            params.shallow_depth_k
+        };
+        SCALE
+            - mul_div(min_res, SCALE, min_res.saturating_add(k), Rounding::Down)
+                .ok_or(MathError::Overflow)?
+    };
+    Ok((vol_1e12, shallow_1e12))
+}
+
+/// Weighted sum of the signals on top of min_fee_ppm, clamped to
+/// max_fee_ppm. The skew term is signed: a negative one rebates the other
+/// terms, but never below min_fee_ppm.
+fn fee_from_signals(
+    params: &PoolParams,
+    vol_1e12: u128,
+    slip_1e12: u128,
+    shallow_1e12: u128,
+    oracle_conf_1e12: u128,
+    trade_skew_1e12: i128,
+) -> MathResult<u32> {
+    // Linear combo (ppm) + clamp; a signal past 100% (e.g. spot 50x the
+    // EMA) weighs no more than 100%. The terms are summed at 1e12 and
+    // divided once, so rounding cannot make a larger trade (higher slip,
+    // everything else fixed) pay a lower fee. Each term is at most
+    // u16::MAX * 100 * 1e12, so the sum cannot overflow.
+    let term = |coeff_bps_per1e12: u16, signal_1e12: u128| {
+        bps_to_ppm(coeff_bps_per1e12) as u128 * u128::min(signal_1e12, SCALE)
+    };
+    let signals = term(params.beta_vol_bps_per1e12, vol_1e12)
+        + term(params.gamma_slip_bps_per1e12, slip_1e12)
+        + term(params.delta_shallow_bps_per1e12, shallow_1e12)
+        + term(params.epsilon_oracle_bps_per1e12, oracle_conf_1e12);
+    let skew = term(params.zeta_skew_bps_per1e12, trade_skew_1e12.unsigned_abs());
+    let dyn_sum = if trade_skew_1e12 >= 0 {
+        signals + skew
+    } else {
+        signals.saturating_sub(skew)
+    };
+    let dyn_part_ppm = dyn_sum / SCALE;
+
+    let raw_ppm = (params.min_fee_ppm as u128)
+        .saturating_add(dyn_part_ppm)
+        .min(params.max_fee_ppm as u128);
+    u32::try_from(raw_ppm).map_err(|_| MathError::Overflow)
+}
+
+/// Golden vectors: `test_vectors/math.json` holds curated inputs with this
+/// module's outputs for them, frozen. Re-implementations of the math (the
+/// wasm build, the web app, research scripts) check against the same file.
+/// Numbers are decimal strings so no reader loses precision. Any change in
+/// behavior fails `frozen_vectors_match`; regenerate deliberately with
+/// `cargo test --lib math::tests::regenerate_vectors -- --ignored` and
+/// review the fixture diff.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    const VECTORS: &str = include_str!("../test_vectors/math.json");
+    const VECTORS_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_vectors/math.json");
+    const U64_MAX: u128 = u64::MAX as u128;
+
+    #[test]
+    fn mul_div_keeps_the_full_product_and_rounds_as_asked() {
+        assert_eq!(mul_div(u128::MAX, u128::MAX, u128::MAX, Rounding::Down), Some(u128::MAX));
+        assert_eq!(mul_div(u128::MAX, 2, 4, Rounding::Down), Some(u128::MAX / 2));
+        assert_eq!(mul_div(7, 3, 2, Rounding::Down), Some(10));
+        assert_eq!(mul_div(7, 3, 2, Rounding::Up), Some(11));
+        assert_eq!(mul_div(6, 3, 2, Rounding::Up), Some(9));
+        assert_eq!(mul_div(u128::MAX, u128::MAX, 1, Rounding::Down), None);
+        assert_eq!(mul_div(u128::MAX, 1, 1, Rounding::Up), Some(u128::MAX));
+        assert_eq!(mul_div(1, 1, 0, Rounding::Down), None);
+    }
+
+    #[test]
+    fn u256_multiplies_and_divides_across_the_limbs() {
+        // (2^128 - 1)^2 = 2^256 - 2^129 + 1
+        let square = U256::mul(u128::MAX, u128::MAX);
+        assert_eq!(square, U256 { hi: u128::MAX - 1, lo: 1 });
+        assert_eq!(square.div_rem(u128::MAX), Some((u128::MAX, 0)));
+        assert_eq!(U256::mul(1 << 64, 1 << 64), U256 { hi: 1, lo: 0 });
+        assert_eq!(U256::mul(1 << 64, 1 << 64).div_rem(3), Some((u128::MAX / 3, 1)));
+        assert_eq!(square.div_rem(u128::MAX - 1), None);
+        assert!(U256::mul(2, 1 << 127) > U256::mul(u128::MAX, 1));
+    }
+
+    #[test]
+    fn isqrt_floors_at_perfect_squares_and_between_them() {
+        for (y, root) in [(0, 0), (1, 1), (3, 1), (4, 2), (8, 2), (9, 3), (1 << 64, 1 << 32)] {
+            assert_eq!(isqrt(y), root, "isqrt({y})");
+        }
+        assert_eq!(isqrt(U64_MAX * U64_MAX), U64_MAX);
+        assert_eq!(isqrt(U64_MAX * U64_MAX - 1), U64_MAX - 1);
+        assert_eq!(isqrt(u128::MAX), U64_MAX);
+    }
+
+    #[test]
+    fn bps_and_ppm_convert_without_understating_the_fee() {
+        assert_eq!(bps_to_ppm(30), 3_000);
+        assert_eq!(bps_to_ppm(u16::MAX), 6_553_500);
+        assert_eq!(ppm_to_bps(3_000), 30);
+        assert_eq!(ppm_to_bps(3_001), 31);
+        assert_eq!(ppm_to_bps(0), 0);
+        assert_eq!(ppm_to_bps(u32::MAX), u16::MAX);
+    }
+
+    #[test]
+    fn spot_prices_in_both_directions() {
+        assert_eq!(spot_price_1e18(1_000, 4_000), Ok(4 * PRICE_SCALE));
+        assert_eq!(spot_price_0_in_1(1_000, 4_000), Ok(4 * SCALE as u64));
+        assert_eq!(spot_price_1_in_0(1_000, 4_000), Ok(SCALE as u64 / 4));
+        // 1/3 floors on the 1e12 grid in each direction separately
+        assert_eq!(spot_price_0_in_1(3, 1), Ok(333_333_333_333));
+        assert_eq!(spot_price_1_in_0(1, 3), Ok(333_333_333_333));
+        assert_eq!(spot_price_1e18(0, 1), Err(MathError::NoLiquidity));
+        assert_eq!(spot_price_1e18(1, 0), Err(MathError::NoLiquidity));
+        assert_eq!(price_to_1e12(u128::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn k_growth_telescopes_and_ignores_an_empty_pool() {
+        assert_eq!(sqrt_k(1_000_000, 4_000_000), 2_000_000);
+        assert_eq!(accrue_k_growth(SCALE, 0, 5), Ok(SCALE));
+        let once = accrue_k_growth(SCALE, 1_000, 1_010).unwrap();
+        assert_eq!(once, 1_010_000_000_000);
+        assert_eq!(accrue_k_growth(once, 1_010, 1_111), Ok(1_111_000_000_000));
+        assert_eq!(growth_between_1e12(SCALE, 1_111_000_000_000), Ok(1_111_000_000_000));
+        assert_eq!(growth_between_1e12(0, SCALE), Err(MathError::ZeroAmount));
+    }
+
+    #[test]
+    fn payouts_and_withdrawal_shares_round_for_the_pool() {
+        assert_eq!(amounts_for_shares(1, 10, 20, 3), Ok((3, 6)));
+        assert_eq!(amounts_for_shares(3, 10, 20, 3), Ok((10, 20)));
+        // ceil(4 * 3 / 10) = 2 shares, which pay out (6, 13)
+        assert_eq!(shares_for_withdrawal(4, 0, 10, 20, 3), Ok(2));
+        assert_eq!(amounts_for_shares(2, 10, 20, 3), Ok((6, 13)));
+        assert_eq!(shares_for_withdrawal(0, 0, 10, 20, 3), Ok(0));
+        assert_eq!(shares_for_withdrawal(11, 0, 10, 20, 3), Err(MathError::NoLiquidity));
+    }
+
+    #[test]
+    fn impermanent_loss_of_a_2x_move_is_2_sqrt_2_over_3_minus_1() {
+        // 1e6 of each at price 1; at price 2 the pool holds 1e6/sqrt(2) of
+        // token0 and 1e6*sqrt(2) of token1, floored
+        let (out0, out1) = (707_106, 1_414_213);
+        // hodl 2 * 1e6 + 1e6; lp 2 * 707_106 + 1_414_213
+        // il = 2 * sqrt(2) / 3 - 1 = -5.719%, truncated to -571 bps
+        assert_eq!(
+            impermanent_loss(1_000_000, 1_000_000, out0, out1, 2 * SCALE as u64),
+            Ok((3_000_000, 2_828_425, -571))
+        );
+        // the same move the other way: the token0 price halves
+        assert_eq!(
+            impermanent_loss(1_000_000, 1_000_000, out1, out0, SCALE as u64 / 2),
+            Ok((1_500_000, 1_414_212, -571))
+        );
+        // no move, no loss; nothing held, nothing to compare
+        assert_eq!(impermanent_loss(10, 10, 10, 10, SCALE as u64), Ok((20, 20, 0)));
+        assert_eq!(impermanent_loss(0, 0, 5, 5, SCALE as u64), Ok((0, 10, 0)));
+    }
+
+    #[test]
+    fn ratio_helpers_use_the_reduced_reserve_ratio() {
+        assert!(ratio_matches(1_000, 4_000, 250, 1_000));
+        assert!(!ratio_matches(1_000, 4_000, 250, 1_001));
+        assert!(ratio_matches(u64::MAX, u64::MAX, u64::MAX, u64::MAX));
+        assert_eq!(ratio_matched_amounts(1_000, 1_000, 6, 4), (999, 666));
+        assert_eq!(ratio_matched_amounts(7, 100, 3, 5), (6, 10));
+        assert_eq!(ratio_matched_amounts(7, 100, 0, 5), (7, 100));
+    }
+
+    #[test]
+    fn amount_in_for_an_output_is_the_least_that_buys_it() {
+        let (rin, rout) = (1_000_000_000u128, 1_000_000_000u128);
+        let amount_in = get_amount_in(996_006, rin, rout, 3_000).unwrap();
+        assert_eq!(get_amount_out(amount_in, rin, rout, 3_000).unwrap().0, 996_006);
+        assert!(get_amount_out(amount_in - 1, rin, rout, 3_000).unwrap().0 < 996_006);
+        assert_eq!(get_amount_in(0, rin, rout, 3_000), Err(MathError::ZeroAmount));
+        assert_eq!(get_amount_in(1_000_000_000, rin, rout, 3_000), Err(MathError::NoLiquidity));
+        assert_eq!(get_amount_in(1, rin, rout, 1_000_001), Err(MathError::Overflow));
+    }
+
+    #[test]
+    fn swap_amounts_reports_the_fee_in_the_side_it_is_charged() {
+        // 1000 in at 0.3%: 3 stay as input fee, or 3 of the 999 gross out
+        assert_eq!(swap_amounts(1_000, 1_000_000_000, 1_000_000_000, 3_000, false), Ok((996, 3)));
+        assert_eq!(swap_amounts(1_000, 1_000_000_000, 1_000_000_000, 3_000, true), Ok((996, 3)));
+        assert_eq!(get_amount_out_fee_on_output(1_000, 1_000_000_000, 1_000_000_000, 0), Ok((999, 0)));
+    }
+
+    #[test]
+    fn k_checks_hold_exactly_at_the_boundary() {
+        // 100 fee on the input side after a swap leaving it at 1_100
+        assert_eq!(expected_k_growth(1_000_000, 1_100, 100), Ok(1_100_000));
+        assert_eq!(expected_k_growth(1_000_000, 100, 100), Err(MathError::NoLiquidity));
+        assert_eq!(expected_k_growth(1_000_000, 99, 100), Err(MathError::Overflow));
+        // untouched balances keep k exactly
+        assert_eq!(k_with_fee_holds((1_000, 1_000), (1_000, 1_000), (0, 0), 10_000), Ok(true));
+        // 101 paid in at 1%: 1_099.99 * 910 clears 1e6, 1_099.99 * 909 does not,
+        // though 1_101 * 909 would without the fee
+        assert_eq!(k_with_fee_holds((1_000, 1_000), (1_101, 910), (101, 0), 10_000), Ok(true));
+        assert_eq!(k_with_fee_holds((1_000, 1_000), (1_101, 909), (101, 0), 10_000), Ok(false));
+        assert_eq!(k_with_fee_holds((1_000, 1_000), (1_101, 909), (101, 0), 0), Ok(true));
+    }
+
+    #[test]
+    fn deviation_and_skew_are_symmetric_in_direction() {
+        assert_eq!(ema_deviation_1e12(2 * PRICE_SCALE, PRICE_SCALE), Ok(SCALE));
+        assert_eq!(ema_deviation_1e12(PRICE_SCALE / 2, PRICE_SCALE), Ok(SCALE));
+        assert_eq!(ema_deviation_1e12(PRICE_SCALE, 0), Ok(0));
+        assert_eq!(ema_deviation_1e12(0, u128::MAX), Ok(u128::MAX));
+        // 2 of token0 worth 1 each against 1 of token1: (2 - 1) / 3
+        assert_eq!(inventory_skew_1e12(2, 1, PRICE_SCALE), Ok(333_333_333_333));
+        assert_eq!(inventory_skew_1e12(1, 2, PRICE_SCALE), Ok(-333_333_333_333));
+        assert_eq!(inventory_skew_1e12(1, 2, 0), Ok(0));
+        assert_eq!(inventory_skew_1e12(0, 0, PRICE_SCALE), Ok(0));
+    }
+
+    #[test]
+    fn post_trade_deviation_reads_the_reserves_the_trade_leaves() {
+        // 1000/1000 at an EMA of 1: 1000 in, 500 out leaves 2000/500
+        assert_eq!(post_trade_deviation_1e12(PRICE_SCALE, true, 1_000, 1_000, 1_000, 500), Ok(3 * SCALE));
+        assert_eq!(post_trade_deviation_1e12(PRICE_SCALE, false, 1_000, 1_000, 1_000, 500), Ok(3 * SCALE));
+        assert_eq!(
+            post_trade_deviation_1e12(PRICE_SCALE, true, 1_000, 1_000, 1, 1_000),
+            Err(MathError::NoLiquidity)
+        );
+    }
+
+    #[test]
+    fn breaker_verdict_follows_its_table() {
+        use BreakerVerdict::*;
+        assert_eq!(breaker_verdict(true, None, 0, 0), ManualTrip);
+        assert_eq!(breaker_verdict(false, None, u128::MAX, u128::MAX), Open);
+        assert_eq!(breaker_verdict(false, Some(10), 11, 0), PreTradeDeviation);
+        assert_eq!(breaker_verdict(false, Some(10), 10, 11), PostTradeDeviation);
+        assert_eq!(breaker_verdict(false, Some(10), 10, 10), Open);
+        assert!(breaker_verdict(false, Some(10), 0, 0).is_open());
+    }
+
+    #[test]
+    fn shallow_signals_ramp_from_empty_to_deep() {
+        assert_eq!(target_depth_shallow_1e12(0, 100), Ok(SCALE));
+        assert_eq!(target_depth_shallow_1e12(25, 100), Ok(SCALE * 3 / 4));
+        assert_eq!(target_depth_shallow_1e12(100, 100), Ok(0));
+        assert_eq!(shallow_depth_k(6, 9), 1_000_000_000);
+        assert_eq!(shallow_depth_k(0, 0), 1_000);
+        // at reserves equal to the depth factor the proxy is 50%
+        let params = PoolParams { shallow_depth_k: 1_000, ..Default::default() };
+        assert_eq!(compute_marginal_fee(&params, 1_000, 1_000, 0), Ok((0, 0, SCALE / 2)));
+    }
+
+    #[test]
+    fn reversion_penalty_needs_a_reversal_inside_the_window_and_band() {
+        let params = ReversionParams { window_slots: 5, band_bps: 100, penalty_bps: 50 };
+        let last = LastTrade { token_in_is_0: true, amount_out: 10_000, slot: 100 };
+        assert_eq!(reversion_penalty_bps(&params, &[last], false, 10_000, 105), 50);
+        assert_eq!(reversion_penalty_bps(&params, &[last], false, 10_100, 101), 50);
+        assert_eq!(reversion_penalty_bps(&params, &[last], false, 10_101, 101), 0);
+        assert_eq!(reversion_penalty_bps(&params, &[last], false, 10_000, 106), 0);
+        assert_eq!(reversion_penalty_bps(&params, &[last], true, 10_000, 101), 0);
+        assert_eq!(reversion_penalty_bps(&params, &[LastTrade::default()], false, 10_000, 1), 0);
+        assert_eq!(reversion_penalty_bps(&params, &[], false, 10_000, 101), 0);
+        let off = ReversionParams { penalty_bps: 0, ..params };
+        assert_eq!(reversion_penalty_bps(&off, &[last], false, 10_000, 101), 0);
+        // a trade in between, either way, does not hide the reversal
+        let victim = LastTrade { token_in_is_0: true, amount_out: 3_000, slot: 100 };
+        assert_eq!(reversion_penalty_bps(&params, &[victim, last], false, 10_000, 101), 50);
+        assert_eq!(reversion_penalty_bps(&params, &[last, victim], false, 10_000, 101), 50);
+        assert_eq!(reversion_penalty_bps(&params, &[victim], false, 10_000, 101), 0);
+        assert_eq!(with_reversion_penalty(3_000, 50), 8_000);
+        assert_eq!(with_reversion_penalty(99_000, 50), 100_000);
+        assert_eq!(with_reversion_penalty(120_000, 50), 120_000);
+    }
+
+    type Eval = fn(&Value) -> Value;
+    const SECTIONS: [(&str, Eval); 4] = [
+        ("compute_dynamic_fee", eval_dynamic_fee),
+        ("get_amount_out", eval_amount_out),
+        ("shares_for_deposit", eval_shares_for_deposit),
+        ("ema_update", eval_ema_update),
+    ];
+
+    fn num(value: &Value) -> u128 {
+        value.as_str().expect("numbers are decimal strings").parse().unwrap()
+    }
+
+    fn text(n: impl ToString) -> Value {
+        Value::String(n.to_string())
+    }
+
+    fn outcome<T>(result: MathResult<T>, ok: impl FnOnce(T) -> Value) -> Value {
+        match result {
+            Ok(value) => ok(value),
+            Err(error) => json!({ "error": format!("{error:?}") }),
+        }
+    }
+
+    fn params_json(p: &PoolParams) -> Value {
+        json!({
+            "min_fee_ppm": p.min_fee_ppm,
+            "max_fee_ppm": p.max_fee_ppm,
+            "beta_vol_bps_per1e12": p.beta_vol_bps_per1e12,
+            "gamma_slip_bps_per1e12": p.gamma_slip_bps_per1e12,
+            "delta_shallow_bps_per1e12": p.delta_shallow_bps_per1e12,
+            "epsilon_oracle_bps_per1e12": p.epsilon_oracle_bps_per1e12,
+            "zeta_skew_bps_per1e12": p.zeta_skew_bps_per1e12,
+            "ema_price_1e18": text(p.ema_price_1e18),
+            "shallow_depth_k": text(p.shallow_depth_k),
+            "slip_price_impact": p.slip_price_impact,
+            "shallow_target_depth0": text(p.shallow_target_depth0),
+            "ema_depth0": text(p.ema_depth0),
+        })
+    }
+
+    fn params_from(v: &Value) -> PoolParams {
+        let small = |key: &str| v[key].as_u64().unwrap();
+        PoolParams {
+            min_fee_ppm: small("min_fee_ppm") as u32,
+            max_fee_ppm: small("max_fee_ppm") as u32,
+            beta_vol_bps_per1e12: small("beta_vol_bps_per1e12") as u16,
+            gamma_slip_bps_per1e12: small("gamma_slip_bps_per1e12") as u16,
+            delta_shallow_bps_per1e12: small("delta_shallow_bps_per1e12") as u16,
+            epsilon_oracle_bps_per1e12: small("epsilon_oracle_bps_per1e12") as u16,
+            zeta_skew_bps_per1e12: small("zeta_skew_bps_per1e12") as u16,
+            ema_price_1e18: num(&v["ema_price_1e18"]),
+            shallow_depth_k: num(&v["shallow_depth_k"]),
+            slip_price_impact: v["slip_price_impact"].as_bool().unwrap(),
+            shallow_target_depth0: num(&v["shallow_target_depth0"]),
+            ema_depth0: num(&v["ema_depth0"]),
+        }
+    }
+
+    fn eval_dynamic_fee(case: &Value) -> Value {
+        let result = compute_dynamic_fee(
+            &params_from(&case["params"]),
+            case["token_in_is_0"].as_bool().unwrap(),
+            num(&case["amount_in"]),
+            num(&case["r0"]),
+            num(&case["r1"]),
+            num(&case["oracle_conf_1e12"]),
+        );
+        outcome(result, |(fee_ppm, vol_1e12, slip_1e12, shallow_1e12)| {
+            json!({
+                "fee_ppm": fee_ppm,
+                "vol_1e12": text(vol_1e12),
+                "slip_1e12": text(slip_1e12),
+                "shallow_1e12": text(shallow_1e12),
+            })
+        })
+    }
+
+    fn eval_amount_out(case: &Value) -> Value {
+        let amount_in = num(&case["amount_in"]) as u64;
+        let (rin, rout) = (num(&case["rin"]), num(&case["rout"]));
+        let fee_ppm = case["fee_ppm"].as_u64().unwrap() as u32;
+        if case["fee_on_output"].as_bool().unwrap() {
+            outcome(get_amount_out_fee_on_output(amount_in, rin, rout, fee_ppm), |(amount_out, fee)| {
+                json!({ "amount_out": text(amount_out), "fee_amount": text(fee) })
+            })
+        } else {
+            outcome(get_amount_out(amount_in, rin, rout, fee_ppm), |(amount_out, after_fee)| {
+                json!({ "amount_out": text(amount_out), "amount_in_after_fee": text(after_fee) })
+            })
+        }
+    }
+
+    fn eval_shares_for_deposit(case: &Value) -> Value {
+        let arg = |key: &str| num(&case[key]) as u64;
+        let result = shares_for_deposit(
+            arg("amount0"),
+            arg("amount1"),
+            arg("reserve0"),
+            arg("reserve1"),
+            arg("total_supply"),
+        );
+        outcome(result, |shares| json!({ "shares": text(shares) }))
+    }
+
+    fn eval_ema_update(case: &Value) -> Value {
+        let mut ema = num(&case["ema_1e18"]);
+        ema_update(&mut ema, num(&case["alpha_1e12"]) as u64, num(&case["price_1e18"]));
+        json!({ "ema_1e18": text(ema) })
+    }
+
+    #[test]
+    fn frozen_vectors_match() {
+        let vectors: Value = serde_json::from_str(VECTORS).unwrap();
+        let mut checked = 0;
+        for (section, eval) in SECTIONS {
+            for case in vectors[section].as_array().unwrap() {
+                assert_eq!(eval(case), case["expected"], "{section}: {}", case["name"]);
+                checked += 1;
+            }
+        }
+        assert!(checked >= 50, "only {checked} vectors");
+    }
+
+    /// The curated inputs `regenerate_vectors` computes the outputs for.
+    fn vector_inputs() -> Value {
+        let base = PoolParams {
+            min_fee_ppm: 3_000,
+            max_fee_ppm: 30_000,
+            beta_vol_bps_per1e12: 100,
+            gamma_slip_bps_per1e12: 100,
+            shallow_depth_k: shallow_depth_k(6, 6),
+            ..Default::default()
+        };
+        let at_ema = PoolParams { ema_price_1e18: PRICE_SCALE, ..base };
+        let fee = |name: &str, params: PoolParams, token_in_is_0: bool, amount_in: u128, r0: u128, r1: u128, conf: u128| {
+            json!({
+                "name": name,
+                "params": params_json(&params),
+                "token_in_is_0": token_in_is_0,
+                "amount_in": text(amount_in),
+                "r0": text(r0),
+                "r1": text(r1),
+                "oracle_conf_1e12": text(conf),
+            })
+        };
+        let e9 = 1_000_000_000u128;
+        let compute_dynamic_fee = vec![
+            fee("1-lamport trade, no EMA", base, true, 1, e9, e9, 0),
+            fee("1-lamport trade at the EMA", at_ema, true, 1, e9, e9, 0),
+            fee("0.1% of the in-side reserve", at_ema, true, e9 / 1_000, e9, e9, 0),
+            fee("1% of the in-side reserve", at_ema, false, e9 / 100, e9, e9, 0),
+            fee("half the in-side reserve", at_ema, true, e9 / 2, e9, e9, 0),
+            fee("as much as the in-side reserve", at_ema, true, e9, e9, e9, 0),
+            fee(
+                "fee clamped at max by a steep slip weight",
+                PoolParams { gamma_slip_bps_per1e12: u16::MAX, ..at_ema },
+                true,
+                e9,
+                e9,
+                e9,
+                0,
+            ),
+            fee("spot 10% above the EMA", at_ema, true, 1_000, e9, e9 + e9 / 10, 0),
+            fee("spot 50x the EMA: vol signal clamped", at_ema, true, 1_000, e9, 50 * e9, 0),
+            fee(
+                "EMA at 0 ignores vol and skew",
+                PoolParams { zeta_skew_bps_per1e12: 500, ..base },
+                true,
+                1_000,
+                2 * e9,
+                e9,
+                0,
+            ),
+            fee("near-u64 reserves, 1-lamport trade", at_ema, true, 1, U64_MAX - 1, U64_MAX - 1, 0),
+            fee("near-u64 trade into a tiny pool", base, false, U64_MAX, 1_000, 1_000, 0),
+            fee("empty in-side reserve", base, true, 1_000, 0, e9, 0),
+            fee("zero amount", base, true, 0, e9, e9, 0),
+            fee(
+                "price impact mode, 1-lamport trade paying out nothing",
+                PoolParams { slip_price_impact: true, ..base },
+                true,
+                1,
+                e9,
+                10,
+                0,
+            ),
+            fee(
+                "price impact mode, 10% of the reserve",
+                PoolParams { slip_price_impact: true, ..at_ema },
+                true,
+                e9 / 10,
+                e9,
+                e9,
+                0,
+            ),
+            fee(
+                "oracle confidence of 1%",
+                PoolParams { epsilon_oracle_bps_per1e12: 200, ..at_ema },
+                true,
+                1_000,
+                e9,
+                e9,
+                10_000_000_000,
+            ),
+            fee(
+                "shallow pool below the depth factor",
+                PoolParams { delta_shallow_bps_per1e12: 300, ..at_ema },
+                true,
+                1_000,
+                1_000_000,
+                1_000_000,
+                0,
+            ),
+            fee(
+                "half of the target depth",
+                PoolParams { delta_shallow_bps_per1e12: 300, shallow_target_depth0: 4 * e9, ..at_ema },
+                true,
+                1_000,
+                e9,
+                e9,
+                0,
+            ),
+            fee(
+                "smoothed depth read instead of the reserves",
+                PoolParams { delta_shallow_bps_per1e12: 300, ema_depth0: 200_000_000, ..at_ema },
+                true,
+                1_000,
+                e9,
+                e9,
+                0,
+            ),
+            fee(
+                "skew charged to a trade deepening the imbalance",
+                PoolParams { zeta_skew_bps_per1e12: 500, ..at_ema },
+                true,
+                1_000,
+                2 * e9,
+                e9,
+                0,
+            ),
+            fee(
+                "skew rebated, down to min, to a trade easing it",
+                PoolParams { zeta_skew_bps_per1e12: 500, ..at_ema },
+                false,
+                1_000,
+                2 * e9,
+                e9,
+                0,
+            ),
+            fee(
+                "min equal to max pins the fee",
+                PoolParams { min_fee_ppm: 10_000, max_fee_ppm: 10_000, ..at_ema },
+                true,
+                e9,
+                e9,
+                e9,
+                0,
+            ),
+        ];
+
+        let out = |name: &str, amount_in: u128, rin: u128, rout: u128, fee_ppm: u32, fee_on_output: bool| {
+            json!({
+                "name": name,
+                "amount_in": text(amount_in),
+                "rin": text(rin),
+                "rout": text(rout),
+                "fee_ppm": fee_ppm,
+                "fee_on_output": fee_on_output,
+            })
+        };
+        let get_amount_out = vec![
+            out("1 lamport in pays out nothing", 1, e9, e9, 3_000, false),
+            out("small trade", 1_000, e9, e9, 3_000, false),
+            out("0.1% of the reserve", 1_000_000, e9, e9, 3_000, false),
+            out("as much as the in-side reserve", e9, e9, e9, 3_000, false),
+            out("no fee", 1_000_000, e9, 3 * e9, 0, false),
+            out("fee just below 100%", 1_000_000, e9, e9, 999_999, false),
+            out("fee above 100%", 1_000_000, e9, e9, 1_000_001, false),
+            out("near-u64 reserves", e9, U64_MAX - 1, U64_MAX - 1, 3_000, false),
+            out("near-u64 trade into a tiny pool", U64_MAX - 1_000, 1_000, U64_MAX - 1, 30_000, false),
+            out("1-lamport output side", e9, e9, 1, 3_000, false),
+            out("1 lamport into an empty side", 1, 0, e9, 3_000, false),
+            out("fee on output, 1 lamport in", 1, e9, e9, 3_000, true),
+            out("fee on output, small trade", 1_000, e9, e9, 3_000, true),
+            out("fee on output, 0.1% of the reserve", 1_000_000, e9, e9, 3_000, true),
+            out("fee on output, no fee", 1_000_000, e9, 3 * e9, 0, true),
+            out("fee on output, near-u64 reserves", e9, U64_MAX - 1, U64_MAX - 1, 3_000, true),
+        ];
+
+        let deposit = |name: &str, amount0: u128, amount1: u128, reserve0: u128, reserve1: u128, total_supply: u128| {
+            json!({
+                "name": name,
+                "amount0": text(amount0),
+                "amount1": text(amount1),
+                "reserve0": text(reserve0),
+                "reserve1": text(reserve1),
+                "total_supply": text(total_supply),
+            })
+        };
+        let shares_for_deposit = vec![
+            deposit("bootstrap", 1_000_000, 4_000_000, 0, 0, 0),
+            deposit("bootstrap with 1 lamport each", 1, 1, 0, 0, 0),
+            deposit("bootstrap with one side empty", 1_000_000, 0, 0, 0, 0),
+            deposit("bootstrap with near-u64 amounts", U64_MAX, U64_MAX, 0, 0, 0),
+            deposit("pro rata", 500_000, 2_000_000, 1_000_000, 4_000_000, 2_000_000),
+            deposit("off ratio takes the smaller side", 500_000, 1_000_000, 1_000_000, 4_000_000, 2_000_000),
+            deposit("dust rounds to nothing", 1, 1, e9, e9, 1_000),
+            deposit("supply far above the reserves", 1, 1, 1_000, 1_000, U64_MAX / 2),
+            deposit("more shares than a u64 holds", U64_MAX, U64_MAX, 1, 1, 2),
+            deposit("near-u64 reserves", e9, e9, U64_MAX - 1, U64_MAX - 1, U64_MAX - 1),
+        ];
+
+        let ema = |name: &str, ema_1e18: u128, alpha_1e12: u128, price_1e18: u128| {
+            json!({
+                "name": name,
+                "ema_1e18": text(ema_1e18),
+                "alpha_1e12": text(alpha_1e12),
+                "price_1e18": text(price_1e18),
+            })
+        };
+        let alpha = 50_000_000_000;
+        let ema_update = vec![
+            ema("from 0", 0, alpha, PRICE_SCALE),
+            ema("towards a higher price", PRICE_SCALE, alpha, 2 * PRICE_SCALE),
+            ema("towards a lower price", PRICE_SCALE, alpha, PRICE_SCALE / 2),
+            ema("at the price", PRICE_SCALE, alpha, PRICE_SCALE),
+            ema("alpha 0 keeps the EMA", PRICE_SCALE, 0, 2 * PRICE_SCALE),
+            ema("alpha 1 jumps to the price", PRICE_SCALE, SCALE, 3 * PRICE_SCALE),
+            ema("alpha above 1 is capped", PRICE_SCALE, u64::MAX as u128, 3 * PRICE_SCALE),
+            ema("from 0 to u128::MAX", 0, SCALE - 1, u128::MAX),
+            ema("from u128::MAX to 0", u128::MAX, SCALE - 1, 0),
+        ];
+
+        json!({
+            "compute_dynamic_fee": compute_dynamic_fee,
+            "get_amount_out": get_amount_out,
+            "shares_for_deposit": shares_for_deposit,
+            "ema_update": ema_update,
+        })
+    }
+
+    #[test]
+    #[ignore = "rewrites the frozen vectors"]
+    fn regenerate_vectors() {
+        let mut vectors = vector_inputs();
+        for (section, eval) in SECTIONS {
+            for case in vectors[section].as_array_mut().unwrap() {
+                let expected = eval(case);
+                case["expected"] = expected;
+            }
+        }
+        let json = serde_json::to_string_pretty(&vectors).unwrap();
+        std::fs::write(VECTORS_PATH, json + "\n").unwrap();
+    }
+}
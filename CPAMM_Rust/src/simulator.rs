@@ -0,0 +1,161 @@
+//! Off-chain mirror of the pool state machine (`simulator` feature).
+//!
+//! `PoolSimulator` runs the program's own deposit and swap bodies
+//! (`apply_add_liquidity`, `apply_swap`) and mirrors `remove_liquidity`,
+//! returning the same `AmmError`s, so trade sequences can be replayed
+//! without a validator. Token transfers become moves on the virtual vault
+//! balances; a failed call leaves the state untouched, like a reverted
+//! transaction.
+
+use anchor_lang::prelude::*;
+
+use crate::math::{amounts_for_shares, ema_update, spot_price_1e18};
+use crate::{
+    apply_add_liquidity, apply_swap, next_event_seq, rebase_sqrt_k, record_update,
+    reset_if_drained, update_depth_ema, update_high_low, update_price_accumulators,
+};
+use crate::{AmmError, Pool};
+
+/// Result of a simulated swap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SimSwap {
+    pub amount_out: u64,
+    /// fee_ppm rounded up to whole bps
+    pub fee_bps: u16,
+    pub fee_ppm: u32,
+    pub fee_amount: u64,
+    pub fee_token_is_0: bool,
+}
+
+#[derive(Clone)]
+pub struct PoolSimulator {
+    pub pool: Pool,
+    /// Virtual vault balances; may exceed the reserves (donations)
+    pub vault0: u64,
+    pub vault1: u64,
+    /// Simulated cluster time
+    pub now: i64,
+    pub slot: u64,
+}
+
+impl PoolSimulator {
+    /// Start from a pool snapshot (e.g. a decoded on-chain account) with the
+    /// vaults holding exactly the reserves.
+    pub fn new(pool: Pool, now: i64, slot: u64) -> Self {
+        PoolSimulator {
+            vault0: pool.reserve0,
+            vault1: pool.reserve1,
+            pool,
+            now,
+            slot,
+        }
+    }
+
+    /// Advance the clock.
+    pub fn warp(&mut self, seconds: i64, slots: u64) {
+        self.now += seconds;
+        self.slot += slots;
+    }
+
+    fn clock(&self) -> Clock {
+        Clock {
+            slot: self.slot,
+            unix_timestamp: self.now,
+            ..Clock::default()
+        }
+    }
+
+    /// Run `f` on a copy and keep it only on success.
+    fn atomic<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        let mut next = self.clone();
+        let out = f(&mut next)?;
+        *self = next;
+        Ok(out)
+    }
+
+    /// Mirror of `add_liquidity`; returns the shares minted.
+    pub fn add_liquidity(&mut self, amount0: u64, amount1: u64) -> Result<u64> {
+        self.atomic(|sim| {
+            let clock = sim.clock();
+            let shares = apply_add_liquidity(&mut sim.pool, amount0, amount1, &clock)?;
+            sim.vault0 = sim.vault0.checked_add(amount0).ok_or(AmmError::MathOverflow)?;
+            sim.vault1 = sim.vault1.checked_add(amount1).ok_or(AmmError::MathOverflow)?;
+            next_event_seq(&mut sim.pool);
+            Ok(shares)
+        })
+    }
+
+    /// Mirror of `remove_liquidity`; returns the amounts paid out.
+    pub fn remove_liquidity(&mut self, shares: u64) -> Result<(u64, u64)> {
+        self.atomic(|sim| {
+            let clock = sim.clock();
+            let pool = &mut sim.pool;
+            require!(shares > 0, AmmError::ZeroShares);
+            require!(pool.total_lp_supply >= shares, AmmError::InsufficientLP);
+            update_price_accumulators(pool, clock.unix_timestamp)?;
+
+            let (amount0, amount1) =
+                amounts_for_shares(shares, pool.reserve0, pool.reserve1, pool.total_lp_supply)?;
+            pool.total_lp_supply -= shares;
+            sim.vault0 = sim.vault0.checked_sub(amount0).ok_or(AmmError::MathOverflow)?;
+            sim.vault1 = sim.vault1.checked_sub(amount1).ok_or(AmmError::MathOverflow)?;
+            pool.reserve0 -= amount0;
+            pool.reserve1 -= amount1;
+
+            if pool.reserve0 > 0 && pool.reserve1 > 0 {
+                let price = spot_price_1e18(pool.reserve0, pool.reserve1)?;
+                let alpha = pool.ema_alpha_1e12;
+                ema_update(&mut pool.ema_price_1e18, alpha, price);
+                pool.ema_updated_at = clock.unix_timestamp;
+            }
+            update_depth_ema(pool);
+            update_high_low(pool, clock.unix_timestamp);
+            rebase_sqrt_k(pool);
+            reset_if_drained(pool);
+            record_update(pool, &clock);
+            next_event_seq(pool);
+            Ok((amount0, amount1))
+        })
+    }
+
+    /// Mirror of `swap`: the deadline first, then `apply_swap`.
+    /// `oracle_conf_1e12` stands in for the oracle account (0 when the pool
+    /// has no feed).
+    pub fn swap(
+        &mut self,
+        token_in_is_0: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+        deadline: i64,
+        oracle_conf_1e12: u128,
+    ) -> Result<SimSwap> {
+        self.atomic(|sim| {
+            let clock = sim.clock();
+            require!(clock.unix_timestamp <= deadline, AmmError::DeadlineExceeded);
+            let event = apply_swap(
+                &mut sim.pool,
+                Pubkey::default(),
+                Pubkey::default(),
+                |_| Ok(oracle_conf_1e12),
+                token_in_is_0,
+                amount_in,
+                min_amount_out,
+                &clock,
+            )?;
+            let (vault_in, vault_out) = if token_in_is_0 {
+                (&mut sim.vault0, &mut sim.vault1)
+            } else {
+                (&mut sim.vault1, &mut sim.vault0)
+            };
+            *vault_in = vault_in.checked_add(amount_in).ok_or(AmmError::MathOverflow)?;
+            *vault_out = vault_out.checked_sub(event.amount_out).ok_or(AmmError::MathOverflow)?;
+            Ok(SimSwap {
+                amount_out: event.amount_out,
+                fee_bps: event.fee_bps,
+                fee_ppm: event.fee_ppm,
+                fee_amount: event.fee_amount,
+                fee_token_is_0: event.fee_token_is_0,
+            })
+        })
+    }
+}
@@ -0,0 +1,281 @@
+//! wasm-bindgen wrappers around `math` for client-side quoting (`wasm`
+//! feature). Quotes run the very functions the program executes, so the
+//! front-end cannot drift from on-chain results.
+//!
+//! Each quote takes either the raw `Pool` account bytes or the explicit
+//! fields. Like `get_pool_state`, quotes use the stored EMA and no oracle
+//! term; quotes from account bytes also charge the round-trip penalty
+//! the pool's recent swaps would cost at the given slot.
+
+use anchor_lang::AccountDeserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::math::{self, MathError, PoolParams};
+use crate::Pool;
+
+/// Result of `quote_swap`; signals at 1e12.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct SwapQuote {
+    pub amount_out: u64,
+    /// fee_ppm rounded up to whole bps
+    pub fee_bps: u16,
+    pub fee_ppm: u32,
+    /// in the input token, or the output token on fee-on-output pools
+    pub fee_amount: u64,
+    pub fee_token_is_0: bool,
+    pub vol_1e12: u64,
+    pub slip_1e12: u64,
+    pub shallow_1e12: u64,
+    pub breaker_would_trip: bool,
+    /// input is under the pool's minimum trade size (only from pool data)
+    pub below_min_trade: bool,
+    pub min_trade_amount: u64,
+    /// input is over the pool's per-swap cap (only from pool data)
+    pub above_max_trade: bool,
+    pub max_trade_bps_of_reserve: u16,
+    /// round-trip penalty included in fee_ppm (only from pool data)
+    pub reversion_penalty_bps: u16,
+}
+
+/// Result of `quote_marginal_fee`: the fee as amount_in -> 0.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct MarginalFeeQuote {
+    /// fee_ppm rounded up to whole bps
+    pub fee_bps: u16,
+    pub fee_ppm: u32,
+    pub vol_1e12: u64,
+    pub shallow_1e12: u64,
+    pub breaker_would_trip: bool,
+}
+
+/// Result of `quote_add_liquidity`: amounts actually pulled and shares minted.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct LiquidityQuote {
+    pub amount0: u64,
+    pub amount1: u64,
+    pub shares: u64,
+}
+
+fn js_error(e: MathError) -> JsError {
+    JsError::new(&format!("{:?}", e))
+}
+
+fn decode_pool(pool_account: &[u8]) -> Result<Pool, JsError> {
+    Pool::try_deserialize(&mut &pool_account[..]).map_err(|e| JsError::new(&e.to_string()))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn swap_quote(
+    params: &PoolParams,
+    breaker_vol_threshold_1e12: Option<u64>, // None = breaker disabled
+    reserve0: u64,
+    reserve1: u64,
+    token_in_is_0: bool,
+    amount_in: u64,
+    fee_on_output: bool,
+    reversion_penalty_bps: u16,
+) -> Result<SwapQuote, JsError> {
+    let (r0, r1) = (reserve0 as u128, reserve1 as u128);
+    let (fee_ppm, vol_1e12, slip_1e12, shallow_1e12) =
+        math::compute_dynamic_fee(params, token_in_is_0, amount_in as u128, r0, r1, 0)
+            .map_err(js_error)?;
+    let fee_ppm = math::with_reversion_penalty(fee_ppm, reversion_penalty_bps);
+    let (rin, rout) = if token_in_is_0 { (r0, r1) } else { (r1, r0) };
+    let (amount_out, fee_amount) =
+        math::swap_amounts(amount_in, rin, rout, fee_ppm, fee_on_output).map_err(js_error)?;
+    // the whole fee is counted as staying in the reserves: the protocol's
+    // share of it is not known here and only nudges the post-trade price
+    let post_vol_1e12 = math::post_trade_deviation_1e12(
+        params.ema_price_1e18,
+        token_in_is_0,
+        r0,
+        r1,
+        amount_in as u128,
+        amount_out as u128,
+    )
+    .unwrap_or(u128::MAX);
+    let verdict =
+        math::breaker_verdict(false, breaker_vol_threshold_1e12, vol_1e12, post_vol_1e12);
+    let to_u64 = |v: u128| u64::try_from(v).unwrap_or(u64::MAX);
+    Ok(SwapQuote {
+        amount_out,
+        fee_bps: math::ppm_to_bps(fee_ppm),
+        fee_ppm,
+        fee_amount,
+        fee_token_is_0: token_in_is_0 != fee_on_output,
+        vol_1e12: to_u64(vol_1e12),
+        slip_1e12: to_u64(slip_1e12),
+        shallow_1e12: to_u64(shallow_1e12),
+        breaker_would_trip: !verdict.is_open(),
+        below_min_trade: false,
+        min_trade_amount: 0,
+        above_max_trade: false,
+        max_trade_bps_of_reserve: 0,
+        reversion_penalty_bps,
+    })
+}
+
+fn add_liquidity_quote(
+    amount0_desired: u64,
+    amount1_desired: u64,
+    reserve0: u64,
+    reserve1: u64,
+    total_lp_supply: u64,
+) -> Result<LiquidityQuote, JsError> {
+    let (amount0, amount1) =
+        math::ratio_matched_amounts(amount0_desired, amount1_desired, reserve0, reserve1);
+    let shares = math::shares_for_deposit(amount0, amount1, reserve0, reserve1, total_lp_supply)
+        .map_err(js_error)?;
+    Ok(LiquidityQuote { amount0, amount1, shares })
+}
+
+/// Swap quote from the raw `Pool` account data for a swap landing in
+/// `slot`, which decides whether it still reverses a recent one.
+#[wasm_bindgen]
+pub fn quote_swap(
+    pool_account: &[u8],
+    token_in_is_0: bool,
+    amount_in: u64,
+    slot: u64,
+) -> Result<SwapQuote, JsError> {
+    let pool = decode_pool(pool_account)?;
+    let reversion_penalty_bps = math::reversion_penalty_bps(
+        &pool.reversion_params(),
+        &pool.recent_trades(),
+        token_in_is_0,
+        amount_in,
+        slot,
+    );
+    let mut quote = swap_quote(
+        &pool.fee_params(),
+        pool.breaker_threshold_1e12(),
+        pool.reserve0,
+        pool.reserve1,
+        token_in_is_0,
+        amount_in,
+        pool.fee_on_output,
+        reversion_penalty_bps,
+    )?;
+    quote.breaker_would_trip |= pool.manually_tripped;
+    quote.min_trade_amount = if token_in_is_0 {
+        pool.min_trade_amount0
+    } else {
+        pool.min_trade_amount1
+    };
+    quote.below_min_trade = amount_in < quote.min_trade_amount;
+    quote.max_trade_bps_of_reserve = pool.max_trade_bps_of_reserve;
+    let rin = if token_in_is_0 { pool.reserve0 } else { pool.reserve1 };
+    quote.above_max_trade = pool.max_trade_bps_of_reserve > 0
+        && amount_in as u128 * (math::BPS_DENOM as u128)
+            > rin as u128 * pool.max_trade_bps_of_reserve as u128;
+    Ok(quote)
+}
+
+/// Swap quote from explicit pool fields, without the round-trip penalty.
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments)]
+pub fn quote_swap_with_fields(
+    min_fee_ppm: u32,
+    max_fee_ppm: u32,
+    beta_vol_bps_per1e12: u16,
+    gamma_slip_bps_per1e12: u16,
+    delta_shallow_bps_per1e12: u16,
+    epsilon_oracle_bps_per1e12: u16,
+    zeta_skew_bps_per1e12: u16,
+    ema_price_1e18: u128,
+    breaker_vol_threshold_1e12: u64,
+    slip_price_impact: bool,
+    shallow_target_depth0: u64, // 0 = MinReserve mode
+    ema_depth0: u128,           // 0 = unsmoothed
+    reserve0: u64,
+    reserve1: u64,
+    decimals0: u8,
+    decimals1: u8,
+    token_in_is_0: bool,
+    amount_in: u64,
+    fee_on_output: bool,
+) -> Result<SwapQuote, JsError> {
+    let params = PoolParams {
+        min_fee_ppm,
+        max_fee_ppm,
+        beta_vol_bps_per1e12,
+        gamma_slip_bps_per1e12,
+        delta_shallow_bps_per1e12,
+        epsilon_oracle_bps_per1e12,
+        ema_price_1e18,
+        shallow_depth_k: math::shallow_depth_k(decimals0, decimals1),
+        slip_price_impact,
+        shallow_target_depth0: shallow_target_depth0 as u128,
+        ema_depth0,
+        zeta_skew_bps_per1e12,
+    };
+    swap_quote(
+        &params,
+        Some(breaker_vol_threshold_1e12),
+        reserve0,
+        reserve1,
+        token_in_is_0,
+        amount_in,
+        fee_on_output,
+        0,
+    )
+}
+
+/// Current (zero trade size) fee from the raw `Pool` account data.
+#[wasm_bindgen]
+pub fn quote_marginal_fee(pool_account: &[u8]) -> Result<MarginalFeeQuote, JsError> {
+    let pool = decode_pool(pool_account)?;
+    let (fee_ppm, vol_1e12, shallow_1e12) = math::compute_marginal_fee(
+        &pool.fee_params(),
+        pool.reserve0 as u128,
+        pool.reserve1 as u128,
+        0,
+    )
+    .map_err(js_error)?;
+    let verdict = math::breaker_verdict(
+        pool.manually_tripped,
+        pool.breaker_threshold_1e12(),
+        vol_1e12,
+        vol_1e12,
+    );
+    let to_u64 = |v: u128| u64::try_from(v).unwrap_or(u64::MAX);
+    Ok(MarginalFeeQuote {
+        fee_bps: math::ppm_to_bps(fee_ppm),
+        fee_ppm,
+        vol_1e12: to_u64(vol_1e12),
+        shallow_1e12: to_u64(shallow_1e12),
+        breaker_would_trip: !verdict.is_open(),
+    })
+}
+
+/// Deposit quote from the raw `Pool` account data.
+#[wasm_bindgen]
+pub fn quote_add_liquidity(
+    pool_account: &[u8],
+    amount0_desired: u64,
+    amount1_desired: u64,
+) -> Result<LiquidityQuote, JsError> {
+    let pool = decode_pool(pool_account)?;
+    add_liquidity_quote(
+        amount0_desired,
+        amount1_desired,
+        pool.reserve0,
+        pool.reserve1,
+        pool.total_lp_supply,
+    )
+}
+
+/// Deposit quote from explicit pool fields.
+#[wasm_bindgen]
+pub fn quote_add_liquidity_with_fields(
+    reserve0: u64,
+    reserve1: u64,
+    total_lp_supply: u64,
+    amount0_desired: u64,
+    amount1_desired: u64,
+) -> Result<LiquidityQuote, JsError> {
+    add_liquidity_quote(amount0_desired, amount1_desired, reserve0, reserve1, total_lp_supply)
+}
@@ -0,0 +1,116 @@
+//! Once the pool keeps an admin log, admin instructions cannot leave it
+//! out, and rescues and fee collections land in it too.
+
+mod common;
+
+use adaptive_cpamm::client::{self, with_admin_log};
+use adaptive_cpamm::{AdminAction, AdminLogEntry, AmmError};
+use anchor_lang::AnchorSerialize;
+use common::*;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{Keypair, Signer};
+
+async fn with_log() -> TestPool {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let init = client::build_init_admin_log_ix(&PROGRAM_ID, &t.authority.pubkey(), &t.ctx.payer.pubkey());
+    t.send_as_authority(&[init]).await.unwrap();
+    assert!(t.pool().await.admin_log_enabled);
+    t
+}
+
+async fn log_entries(t: &mut TestPool) -> Vec<AdminLogEntry> {
+    let address = client::derive_admin_log_address(&PROGRAM_ID, &t.keys.pool);
+    let account = t.account(&address).await.unwrap();
+    client::decode_admin_log(&account.data).unwrap()
+}
+
+fn action_code(action: &AdminAction) -> u8 {
+    action.try_to_vec().unwrap()[0]
+}
+
+fn logged(ix: Instruction) -> Instruction {
+    with_admin_log(ix, &PROGRAM_ID)
+}
+
+#[tokio::test]
+async fn admin_instruction_without_the_log_fails() {
+    let mut t = with_log().await;
+    let pause = client::build_set_paused_ix(&PROGRAM_ID, &t.authority.pubkey(), true);
+    assert_amm_error(t.send_as_authority(std::slice::from_ref(&pause)).await, AmmError::AdminLogMissing);
+    assert!(!t.pool().await.paused);
+
+    t.send_as_authority(&[logged(pause)]).await.unwrap();
+    assert!(t.pool().await.paused);
+    assert_eq!(log_entries(&mut t).await.len(), 2);
+}
+
+#[tokio::test]
+async fn without_a_log_admin_instructions_need_none() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let pause = client::build_set_paused_ix(&PROGRAM_ID, &t.authority.pubkey(), true);
+    t.send_as_authority(&[pause]).await.unwrap();
+    assert!(!t.pool().await.admin_log_enabled);
+}
+
+#[tokio::test]
+async fn rescue_is_logged() {
+    let mut t = with_log().await;
+    let stray_mint = t.create_mint(6).await;
+    let pool = t.keys.pool;
+    let source = t.create_token_account(&pool, &stray_mint, 500).await;
+    let authority = t.authority.pubkey();
+    let destination = t.create_token_account(&authority, &stray_mint, 0).await;
+
+    let rescue = client::build_rescue_tokens_ix(&PROGRAM_ID, &authority, &source, &destination, 500);
+    assert_amm_error(t.send_as_authority(std::slice::from_ref(&rescue)).await, AmmError::AdminLogMissing);
+    t.send_as_authority(&[logged(rescue)]).await.unwrap();
+
+    let action = AdminAction::RescueTokens { mint: stray_mint, source, destination, amount: 500 };
+    let last = *log_entries(&mut t).await.last().unwrap();
+    assert_eq!(last.action_code, action_code(&action));
+    assert_eq!(last.actor, authority);
+    assert_eq!(
+        last.payload_hash,
+        anchor_lang::solana_program::hash::hash(&action.try_to_vec().unwrap()).to_bytes()
+    );
+    assert_eq!(t.token_balance(&destination).await, 500);
+}
+
+#[tokio::test]
+async fn fee_collection_is_logged() {
+    let mut t = with_log().await;
+    let authority = t.authority.pubkey();
+    let treasury = Keypair::new().pubkey();
+    let setup = [
+        client::build_set_treasury_ix(&PROGRAM_ID, &authority, treasury, false),
+        client::build_set_protocol_fee_ix(&PROGRAM_ID, &authority, 5_000),
+    ];
+    for ix in setup {
+        t.send_as_authority(&[logged(ix)]).await.unwrap();
+    }
+    t.seed_liquidity(10_000_000, 10_000_000).await;
+    let trader = t.fund_user(100_000, 0).await;
+    t.swap(&trader, true, 100_000, 1).await.unwrap();
+    let owed = t.pool().await.protocol_fees_owed0;
+    assert!(owed > 0);
+
+    let (mint0, mint1) = (t.keys.token0_mint, t.keys.token1_mint);
+    let treasury0 = t.create_token_account(&treasury, &mint0, 0).await;
+    let treasury1 = t.create_token_account(&treasury, &mint1, 0).await;
+    let collect = client::build_collect_protocol_fees_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &authority,
+        &treasury0,
+        &treasury1,
+        None,
+        0,
+    );
+    assert_amm_error(t.send_as_authority(std::slice::from_ref(&collect)).await, AmmError::AdminLogMissing);
+    t.send_as_authority(&[logged(collect)]).await.unwrap();
+
+    let action = AdminAction::CollectProtocolFees { amount0: owed, amount1: 0 };
+    let last = *log_entries(&mut t).await.last().unwrap();
+    assert_eq!(last.action_code, action_code(&action));
+    assert_eq!(t.token_balance(&treasury0).await, owed);
+}
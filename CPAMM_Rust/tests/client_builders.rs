@@ -0,0 +1,479 @@
+//! Every client builder the other suites do not already exercise, submitted
+//! through program-test: the instruction it builds is accepted and does
+//! what it is named for.
+
+mod common;
+
+use adaptive_cpamm::event_parser::AmmEvent;
+use adaptive_cpamm::math::ratio_matched_amounts;
+use adaptive_cpamm::simulator::PoolSimulator;
+use adaptive_cpamm::{
+    client, instruction, AmmError, Config, LaunchBuys, LaunchConfig, MarginalFee, MintBadge, PoolFlags, PoolState,
+    Position, RemoveLiquidityQuote, CONFIG_SEED,
+};
+use anchor_lang::error::ErrorCode;
+use anchor_lang::solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::{AccountDeserialize, AccountSerialize, AnchorDeserialize};
+use anchor_spl::token::spl_token;
+use anchor_spl::token::spl_token::state::AccountState;
+use common::*;
+use solana_sdk::account::{AccountSharedData, WritableAccount};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+async fn decode<T: AccountDeserialize>(t: &mut TestPool, address: &Pubkey) -> T {
+    let account = t.account(address).await.expect("account exists");
+    T::try_deserialize(&mut &account.data[..]).unwrap()
+}
+
+async fn lp_state(t: &mut TestPool, lp_account: &Pubkey) -> AccountState {
+    let account = t.account(lp_account).await.unwrap();
+    spl_token::state::Account::unpack(&account.data).unwrap().state
+}
+
+/// A Config whose admin is the pool authority. `initialize_config` needs
+/// the program's upgrade authority, which a natively loaded program does
+/// not have, so the account is written directly.
+fn with_config(t: &mut TestPool) -> Pubkey {
+    let address = client::derive_config_address(&PROGRAM_ID);
+    let bump = Pubkey::find_program_address(&[CONFIG_SEED], &PROGRAM_ID).1;
+    let config = Config { admin: t.authority.pubkey(), require_badges: false, bump };
+    let mut data = Vec::new();
+    config.try_serialize(&mut data).unwrap();
+    data.resize(8 + 32 + 1 + 1, 0);
+    t.ctx.set_account(&address, &AccountSharedData::create(1_000_000_000, data, PROGRAM_ID, false, 0));
+    address
+}
+
+/// A seeded pool and a user holding a position with LP shares in it.
+async fn with_position() -> (TestPool, User) {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let user = t.fund_user(10_000_000, 10_000_000).await;
+    let open = client::build_open_position_ix(&PROGRAM_ID, &t.keys, &user.pubkey());
+    let deposit = client::build_add_liquidity_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &user.pubkey(),
+        &user.token0,
+        &user.token1,
+        10_000_000,
+        10_000_000,
+        true,
+    );
+    t.send(&[open, deposit], &[&user.keypair]).await.unwrap();
+    (t, user)
+}
+
+#[tokio::test]
+async fn initialize_config_resolves_its_accounts_up_to_the_upgradeable_check() {
+    // a natively loaded program is owned by the native loader, so even with
+    // program data for it in place the builder's instruction stops at the
+    // check that the program is upgradeable
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let admin = t.authority.pubkey();
+    let program_data = Pubkey::find_program_address(&[PROGRAM_ID.as_ref()], &bpf_loader_upgradeable::ID).0;
+    let state = UpgradeableLoaderState::ProgramData { slot: 0, upgrade_authority_address: Some(admin) };
+    let account = AccountSharedData::new_data(1_000_000_000, &state, &bpf_loader_upgradeable::ID).unwrap();
+    t.ctx.set_account(&program_data, &account);
+    let init = client::build_initialize_config_ix(&PROGRAM_ID, &admin, true);
+    let Err(failure) = t.send_as_authority(&[init]).await else { panic!("initialize_config succeeded") };
+    assert_eq!(failure.custom_code(), Some(ErrorCode::ConstraintRaw.into()));
+    assert!(failure.logs.iter().any(|log| log.contains("caused by account: program.")), "{:?}", failure.logs);
+}
+
+#[tokio::test]
+async fn set_config_hands_over_the_admin() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let config = with_config(&mut t);
+    let new_admin = Pubkey::new_unique();
+    let set = client::build_set_config_ix(&PROGRAM_ID, &t.authority.pubkey(), &new_admin, true);
+    t.send_as_authority(&[set]).await.unwrap();
+    let config: Config = decode(&mut t, &config).await;
+    assert_eq!((config.admin, config.require_badges), (new_admin, true));
+}
+
+#[tokio::test]
+async fn approve_mint_and_revoke_mint_create_and_close_the_badge() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    with_config(&mut t);
+    let admin = t.authority.pubkey();
+    let mint = t.create_mint(6).await;
+    let badge = client::derive_mint_badge_address(&PROGRAM_ID, &mint);
+
+    t.send_as_authority(&[client::build_approve_mint_ix(&PROGRAM_ID, &admin, &mint)]).await.unwrap();
+    let approved: MintBadge = decode(&mut t, &badge).await;
+    assert_eq!(approved.mint, mint);
+
+    t.send_as_authority(&[client::build_revoke_mint_ix(&PROGRAM_ID, &admin, &mint)]).await.unwrap();
+    assert!(t.account(&badge).await.is_none());
+}
+
+#[tokio::test]
+async fn pool_settings_builders_update_the_pool() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let authority = t.authority.pubkey();
+    let (guardian, fee_manager, pauser, treasurer) =
+        (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+    let (co_authority, router) = (Pubkey::new_unique(), Pubkey::new_unique());
+    t.send_as_authority(&[
+        client::build_set_ema_max_age_ix(&PROGRAM_ID, &authority, 600),
+        client::build_set_guardian_ix(&PROGRAM_ID, &authority, guardian),
+        client::build_set_twap_defaults_ix(&PROGRAM_ID, &authority, 120, 3_600),
+        client::build_set_max_trade_size_ix(&PROGRAM_ID, &authority, 2_500),
+        client::build_set_roles_ix(&PROGRAM_ID, &authority, fee_manager, pauser, treasurer),
+        client::build_set_metadata_ix(&PROGRAM_ID, &authority, "USDC/SOL".into(), "https://example.com".into()),
+        client::build_set_co_authority_ix(&PROGRAM_ID, &authority, co_authority),
+        client::build_set_routers_ix(&PROGRAM_ID, &authority, false, vec![router]),
+    ])
+    .await
+    .unwrap();
+
+    let pool = t.pool().await;
+    assert_eq!(pool.ema_max_age_seconds, 600);
+    assert_eq!(pool.guardian, guardian);
+    assert_eq!((pool.default_twap_window_seconds, pool.max_observation_staleness_seconds), (120, 3_600));
+    assert_eq!(pool.max_trade_bps_of_reserve, 2_500);
+    assert_eq!((pool.fee_manager, pool.pauser, pool.treasurer), (fee_manager, pauser, treasurer));
+    assert_eq!((pool.name(), pool.uri()), ("USDC/SOL".to_string(), "https://example.com".to_string()));
+    assert_eq!(pool.co_authority, co_authority);
+    assert_eq!((pool.only_via_router, pool.routers[0]), (false, router));
+}
+
+#[tokio::test]
+async fn allow_address_closes_the_denylist_marker() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let authority = t.authority.pubkey();
+    let payer = t.ctx.payer.pubkey();
+    let listed = Pubkey::new_unique();
+    t.send_as_authority(&[
+        client::build_set_compliance_ix(&PROGRAM_ID, &authority, authority, true, true),
+        client::build_deny_address_ix(&PROGRAM_ID, &authority, &payer, listed),
+    ])
+    .await
+    .unwrap();
+    let marker = client::derive_denylist_address(&PROGRAM_ID, &t.keys.pool, &listed);
+    assert!(t.account(&marker).await.is_some());
+
+    let allow = client::build_allow_address_ix(&PROGRAM_ID, &authority, &payer, &listed);
+    t.send_as_authority(&[allow]).await.unwrap();
+    assert!(t.account(&marker).await.is_none());
+}
+
+#[tokio::test]
+async fn the_guardian_trips_and_resets_the_breaker() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let lp = t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let trader = t.fund_user(1_000_000, 0).await;
+    let guardian = Keypair::new();
+    t.airdrop(&guardian.pubkey(), 1_000_000_000).await;
+    let authority = t.authority.pubkey();
+    t.send_as_authority(&[
+        client::build_set_guardian_ix(&PROGRAM_ID, &authority, guardian.pubkey()),
+        client::build_set_ema_max_age_ix(&PROGRAM_ID, &authority, 600),
+    ])
+    .await
+    .unwrap();
+
+    let trip = client::build_trip_breaker_ix(&PROGRAM_ID, &guardian.pubkey());
+    t.send(&[trip], &[&guardian]).await.unwrap();
+    assert!(t.pool().await.manually_tripped);
+
+    // swaps halt, withdrawals do not
+    assert_amm_error(t.swap(&trader, true, 1_000_000, 1).await, AmmError::BreakerTripped);
+    t.remove_liquidity(&lp, 1_000_000).await.unwrap();
+
+    // unlike a volatility trip, which a stale EMA's reset to spot clears,
+    // a manual one outlasts any wait
+    t.warp_seconds(3_600).await;
+    assert!(t.pool().await.manually_tripped);
+    assert_amm_error(t.swap(&trader, true, 1_000_000, 1).await, AmmError::BreakerTripped);
+
+    let reset = client::build_reset_breaker_ix(&PROGRAM_ID, &guardian.pubkey());
+    t.send(&[reset], &[&guardian]).await.unwrap();
+    assert!(!t.pool().await.manually_tripped);
+    t.next_slot().await;
+    t.swap(&trader, true, 1_000_000, 1).await.unwrap();
+}
+
+#[tokio::test]
+async fn lp_accounts_freeze_and_thaw_until_the_authority_is_revoked() {
+    let flags = PoolFlags { lp_freezable: true, ..default_flags() };
+    let mut t = TestPool::new(PoolSetup { flags, ..Default::default() }).await;
+    let lp = t.seed_liquidity(1_000_000, 1_000_000).await;
+    let lp_account = lp.lp(&t.keys);
+    let authority = t.authority.pubkey();
+
+    let freeze = client::build_freeze_lp_account_ix(&PROGRAM_ID, &t.keys, &authority, &lp_account);
+    t.send_as_authority(&[freeze]).await.unwrap();
+    assert_eq!(lp_state(&mut t, &lp_account).await, AccountState::Frozen);
+
+    // frozen shares cannot move, but their owner can still redeem them
+    let lp_mint = t.keys.lp_mint;
+    let other = t.create_token_account(&Pubkey::new_unique(), &lp_mint, 0).await;
+    let transfer =
+        spl_token::instruction::transfer(&spl_token::ID, &lp_account, &other, &lp.pubkey(), &[], 1_000).unwrap();
+    let Err(failure) = t.send(&[transfer], &[&lp.keypair]).await else { panic!("a frozen account transferred") };
+    assert_eq!(failure.custom_code(), Some(spl_token::error::TokenError::AccountFrozen as u32));
+    let shares = t.token_balance(&lp_account).await;
+    t.remove_liquidity(&lp, 1_000).await.unwrap();
+    assert_eq!(t.token_balance(&lp_account).await, shares - 1_000);
+    assert_eq!(lp_state(&mut t, &lp_account).await, AccountState::Frozen);
+
+    let thaw = client::build_thaw_lp_account_ix(&PROGRAM_ID, &t.keys, &authority, &lp_account);
+    t.send_as_authority(&[thaw]).await.unwrap();
+    assert_eq!(lp_state(&mut t, &lp_account).await, AccountState::Initialized);
+
+    let revoke = client::build_revoke_lp_freeze_authority_ix(&PROGRAM_ID, &t.keys, &authority);
+    t.send_as_authority(std::slice::from_ref(&revoke)).await.unwrap();
+    assert!(t.pool().await.lp_freeze_revoked);
+    let mint = t.account(&lp_mint).await.unwrap();
+    assert!(spl_token::state::Mint::unpack(&mint.data).unwrap().freeze_authority.is_none());
+
+    // nothing can freeze an account or revoke the authority again
+    let freeze = client::build_freeze_lp_account_ix(&PROGRAM_ID, &t.keys, &authority, &lp_account);
+    assert_amm_error(t.send_as_authority(&[freeze]).await, AmmError::FreezeDisabled);
+    t.next_slot().await;
+    assert_amm_error(t.send_as_authority(&[revoke]).await, AmmError::FreezeDisabled);
+}
+
+#[tokio::test]
+async fn remove_liquidity_and_swap_exits_into_one_token() {
+    let (mut t, user) = with_position().await;
+    let shares = t.token_balance(&user.lp(&t.keys)).await;
+    let token1_before = t.token_balance(&user.token1).await;
+    let args = instruction::RemoveLiquidityAndSwap {
+        shares,
+        exit_to_0: true,
+        min_total_out: 1,
+        deadline: NO_DEADLINE,
+    };
+    let exit = client::build_remove_liquidity_and_swap_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &user.pubkey(),
+        &user.token0,
+        &user.token1,
+        true,
+        args,
+    );
+    t.send(&[exit], &[&user.keypair]).await.unwrap();
+    assert_eq!(t.token_balance(&user.lp(&t.keys)).await, 0);
+    assert_eq!(t.token_balance(&user.token1).await, token1_before);
+    assert!(t.token_balance(&user.token0).await > 10_000_000);
+}
+
+#[tokio::test]
+async fn swap_and_add_liquidity_deposits_from_one_token() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let user = t.fund_user(4_000_000, 0).await;
+    // the deposit must match the reserve ratio after the swap exactly, so
+    // pick an input that leaves reserves the deposit can meet
+    let (pool, clock) = (t.pool().await, t.clock().await);
+    let amount_in = (1_000_000..1_100_000)
+        .find(|&amount_in| {
+            let mut sim = PoolSimulator::new(pool.clone(), clock.unix_timestamp, clock.slot);
+            let out = sim.swap(true, amount_in, 1, NO_DEADLINE, 0).unwrap().amount_out;
+            ratio_matched_amounts(2_000_000, out, sim.pool.reserve0, sim.pool.reserve1).0 > 0
+        })
+        .expect("an input leaving matchable reserves");
+    let args = instruction::SwapAndAddLiquidity {
+        token_in_is_0: true,
+        amount_in,
+        min_amount_out: 1,
+        amount_other_desired: 2_000_000,
+        min_shares: 1,
+        deadline: NO_DEADLINE,
+    };
+    let zap = client::build_swap_and_add_liquidity_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &user.pubkey(),
+        &user.token0,
+        &user.token1,
+        false,
+        args,
+    );
+    let out = t.send(&[zap], &[&user.keypair]).await.unwrap();
+    assert_eq!(out.swap_events().len(), 1);
+    assert!(t.token_balance(&user.lp(&t.keys)).await > 0);
+}
+
+#[tokio::test]
+async fn close_launch_buys_returns_the_record_once_the_window_ends() {
+    let launch = LaunchConfig { launch_token_is_0: true, launch_end_slot: 100, max_buy_per_wallet: 1_000_000 };
+    let mut t = TestPool::new(PoolSetup { launch, ..Default::default() }).await;
+    t.seed_liquidity(10_000_000, 10_000_000).await;
+    let buyer = t.fund_user(0, 50_000).await;
+    t.swap(&buyer, false, 50_000, 1).await.unwrap();
+    let record = client::derive_launch_buys_address(&PROGRAM_ID, &t.keys.pool, &buyer.pubkey());
+    let bought: LaunchBuys = decode(&mut t, &record).await;
+    assert!(bought.bought > 0);
+
+    t.warp_slots(100).await;
+    let close = client::build_close_launch_buys_ix(&PROGRAM_ID, &t.keys, &buyer.pubkey());
+    t.send(&[close], &[&buyer.keypair]).await.unwrap();
+    assert!(t.account(&record).await.is_none());
+}
+
+/// What `shares` would redeem for, from `quote_remove_liquidity`.
+async fn redeemable(t: &mut TestPool, shares: u64) -> RemoveLiquidityQuote {
+    t.next_slot().await;
+    let out = t.send(&[client::build_quote_remove_liquidity_ix(&PROGRAM_ID, shares)], &[]).await.unwrap();
+    RemoveLiquidityQuote::deserialize(&mut out.return_data.as_deref().unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn donate_adds_to_the_reserves() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000, 1_000_000).await;
+    let lp_mint = t.keys.lp_mint;
+    let supply = t.mint_supply(&lp_mint).await;
+    let before = redeemable(&mut t, 100_000).await;
+    let donor = t.fund_user(1_000, 2_000).await;
+    let donate = client::build_donate_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &donor.pubkey(),
+        &donor.token0,
+        &donor.token1,
+        1_000,
+        2_000,
+    );
+    let out = t.send(&[donate], &[&donor.keypair]).await.unwrap();
+    let pool = t.pool().await;
+    assert_eq!((pool.reserve0, pool.reserve1), (1_001_000, 1_002_000));
+    let [AmmEvent::Donation(donation)] = &out.events[..] else { panic!("no Donation event: {:?}", out.logs) };
+    assert_eq!((donation.donor, donation.amount0, donation.amount1), (donor.pubkey(), 1_000, 2_000));
+    assert_eq!((donation.reserve0_after, donation.reserve1_after), (1_001_000, 1_002_000));
+
+    // no shares are minted for it, so every existing share redeems for more
+    assert_eq!(t.mint_supply(&lp_mint).await, supply);
+    let after = redeemable(&mut t, 100_000).await;
+    assert_eq!((before.amount0, before.amount1), (100_000, 100_000));
+    assert_eq!((after.amount0, after.amount1), (100_100, 100_200));
+}
+
+/// What prices a swap: the reserves, the spot prices and the marginal fee.
+async fn pricing(t: &mut TestPool) -> (u64, u64, u64, u64, MarginalFee) {
+    t.next_slot().await;
+    let state = t.send(&[client::build_get_pool_state_ix(&PROGRAM_ID, &t.keys)], &[]).await.unwrap();
+    let state = PoolState::deserialize(&mut state.return_data.as_deref().unwrap()).unwrap();
+    let fee = t.send(&[client::build_quote_marginal_fee_ix(&PROGRAM_ID, &t.keys)], &[]).await.unwrap();
+    let fee = MarginalFee::deserialize(&mut fee.return_data.as_deref().unwrap()).unwrap();
+    (state.reserve0, state.reserve1, state.spot_price_1e12, state.spot_price_1_in_0_1e12, fee)
+}
+
+/// A raw SPL transfer of `amount` of token0 into vault0, past the program.
+async fn send_to_vault0(t: &mut TestPool, amount: u64) {
+    let sender = t.fund_user(amount, 0).await;
+    let vault0 = t.keys.vault0;
+    let transfer =
+        spl_token::instruction::transfer(&spl_token::ID, &sender.token0, &vault0, &sender.pubkey(), &[], amount)
+            .unwrap();
+    t.send(&[transfer], &[&sender.keypair]).await.unwrap();
+}
+
+#[tokio::test]
+async fn skim_sends_the_unaccounted_balance_to_the_recipient() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000, 1_000_000).await;
+    let before = pricing(&mut t).await;
+    send_to_vault0(&mut t, 5_000).await;
+    assert_eq!(pricing(&mut t).await, before);
+
+    let recipient = t.fund_user(0, 0).await;
+    let skim = client::build_skim_ix(&PROGRAM_ID, &t.keys, &recipient.pubkey(), &recipient.token0, &recipient.token1);
+    t.send(&[skim], &[]).await.unwrap();
+    assert_eq!(t.token_balance(&recipient.token0).await, 5_000);
+    let vault0 = t.keys.vault0;
+    assert_eq!(t.token_balance(&vault0).await, t.pool().await.reserve0);
+    assert_eq!(pricing(&mut t).await, before);
+}
+
+#[tokio::test]
+async fn skim_to_treasury_sends_the_unaccounted_balance_to_the_treasury() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000, 1_000_000).await;
+    let treasury = Pubkey::new_unique();
+    t.send_as_authority(&[client::build_set_treasury_ix(&PROGRAM_ID, &t.authority.pubkey(), treasury, true)])
+        .await
+        .unwrap();
+    let before = pricing(&mut t).await;
+    send_to_vault0(&mut t, 5_000).await;
+
+    // whoever the caller names, only the treasury's accounts are accepted
+    let caller = t.fund_user(0, 0).await;
+    let skim = client::build_skim_ix(&PROGRAM_ID, &t.keys, &caller.pubkey(), &caller.token0, &caller.token1);
+    assert_amm_error(t.send(&[skim], &[]).await, AmmError::InvalidRecipient);
+
+    let (mint0, mint1) = (t.keys.token0_mint, t.keys.token1_mint);
+    let treasury0 = t.create_token_account(&treasury, &mint0, 0).await;
+    let treasury1 = t.create_token_account(&treasury, &mint1, 0).await;
+    let skim = client::build_skim_ix(&PROGRAM_ID, &t.keys, &caller.pubkey(), &treasury0, &treasury1);
+    t.send(&[skim], &[]).await.unwrap();
+    assert_eq!(t.token_balance(&treasury0).await, 5_000);
+    assert_eq!(t.token_balance(&caller.token0).await, 0);
+    assert_eq!(pricing(&mut t).await, before);
+}
+
+#[tokio::test]
+async fn staked_lp_earns_claimable_rewards_and_unstakes() {
+    let (mut t, user) = with_position().await;
+    let reward_mint = t.create_mint(6).await;
+    let authority = t.authority.pubkey();
+    let payer = t.ctx.payer.pubkey();
+    let init = client::build_init_rewards_ix(&PROGRAM_ID, &t.keys, &authority, &payer, &reward_mint);
+    t.send_as_authority(&[init]).await.unwrap();
+    t.refresh_keys().await;
+    t.create_token_account(&authority, &reward_mint, 1_000_000).await;
+    let now = t.clock().await.unix_timestamp;
+    let fund = client::build_fund_campaign_ix(&PROGRAM_ID, &reward_mint, &authority, 1_000_000, now + 100, now + 1_100);
+    t.send_as_authority(&[fund]).await.unwrap();
+
+    let position = client::derive_position_address(&PROGRAM_ID, &t.keys.pool, &user.pubkey());
+    let shares = t.token_balance(&user.lp(&t.keys)).await;
+    let stake = client::build_stake_lp_ix(&PROGRAM_ID, &t.keys, &user.pubkey(), shares);
+    t.send(&[stake], &[&user.keypair]).await.unwrap();
+    let staked: Position = decode(&mut t, &position).await;
+    assert_eq!(staked.staked, shares);
+
+    let reward_account = t.create_token_account(&user.pubkey(), &reward_mint, 0).await;
+    t.warp_seconds(600).await;
+    let unstake = client::build_unstake_lp_ix(&PROGRAM_ID, &t.keys, &user.pubkey(), shares);
+    let claim = client::build_claim_rewards_ix(&PROGRAM_ID, &t.keys, &reward_mint, &user.pubkey());
+    t.send(&[unstake, claim], &[&user.keypair]).await.unwrap();
+    assert_eq!(t.token_balance(&user.lp(&t.keys)).await, shares);
+    assert!(t.token_balance(&reward_account).await > 0);
+}
+
+#[tokio::test]
+async fn view_builders_answer() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let payer = t.ctx.payer.pubkey();
+    let checkpoint = Keypair::new();
+    let record = client::build_checkpoint_twap_ix(&PROGRAM_ID, &payer, &checkpoint.pubkey());
+    t.send(&[record], &[&checkpoint]).await.unwrap();
+    t.warp_seconds(120).await;
+
+    let simulate = client::build_simulate_swap_ix(&PROGRAM_ID, &t.keys, true, 1_000_000);
+    let result = t.send(&[simulate], &[]).await;
+    let logs = result.as_ref().err().map(|failure| failure.logs.clone()).unwrap_or_default();
+    assert!(logs.iter().any(|log| log.contains("simulate_swap: amount_out=")), "{logs:?}");
+    assert_amm_error(result, AmmError::SimulationOnly);
+
+    let views = [
+        client::build_quote_marginal_fee_ix(&PROGRAM_ID, &t.keys),
+        client::build_health_check_ix(&PROGRAM_ID, &t.keys),
+        client::build_get_twap_ix(&PROGRAM_ID, &checkpoint.pubkey(), 60, false),
+        client::build_quote_add_liquidity_ix(&PROGRAM_ID, 1_000, 1_000),
+        client::build_quote_remove_liquidity_ix(&PROGRAM_ID, 1_000),
+    ];
+    for view in views {
+        let out = t.send(&[view], &[]).await.unwrap();
+        assert!(out.return_data.is_some_and(|data| !data.is_empty()), "{:?}", out.logs);
+    }
+}
@@ -0,0 +1,131 @@
+//! close_pool leaves nothing of the pool behind: every account the program
+//! or the pool owned is gone afterwards.
+
+mod common;
+
+use adaptive_cpamm::client::{self, with_admin_log, PoolRecord};
+use adaptive_cpamm::{AmmError, LaunchConfig};
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// A pool with every kind of per-pool account: snapshots, rewards, a
+/// denylist marker, a launch purchase, a position, a TWAP checkpoint and
+/// the admin log, emptied of liquidity again. Returns the records to close
+/// and the position owner.
+async fn fully_used_pool() -> (TestPool, Vec<PoolRecord>, User) {
+    let launch = LaunchConfig { launch_token_is_0: true, launch_end_slot: 1_000_000, max_buy_per_wallet: 1_000_000 };
+    let mut t = TestPool::new(PoolSetup { launch, ..Default::default() }).await;
+    let authority = t.authority.pubkey();
+    let payer = t.ctx.payer.pubkey();
+    let reward_mint = t.create_mint(6).await;
+    let listed = Pubkey::new_unique();
+    let checkpoint = Keypair::new();
+    t.send_as_authority(&[
+        client::build_init_snapshots_ix(&PROGRAM_ID, &authority, &payer, 60),
+        client::build_init_rewards_ix(&PROGRAM_ID, &t.keys, &authority, &payer, &reward_mint),
+        client::build_set_compliance_ix(&PROGRAM_ID, &authority, authority, false, false),
+        client::build_deny_address_ix(&PROGRAM_ID, &authority, &payer, listed),
+    ])
+    .await
+    .unwrap();
+    t.refresh_keys().await;
+
+    let lp = t.fund_user(5_000_000, 5_000_000).await;
+    let open = client::build_open_position_ix(&PROGRAM_ID, &t.keys, &lp.pubkey());
+    let deposit = client::build_add_liquidity_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &lp.pubkey(),
+        &lp.token0,
+        &lp.token1,
+        5_000_000,
+        5_000_000,
+        true,
+    );
+    t.send(&[open, deposit], &[&lp.keypair]).await.unwrap();
+    let buyer = t.fund_user(0, 50_000).await;
+    t.swap(&buyer, false, 50_000, 1).await.unwrap();
+    t.send(
+        &[client::build_checkpoint_twap_ix(&PROGRAM_ID, &payer, &checkpoint.pubkey())],
+        &[&checkpoint],
+    )
+    .await
+    .unwrap();
+    t.send_as_authority(&[client::build_init_admin_log_ix(&PROGRAM_ID, &authority, &payer)])
+        .await
+        .unwrap();
+
+    let lp_account = lp.lp(&t.keys);
+    let shares = t.token_balance(&lp_account).await;
+    let withdraw = client::build_remove_liquidity_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &lp.pubkey(),
+        &lp.token0,
+        &lp.token1,
+        shares,
+        true,
+    );
+    t.send(&[withdraw], &[&lp.keypair]).await.unwrap();
+
+    let records = vec![
+        PoolRecord::Denylisted(listed),
+        PoolRecord::LaunchBuys(buyer.pubkey()),
+        PoolRecord::Position(lp.pubkey()),
+        PoolRecord::TwapCheckpoint(checkpoint.pubkey()),
+    ];
+    (t, records, lp)
+}
+
+#[tokio::test]
+async fn no_account_of_the_pool_survives_closing() {
+    let (mut t, records, lp) = fully_used_pool().await;
+    let authority = t.authority.pubkey();
+    let recipient = Pubkey::new_unique();
+    let position = client::derive_position_address(&PROGRAM_ID, &t.keys.pool, &lp.pubkey());
+    let position_rent = t.account(&position).await.unwrap().lamports;
+    let lp_lamports = t.account(&lp.pubkey()).await.unwrap().lamports;
+
+    let close = client::build_close_pool_ix(&PROGRAM_ID, &t.keys, &authority, &recipient, true, &records);
+    t.send_as_authority(&[with_admin_log(close, &PROGRAM_ID)]).await.unwrap();
+
+    let pool = t.keys.pool;
+    for address in t.touched.clone() {
+        let Some(account) = t.account(&address).await else { continue };
+        assert_ne!(account.owner, PROGRAM_ID, "{address} is still owned by the program");
+        if account.owner == spl_token::ID && account.data.len() == spl_token::state::Account::LEN {
+            let token = spl_token::state::Account::unpack(&account.data).unwrap();
+            assert_ne!(token.owner, pool, "token account {address} of the pool survives");
+        }
+    }
+    // the position's rent went back to its owner, not to the recipient
+    assert_eq!(t.account(&lp.pubkey()).await.unwrap().lamports, lp_lamports + position_rent);
+    assert!(t.account(&recipient).await.unwrap().lamports > 0);
+}
+
+#[tokio::test]
+async fn close_pool_refuses_accounts_that_are_not_its_records() {
+    let (mut t, records, lp) = fully_used_pool().await;
+    let authority = t.authority.pubkey();
+    let recipient = Pubkey::new_unique();
+    let snapshots = client::derive_snapshots_address(&PROGRAM_ID, &t.keys.pool);
+
+    // a program account that is no record
+    let mut close = client::build_close_pool_ix(&PROGRAM_ID, &t.keys, &authority, &recipient, false, &[]);
+    close.accounts.push(AccountMeta::new(snapshots, false));
+    let close = with_admin_log(close, &PROGRAM_ID);
+    assert_amm_error(t.send_as_authority(&[close]).await, AmmError::NotPoolRecord);
+
+    // a position whose rent would go to someone else
+    let mut close = client::build_close_pool_ix(&PROGRAM_ID, &t.keys, &authority, &recipient, true, &records[2..3]);
+    close.accounts.last_mut().unwrap().pubkey = recipient;
+    let close = with_admin_log(close, &PROGRAM_ID);
+    assert_amm_error(t.send_as_authority(&[close]).await, AmmError::NotPoolRecord);
+
+    let position = client::derive_position_address(&PROGRAM_ID, &t.keys.pool, &lp.pubkey());
+    assert!(t.account(&position).await.is_some());
+}
@@ -0,0 +1,729 @@
+//! Program-test fixtures shared by the integration tests.
+//!
+//! `TestPool::new` starts a bank with the program loaded natively, creates
+//! the two mints and initializes the pool; `fund_user` hands out token
+//! accounts, and `send` returns the logs, the decoded events and the
+//! return data of a transaction. Errors compare by `AmmError` variant.
+
+#![allow(dead_code)]
+
+use adaptive_cpamm::client::{self, PoolKeys};
+use adaptive_cpamm::event_parser::{decode_event, AmmEvent};
+use adaptive_cpamm::{
+    instruction, AmmError, FeeConfig, FeeCurve, LaunchConfig, Pool, PoolFlags, SwapEvent,
+};
+use anchor_lang::prelude::{AccountInfo, Pubkey};
+use anchor_lang::solana_program::entrypoint::ProgramResult;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::program_stubs::{set_syscall_stubs, SyscallStubs};
+use anchor_lang::AccountDeserialize;
+use anchor_spl::associated_token::get_associated_token_address;
+use anchor_spl::associated_token::spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use anchor_spl::token::spl_token;
+use base64::Engine;
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::account::Account;
+use solana_sdk::clock::Clock;
+use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+use solana_sdk::signature::{Keypair, Signer};
+use solana_sdk::system_instruction;
+use solana_sdk::transaction::{Transaction, TransactionError};
+use std::collections::BTreeSet;
+
+pub const PROGRAM_ID: Pubkey = adaptive_cpamm::ID;
+
+/// Far enough out that no test runs into it.
+pub const NO_DEADLINE: i64 = i64::MAX;
+
+thread_local! {
+    /// How many calls into the program are running on this thread; see
+    /// `EventLogStubs::sol_log`.
+    static PROGRAM_DEPTH: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    // Anchor's entry ties the slice to its accounts' lifetime; the bank
+    // keeps both alive for the whole call
+    let accounts = unsafe { std::mem::transmute::<&[AccountInfo], &[AccountInfo]>(accounts) };
+    PROGRAM_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let result = adaptive_cpamm::entry(program_id, accounts, data);
+    PROGRAM_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    result
+}
+
+/// A bank with the program loaded as a native builtin.
+pub fn program_test() -> ProgramTest {
+    ProgramTest::new("adaptive_cpamm", PROGRAM_ID, processor!(process_instruction))
+}
+
+/// Stand-in for a flash callback or any other caller: invokes the first
+/// account as a program with the rest as its accounts and the instruction
+/// data as is. No accounts means do nothing.
+pub const FORWARDER_ID: Pubkey = Pubkey::new_from_array([0xf0; 32]);
+
+fn process_forward(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let Some((program, rest)) = accounts.split_first() else {
+        return Ok(());
+    };
+    let metas = rest
+        .iter()
+        .map(|info| AccountMeta {
+            pubkey: *info.key,
+            is_signer: info.is_signer,
+            is_writable: info.is_writable,
+        })
+        .collect();
+    let forwarded = Instruction { program_id: *program.key, accounts: metas, data: data.to_vec() };
+    anchor_lang::solana_program::program::invoke(&forwarded, accounts)
+}
+
+/// `program_test` plus the forwarder.
+pub fn program_test_with_forwarder() -> ProgramTest {
+    let mut program_test = program_test();
+    program_test.add_program("forwarder", FORWARDER_ID, processor!(process_forward));
+    program_test
+}
+
+/// Stand-in for an SPL Governance executor: like the forwarder, but the
+/// instruction it invokes is signed by its treasury PDA, the way a passed
+/// proposal is executed by the DAO's native treasury.
+pub const GOVERNANCE_ID: Pubkey = Pubkey::new_from_array([0x60; 32]);
+pub const GOVERNANCE_TREASURY_SEED: &[u8] = b"native-treasury";
+
+/// The PDA the governance executor signs as.
+pub fn governance_treasury() -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[GOVERNANCE_TREASURY_SEED], &GOVERNANCE_ID)
+}
+
+fn process_governance(_program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    let (program, rest) = accounts.split_first().expect("the program to execute");
+    let (treasury, bump) = governance_treasury();
+    let metas = rest
+        .iter()
+        .map(|info| AccountMeta {
+            pubkey: *info.key,
+            is_signer: info.is_signer || *info.key == treasury,
+            is_writable: info.is_writable,
+        })
+        .collect();
+    let executed = Instruction { program_id: *program.key, accounts: metas, data: data.to_vec() };
+    anchor_lang::solana_program::program::invoke_signed(&executed, accounts, &[&[GOVERNANCE_TREASURY_SEED, &[bump]]])
+}
+
+/// `program_test` plus the governance executor.
+pub fn program_test_with_governance() -> ProgramTest {
+    let mut program_test = program_test();
+    program_test.add_program("governance", GOVERNANCE_ID, processor!(process_governance));
+    program_test
+}
+
+/// A bank running the program's SBF build rather than the native builtin,
+/// so the runtime meters its compute units. `anchor build` writes the
+/// shared object to target/deploy, which `SBF_OUT_DIR` must name; the
+/// forwarder stays native.
+pub fn program_test_sbf() -> ProgramTest {
+    let mut program_test = ProgramTest::default();
+    program_test.prefer_bpf(false);
+    program_test.add_program("forwarder", FORWARDER_ID, processor!(process_forward));
+    program_test.prefer_bpf(true);
+    program_test.add_program("adaptive_cpamm", PROGRAM_ID, None);
+    program_test
+}
+
+/// The executor instruction that runs `ix` with the treasury PDA as a
+/// signer; `ix` names the treasury as its signer, which the transaction
+/// itself cannot provide.
+pub fn execute_as_governance(ix: Instruction) -> Instruction {
+    let treasury = governance_treasury().0;
+    let mut accounts = vec![AccountMeta::new_readonly(ix.program_id, false)];
+    accounts.extend(ix.accounts.into_iter().map(|meta| AccountMeta {
+        is_signer: meta.is_signer && meta.pubkey != treasury,
+        ..meta
+    }));
+    Instruction { program_id: GOVERNANCE_ID, accounts, data: ix.data }
+}
+
+/// Callback data and accounts that make the forwarder run `ix`.
+pub fn forward(ix: Instruction) -> (Vec<u8>, Vec<AccountMeta>) {
+    let mut accounts = vec![AccountMeta::new_readonly(ix.program_id, false)];
+    accounts.extend(ix.accounts);
+    (ix.data, accounts)
+}
+
+pub fn default_fee_curve() -> FeeCurve {
+    FeeCurve {
+        min_fee_ppm: 3_000,
+        max_fee_ppm: 30_000,
+        beta_vol_bps_per1e12: 100,
+        gamma_slip_bps_per1e12: 100,
+        delta_shallow_bps_per1e12: 0,
+        zeta_skew_bps_per1e12: 0,
+        ema_alpha_1e12: 50_000_000_000,
+        breaker_vol_threshold_1e12: 200_000_000_000,
+        breaker_enabled: true,
+    }
+}
+
+pub fn default_flags() -> PoolFlags {
+    PoolFlags {
+        geometric_twap_enabled: true,
+        sync_donations: false,
+        lp_freezable: false,
+        allow_risky_mints: false,
+        fee_on_output: false,
+    }
+}
+
+/// Everything `initialize_pool` is called with.
+#[derive(Clone, Copy, Debug)]
+pub struct PoolSetup {
+    pub decimals0: u8,
+    pub decimals1: u8,
+    pub fee_curve: FeeCurve,
+    pub flags: PoolFlags,
+    pub launch: LaunchConfig,
+}
+
+impl Default for PoolSetup {
+    fn default() -> Self {
+        PoolSetup {
+            decimals0: 6,
+            decimals1: 6,
+            fee_curve: default_fee_curve(),
+            flags: default_flags(),
+            launch: LaunchConfig::default(),
+        }
+    }
+}
+
+/// A funded wallet with token accounts for both pool mints.
+pub struct User {
+    pub keypair: Keypair,
+    pub token0: Pubkey,
+    pub token1: Pubkey,
+}
+
+impl User {
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    pub fn lp(&self, keys: &PoolKeys) -> Pubkey {
+        get_associated_token_address(&self.pubkey(), &keys.lp_mint)
+    }
+}
+
+/// What a successful transaction left behind.
+pub struct TxOutput {
+    pub logs: Vec<String>,
+    pub events: Vec<AmmEvent>,
+    pub return_data: Option<Vec<u8>>,
+}
+
+impl TxOutput {
+    pub fn swap_events(&self) -> Vec<&SwapEvent> {
+        self.events
+            .iter()
+            .filter_map(|e| match e {
+                AmmEvent::Swap(swap) => Some(swap),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A transaction that failed, with its logs.
+#[derive(Debug)]
+pub struct TxFailure {
+    pub error: TransactionError,
+    pub logs: Vec<String>,
+}
+
+impl TxFailure {
+    /// Custom program error code of the failing instruction, if any.
+    pub fn custom_code(&self) -> Option<u32> {
+        match self.error {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => Some(code),
+            _ => None,
+        }
+    }
+}
+
+/// Panic unless `result` failed with `expected`.
+#[track_caller]
+pub fn assert_amm_error<T>(result: Result<T, TxFailure>, expected: AmmError) {
+    let name = format!("{expected:?}");
+    let expected_code = u32::from(expected);
+    match result {
+        Ok(_) => panic!("expected {name}, transaction succeeded"),
+        Err(failure) => assert_eq!(
+            failure.custom_code(),
+            Some(expected_code),
+            "expected {name}, got {:?}\n{}",
+            failure.error,
+            failure.logs.join("\n")
+        ),
+    }
+}
+
+/// Forwards every syscall to program-test's stubs, except that
+/// `sol_log_data` goes through `sol_log` instead of stdout, so events show
+/// up in the transaction logs of a natively loaded program.
+struct EventLogStubs(Box<dyn SyscallStubs>);
+
+struct NoStubs;
+impl SyscallStubs for NoStubs {}
+
+impl SyscallStubs for EventLogStubs {
+    fn sol_log(&self, message: &str) {
+        // program-test logs into the invoke context of the last transaction,
+        // which is freed by the time the program's code runs natively, e.g.
+        // in the simulator, so only log there from inside the program
+        if PROGRAM_DEPTH.with(|depth| depth.get()) > 0 {
+            self.0.sol_log(message)
+        } else {
+            println!("{message}")
+        }
+    }
+    fn sol_log_compute_units(&self) {
+        if PROGRAM_DEPTH.with(|depth| depth.get()) > 0 {
+            self.0.sol_log_compute_units()
+        }
+    }
+    fn sol_remaining_compute_units(&self) -> u64 {
+        self.0.sol_remaining_compute_units()
+    }
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        self.0.sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_clock_sysvar(var_addr)
+    }
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_schedule_sysvar(var_addr)
+    }
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_fees_sysvar(var_addr)
+    }
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_rent_sysvar(var_addr)
+    }
+    fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_epoch_rewards_sysvar(var_addr)
+    }
+    fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+        self.0.sol_get_last_restart_slot(var_addr)
+    }
+    unsafe fn sol_memcpy(&self, dst: *mut u8, src: *const u8, n: usize) {
+        self.0.sol_memcpy(dst, src, n)
+    }
+    unsafe fn sol_memmove(&self, dst: *mut u8, src: *const u8, n: usize) {
+        self.0.sol_memmove(dst, src, n)
+    }
+    unsafe fn sol_memcmp(&self, s1: *const u8, s2: *const u8, n: usize, result: *mut i32) {
+        self.0.sol_memcmp(s1, s2, n, result)
+    }
+    unsafe fn sol_memset(&self, s: *mut u8, c: u8, n: usize) {
+        self.0.sol_memset(s, c, n)
+    }
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.0.sol_get_return_data()
+    }
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.0.sol_set_return_data(data)
+    }
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let encoded: Vec<String> = fields.iter().map(|field| engine.encode(field)).collect();
+        self.sol_log(&format!("{DATA_PREFIX}{}", encoded.join(" ")))
+    }
+    fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+        self.0.sol_get_processed_sibling_instruction(index)
+    }
+    fn sol_get_stack_height(&self) -> u64 {
+        self.0.sol_get_stack_height()
+    }
+}
+
+const DATA_PREFIX: &str = "Program data: ";
+
+/// Wrap program-test's stubs once; they're process-global and installed by
+/// the first `start_with_context`.
+fn capture_event_logs() {
+    static WRAP: std::sync::Once = std::sync::Once::new();
+    WRAP.call_once(|| {
+        let inner = set_syscall_stubs(Box::new(NoStubs));
+        set_syscall_stubs(Box::new(EventLogStubs(inner)));
+    });
+}
+
+/// Decode every event the program logged, whether the runtime wrote it as
+/// `Program data:` or the native harness as a `Program log:` line.
+pub fn decode_events(logs: &[String]) -> Vec<AmmEvent> {
+    let engine = base64::engine::general_purpose::STANDARD;
+    logs.iter()
+        .map(|line| line.strip_prefix("Program log: ").unwrap_or(line))
+        .filter_map(|line| line.strip_prefix(DATA_PREFIX))
+        .filter_map(|data| engine.decode(data).ok())
+        .filter_map(|bytes| decode_event(&bytes))
+        .collect()
+}
+
+/// A started bank with an initialized pool.
+pub struct TestPool {
+    pub ctx: ProgramTestContext,
+    /// pool authority, separate from the fee payer
+    pub authority: Keypair,
+    pub keys: PoolKeys,
+    /// every account a transaction sent through `send` referenced, which
+    /// covers every account the program can have created
+    pub touched: BTreeSet<Pubkey>,
+}
+
+impl TestPool {
+    pub async fn new(setup: PoolSetup) -> Self {
+        Self::start(program_test(), setup).await
+    }
+
+    /// Like `new`, on a bank the caller has added programs or accounts to.
+    pub async fn start(program_test: ProgramTest, setup: PoolSetup) -> Self {
+        let mut pool = Self::without_pool(program_test).await;
+        let (mint0, mint1) = pool.create_mints(setup.decimals0, setup.decimals1).await;
+        pool.create_pool(&mint0, &mint1, setup).await;
+        pool
+    }
+
+    /// A started bank with a funded authority but no pool yet, for tests
+    /// of `initialize_pool` itself.
+    pub async fn without_pool(program_test: ProgramTest) -> Self {
+        let ctx = program_test.start_with_context().await;
+        capture_event_logs();
+        let authority = Keypair::new();
+        let mut pool = TestPool {
+            ctx,
+            authority,
+            keys: PoolKeys {
+                pool: client::derive_pool_address(&PROGRAM_ID).0,
+                token0_mint: Pubkey::default(),
+                token1_mint: Pubkey::default(),
+                vault0: Pubkey::default(),
+                vault1: Pubkey::default(),
+                lp_mint: Pubkey::default(),
+                oracle: None,
+                reward_mint: None,
+                enforce_denylist: false,
+                launch_end_slot: 0,
+            },
+            touched: BTreeSet::new(),
+        };
+        pool.airdrop(&pool.authority.pubkey(), 10_000_000_000).await;
+        pool
+    }
+
+    /// Two fresh mints whose mint authority is the fee payer.
+    pub async fn create_mints(&mut self, decimals0: u8, decimals1: u8) -> (Pubkey, Pubkey) {
+        let mint0 = self.create_mint(decimals0).await;
+        let mint1 = self.create_mint(decimals1).await;
+        (mint0, mint1)
+    }
+
+    pub async fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        self.create_mint_with(decimals, None).await
+    }
+
+    /// A fresh mint whose issuer can freeze its token accounts.
+    pub async fn create_freezable_mint(&mut self, decimals: u8, freeze_authority: &Pubkey) -> Pubkey {
+        self.create_mint_with(decimals, Some(freeze_authority)).await
+    }
+
+    async fn create_mint_with(&mut self, decimals: u8, freeze_authority: Option<&Pubkey>) -> Pubkey {
+        let mint = Keypair::new();
+        let payer = self.ctx.payer.pubkey();
+        let rent = self.ctx.banks_client.get_rent().await.unwrap();
+        let ixs = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(spl_token::state::Mint::LEN),
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint.pubkey(),
+                &payer,
+                freeze_authority,
+                decimals,
+            )
+            .unwrap(),
+        ];
+        self.send(&ixs, &[&mint]).await.unwrap();
+        mint.pubkey()
+    }
+
+    /// `initialize_pool` over (mint0, mint1), signed by `authority`.
+    pub async fn create_pool(&mut self, mint0: &Pubkey, mint1: &Pubkey, setup: PoolSetup) {
+        self.try_create_pool(mint0, mint1, setup).await.unwrap();
+        self.refresh_keys().await;
+    }
+
+    pub async fn try_create_pool(
+        &mut self,
+        mint0: &Pubkey,
+        mint1: &Pubkey,
+        setup: PoolSetup,
+    ) -> Result<TxOutput, TxFailure> {
+        let lp_mint = Keypair::new();
+        let init = client::build_initialize_pool_ix(
+            &PROGRAM_ID,
+            &self.authority.pubkey(),
+            mint0,
+            mint1,
+            &lp_mint.pubkey(),
+            false,
+            instruction::InitializePool {
+                fee_curve: setup.fee_curve,
+                flags: setup.flags,
+                launch: setup.launch,
+                name: String::new(),
+                uri: String::new(),
+            },
+        );
+        let authority = self.authority.insecure_clone();
+        self.send(&[init], &[&authority, &lp_mint]).await
+    }
+
+    /// Re-read the builder keys after a change to the pool account.
+    pub async fn refresh_keys(&mut self) {
+        let pool = self.pool().await;
+        self.keys = PoolKeys::from_pool(&PROGRAM_ID, &pool);
+    }
+
+    pub async fn pool(&mut self) -> Pool {
+        let account = self.account(&self.keys.pool.clone()).await.expect("pool account");
+        Pool::try_deserialize(&mut &account.data[..]).unwrap()
+    }
+
+    pub async fn account(&mut self, address: &Pubkey) -> Option<Account> {
+        self.ctx.banks_client.get_account(*address).await.unwrap()
+    }
+
+    pub async fn token_balance(&mut self, token_account: &Pubkey) -> u64 {
+        match self.account(token_account).await {
+            Some(account) => spl_token::state::Account::unpack(&account.data).unwrap().amount,
+            None => 0,
+        }
+    }
+
+    pub async fn mint_supply(&mut self, mint: &Pubkey) -> u64 {
+        let account = self.account(mint).await.expect("mint account");
+        spl_token::state::Mint::unpack(&account.data).unwrap().supply
+    }
+
+    pub async fn airdrop(&mut self, to: &Pubkey, lamports: u64) {
+        let transfer = system_instruction::transfer(&self.ctx.payer.pubkey(), to, lamports);
+        self.send(&[transfer], &[]).await.unwrap();
+    }
+
+    /// A new wallet with SOL, ATAs for both pool mints and the given token
+    /// amounts minted into them.
+    pub async fn fund_user(&mut self, amount0: u64, amount1: u64) -> User {
+        let keypair = Keypair::new();
+        let owner = keypair.pubkey();
+        self.airdrop(&owner, 1_000_000_000).await;
+        let (mint0, mint1) = (self.keys.token0_mint, self.keys.token1_mint);
+        let token0 = self.create_token_account(&owner, &mint0, amount0).await;
+        let token1 = self.create_token_account(&owner, &mint1, amount1).await;
+        User { keypair, token0, token1 }
+    }
+
+    /// `owner`'s ATA for `mint`, topped up by `amount`.
+    pub async fn create_token_account(&mut self, owner: &Pubkey, mint: &Pubkey, amount: u64) -> Pubkey {
+        let payer = self.ctx.payer.pubkey();
+        let ata = get_associated_token_address(owner, mint);
+        let mut ixs = vec![create_associated_token_account_idempotent(
+            &payer,
+            owner,
+            mint,
+            &spl_token::ID,
+        )];
+        if amount > 0 {
+            ixs.push(
+                spl_token::instruction::mint_to(&spl_token::ID, mint, &ata, &payer, &[], amount)
+                    .unwrap(),
+            );
+        }
+        self.send(&ixs, &[]).await.unwrap();
+        ata
+    }
+
+    /// Sign with the fee payer plus `signers` and process.
+    pub async fn send(
+        &mut self,
+        ixs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<TxOutput, TxFailure> {
+        let blockhash = self.ctx.banks_client.get_latest_blockhash().await.unwrap();
+        let mut all: Vec<&Keypair> = vec![&self.ctx.payer];
+        all.extend_from_slice(signers);
+        let tx = Transaction::new_signed_with_payer(
+            ixs,
+            Some(&self.ctx.payer.pubkey()),
+            &all,
+            blockhash,
+        );
+        self.touched.extend(tx.message.account_keys.iter().copied());
+        let outcome = self
+            .ctx
+            .banks_client
+            .process_transaction_with_metadata(tx)
+            .await
+            .expect("banks client");
+        let (logs, return_data) = outcome
+            .metadata
+            .map(|m| (m.log_messages, m.return_data))
+            .unwrap_or_default();
+        match outcome.result {
+            Ok(()) => Ok(TxOutput {
+                events: decode_events(&logs),
+                return_data: return_data.map(|r| r.data),
+                logs,
+            }),
+            Err(error) => Err(TxFailure { error, logs }),
+        }
+    }
+
+    /// `send` signed by the pool authority.
+    pub async fn send_as_authority(&mut self, ixs: &[Instruction]) -> Result<TxOutput, TxFailure> {
+        let authority = self.authority.insecure_clone();
+        self.send(ixs, &[&authority]).await
+    }
+
+    /// Advance to the next slot, which also yields a fresh blockhash.
+    pub async fn next_slot(&mut self) {
+        self.warp_slots(1).await;
+    }
+
+    pub async fn warp_slots(&mut self, slots: u64) {
+        let clock = self.clock().await;
+        self.ctx.warp_to_slot(clock.slot + slots).unwrap();
+        // keep the clock monotonic: the warped bank may not move it
+        let mut warped = self.clock().await;
+        warped.unix_timestamp = warped.unix_timestamp.max(clock.unix_timestamp);
+        self.ctx.set_sysvar(&warped);
+    }
+
+    /// Move the clock forward `seconds` (and one slot).
+    pub async fn warp_seconds(&mut self, seconds: i64) {
+        let before = self.clock().await;
+        self.next_slot().await;
+        let mut clock = self.clock().await;
+        clock.unix_timestamp = before.unix_timestamp + seconds;
+        self.ctx.set_sysvar(&clock);
+    }
+
+    /// Write a trading Pyth price account (`price`/`conf` at `expo`)
+    /// published now to `address`.
+    pub async fn set_pyth_price(&mut self, address: &Pubkey, price: i64, conf: u64, expo: i32) {
+        let now = self.clock().await.unix_timestamp;
+        let mut data = vec![0u8; 240];
+        data[0..4].copy_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        data[8..12].copy_from_slice(&3u32.to_le_bytes());
+        data[20..24].copy_from_slice(&expo.to_le_bytes());
+        data[96..104].copy_from_slice(&now.to_le_bytes());
+        data[208..216].copy_from_slice(&price.to_le_bytes());
+        data[216..224].copy_from_slice(&conf.to_le_bytes());
+        data[224..228].copy_from_slice(&1u32.to_le_bytes());
+        let account = Account {
+            lamports: 10_000_000,
+            data,
+            owner: Pubkey::new_unique(),
+            executable: false,
+            rent_epoch: 0,
+        };
+        self.ctx.set_account(address, &account.into());
+    }
+
+    pub async fn clock(&mut self) -> Clock {
+        self.ctx.banks_client.get_sysvar::<Clock>().await.unwrap()
+    }
+
+    pub async fn add_liquidity(
+        &mut self,
+        user: &User,
+        amount0: u64,
+        amount1: u64,
+    ) -> Result<TxOutput, TxFailure> {
+        let ix = client::build_add_liquidity_ix(
+            &PROGRAM_ID,
+            &self.keys,
+            &user.pubkey(),
+            &user.token0,
+            &user.token1,
+            amount0,
+            amount1,
+            false,
+        );
+        self.send(&[ix], &[&user.keypair]).await
+    }
+
+    pub async fn remove_liquidity(&mut self, user: &User, shares: u64) -> Result<TxOutput, TxFailure> {
+        let ix = client::build_remove_liquidity_ix(
+            &PROGRAM_ID,
+            &self.keys,
+            &user.pubkey(),
+            &user.token0,
+            &user.token1,
+            shares,
+            false,
+        );
+        self.send(&[ix], &[&user.keypair]).await
+    }
+
+    pub async fn swap(
+        &mut self,
+        user: &User,
+        token_in_is_0: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<TxOutput, TxFailure> {
+        let (token_in, token_out) = if token_in_is_0 {
+            (user.token0, user.token1)
+        } else {
+            (user.token1, user.token0)
+        };
+        let ix = client::build_swap_ix(
+            &PROGRAM_ID,
+            &self.keys,
+            &user.pubkey(),
+            &token_in,
+            &token_out,
+            token_in_is_0,
+            amount_in,
+            min_amount_out,
+            NO_DEADLINE,
+        );
+        self.send(&[ix], &[&user.keypair]).await
+    }
+
+    pub async fn set_params(&mut self, fee_config: FeeConfig) -> Result<TxOutput, TxFailure> {
+        let ix = client::build_set_params_ix(
+            &PROGRAM_ID,
+            &self.authority.pubkey(),
+            instruction::SetParams { fee_config },
+        );
+        self.send_as_authority(&[ix]).await
+    }
+
+    /// A user holding `amount0`/`amount1` who deposited all of it.
+    pub async fn seed_liquidity(&mut self, amount0: u64, amount1: u64) -> User {
+        let lp = self.fund_user(amount0, amount1).await;
+        self.add_liquidity(&lp, amount0, amount1).await.unwrap();
+        lp
+    }
+}
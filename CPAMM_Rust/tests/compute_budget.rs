@@ -0,0 +1,178 @@
+//! Compute units the SBF build spends per instruction, against ceilings a
+//! change must not push it past. Only the runtime meters a loaded program,
+//! not a native builtin, so these run against target/deploy and are
+//! ignored by `cargo test`: `anchor run test-cu`.
+
+mod common;
+
+use adaptive_cpamm::{client, PoolFlags};
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::compute_budget::ComputeBudgetInstruction;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::{Keypair, Signer};
+
+const SWAP_CEILING: u64 = 80_000;
+const ADD_LIQUIDITY_CEILING: u64 = 60_000;
+const REMOVE_LIQUIDITY_CEILING: u64 = 60_000;
+/// borrow and repay together
+const FLASH_LOAN_CEILING: u64 = 70_000;
+/// including the callback's repayment transfer
+const FLASH_SWAP_CEILING: u64 = 100_000;
+
+const RESERVE: u64 = 1_000_000_000;
+
+async fn sbf_pool(setup: PoolSetup) -> TestPool {
+    TestPool::start(program_test_sbf(), setup).await
+}
+
+/// Send `ixs` under the largest compute limit; the units the program's
+/// top-level invocations consumed, CPIs included.
+async fn metered(t: &mut TestPool, ixs: &[Instruction], signers: &[&Keypair]) -> u64 {
+    t.next_slot().await;
+    let mut all = vec![ComputeBudgetInstruction::set_compute_unit_limit(1_400_000)];
+    all.extend_from_slice(ixs);
+    let out = t.send(&all, signers).await.unwrap();
+    let prefix = format!("Program {PROGRAM_ID} consumed ");
+    let consumed: Vec<u64> = out
+        .logs
+        .iter()
+        .filter_map(|log| log.strip_prefix(&prefix)?.split_whitespace().next()?.parse().ok())
+        .collect();
+    assert!(!consumed.is_empty(), "the program was not metered: {:?}", out.logs);
+    consumed.iter().sum()
+}
+
+#[track_caller]
+fn assert_within(name: &str, consumed: u64, ceiling: u64) {
+    println!("{name}: {consumed} CU (ceiling {ceiling})");
+    assert!(consumed <= ceiling, "{name} used {consumed} CU, over its {ceiling} CU ceiling");
+}
+
+#[tokio::test]
+#[ignore = "needs target/deploy/adaptive_cpamm.so: anchor run test-cu"]
+async fn swap_stays_within_its_budget() {
+    // the first trade writes the first observation, later ones update the
+    // EMAs from a populated state; each fee placement, both directions
+    for fee_on_output in [false, true] {
+        let flags = PoolFlags { fee_on_output, ..default_flags() };
+        let mut t = sbf_pool(PoolSetup { flags, ..Default::default() }).await;
+        t.seed_liquidity(RESERVE, RESERVE).await;
+        let trader = t.fund_user(RESERVE, RESERVE).await;
+        for (token_in_is_0, amount_in) in [(true, 1_000_000), (false, 25_000_000), (true, 10_000_000), (false, 7)] {
+            let (token_in, token_out) =
+                if token_in_is_0 { (trader.token0, trader.token1) } else { (trader.token1, trader.token0) };
+            let swap = client::build_swap_ix(
+                &PROGRAM_ID,
+                &t.keys,
+                &trader.pubkey(),
+                &token_in,
+                &token_out,
+                token_in_is_0,
+                amount_in,
+                1,
+                NO_DEADLINE,
+            );
+            let consumed = metered(&mut t, &[swap], &[&trader.keypair]).await;
+            assert_within("swap", consumed, SWAP_CEILING);
+            t.warp_seconds(30).await;
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs target/deploy/adaptive_cpamm.so: anchor run test-cu"]
+async fn add_and_remove_liquidity_stay_within_their_budgets() {
+    let mut t = sbf_pool(PoolSetup::default()).await;
+    let lp = t.fund_user(RESERVE, RESERVE).await;
+    let (user, token0, token1) = (lp.pubkey(), lp.token0, lp.token1);
+    let bootstrap =
+        client::build_add_liquidity_ix(&PROGRAM_ID, &t.keys, &user, &token0, &token1, RESERVE, RESERVE, false);
+    assert_within("add_liquidity (first)", metered(&mut t, &[bootstrap], &[&lp.keypair]).await, ADD_LIQUIDITY_CEILING);
+
+    let depositor = t.fund_user(RESERVE, RESERVE).await;
+    let deposit = client::build_add_liquidity_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &depositor.pubkey(),
+        &depositor.token0,
+        &depositor.token1,
+        RESERVE / 2,
+        RESERVE / 2,
+        false,
+    );
+    assert_within("add_liquidity", metered(&mut t, &[deposit], &[&depositor.keypair]).await, ADD_LIQUIDITY_CEILING);
+
+    let shares = t.token_balance(&depositor.lp(&t.keys)).await;
+    let withdraw = client::build_remove_liquidity_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &depositor.pubkey(),
+        &depositor.token0,
+        &depositor.token1,
+        shares,
+        false,
+    );
+    let consumed = metered(&mut t, &[withdraw], &[&depositor.keypair]).await;
+    assert_within("remove_liquidity", consumed, REMOVE_LIQUIDITY_CEILING);
+}
+
+#[tokio::test]
+#[ignore = "needs target/deploy/adaptive_cpamm.so: anchor run test-cu"]
+async fn a_first_deposit_at_the_largest_product_stays_within_its_budget() {
+    // the bootstrap's isqrt of reserve0 * reserve1 runs its full 64 rounds
+    // here; the Babylonian loop it replaced needed 70 u128 divisions
+    let mut t = sbf_pool(PoolSetup::default()).await;
+    let lp = t.fund_user(u64::MAX, u64::MAX).await;
+    let (user, token0, token1) = (lp.pubkey(), lp.token0, lp.token1);
+    let bootstrap =
+        client::build_add_liquidity_ix(&PROGRAM_ID, &t.keys, &user, &token0, &token1, u64::MAX, u64::MAX, false);
+    let consumed = metered(&mut t, &[bootstrap], &[&lp.keypair]).await;
+    assert_within("add_liquidity (first, u64::MAX each)", consumed, ADD_LIQUIDITY_CEILING);
+}
+
+#[tokio::test]
+#[ignore = "needs target/deploy/adaptive_cpamm.so: anchor run test-cu"]
+async fn flash_loans_stay_within_their_budget() {
+    let mut t = sbf_pool(PoolSetup::default()).await;
+    t.seed_liquidity(RESERVE, RESERVE).await;
+    let set_fee = client::build_set_flash_fee_ix(&PROGRAM_ID, &t.authority.pubkey(), 30);
+    t.send_as_authority(&[set_fee]).await.unwrap();
+    let borrower = t.fund_user(3_000, 0).await;
+    let (payer, token) = (borrower.pubkey(), borrower.token0);
+    let loan = [
+        client::build_flash_borrow_ix(&PROGRAM_ID, &t.keys, &payer, &token, true, 1_000_000),
+        client::build_flash_repay_ix(&PROGRAM_ID, &t.keys, &payer, &token, true, 1_000_000),
+    ];
+    assert_within("flash_borrow + flash_repay", metered(&mut t, &loan, &[&borrower.keypair]).await, FLASH_LOAN_CEILING);
+}
+
+#[tokio::test]
+#[ignore = "needs target/deploy/adaptive_cpamm.so: anchor run test-cu"]
+async fn flash_swap_stays_within_its_budget() {
+    let mut t = sbf_pool(PoolSetup::default()).await;
+    t.seed_liquidity(RESERVE, RESERVE).await;
+    let borrower = t.fund_user(2_000_000, 0).await;
+    let repay = spl_token::instruction::transfer(
+        &spl_token::ID,
+        &borrower.token0,
+        &t.keys.vault0,
+        &borrower.pubkey(),
+        &[],
+        1_100_000,
+    )
+    .unwrap();
+    let (data, accounts) = forward(repay);
+    let flash = client::build_flash_swap_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &borrower.pubkey(),
+        &borrower.token1,
+        &FORWARDER_ID,
+        false,
+        1_000_000,
+        data,
+        &accounts,
+    );
+    assert_within("flash_swap", metered(&mut t, &[flash], &[&borrower.keypair]).await, FLASH_SWAP_CEILING);
+}
@@ -0,0 +1,126 @@
+//! The denylist covers every holder of the tokens moved, not just the signer.
+
+mod common;
+
+use adaptive_cpamm::{client, AmmError};
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// A seeded pool enforcing its denylist, with `listed` on it.
+async fn pool_with_listed(allows_removal: bool) -> (TestPool, User, Keypair) {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let lp = t.seed_liquidity(10_000_000, 10_000_000).await;
+    let authority = t.authority.pubkey();
+    let listed = Keypair::new();
+    let compliance = client::build_set_compliance_ix(&PROGRAM_ID, &authority, authority, true, allows_removal);
+    let deny = client::build_deny_address_ix(&PROGRAM_ID, &authority, &authority, listed.pubkey());
+    t.send_as_authority(&[compliance, deny]).await.unwrap();
+    t.refresh_keys().await;
+    (t, lp, listed)
+}
+
+#[tokio::test]
+async fn swap_paying_a_listed_recipient_fails() {
+    let (mut t, _, listed) = pool_with_listed(false).await;
+    let trader = t.fund_user(100_000, 0).await;
+    let token1_mint = t.keys.token1_mint;
+    let listed_token1 = t.create_token_account(&listed.pubkey(), &token1_mint, 0).await;
+
+    let to_listed = client::build_swap_to_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &trader.pubkey(),
+        &trader.token0,
+        &listed.pubkey(),
+        &listed_token1,
+        true,
+        100_000,
+        1,
+        NO_DEADLINE,
+    );
+    assert_amm_error(t.send(&[to_listed], &[&trader.keypair]).await, AmmError::AddressDenylisted);
+    // the same trade paying the trader itself goes through
+    t.swap(&trader, true, 100_000, 1).await.unwrap();
+}
+
+#[tokio::test]
+async fn delegate_spending_a_listed_owners_tokens_fails() {
+    let (mut t, _, listed) = pool_with_listed(false).await;
+    let delegate = t.fund_user(0, 0).await;
+    let token0_mint = t.keys.token0_mint;
+    let listed_token0 = t.create_token_account(&listed.pubkey(), &token0_mint, 100_000).await;
+    let approve = spl_token::instruction::approve(
+        &spl_token::ID,
+        &listed_token0,
+        &delegate.pubkey(),
+        &listed.pubkey(),
+        &[],
+        100_000,
+    )
+    .unwrap();
+    t.send(&[approve], &[&listed]).await.unwrap();
+
+    let spend = client::build_swap_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &delegate.pubkey(),
+        &listed_token0,
+        &delegate.token1,
+        true,
+        100_000,
+        1,
+        NO_DEADLINE,
+    );
+    // the owner's marker is required, not just the signer's
+    assert_amm_error(
+        t.send(std::slice::from_ref(&spend), &[&delegate.keypair]).await,
+        AmmError::DenylistAccountMissing,
+    );
+    let spend = client::with_denylist_markers(spend, &PROGRAM_ID, &t.keys, &[listed.pubkey()]);
+    assert_amm_error(t.send(&[spend], &[&delegate.keypair]).await, AmmError::AddressDenylisted);
+}
+
+#[tokio::test]
+async fn withdrawal_to_a_listed_owner_fails_unless_removals_are_allowed() {
+    for allows_removal in [false, true] {
+        let (mut t, lp, listed) = pool_with_listed(allows_removal).await;
+        let (token0_mint, token1_mint) = (t.keys.token0_mint, t.keys.token1_mint);
+        let listed_token0 = t.create_token_account(&listed.pubkey(), &token0_mint, 0).await;
+        let listed_token1 = t.create_token_account(&listed.pubkey(), &token1_mint, 0).await;
+        let withdraw = client::build_remove_liquidity_ix(
+            &PROGRAM_ID,
+            &t.keys,
+            &lp.pubkey(),
+            &listed_token0,
+            &listed_token1,
+            1_000,
+            false,
+        );
+        let withdraw = client::with_denylist_markers(withdraw, &PROGRAM_ID, &t.keys, &[listed.pubkey()]);
+        let result = t.send(&[withdraw], &[&lp.keypair]).await;
+        if allows_removal {
+            result.unwrap();
+            assert!(t.token_balance(&listed_token0).await > 0);
+        } else {
+            assert_amm_error(result, AmmError::AddressDenylisted);
+        }
+    }
+}
+
+#[tokio::test]
+async fn flash_loan_into_a_listed_owners_account_fails() {
+    let (mut t, _, listed) = pool_with_listed(false).await;
+    let authority = t.authority.pubkey();
+    t.send_as_authority(&[client::build_set_flash_fee_ix(&PROGRAM_ID, &authority, 9)])
+        .await
+        .unwrap();
+    let borrower = t.fund_user(0, 0).await;
+    let token0_mint = t.keys.token0_mint;
+    let listed_token0 = t.create_token_account(&listed.pubkey(), &token0_mint, 0).await;
+
+    let borrow =
+        client::build_flash_borrow_ix(&PROGRAM_ID, &t.keys, &borrower.pubkey(), &listed_token0, true, 1_000);
+    let borrow = client::with_denylist_markers(borrow, &PROGRAM_ID, &t.keys, &[listed.pubkey()]);
+    assert_amm_error(t.send(&[borrow], &[&borrower.keypair]).await, AmmError::AddressDenylisted);
+}
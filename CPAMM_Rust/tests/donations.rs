@@ -0,0 +1,171 @@
+//! Tokens sent straight to a vault are not reserves: the fee signals and
+//! the swap math read the recorded reserves, so a donation made just before
+//! a large trade neither shrinks its slippage proxy nor deepens the pool.
+//! Only `sync`, on a pool created with `sync_donations`, adopts them;
+//! before the first deposit nothing does, and they stay skimmable.
+
+mod common;
+
+use adaptive_cpamm::client::{self, SimulatedSwap};
+use adaptive_cpamm::event_parser::AmmEvent;
+use adaptive_cpamm::math::shares_for_deposit;
+use adaptive_cpamm::{AmmError, Pool, PoolFlags};
+use anchor_lang::{AnchorSerialize, Discriminator};
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::pubkey::Pubkey;
+
+const SEED: u64 = 1_000_000_000;
+const TRADE: u64 = 60_000_000;
+
+/// The logged `simulate_swap` quote for the trade.
+async fn quote(t: &mut TestPool, token_in_is_0: bool, amount_in: u64) -> SimulatedSwap {
+    t.next_slot().await;
+    let simulate = client::build_simulate_swap_ix(&PROGRAM_ID, &t.keys, token_in_is_0, amount_in);
+    let result = t.send(&[simulate], &[]).await;
+    let logs = result.as_ref().err().map(|failure| failure.logs.clone()).unwrap_or_default();
+    assert_amm_error(result, AmmError::SimulationOnly);
+    client::parse_simulate_swap_logs(&logs).expect("simulate_swap logged a quote")
+}
+
+/// A raw SPL transfer of `amount` into `vault`, bypassing the program.
+async fn transfer_into(t: &mut TestPool, vault: Pubkey, token0: bool, amount: u64) {
+    let sender = if token0 { t.fund_user(amount, 0).await } else { t.fund_user(0, amount).await };
+    let from = if token0 { sender.token0 } else { sender.token1 };
+    let transfer = spl_token::instruction::transfer(&spl_token::ID, &from, &vault, &sender.pubkey(), &[], amount)
+        .unwrap();
+    t.send(&[transfer], &[&sender.keypair]).await.unwrap();
+}
+
+/// A seeded pool after a first, small trade, holding a trader for the large one.
+async fn mid_flow_pool() -> (TestPool, User) {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(SEED, SEED).await;
+    let trader = t.fund_user(TRADE + 1_000_000, 0).await;
+    t.swap(&trader, true, 1_000_000, 1).await.unwrap();
+    (t, trader)
+}
+
+#[tokio::test]
+async fn a_donation_before_a_swap_leaves_its_fee_and_signals_unchanged() {
+    let (mut undonated, honest) = mid_flow_pool().await;
+    let (mut donated, attacker) = mid_flow_pool().await;
+    let before = quote(&mut donated, true, TRADE).await;
+
+    // ten times the reserves into each vault: read from the vaults, the
+    // trade would look a fraction of its size against a far deeper pool
+    let (vault0, vault1) = (donated.keys.vault0, donated.keys.vault1);
+    transfer_into(&mut donated, vault0, true, 10 * SEED).await;
+    transfer_into(&mut donated, vault1, false, 10 * SEED).await;
+    let pool = donated.pool().await;
+    assert_eq!(donated.token_balance(&vault0).await, pool.reserve0 + 10 * SEED);
+
+    let after = quote(&mut donated, true, TRADE).await;
+    assert_eq!(after, before);
+    assert!(after.slip_1e12 > 0 && after.shallow_1e12 > 0, "{after:?}");
+    assert_eq!(quote(&mut undonated, true, TRADE).await, after);
+
+    let attack = donated.swap(&attacker, true, TRADE, 1).await.unwrap();
+    let plain = undonated.swap(&honest, true, TRADE, 1).await.unwrap();
+    let (attack, plain) = (attack.swap_events()[0], plain.swap_events()[0]);
+    assert_eq!((attack.fee_ppm, attack.amount_out), (before.fee_ppm, before.amount_out));
+    assert_eq!((plain.fee_ppm, plain.amount_out), (before.fee_ppm, before.amount_out));
+    assert_eq!(attack.fee_amount, plain.fee_amount);
+    assert_eq!((attack.reserve0_after, attack.reserve1_after), (plain.reserve0_after, plain.reserve1_after));
+}
+
+async fn sync_pool() -> (TestPool, User) {
+    let flags = PoolFlags { sync_donations: true, ..default_flags() };
+    let mut t = TestPool::new(PoolSetup { flags, ..Default::default() }).await;
+    let lp = t.seed_liquidity(SEED, SEED).await;
+    (t, lp)
+}
+
+/// What `shares` redeem for right now, by redeeming them.
+async fn redeem(t: &mut TestPool, lp: &User, shares: u64) -> (u64, u64) {
+    // the same withdrawal twice in one slot would be the same transaction
+    t.next_slot().await;
+    let (before0, before1) = (t.token_balance(&lp.token0).await, t.token_balance(&lp.token1).await);
+    t.remove_liquidity(lp, shares).await.unwrap();
+    (t.token_balance(&lp.token0).await - before0, t.token_balance(&lp.token1).await - before1)
+}
+
+#[tokio::test]
+async fn sync_adopts_a_donation_and_raises_what_each_share_redeems() {
+    let (mut t, lp) = sync_pool().await;
+    let shares = t.token_balance(&lp.lp(&t.keys)).await / 10;
+    let before = redeem(&mut t, &lp, shares).await;
+
+    let (vault0, vault1) = (t.keys.vault0, t.keys.vault1);
+    transfer_into(&mut t, vault0, true, SEED / 10).await;
+    transfer_into(&mut t, vault1, false, SEED / 20).await;
+    let supply = t.pool().await.total_lp_supply;
+    let out = t.send(&[client::build_sync_ix(&PROGRAM_ID, &t.keys)], &[]).await.unwrap();
+
+    let pool = t.pool().await;
+    assert_eq!(pool.reserve0, t.token_balance(&vault0).await);
+    assert_eq!(pool.reserve1, t.token_balance(&vault1).await);
+    assert_eq!(pool.total_lp_supply, supply);
+    let [AmmEvent::Sync(synced)] = &out.events[..] else { panic!("no SyncEvent: {:?}", out.logs) };
+    assert_eq!((synced.reserve0, synced.reserve1), (pool.reserve0, pool.reserve1));
+
+    let after = redeem(&mut t, &lp, shares).await;
+    assert!(after.0 > before.0 && after.1 > before.1, "{before:?} -> {after:?}");
+}
+
+#[tokio::test]
+async fn sync_with_nothing_donated_changes_nothing() {
+    let (mut t, _) = sync_pool().await;
+    let address = t.keys.pool;
+    let before = t.account(&address).await.unwrap().data;
+    t.next_slot().await;
+    let out = t.send(&[client::build_sync_ix(&PROGRAM_ID, &t.keys)], &[]).await.unwrap();
+    assert!(out.events.is_empty(), "{:?}", out.logs);
+    assert_eq!(t.account(&address).await.unwrap().data, before);
+}
+
+/// First deposit into a pool whose vault0 already holds `stray` token0:
+/// the shares are priced from the deposit alone and the stray tokens are
+/// left for `skim`.
+async fn bootstrap_over_stray_token0(mut t: TestPool, stray: u64) {
+    let (amount0, amount1) = (1_000_000, 4_000_000);
+    let lp = t.fund_user(amount0, amount1).await;
+    let out = t.add_liquidity(&lp, amount0, amount1).await.unwrap();
+    let [AmmEvent::Mint(minted)] = &out.events[..] else { panic!("no MintEvent: {:?}", out.logs) };
+    let pool = t.pool().await;
+    assert_eq!(pool.total_lp_supply, shares_for_deposit(amount0, amount1, 0, 0, 0).unwrap());
+    assert_eq!(t.token_balance(&lp.lp(&t.keys)).await, minted.shares);
+    assert_eq!((pool.reserve0, pool.reserve1), (amount0, amount1));
+
+    let vault0 = t.keys.vault0;
+    assert_eq!(t.token_balance(&vault0).await, amount0 + stray);
+    let recipient = t.fund_user(0, 0).await;
+    let skim = client::build_skim_ix(&PROGRAM_ID, &t.keys, &recipient.pubkey(), &recipient.token0, &recipient.token1);
+    t.send(&[skim], &[]).await.unwrap();
+    assert_eq!(t.token_balance(&recipient.token0).await, stray);
+    assert_eq!(t.token_balance(&vault0).await, amount0);
+}
+
+#[tokio::test]
+async fn a_one_sided_transfer_before_the_first_deposit_is_not_credited() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let vault0 = t.keys.vault0;
+    transfer_into(&mut t, vault0, true, 50_000).await;
+    bootstrap_over_stray_token0(t, 50_000).await;
+}
+
+#[tokio::test]
+async fn orphaned_one_sided_reserves_are_dropped_at_the_first_deposit() {
+    // reserves with no shares against them, as a pool whose last LP left
+    // one side behind would record them
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let vault0 = t.keys.vault0;
+    transfer_into(&mut t, vault0, true, 50_000).await;
+    let mut pool = t.pool().await;
+    pool.reserve0 = 50_000;
+    let address = t.keys.pool;
+    let mut account = t.account(&address).await.unwrap();
+    account.data = [&Pool::DISCRIMINATOR[..], &pool.try_to_vec().unwrap()].concat();
+    t.ctx.set_account(&address, &account.into());
+    bootstrap_over_stray_token0(t, 50_000).await;
+}
@@ -0,0 +1,158 @@
+//! Each swap failure returns its own code, and `client::amm_error` maps
+//! every code back to its `AmmError`.
+
+mod common;
+
+use adaptive_cpamm::client::{self, amm_error, amm_error_from_instruction, AMM_ERRORS};
+use adaptive_cpamm::{AmmError, FeeConfig, Pool};
+use anchor_lang::error::ERROR_CODE_OFFSET;
+use anchor_lang::{AnchorSerialize, Discriminator};
+use common::*;
+use solana_sdk::instruction::InstructionError;
+use solana_sdk::signature::Signer;
+use solana_sdk::transaction::TransactionError;
+
+/// The `AmmError` a failed transaction maps to through the client helper.
+#[track_caller]
+fn mapped<T>(result: Result<T, TxFailure>) -> AmmError {
+    let Err(failure) = result else { panic!("transaction succeeded") };
+    let TransactionError::InstructionError(_, error) = &failure.error else {
+        panic!("not an instruction error: {:?}", failure.error)
+    };
+    amm_error_from_instruction(error).unwrap_or_else(|| panic!("not an AmmError: {error:?}"))
+}
+
+#[track_caller]
+fn assert_mapped<T>(result: Result<T, TxFailure>, expected: AmmError) {
+    let error = mapped(result);
+    assert_eq!(u32::from(error), u32::from(expected), "got {error:?}, expected {expected:?}");
+}
+
+#[test]
+fn every_code_maps_back_to_its_variant() {
+    for (index, error) in AMM_ERRORS.into_iter().enumerate() {
+        let code = ERROR_CODE_OFFSET + index as u32;
+        assert_eq!(u32::from(error), code, "{error:?} out of place");
+        assert_eq!(amm_error(code).map(u32::from), Some(code));
+    }
+    assert_eq!(u32::from(AMM_ERRORS[AMM_ERRORS.len() - 1]), u32::from(AmmError::NotPoolRecord));
+    assert!(amm_error(ERROR_CODE_OFFSET + AMM_ERRORS.len() as u32).is_none());
+    // Anchor's own codes and other programs' are not ours
+    assert!(amm_error(3003).is_none());
+    assert!(amm_error_from_instruction(&InstructionError::Custom(1)).is_none());
+    assert!(amm_error_from_instruction(&InstructionError::InvalidAccountData).is_none());
+}
+
+#[test]
+fn retired_variants_keep_their_codes() {
+    assert_eq!(u32::from(AmmError::RateLimited), ERROR_CODE_OFFSET + 21);
+    assert_eq!(u32::from(AmmError::FreezeDisabled), ERROR_CODE_OFFSET + 22);
+    assert_eq!(u32::from(AmmError::CoefficientTooLarge), ERROR_CODE_OFFSET + 53);
+    assert_eq!(u32::from(AmmError::FeeAboveDenominator), ERROR_CODE_OFFSET + 54);
+}
+
+async fn seeded_pool() -> (TestPool, User) {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let trader = t.fund_user(100_000_000, 100_000_000).await;
+    (t, trader)
+}
+
+#[tokio::test]
+async fn a_paused_pool_returns_pool_paused() {
+    let (mut t, trader) = seeded_pool().await;
+    let authority = t.authority.pubkey();
+    t.send_as_authority(&[client::build_set_paused_ix(&PROGRAM_ID, &authority, true)])
+        .await
+        .unwrap();
+    assert_mapped(t.swap(&trader, true, 1_000_000, 1).await, AmmError::PoolPaused);
+}
+
+#[tokio::test]
+async fn too_little_out_returns_slippage_exceeded() {
+    let (mut t, trader) = seeded_pool().await;
+    assert_mapped(t.swap(&trader, true, 1_000_000, u64::MAX).await, AmmError::SlippageExceeded);
+}
+
+#[tokio::test]
+async fn a_passed_deadline_returns_deadline_exceeded() {
+    let (mut t, trader) = seeded_pool().await;
+    let late = client::build_swap_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &trader.pubkey(),
+        &trader.token0,
+        &trader.token1,
+        true,
+        1_000_000,
+        1,
+        0,
+    );
+    assert_mapped(t.send(&[late], &[&trader.keypair]).await, AmmError::DeadlineExceeded);
+}
+
+#[tokio::test]
+async fn an_impact_above_the_cap_returns_price_impact_too_high() {
+    let (mut t, trader) = seeded_pool().await;
+    let authority = t.authority.pubkey();
+    t.send_as_authority(&[client::build_set_max_price_impact_ix(&PROGRAM_ID, &authority, 100)])
+        .await
+        .unwrap();
+    // 5% of the reserve moves the price by far more than 1%
+    assert_mapped(t.swap(&trader, true, 50_000_000, 1).await, AmmError::PriceImpactTooHigh);
+    t.swap(&trader, true, 1_000_000, 1).await.unwrap();
+}
+
+#[tokio::test]
+async fn an_input_account_of_the_other_mint_returns_wrong_token_account() {
+    let (mut t, trader) = seeded_pool().await;
+    let token1_mint = t.keys.token1_mint;
+    // the input account is only checked by the program, not by a constraint
+    let other_token1 = t.create_token_account(&trader.pubkey(), &token1_mint, 1_000_000).await;
+    let crossed = client::build_swap_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &trader.pubkey(),
+        &other_token1,
+        &trader.token1,
+        true,
+        1_000_000,
+        1,
+        NO_DEADLINE,
+    );
+    assert_mapped(t.send(&[crossed], &[&trader.keypair]).await, AmmError::WrongTokenAccount);
+}
+
+#[tokio::test]
+async fn a_fee_of_100_percent_returns_fee_too_high() {
+    // set_params refuses such bounds, but a pool from before ppm fees
+    // reads them from its bps fields, which it may have at 100%
+    let (mut t, trader) = seeded_pool().await;
+    let mut pool = t.pool().await;
+    (pool.min_fee_ppm, pool.max_fee_ppm) = (0, 0);
+    (pool.min_fee_bps, pool.max_fee_bps) = (10_000, 10_000);
+    let address = t.keys.pool;
+    let mut account = t.account(&address).await.unwrap();
+    account.data = [&Pool::DISCRIMINATOR[..], &pool.try_to_vec().unwrap()].concat();
+    t.ctx.set_account(&address, &account.into());
+    assert_mapped(t.swap(&trader, true, 1_000_000, 1).await, AmmError::FeeTooHigh);
+}
+
+#[tokio::test]
+async fn set_params_may_run_twice_in_a_slot() {
+    let (mut t, _) = seeded_pool().await;
+    for max_fee_ppm in [40_000, 50_000] {
+        let config = FeeConfig { max_fee_ppm, ..t.pool().await.fee_config() };
+        t.set_params(config).await.unwrap();
+    }
+    let pool = t.pool().await;
+    assert_eq!(pool.fee_bounds_ppm().1, 50_000);
+    // the field dates the curve; other admin updates leave it alone
+    assert_eq!(pool.params_updated_slot, t.clock().await.slot);
+    t.warp_slots(10).await;
+    let authority = t.authority.pubkey();
+    t.send_as_authority(&[client::build_set_paused_ix(&PROGRAM_ID, &authority, true)]).await.unwrap();
+    let paused = t.pool().await;
+    assert!(paused.last_update_slot > pool.params_updated_slot);
+    assert_eq!(paused.params_updated_slot, pool.params_updated_slot);
+}
@@ -0,0 +1,79 @@
+//! For a fixed pool state a larger amount_in never pays a lower fee, in
+//! either slip mode: swept over a geometric grid of trade sizes for a
+//! spread of reserves, EMAs, curves and both directions.
+
+use adaptive_cpamm::math::{compute_dynamic_fee, PoolParams};
+
+/// amount_in from 1 up to 10^19, each step about 5% above the last.
+fn geometric_grid() -> Vec<u128> {
+    let mut grid = vec![1u128];
+    while *grid.last().unwrap() < 10_000_000_000_000_000_000 {
+        let last = *grid.last().unwrap();
+        grid.push(u128::max(last + 1, last * 21 / 20));
+    }
+    grid
+}
+
+fn curves() -> Vec<PoolParams> {
+    let base = PoolParams {
+        min_fee_ppm: 3_000,
+        max_fee_ppm: 30_000,
+        beta_vol_bps_per1e12: 300,
+        gamma_slip_bps_per1e12: 500,
+        delta_shallow_bps_per1e12: 100,
+        epsilon_oracle_bps_per1e12: 50,
+        zeta_skew_bps_per1e12: 200,
+        ..Default::default()
+    };
+    vec![
+        base,
+        // a small slip weight, where per-term rounding used to plateau
+        PoolParams { gamma_slip_bps_per1e12: 1, ..base },
+        PoolParams { min_fee_ppm: 1, max_fee_ppm: 1_000_000, gamma_slip_bps_per1e12: u16::MAX, ..base },
+        PoolParams { shallow_target_depth0: 1_000_000_000, ema_depth0: 40_000_000, ..base },
+        PoolParams { shallow_depth_k: 7, ..base },
+    ]
+}
+
+#[test]
+fn fee_is_monotone_in_amount_in_over_a_geometric_grid() {
+    let grid = geometric_grid();
+    let reserves = [
+        (1_000u128, 1_000u128),
+        (1_000_000_000, 1_000_000),
+        (1_000_000, 1_000_000_000_000),
+        (7, 1_000_000_000_000_000_000),
+        (u64::MAX as u128, u64::MAX as u128),
+    ];
+    let mut checked = 0;
+    for slip_price_impact in [false, true] {
+        for curve in curves() {
+            for (r0, r1) in reserves {
+                // no EMA, the EMA at spot, and well off it in both directions
+                let spot_1e18 = r1 * 1_000_000_000_000_000_000 / r0;
+                for ema_price_1e18 in [0, spot_1e18, spot_1e18 / 3 + 1, spot_1e18.saturating_mul(3)] {
+                    let params = PoolParams { ema_price_1e18, slip_price_impact, ..curve };
+                    for token_in_is_0 in [true, false] {
+                        for oracle_conf_1e12 in [0, 5_000_000_000] {
+                            let mut last = 0u32;
+                            for &amount_in in &grid {
+                                let (fee_ppm, ..) =
+                                    compute_dynamic_fee(&params, token_in_is_0, amount_in, r0, r1, oracle_conf_1e12)
+                                        .unwrap();
+                                assert!(
+                                    fee_ppm >= last,
+                                    "fee fell from {last} to {fee_ppm} at amount_in {amount_in}: \
+                                     {params:?} in0 {token_in_is_0} reserves {r0}/{r1}"
+                                );
+                                assert!((params.min_fee_ppm..=params.max_fee_ppm).contains(&fee_ppm));
+                                last = fee_ppm;
+                                checked += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    assert!(checked > 100_000);
+}
@@ -0,0 +1,161 @@
+//! flash_borrow / flash_repay with the repayment missing, short, misplaced
+//! or out of order.
+
+mod common;
+
+use adaptive_cpamm::{client, AmmError, PoolFlags};
+use common::*;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::signature::Signer;
+
+const RESERVE: u64 = 10_000_000;
+const AMOUNT: u64 = 1_000_000;
+/// 30 bps of AMOUNT
+const FEE: u64 = 3_000;
+
+/// 10M/10M at a 30 bps flash fee; the borrower holds exactly the fee.
+async fn setup() -> (TestPool, User) {
+    setup_with(PoolSetup::default()).await
+}
+
+async fn setup_with(pool_setup: PoolSetup) -> (TestPool, User) {
+    let mut t = TestPool::start(program_test_with_forwarder(), pool_setup).await;
+    t.seed_liquidity(RESERVE, RESERVE).await;
+    let ix = client::build_set_flash_fee_ix(&PROGRAM_ID, &t.authority.pubkey(), 30);
+    t.send_as_authority(&[ix]).await.unwrap();
+    let borrower = t.fund_user(FEE, 0).await;
+    (t, borrower)
+}
+
+fn borrow(t: &TestPool, borrower: &User, amount: u64) -> Instruction {
+    client::build_flash_borrow_ix(&PROGRAM_ID, &t.keys, &borrower.pubkey(), &borrower.token0, true, amount)
+}
+
+fn repay(t: &TestPool, borrower: &User, amount: u64) -> Instruction {
+    client::build_flash_repay_ix(&PROGRAM_ID, &t.keys, &borrower.pubkey(), &borrower.token0, true, amount)
+}
+
+async fn assert_untouched(t: &mut TestPool, borrower: &User) {
+    let pool = t.pool().await;
+    assert_eq!((pool.reserve0, pool.reserve1), (RESERVE, RESERVE));
+    assert_eq!((pool.flash_amount, pool.flash_loan_count), (0, 0));
+    let vault0 = t.keys.vault0;
+    assert_eq!(t.token_balance(&vault0).await, RESERVE);
+    assert_eq!(t.token_balance(&borrower.token0).await, FEE);
+}
+
+#[tokio::test]
+async fn borrow_and_repay_with_the_fee_succeeds() {
+    let (mut t, borrower) = setup().await;
+    let ixs = [borrow(&t, &borrower, AMOUNT), repay(&t, &borrower, AMOUNT)];
+    t.send(&ixs, &[&borrower.keypair]).await.unwrap();
+
+    let pool = t.pool().await;
+    assert_eq!(pool.flash_amount, 0);
+    assert_eq!(pool.flash_loan_count, 1);
+    assert_eq!(pool.reserve0 + pool.protocol_fees_owed0, RESERVE + FEE);
+    assert_eq!(t.token_balance(&borrower.token0).await, 0);
+}
+
+#[tokio::test]
+async fn borrow_without_repay_fails() {
+    let (mut t, borrower) = setup().await;
+    let ixs = [borrow(&t, &borrower, AMOUNT)];
+    assert_amm_error(t.send(&ixs, &[&borrower.keypair]).await, AmmError::FlashRepayMissing);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn repay_of_a_smaller_amount_does_not_count() {
+    let (mut t, borrower) = setup().await;
+    let ixs = [borrow(&t, &borrower, AMOUNT), repay(&t, &borrower, AMOUNT / 2)];
+    assert_amm_error(t.send(&ixs, &[&borrower.keypair]).await, AmmError::FlashRepayMissing);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn repay_of_the_other_token_does_not_count() {
+    let (mut t, borrower) = setup().await;
+    let other_side = client::build_flash_repay_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &borrower.pubkey(),
+        &borrower.token1,
+        false,
+        AMOUNT,
+    );
+    let ixs = [borrow(&t, &borrower, AMOUNT), other_side];
+    assert_amm_error(t.send(&ixs, &[&borrower.keypair]).await, AmmError::FlashRepayMissing);
+}
+
+#[tokio::test]
+async fn repay_short_of_the_fee_fails() {
+    let (mut t, borrower) = setup().await;
+    // spend part of the fee money before repaying
+    let vault1 = t.keys.vault1;
+    let mint0 = t.keys.token0_mint;
+    let elsewhere = t.create_token_account(&vault1, &mint0, 0).await;
+    let leak = anchor_spl::token::spl_token::instruction::transfer(
+        &anchor_spl::token::spl_token::ID,
+        &borrower.token0,
+        &elsewhere,
+        &borrower.pubkey(),
+        &[],
+        AMOUNT + 1,
+    )
+    .unwrap();
+    let ixs = [borrow(&t, &borrower, AMOUNT), leak, repay(&t, &borrower, AMOUNT)];
+    let failure = t.send(&ixs, &[&borrower.keypair]).await.err().expect("short repay went through");
+    assert_eq!(failure.custom_code(), Some(1), "spl-token InsufficientFunds: {:?}", failure.error);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn repay_before_borrow_fails() {
+    let (mut t, borrower) = setup().await;
+    let ixs = [repay(&t, &borrower, AMOUNT), borrow(&t, &borrower, AMOUNT)];
+    assert_amm_error(t.send(&ixs, &[&borrower.keypair]).await, AmmError::FlashLoanMismatch);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn second_borrow_against_one_repay_fails() {
+    let (mut t, borrower) = setup().await;
+    let ixs = [
+        borrow(&t, &borrower, AMOUNT),
+        borrow(&t, &borrower, AMOUNT),
+        repay(&t, &borrower, AMOUNT),
+    ];
+    assert_amm_error(t.send(&ixs, &[&borrower.keypair]).await, AmmError::FlashLoanActive);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn repaying_twice_fails() {
+    let (mut t, borrower) = setup().await;
+    let ixs = [
+        borrow(&t, &borrower, AMOUNT),
+        repay(&t, &borrower, AMOUNT),
+        repay(&t, &borrower, AMOUNT),
+    ];
+    assert_amm_error(t.send(&ixs, &[&borrower.keypair]).await, AmmError::FlashLoanMismatch);
+}
+
+#[tokio::test]
+async fn repay_hidden_in_a_cpi_does_not_count() {
+    let (mut t, borrower) = setup().await;
+    let (data, accounts) = forward(repay(&t, &borrower, AMOUNT));
+    let wrapped = Instruction { program_id: FORWARDER_ID, accounts, data };
+    let ixs = [borrow(&t, &borrower, AMOUNT), wrapped];
+    assert_amm_error(t.send(&ixs, &[&borrower.keypair]).await, AmmError::FlashRepayMissing);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn sync_during_the_loan_fails() {
+    let flags = PoolFlags { sync_donations: true, ..default_flags() };
+    let (mut t, borrower) = setup_with(PoolSetup { flags, ..Default::default() }).await;
+    let sync = client::build_sync_ix(&PROGRAM_ID, &t.keys);
+    let ixs = [borrow(&t, &borrower, AMOUNT), sync, repay(&t, &borrower, AMOUNT)];
+    assert_amm_error(t.send(&ixs, &[&borrower.keypair]).await, AmmError::FlashLoanActive);
+}
@@ -0,0 +1,209 @@
+//! flash_swap against callbacks that underpay or try to reenter the pool.
+
+mod common;
+
+use adaptive_cpamm::{client, AmmError};
+use anchor_spl::token::spl_token;
+use common::*;
+use solana_sdk::instruction::{AccountMeta, Instruction};
+use solana_sdk::signature::{Keypair, Signer};
+
+const RESERVE: u64 = 10_000_000;
+const AMOUNT_OUT: u64 = 100_000;
+/// token0 owed for AMOUNT_OUT of token1 with no fee at all
+const FEE_FREE_IN: u64 = 101_011;
+
+async fn setup() -> (TestPool, User) {
+    let mut t = TestPool::start(program_test_with_forwarder(), PoolSetup::default()).await;
+    t.seed_liquidity(RESERVE, RESERVE).await;
+    let borrower = t.fund_user(200_000, 0).await;
+    (t, borrower)
+}
+
+/// Borrow AMOUNT_OUT of token1 and have `callback_program` run with `data`
+/// and `accounts`.
+async fn flash(
+    t: &mut TestPool,
+    borrower: &User,
+    callback_program: &solana_sdk::pubkey::Pubkey,
+    data: Vec<u8>,
+    accounts: &[AccountMeta],
+    signers: &[&Keypair],
+) -> Result<TxOutput, TxFailure> {
+    let ix = client::build_flash_swap_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &borrower.pubkey(),
+        &borrower.token1,
+        callback_program,
+        false,
+        AMOUNT_OUT,
+        data,
+        accounts,
+    );
+    let mut all = vec![&borrower.keypair];
+    all.extend_from_slice(signers);
+    t.send(&[ix], &all).await
+}
+
+/// Callback that pays `amount` of token0 back into the vault.
+fn repay(t: &TestPool, borrower: &User, amount: u64) -> (Vec<u8>, Vec<AccountMeta>) {
+    forward(
+        spl_token::instruction::transfer(
+            &spl_token::ID,
+            &borrower.token0,
+            &t.keys.vault0,
+            &borrower.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap(),
+    )
+}
+
+/// Borrow with the pool program itself as the callback, running `ix`.
+async fn reenter(t: &mut TestPool, borrower: &User, ix: Instruction, signers: &[&Keypair]) -> Result<TxOutput, TxFailure> {
+    assert_eq!(ix.program_id, PROGRAM_ID);
+    flash(t, borrower, &PROGRAM_ID, ix.data, &ix.accounts, signers).await
+}
+
+async fn assert_untouched(t: &mut TestPool, borrower: &User) {
+    let pool = t.pool().await;
+    assert_eq!((pool.reserve0, pool.reserve1), (RESERVE, RESERVE));
+    assert!(!pool.locked);
+    assert_eq!(t.token_balance(&borrower.token0).await, 200_000);
+    assert_eq!(t.token_balance(&borrower.token1).await, 0);
+}
+
+#[tokio::test]
+async fn repaid_with_the_fee_succeeds() {
+    let (mut t, borrower) = setup().await;
+    let (data, accounts) = repay(&t, &borrower, 110_000);
+    flash(&mut t, &borrower, &FORWARDER_ID, data, &accounts, &[]).await.unwrap();
+
+    assert_eq!(t.token_balance(&borrower.token1).await, AMOUNT_OUT);
+    let pool = t.pool().await;
+    assert!(!pool.locked);
+    assert_eq!(pool.reserve1, RESERVE - AMOUNT_OUT);
+    assert!(pool.reserve0 > RESERVE + FEE_FREE_IN);
+    assert!(pool.reserve0 as u128 * pool.reserve1 as u128 > RESERVE as u128 * RESERVE as u128);
+}
+
+#[tokio::test]
+async fn borrowing_the_whole_reserve_fails() {
+    let (mut t, borrower) = setup().await;
+    let (data, accounts) = repay(&t, &borrower, 200_000);
+    let ix = client::build_flash_swap_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &borrower.pubkey(),
+        &borrower.token1,
+        &FORWARDER_ID,
+        false,
+        RESERVE,
+        data,
+        &accounts,
+    );
+    let result = t.send(&[ix], &[&borrower.keypair]).await;
+    assert_amm_error(result, AmmError::InsufficientOutputReserve);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn callback_that_repays_nothing_fails() {
+    let (mut t, borrower) = setup().await;
+    let result = flash(&mut t, &borrower, &FORWARDER_ID, vec![], &[], &[]).await;
+    assert_amm_error(result, AmmError::FlashSwapUnderpaid);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn repaying_without_the_fee_fails() {
+    let (mut t, borrower) = setup().await;
+    let (data, accounts) = repay(&t, &borrower, FEE_FREE_IN);
+    let result = flash(&mut t, &borrower, &FORWARDER_ID, data, &accounts, &[]).await;
+    assert_amm_error(result, AmmError::FlashSwapUnderpaid);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+/// Callback that hands `amount` of the borrowed token1 straight back.
+fn return_borrowed(t: &TestPool, borrower: &User, amount: u64) -> (Vec<u8>, Vec<AccountMeta>) {
+    forward(
+        spl_token::instruction::transfer(
+            &spl_token::ID,
+            &borrower.token1,
+            &t.keys.vault1,
+            &borrower.pubkey(),
+            &[],
+            amount,
+        )
+        .unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn returning_the_borrowed_side_without_the_fee_fails() {
+    let (mut t, borrower) = setup().await;
+    let (data, accounts) = return_borrowed(&t, &borrower, AMOUNT_OUT);
+    let result = flash(&mut t, &borrower, &FORWARDER_ID, data, &accounts, &[]).await;
+    assert_amm_error(result, AmmError::FlashSwapUnderpaid);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn returning_the_borrowed_side_with_the_fee_succeeds() {
+    let (mut t, borrower) = setup().await;
+    // the borrower already holds a little token1 to cover the fee
+    let mint1 = t.keys.token1_mint;
+    t.create_token_account(&borrower.pubkey(), &mint1, 5_000).await;
+    let (data, accounts) = return_borrowed(&t, &borrower, AMOUNT_OUT + 5_000);
+    flash(&mut t, &borrower, &FORWARDER_ID, data, &accounts, &[]).await.unwrap();
+
+    assert_eq!(t.token_balance(&borrower.token1).await, 0);
+    let pool = t.pool().await;
+    assert_eq!(pool.reserve0, RESERVE);
+    assert!(pool.reserve1 > RESERVE);
+}
+
+#[tokio::test]
+async fn reentrant_swap_fails_on_the_lock() {
+    let (mut t, borrower) = setup().await;
+    let swap = client::build_swap_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &borrower.pubkey(),
+        &borrower.token0,
+        &borrower.token1,
+        true,
+        50_000,
+        1,
+        NO_DEADLINE,
+    );
+    assert_amm_error(reenter(&mut t, &borrower, swap, &[]).await, AmmError::PoolLocked);
+    assert_untouched(&mut t, &borrower).await;
+}
+
+#[tokio::test]
+async fn reentrant_snapshot_fails_on_the_lock() {
+    let (mut t, borrower) = setup().await;
+    let init = client::build_init_snapshots_ix(
+        &PROGRAM_ID,
+        &t.authority.pubkey(),
+        &t.ctx.payer.pubkey(),
+        60,
+    );
+    t.send_as_authority(&[init]).await.unwrap();
+    let snapshot = client::build_snapshot_ix(&PROGRAM_ID);
+    assert_amm_error(reenter(&mut t, &borrower, snapshot, &[]).await, AmmError::PoolLocked);
+
+    let grow = client::build_increase_snapshot_cardinality_ix(&PROGRAM_ID, &borrower.pubkey(), 4);
+    assert_amm_error(reenter(&mut t, &borrower, grow, &[]).await, AmmError::PoolLocked);
+}
+
+#[tokio::test]
+async fn reentrant_twap_checkpoint_fails_on_the_lock() {
+    let (mut t, borrower) = setup().await;
+    let checkpoint = Keypair::new();
+    let ix = client::build_checkpoint_twap_ix(&PROGRAM_ID, &borrower.pubkey(), &checkpoint.pubkey());
+    assert_amm_error(reenter(&mut t, &borrower, ix, &[&checkpoint]).await, AmmError::PoolLocked);
+}
@@ -0,0 +1,52 @@
+//! A DAO-held pool: the authority is a governance treasury PDA, which
+//! never signs a transaction and only signs the CPIs its program makes.
+//! Admin instructions must run when a mock executor invokes them with the
+//! PDA's seeds.
+
+mod common;
+
+use adaptive_cpamm::{client, instruction, AmmError, FeeConfig, Pool};
+use anchor_lang::{AnchorSerialize, Discriminator};
+use common::*;
+use solana_sdk::signature::Signer;
+
+/// A seeded pool whose authority, and every role the creator starts with,
+/// is the governance treasury. No instruction hands the authority over, so
+/// the account is rewritten.
+async fn dao_pool() -> TestPool {
+    let mut t = TestPool::start(program_test_with_governance(), PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let mut pool = t.pool().await;
+    let treasury = governance_treasury().0;
+    (pool.authority, pool.fee_manager, pool.pauser, pool.treasurer) = (treasury, treasury, treasury, treasury);
+    let address = t.keys.pool;
+    let mut account = t.account(&address).await.unwrap();
+    account.data = [&Pool::DISCRIMINATOR[..], &pool.try_to_vec().unwrap()].concat();
+    t.ctx.set_account(&address, &account.into());
+    t
+}
+
+#[tokio::test]
+async fn the_treasury_pda_sets_params_through_cpi() {
+    let mut t = dao_pool().await;
+    let treasury = governance_treasury().0;
+    let fee_config = FeeConfig { min_fee_ppm: 1_000, max_fee_ppm: 20_000, ..t.pool().await.fee_config() };
+    let set = client::build_set_params_ix(&PROGRAM_ID, &treasury, instruction::SetParams { fee_config });
+    let out = t.send(&[execute_as_governance(set)], &[]).await.unwrap();
+    assert!(!out.events.is_empty(), "no admin event: {:?}", out.logs);
+    let pool = t.pool().await;
+    assert_eq!((pool.min_fee_ppm, pool.max_fee_ppm), (1_000, 20_000));
+
+    // so do the other admin instructions, e.g. pausing
+    let pause = client::build_set_paused_ix(&PROGRAM_ID, &treasury, true);
+    t.send(&[execute_as_governance(pause)], &[]).await.unwrap();
+    assert!(t.pool().await.paused);
+}
+
+#[tokio::test]
+async fn the_former_authority_key_no_longer_sets_params() {
+    let mut t = dao_pool().await;
+    let fee_config = t.pool().await.fee_config();
+    let set = client::build_set_params_ix(&PROGRAM_ID, &t.authority.pubkey(), instruction::SetParams { fee_config });
+    assert_amm_error(t.send_as_authority(&[set]).await, AmmError::NotAuthorized);
+}
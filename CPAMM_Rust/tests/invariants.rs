@@ -0,0 +1,88 @@
+//! The `invariants` assertions, run against the program: only built with
+//! the feature, `anchor run test-invariants`. The program asserts
+//! after every instruction, so each sequence here passes only if none of
+//! its steps broke an invariant.
+
+#![cfg(feature = "invariants")]
+
+mod common;
+
+use adaptive_cpamm::invariants::{check_k_fee_growth, check_share_value_non_decreasing, k, share_value};
+use adaptive_cpamm::{client, PoolFlags};
+use common::*;
+use solana_sdk::signature::Signer;
+
+/// Neither reserve per share dropped from `before` to `after`.
+fn assert_share_value_kept(before: (u64, u64, u64), after: (u64, u64, u64)) {
+    for (r, r_after) in [(before.0, after.0), (before.1, after.1)] {
+        assert!(r_after as u128 * before.2 as u128 >= r as u128 * after.2 as u128, "{before:?} -> {after:?}");
+    }
+}
+
+#[tokio::test]
+async fn add_remove_add_at_adversarial_sizes_keeps_the_share_value() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let lp = t.seed_liquidity(3_000_000_007, 2_000_000_011).await;
+    let depositor = t.fund_user(1_000_000_000_000, 1_000_000_000_000).await;
+    for shares in [1u64, 7, 999, 1_000_003, 3] {
+        let before = share_value(&t.pool().await);
+        t.remove_liquidity(&lp, shares).await.unwrap();
+        assert_share_value_kept(before, share_value(&t.pool().await));
+
+        // the floored payouts leave reserves of no common unit, which only
+        // a deposit of whole reserves matches
+        let pool = t.pool().await;
+        let before = share_value(&pool);
+        t.add_liquidity(&depositor, pool.reserve0, pool.reserve1).await.unwrap();
+        assert_share_value_kept(before, share_value(&t.pool().await));
+        t.next_slot().await;
+    }
+}
+
+#[tokio::test]
+#[should_panic(expected = "reserve0 per share fell")]
+async fn a_falling_share_value_trips_the_assertion() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000, 1_000_000).await;
+    let mut pool = t.pool().await;
+    let before = share_value(&pool);
+    pool.reserve0 -= 1;
+    check_share_value_non_decreasing(before, &pool);
+}
+
+#[tokio::test]
+async fn swaps_grow_k_by_their_lp_fee() {
+    // each fee placement, with and without a protocol share of the fee
+    for fee_on_output in [false, true] {
+        let flags = PoolFlags { fee_on_output, ..default_flags() };
+        let mut t = TestPool::new(PoolSetup { flags, ..Default::default() }).await;
+        t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+        let trader = t.fund_user(50_000_000, 50_000_000).await;
+        for protocol_fee_share_bps in [0, 2_500] {
+            let authority = t.authority.pubkey();
+            let share = client::build_set_protocol_fee_ix(&PROGRAM_ID, &authority, protocol_fee_share_bps);
+            t.send_as_authority(&[share]).await.unwrap();
+            for (token_in_is_0, amount_in) in [(true, 1_000), (false, 3_000_000), (true, 20_000_000), (false, 7)] {
+                t.next_slot().await;
+                let k_before = k(&t.pool().await);
+                t.swap(&trader, token_in_is_0, amount_in, 1).await.unwrap();
+                assert!(k(&t.pool().await) >= k_before);
+            }
+        }
+    }
+}
+
+#[tokio::test]
+#[should_panic(expected = "invariant: k")]
+async fn a_swap_keeping_less_than_its_lp_fee_trips_the_assertion() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let trader = t.fund_user(10_000_000, 0).await;
+    let k_before = k(&t.pool().await);
+    let out = t.swap(&trader, true, 10_000_000, 1).await.unwrap();
+    let event = out.swap_events()[0];
+    // the fee left the pool again
+    let mut pool = t.pool().await;
+    pool.reserve0 -= event.fee_amount;
+    check_k_fee_growth(k_before, &pool, event);
+}
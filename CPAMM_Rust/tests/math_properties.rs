@@ -0,0 +1,265 @@
+//! Property tests of the fee and pricing math over randomized inputs.
+
+use adaptive_cpamm::math::{
+    amounts_for_shares, compute_dynamic_fee, ema_update, get_amount_in, get_amount_out, isqrt, price_to_1e12,
+    shares_for_deposit, shares_for_withdrawal, spot_price_0_in_1, spot_price_1e18, swap_amounts, PoolParams,
+    PPM_DENOM,
+};
+use adaptive_cpamm::{accumulator, compute_geometric_twap, compute_twap, exp2_q32, log2_q32};
+use proptest::prelude::*;
+
+prop_compose! {
+    fn pool_params()(
+        min_fee_ppm in 0u32..=PPM_DENOM as u32,
+        spread in 0u32..=PPM_DENOM as u32,
+        coefficients in any::<[u16; 5]>(),
+        ema_price_1e18 in prop_oneof![Just(0u128), 1u128..=1_000_000_000_000_000_000_000_000_000],
+        shallow_depth_k in any::<u64>(),
+        shallow_target_depth0 in prop_oneof![Just(0u128), any::<u64>().prop_map(u128::from)],
+        ema_depth0 in prop_oneof![Just(0u128), any::<u64>().prop_map(u128::from)],
+        slip_price_impact in any::<bool>(),
+    ) -> PoolParams {
+        let [beta, gamma, delta, epsilon, zeta] = coefficients;
+        PoolParams {
+            min_fee_ppm,
+            max_fee_ppm: min_fee_ppm.saturating_add(spread).min(PPM_DENOM as u32),
+            beta_vol_bps_per1e12: beta,
+            gamma_slip_bps_per1e12: gamma,
+            delta_shallow_bps_per1e12: delta,
+            epsilon_oracle_bps_per1e12: epsilon,
+            ema_price_1e18,
+            shallow_depth_k: shallow_depth_k as u128,
+            slip_price_impact,
+            shallow_target_depth0,
+            ema_depth0,
+            zeta_skew_bps_per1e12: zeta,
+        }
+    }
+}
+
+fn reserve() -> impl Strategy<Value = u64> {
+    prop_oneof![1u64..1_000, 1u64..u64::MAX]
+}
+
+/// A reserve and an amount that still fits in a u64 vault on top of it.
+fn reserve_and_amount(min_amount: u64) -> impl Strategy<Value = (u64, u64)> {
+    reserve().prop_flat_map(move |reserve| (Just(reserve), min_amount..=u64::MAX - reserve))
+}
+
+/// Reserves whose ratio reserve1 / reserve0 is exactly 10^exponent, for
+/// exponents from -12 to 12.
+fn reserves_at_ratio() -> impl Strategy<Value = (i32, u64, u64)> {
+    (-12i32..=12).prop_flat_map(|exponent| {
+        let factor = 10u64.pow(exponent.unsigned_abs());
+        (1..=u64::MAX / factor).prop_map(move |base| {
+            if exponent >= 0 {
+                (exponent, base, base * factor)
+            } else {
+                (exponent, base * factor, base)
+            }
+        })
+    })
+}
+
+proptest! {
+    #[test]
+    fn fee_stays_within_its_bounds(
+        params in pool_params(),
+        token_in_is_0 in any::<bool>(),
+        amount_in in 1u64..=u64::MAX,
+        r0 in reserve(),
+        r1 in reserve(),
+        oracle_conf_1e12 in any::<u64>(),
+    ) {
+        let (fee_ppm, ..) = compute_dynamic_fee(
+            &params, token_in_is_0, amount_in as u128, r0 as u128, r1 as u128, oracle_conf_1e12 as u128,
+        ).unwrap();
+        prop_assert!((params.min_fee_ppm..=params.max_fee_ppm).contains(&fee_ppm));
+    }
+
+    #[test]
+    fn fee_is_monotone_in_amount_in(
+        params in pool_params(),
+        token_in_is_0 in any::<bool>(),
+        smaller in 1u64..=u64::MAX,
+        larger in 1u64..=u64::MAX,
+        r0 in reserve(),
+        r1 in reserve(),
+        oracle_conf_1e12 in any::<u64>(),
+    ) {
+        let (smaller, larger) = (smaller.min(larger), smaller.max(larger));
+        let fee = |amount_in: u64| compute_dynamic_fee(
+            &params, token_in_is_0, amount_in as u128, r0 as u128, r1 as u128, oracle_conf_1e12 as u128,
+        ).unwrap().0;
+        prop_assert!(fee(smaller) <= fee(larger));
+    }
+
+    #[test]
+    fn a_swap_never_decreases_k(
+        (rin, amount_in) in reserve_and_amount(1),
+        rout in reserve(),
+        fee_ppm in 0u32..PPM_DENOM as u32,
+        fee_on_output in any::<bool>(),
+    ) {
+        let (amount_out, _) = swap_amounts(amount_in, rin as u128, rout as u128, fee_ppm, fee_on_output).unwrap();
+        // the curve alone keeps the out side non-empty; `swap`'s
+        // InsufficientOutputReserve check only backs it up
+        prop_assert!(amount_out < rout);
+        // the whole input lands in the pool, fee included
+        let k_before = rin as u128 * rout as u128;
+        let k_after = (rin + amount_in) as u128 * (rout - amount_out) as u128;
+        prop_assert!(k_after >= k_before, "k {} -> {}", k_before, k_after);
+    }
+
+    #[test]
+    fn quoting_the_input_for_an_output_never_asks_more_than_paid_for_it(
+        (rin, amount_in) in reserve_and_amount(1),
+        rout in reserve(),
+        fee_ppm in 0u32..PPM_DENOM as u32,
+    ) {
+        let (amount_out, _) = get_amount_out(amount_in, rin as u128, rout as u128, fee_ppm).unwrap();
+        // an input buying nothing has no input quote to compare with
+        prop_assume!(amount_out > 0);
+        let quoted_in = get_amount_in(amount_out, rin as u128, rout as u128, fee_ppm).unwrap();
+        prop_assert!(quoted_in <= amount_in, "{} in -> {} out -> {} in", amount_in, amount_out, quoted_in);
+    }
+
+    #[test]
+    fn the_quoted_input_buys_at_least_the_output(
+        rin in reserve(),
+        (rout, amount_out) in reserve().prop_flat_map(|rout| (Just(rout), 0..rout)),
+        fee_ppm in 0u32..PPM_DENOM as u32,
+    ) {
+        prop_assume!(amount_out > 0);
+        // an input past u64 is refused rather than quoted
+        let Ok(amount_in) = get_amount_in(amount_out, rin as u128, rout as u128, fee_ppm) else { return Ok(()) };
+        let (bought, _) = get_amount_out(amount_in, rin as u128, rout as u128, fee_ppm).unwrap();
+        prop_assert!(bought >= amount_out, "{} out -> {} in -> {} out", amount_out, amount_in, bought);
+    }
+
+    #[test]
+    fn add_then_remove_never_returns_more_than_deposited(
+        (reserve0, amount0) in reserve_and_amount(0),
+        (reserve1, amount1) in reserve_and_amount(0),
+        total_supply in prop_oneof![Just(0u64), 1u64..=u64::MAX],
+    ) {
+        // an empty pool has no reserves to price against
+        let (reserve0, reserve1) = if total_supply == 0 { (0, 0) } else { (reserve0, reserve1) };
+        // deposits minting nothing, or more shares than a u64 supply holds,
+        // are refused before any token moves
+        let Ok(shares) = shares_for_deposit(amount0, amount1, reserve0, reserve1, total_supply) else {
+            return Ok(());
+        };
+        let Some(supply) = total_supply.checked_add(shares).filter(|_| shares > 0) else { return Ok(()) };
+        let (out0, out1) = amounts_for_shares(shares, reserve0 + amount0, reserve1 + amount1, supply).unwrap();
+        prop_assert!(out0 <= amount0 && out1 <= amount1, "in {}/{} out {}/{}", amount0, amount1, out0, out1);
+    }
+
+    #[test]
+    fn add_remove_sequences_never_lower_the_share_value(
+        reserve0 in 1u64..=1 << 48,
+        reserve1 in 1u64..=1 << 48,
+        total_supply in 1u64..=1 << 48,
+        steps in prop::collection::vec((any::<bool>(), any::<u64>(), any::<u64>()), 1..24),
+    ) {
+        let (mut reserve0, mut reserve1, mut supply) = (reserve0, reserve1, total_supply);
+        for (add, a, b) in steps {
+            let before = (reserve0, reserve1, supply);
+            if add {
+                // up to twice the reserves, adversarially small included;
+                // deposits minting nothing or overflowing are refused
+                let (amount0, amount1) = (a % reserve0.saturating_mul(2), b % reserve1.saturating_mul(2));
+                let Ok(shares) = shares_for_deposit(amount0, amount1, reserve0, reserve1, supply) else { continue };
+                let after = (reserve0.checked_add(amount0), reserve1.checked_add(amount1), supply.checked_add(shares));
+                let (Some(after0), Some(after1), Some(supply_after)) = after else { continue };
+                if shares == 0 {
+                    continue;
+                }
+                (reserve0, reserve1, supply) = (after0, after1, supply_after);
+            } else {
+                // a full exit leaves no per-share value to compare
+                let shares = a % supply;
+                let (out0, out1) = amounts_for_shares(shares, reserve0, reserve1, supply).unwrap();
+                (reserve0, reserve1, supply) = (reserve0 - out0, reserve1 - out1, supply - shares);
+            }
+            for (r, r_after) in [(before.0, reserve0), (before.1, reserve1)] {
+                // r_after / supply >= r / before.supply
+                prop_assert!(
+                    r_after as u128 * before.2 as u128 >= r as u128 * supply as u128,
+                    "{:?} -> {:?}", before, (reserve0, reserve1, supply)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shares_for_withdrawal_is_the_fewest_shares_covering_the_amounts(
+        reserve0 in 1u64..=u64::MAX,
+        reserve1 in 1u64..=u64::MAX,
+        total_supply in 1u64..=u64::MAX,
+        a in any::<u64>(),
+        b in any::<u64>(),
+    ) {
+        let (amount0, amount1) = (a % (reserve0 / 2 + 1), b % (reserve1 / 2 + 1));
+        let Ok(shares) = shares_for_withdrawal(amount0, amount1, reserve0, reserve1, total_supply) else {
+            return Ok(());
+        };
+        prop_assume!(shares <= total_supply);
+        let (out0, out1) = amounts_for_shares(shares, reserve0, reserve1, total_supply).unwrap();
+        prop_assert!(out0 >= amount0 && out1 >= amount1);
+        if shares > 0 {
+            let (out0, out1) = amounts_for_shares(shares - 1, reserve0, reserve1, total_supply).unwrap();
+            prop_assert!(out0 < amount0 || out1 < amount1);
+        }
+    }
+
+    #[test]
+    fn isqrt_is_the_floor_of_the_square_root(y in prop_oneof![0u128..1_000_000, any::<u128>()]) {
+        let r = isqrt(y);
+        prop_assert!(r * r <= y);
+        // (r + 1)^2 past u128::MAX is above any y
+        prop_assert!((r + 1).checked_mul(r + 1).is_none_or(|next| next > y));
+    }
+
+    #[test]
+    fn ema_stays_between_the_old_ema_and_the_price(
+        ema in any::<u128>(),
+        alpha_1e12 in any::<u64>(),
+        price_1e18 in any::<u128>(),
+    ) {
+        let mut updated = ema;
+        ema_update(&mut updated, alpha_1e12, price_1e18);
+        prop_assert!(ema.min(price_1e18) <= updated && updated <= ema.max(price_1e18));
+    }
+
+    #[test]
+    fn the_internal_price_is_exact_for_ratios_from_1e_minus_12_to_1e12(
+        (exponent, reserve0, reserve1) in reserves_at_ratio(),
+    ) {
+        // neither floored to 0 nor saturated anywhere in the range
+        prop_assert_eq!(spot_price_1e18(reserve0, reserve1).unwrap(), 10u128.pow((18 + exponent) as u32));
+    }
+
+    #[test]
+    fn twaps_of_a_constant_price_convert_back_to_the_spot_price(
+        (_exponent, reserve0, reserve1) in reserves_at_ratio(),
+        start in any::<u128>(),
+        log_start in any::<u128>(),
+        elapsed in 1u64..=100_000_000,
+    ) {
+        let price_1e18 = spot_price_1e18(reserve0, reserve1).unwrap();
+        let spot_1e12 = spot_price_0_in_1(reserve0, reserve1).unwrap();
+        // the readings may straddle a wrap of either accumulator
+        let end = accumulator::accumulate(start, price_1e18, elapsed);
+        prop_assert_eq!(compute_twap(start, end, elapsed).unwrap(), spot_1e12);
+
+        let log_price = log2_q32(price_1e18);
+        let log_end = accumulator::accumulate(log_start, log_price, elapsed);
+        let geometric_1e12 = compute_geometric_twap(log_start, log_end, elapsed).unwrap();
+        // log2 and exp2 both floor, to within 2^-31 of the price at 1e18
+        let round_trip_1e18 = exp2_q32(log_price).unwrap();
+        prop_assert!(round_trip_1e18 <= price_1e18);
+        prop_assert!(price_1e18 - round_trip_1e18 <= (price_1e18 >> 31) + 1);
+        prop_assert_eq!(geometric_1e12, price_to_1e12(round_trip_1e18));
+    }
+}
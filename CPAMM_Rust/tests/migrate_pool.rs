@@ -0,0 +1,85 @@
+//! migrate_pool grows a pool account written before the decimals and ppm
+//! fields existed, after which the fallbacks for older pools take over.
+
+mod common;
+
+use adaptive_cpamm::{client, Pool};
+use anchor_lang::AnchorSerialize;
+use common::*;
+use solana_sdk::account::Account;
+use solana_sdk::rent::Rent;
+use solana_sdk::signature::Signer;
+
+/// Offset in the account data of the first field after the fee bounds
+/// that older pools lacked (`decimals0`), found by flipping it.
+fn decimals_offset(pool: &Pool) -> usize {
+    let mut flipped = pool.clone();
+    flipped.decimals0 ^= 0xff;
+    let (data, flipped) = (pool.try_to_vec().unwrap(), flipped.try_to_vec().unwrap());
+    8 + data.iter().zip(&flipped).position(|(a, b)| a != b).unwrap()
+}
+
+/// Rewrite the pool account as an older program left it: cut before the
+/// decimals, holding only the rent for that size.
+async fn make_legacy(t: &mut TestPool) {
+    let pool = t.pool().await;
+    let address = t.keys.pool;
+    let mut account: Account = t.account(&address).await.unwrap();
+    let legacy_len = decimals_offset(&pool);
+    account.data.truncate(legacy_len);
+    account.lamports = Rent::default().minimum_balance(legacy_len);
+    t.ctx.set_account(&address, &account.into());
+}
+
+#[tokio::test]
+async fn a_legacy_pool_trades_again_after_migrating() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let trader = t.fund_user(10_000, 0).await;
+    let (bounds, decimals) = {
+        let pool = t.pool().await;
+        (pool.fee_bounds_ppm(), (pool.decimals0, pool.decimals1))
+    };
+    make_legacy(&mut t).await;
+    t.next_slot().await;
+
+    let Err(stuck) = t.swap(&trader, true, 10_000, 1).await else { panic!("a legacy pool traded") };
+    let did_not_deserialize = anchor_lang::error::ErrorCode::AccountDidNotDeserialize;
+    assert_eq!(stuck.custom_code(), Some(u32::from(did_not_deserialize)));
+    // a pool still missing its decimals cannot be backfilled either
+    let sync = client::build_sync_decimals_ix(&PROGRAM_ID, &t.keys);
+    let Err(stuck) = t.send(std::slice::from_ref(&sync), &[]).await else { panic!("legacy decimals synced") };
+    assert_eq!(stuck.custom_code(), Some(u32::from(did_not_deserialize)));
+
+    let payer = t.ctx.payer.pubkey();
+    t.send(&[client::build_migrate_pool_ix(&PROGRAM_ID, &payer)], &[]).await.unwrap();
+    let address = t.keys.pool;
+    let account = t.account(&address).await.unwrap();
+    assert_eq!(account.data.len(), Pool::SPACE);
+    assert!(account.lamports >= Rent::default().minimum_balance(Pool::SPACE));
+
+    // the ppm bounds come from the bps fields, the decimals from the mints
+    let pool = t.pool().await;
+    assert_eq!((pool.min_fee_ppm, pool.max_fee_ppm), (0, 0));
+    assert_eq!(pool.fee_bounds_ppm(), bounds);
+    assert_eq!((pool.decimals0, pool.decimals1), (0, 0));
+    t.next_slot().await;
+    t.send(&[sync], &[]).await.unwrap();
+    let pool = t.pool().await;
+    assert_eq!((pool.decimals0, pool.decimals1), decimals);
+
+    let out = t.swap(&trader, true, 10_000, 1).await.unwrap();
+    let fee_ppm = out.swap_events()[0].fee_ppm;
+    assert!((bounds.0..=bounds.1).contains(&fee_ppm));
+}
+
+#[tokio::test]
+async fn migrating_a_current_pool_changes_nothing() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000, 1_000_000).await;
+    let address = t.keys.pool;
+    let before = t.account(&address).await.unwrap();
+    let payer = t.ctx.payer.pubkey();
+    t.send(&[client::build_migrate_pool_ix(&PROGRAM_ID, &payer)], &[]).await.unwrap();
+    assert_eq!(t.account(&address).await.unwrap(), before);
+}
@@ -0,0 +1,86 @@
+//! The oracle feed is found among the remaining accounts by key, so it can
+//! sit behind the denylist marker or any other extra account.
+
+mod common;
+
+use adaptive_cpamm::{client, instruction, AmmError, OracleSource};
+use common::*;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+
+/// 10M/10M with a required Pyth feed at 2% confidence.
+async fn setup() -> (TestPool, Pubkey) {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(10_000_000, 10_000_000).await;
+    let feed = Pubkey::new_unique();
+    t.set_pyth_price(&feed, 100_000_000, 2_000_000, -8).await;
+    let ix = client::build_set_oracle_ix(
+        &PROGRAM_ID,
+        &t.authority.pubkey(),
+        instruction::SetOracle {
+            oracle_source: OracleSource::Pyth,
+            oracle: feed,
+            epsilon_oracle_bps_per1e12: 100,
+            oracle_max_age_seconds: 3_600,
+            oracle_required: true,
+        },
+    );
+    t.send_as_authority(&[ix]).await.unwrap();
+    t.refresh_keys().await;
+    (t, feed)
+}
+
+/// Swap 100k of token0 with `extra` as the remaining accounts.
+async fn swap_with(t: &mut TestPool, extra: Vec<AccountMeta>) -> Result<TxOutput, TxFailure> {
+    let trader = t.fund_user(100_000, 0).await;
+    let mut keys = t.keys;
+    keys.oracle = None;
+    let mut ix = client::build_swap_ix(
+        &PROGRAM_ID,
+        &keys,
+        &trader.pubkey(),
+        &trader.token0,
+        &trader.token1,
+        true,
+        100_000,
+        1,
+        NO_DEADLINE,
+    );
+    ix.accounts.extend(extra);
+    t.send(&[ix], &[&trader.keypair]).await
+}
+
+#[tokio::test]
+async fn feed_behind_another_account_is_found() {
+    let (mut t, feed) = setup().await;
+    let first = swap_with(&mut t, vec![AccountMeta::new_readonly(feed, false)]).await.unwrap();
+    let first_fee = first.swap_events()[0].fee_ppm;
+
+    // same trade size from the restored state, feed second this time
+    let (mut t, feed) = setup().await;
+    let extra = vec![
+        AccountMeta::new_readonly(Pubkey::new_unique(), false),
+        AccountMeta::new_readonly(feed, false),
+    ];
+    let second = swap_with(&mut t, extra).await.unwrap();
+    assert_eq!(second.swap_events()[0].fee_ppm, first_fee);
+}
+
+#[tokio::test]
+async fn confidence_raises_the_fee() {
+    let (mut t, feed) = setup().await;
+    let with_feed = swap_with(&mut t, vec![AccountMeta::new_readonly(feed, false)]).await.unwrap();
+
+    let mut plain = TestPool::new(PoolSetup::default()).await;
+    plain.seed_liquidity(10_000_000, 10_000_000).await;
+    let without = swap_with(&mut plain, vec![]).await.unwrap();
+    assert!(with_feed.swap_events()[0].fee_ppm > without.swap_events()[0].fee_ppm);
+}
+
+#[tokio::test]
+async fn required_feed_missing_from_the_accounts_fails() {
+    let (mut t, _) = setup().await;
+    let decoy = vec![AccountMeta::new_readonly(Pubkey::new_unique(), false)];
+    assert_amm_error(swap_with(&mut t, decoy).await, AmmError::OracleRequired);
+}
@@ -0,0 +1,226 @@
+//! Happy paths of initialize/add/remove/swap and the basic failure codes.
+
+mod common;
+
+use adaptive_cpamm::event_parser::AmmEvent;
+use adaptive_cpamm::{client, instruction, AmmError, FeeCurve, PoolFlags, PoolState};
+use anchor_lang::AnchorDeserialize;
+use common::*;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+#[tokio::test]
+async fn initialize_pool_creates_empty_pool() {
+    let mut t = TestPool::new(PoolSetup { decimals0: 6, decimals1: 9, ..Default::default() }).await;
+    let pool = t.pool().await;
+    assert_eq!(pool.authority, t.authority.pubkey());
+    assert_eq!((pool.reserve0, pool.reserve1, pool.total_lp_supply), (0, 0, 0));
+    assert_eq!((pool.decimals0, pool.decimals1), (6, 9));
+    assert_eq!(pool.fee_bounds_ppm(), (3_000, 30_000));
+    let (vault0, vault1) = (t.keys.vault0, t.keys.vault1);
+    assert_eq!(t.token_balance(&vault0).await, 0);
+    assert_eq!(t.token_balance(&vault1).await, 0);
+    let lp_mint = t.keys.lp_mint;
+    assert_eq!(t.mint_supply(&lp_mint).await, 0);
+}
+
+#[tokio::test]
+async fn a_mint_with_a_freeze_authority_is_rejected_unless_allowed() {
+    let mut t = TestPool::without_pool(program_test()).await;
+    let issuer = Pubkey::new_unique();
+    let (mint0, mint1) = (t.create_freezable_mint(6, &issuer).await, t.create_mint(6).await);
+    let result = t.try_create_pool(&mint0, &mint1, PoolSetup::default()).await;
+    assert_amm_error(result, AmmError::RiskyMint);
+    assert!(t.account(&client::derive_pool_address(&PROGRAM_ID).0).await.is_none());
+
+    let flags = PoolFlags { allow_risky_mints: true, ..default_flags() };
+    t.create_pool(&mint0, &mint1, PoolSetup { flags, ..Default::default() }).await;
+    assert!(t.pool().await.allow_risky_mints);
+    let out = t.send(&[client::build_get_pool_state_ix(&PROGRAM_ID, &t.keys)], &[]).await.unwrap();
+    let state = PoolState::deserialize(&mut out.return_data.as_deref().unwrap()).unwrap();
+    assert!(state.allow_risky_mints);
+}
+
+#[tokio::test]
+async fn add_liquidity_mints_shares_and_books_reserves() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let lp = t.fund_user(1_000_000, 4_000_000).await;
+    let out = t.add_liquidity(&lp, 1_000_000, 4_000_000).await.unwrap();
+
+    let minted = match out.events.as_slice() {
+        [AmmEvent::Mint(mint)] => mint.shares,
+        _ => panic!("expected one MintEvent: {}", out.logs.join("\n")),
+    };
+    let pool = t.pool().await;
+    assert_eq!((pool.reserve0, pool.reserve1), (1_000_000, 4_000_000));
+    let lp_account = lp.lp(&t.keys);
+    assert_eq!(t.token_balance(&lp_account).await, minted);
+    assert!(minted > 0 && minted <= pool.total_lp_supply);
+    assert_eq!(t.token_balance(&lp.token0).await, 0);
+    assert_eq!(t.token_balance(&lp.token1).await, 0);
+
+    // a second deposit at the same ratio mints pro rata
+    let second = t.fund_user(500_000, 2_000_000).await;
+    t.add_liquidity(&second, 500_000, 2_000_000).await.unwrap();
+    let pool_after = t.pool().await;
+    let second_lp = second.lp(&t.keys);
+    assert_eq!(
+        t.token_balance(&second_lp).await,
+        pool.total_lp_supply / 2,
+        "half the reserves mint half the supply"
+    );
+    assert_eq!((pool_after.reserve0, pool_after.reserve1), (1_500_000, 6_000_000));
+}
+
+#[tokio::test]
+async fn remove_liquidity_returns_pro_rata_amounts() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let lp = t.seed_liquidity(2_000_000, 2_000_000).await;
+    let lp_account = lp.lp(&t.keys);
+    let shares = t.token_balance(&lp_account).await;
+    let supply = t.pool().await.total_lp_supply;
+
+    let out = t.remove_liquidity(&lp, shares / 2).await.unwrap();
+    let burn = match out.events.as_slice() {
+        [AmmEvent::Burn(burn)] => burn,
+        _ => panic!("expected one BurnEvent"),
+    };
+    let expected = (2_000_000u128 * (shares / 2) as u128 / supply as u128) as u64;
+    assert_eq!((burn.amount0, burn.amount1), (expected, expected));
+    assert_eq!(t.token_balance(&lp.token0).await, expected);
+    assert_eq!(t.token_balance(&lp.token1).await, expected);
+    assert_eq!(t.token_balance(&lp_account).await, shares - shares / 2);
+    let pool = t.pool().await;
+    assert_eq!(pool.reserve0, 2_000_000 - expected);
+    assert_eq!(pool.total_lp_supply, supply - shares / 2);
+}
+
+#[tokio::test]
+async fn a_2x_price_move_reports_its_impermanent_loss_on_exit() {
+    // a near-zero flat fee and no breaker, so one trade can double the price
+    let fee_curve = FeeCurve { min_fee_ppm: 1, max_fee_ppm: 1, breaker_enabled: false, ..default_fee_curve() };
+    let mut t = TestPool::new(PoolSetup { fee_curve, ..Default::default() }).await;
+    let lp = t.fund_user(1_000_000_000, 1_000_000_000).await;
+    let open = client::build_open_position_ix(&PROGRAM_ID, &t.keys, &lp.pubkey());
+    let deposit = client::build_add_liquidity_ix(
+        &PROGRAM_ID,
+        &t.keys,
+        &lp.pubkey(),
+        &lp.token0,
+        &lp.token1,
+        1_000_000_000,
+        1_000_000_000,
+        true,
+    );
+    t.send(&[open, deposit], &[&lp.keypair]).await.unwrap();
+
+    // (sqrt(2) - 1) of the token1 reserve in leaves 1e9/sqrt(2) of token0
+    let trader = t.fund_user(0, 414_213_562).await;
+    t.swap(&trader, false, 414_213_562, 1).await.unwrap();
+    let pool = t.pool().await;
+    let price_1e6 = pool.reserve1 as u128 * 1_000_000 / pool.reserve0 as u128;
+    assert!((1_999_999..=2_000_001).contains(&price_1e6), "price {price_1e6}e-6");
+
+    let lp_account = lp.lp(&t.keys);
+    let shares = t.token_balance(&lp_account).await;
+    let withdraw =
+        client::build_remove_liquidity_ix(&PROGRAM_ID, &t.keys, &lp.pubkey(), &lp.token0, &lp.token1, shares, true);
+    let out = t.send(&[withdraw], &[&lp.keypair]).await.unwrap();
+    let burn = match out.events.as_slice() {
+        [AmmEvent::Burn(burn)] => burn,
+        _ => panic!("expected one BurnEvent"),
+    };
+    assert_eq!(burn.entry_price_1e12, 1_000_000_000_000);
+    // hodl 2 * 1e9 + 1e9 at the exit price, which the trade only brings
+    // to within 1e-6 of 2
+    assert!(burn.hodl_value_in_token1.abs_diff(3_000_000_000) < 1_000);
+    // 2 * sqrt(2) / 3 - 1 = -5.719%, truncated towards zero
+    assert_eq!(burn.il_bps, -571);
+}
+
+#[tokio::test]
+async fn swap_pays_out_and_keeps_the_fee_in_the_pool() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(10_000_000, 10_000_000).await;
+    let trader = t.fund_user(100_000, 0).await;
+
+    let out = t.swap(&trader, true, 100_000, 1).await.unwrap();
+    let swap = out.swap_events()[0];
+    assert_eq!(swap.amount_in, 100_000);
+    assert!((3_000..=30_000).contains(&swap.fee_ppm));
+    assert_eq!(t.token_balance(&trader.token1).await, swap.amount_out);
+    assert_eq!(t.token_balance(&trader.token0).await, 0);
+    // amount_out is returned for CPI callers
+    let returned = u64::from_le_bytes(out.return_data.as_ref().unwrap()[..8].try_into().unwrap());
+    assert_eq!(returned, swap.amount_out);
+
+    let pool = t.pool().await;
+    assert_eq!(pool.reserve0, 10_000_000 + 100_000);
+    assert_eq!(pool.reserve1, 10_000_000 - swap.amount_out);
+    assert!(pool.reserve0 as u128 * pool.reserve1 as u128 > 10_000_000u128 * 10_000_000);
+    // without the fee 100k in would buy 99_009 out
+    assert!(swap.amount_out < 99_009);
+}
+
+#[tokio::test]
+async fn a_swap_never_empties_the_output_reserve() {
+    // no breaker, so nothing but the curve limits a huge trade
+    let fee_curve = FeeCurve { breaker_enabled: false, ..default_fee_curve() };
+    let mut t = TestPool::new(PoolSetup { fee_curve, ..Default::default() }).await;
+    t.seed_liquidity(1_000, 1_000).await;
+    let whale = t.fund_user(u64::MAX / 2, 0).await;
+    let out = t.swap(&whale, true, u64::MAX / 4, 1).await.unwrap();
+    assert_eq!(out.swap_events()[0].amount_out, 999);
+    assert_eq!(t.pool().await.reserve1, 1);
+
+    // x*y=k floors the last unit's price to nothing, so the curve refuses
+    // it before the InsufficientOutputReserve backstop is reached
+    t.next_slot().await;
+    assert_amm_error(t.swap(&whale, true, u64::MAX / 4, 1).await, AmmError::AmountOutZero);
+    assert_eq!(t.pool().await.reserve1, 1);
+}
+
+#[tokio::test]
+async fn swap_below_min_amount_out_fails() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(10_000_000, 10_000_000).await;
+    let trader = t.fund_user(100_000, 0).await;
+    assert_amm_error(t.swap(&trader, true, 100_000, 99_009).await, AmmError::SlippageExceeded);
+}
+
+#[tokio::test]
+async fn off_ratio_deposit_fails_with_bad_ratio() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000, 1_000_000).await;
+    let lp = t.fund_user(1_000_000, 1_000_000).await;
+    assert_amm_error(t.add_liquidity(&lp, 1_000_000, 500_000).await, AmmError::BadRatio);
+}
+
+#[tokio::test]
+async fn swap_against_a_deviated_ema_fails_with_vol_too_high() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(10_000_000, 10_000_000).await;
+    let trader = t.fund_user(2_000_000, 0).await;
+    // ~10% move: inside the 20% breaker, but the EMA only follows 5% of it
+    t.swap(&trader, true, 500_000, 1).await.unwrap();
+
+    let mut config = t.pool().await.fee_config();
+    config.breaker_vol_threshold_1e12 = 10_000_000_000; // 1%
+    t.next_slot().await;
+    t.set_params(config).await.unwrap();
+    t.next_slot().await;
+    assert_amm_error(t.swap(&trader, true, 1_000, 1).await, AmmError::VolTooHigh);
+}
+
+#[tokio::test]
+async fn set_params_from_a_stranger_fails_with_not_authorized() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let stranger = Keypair::new();
+    let fee_config = t.pool().await.fee_config();
+    let ix = client::build_set_params_ix(
+        &PROGRAM_ID,
+        &stranger.pubkey(),
+        instruction::SetParams { fee_config },
+    );
+    assert_amm_error(t.send(&[ix], &[&stranger]).await, AmmError::NotAuthorized);
+}
@@ -0,0 +1,86 @@
+//! The protocol's share of the swap fees accrues outside the reserves and
+//! is collected to the treasury; what LPs gain plus what the treasury
+//! collects is exactly the fees swaps charged.
+
+mod common;
+
+use adaptive_cpamm::{client, Pool, PoolFlags};
+use common::*;
+use solana_sdk::signature::{Keypair, Signer};
+
+/// Swaps in both directions on a pool giving `protocol_fee_share_bps` of
+/// each fee to the protocol, then collects it. Checks, per token, that the
+/// reserves grew by the fees minus what the treasury received.
+async fn fees_are_conserved(fee_on_output: bool, protocol_fee_share_bps: u16) {
+    let flags = PoolFlags { fee_on_output, ..default_flags() };
+    let mut t = TestPool::new(PoolSetup { flags, ..Default::default() }).await;
+    let authority = t.authority.pubkey();
+    let treasury = Keypair::new().pubkey();
+    t.send_as_authority(&[
+        client::build_set_treasury_ix(&PROGRAM_ID, &authority, treasury, false),
+        client::build_set_protocol_fee_ix(&PROGRAM_ID, &authority, protocol_fee_share_bps),
+    ])
+    .await
+    .unwrap();
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let trader = t.fund_user(100_000_000, 100_000_000).await;
+
+    // per token: fees charged, and the LPs' part of them, which is how far
+    // the fee side's reserve moved past the fee-free trade
+    let (mut charged, mut lp_growth) = ([0u64; 2], [0i128; 2]);
+    for (token_in_is_0, amount_in) in [(true, 5_000_000), (false, 12_345_678), (true, 777), (false, 40_000_000)] {
+        t.next_slot().await;
+        let before = t.pool().await;
+        let out = t.swap(&trader, token_in_is_0, amount_in, 1).await.unwrap();
+        let event = out.swap_events()[0];
+        let after = t.pool().await;
+        let side = usize::from(!event.fee_token_is_0);
+        let reserve = |pool: &Pool| [pool.reserve0, pool.reserve1][side] as i128;
+        let fee_free_delta = if fee_on_output {
+            -((event.amount_out + event.fee_amount) as i128)
+        } else {
+            (event.amount_in - event.fee_amount) as i128
+        };
+        charged[side] += event.fee_amount;
+        lp_growth[side] += reserve(&after) - reserve(&before) - fee_free_delta;
+    }
+    assert!(charged.iter().all(|&fees| fees > 0), "{charged:?}");
+
+    let (mint0, mint1) = (t.keys.token0_mint, t.keys.token1_mint);
+    let treasury0 = t.create_token_account(&treasury, &mint0, 0).await;
+    let treasury1 = t.create_token_account(&treasury, &mint1, 0).await;
+    let collect =
+        client::build_collect_protocol_fees_ix(&PROGRAM_ID, &t.keys, &authority, &treasury0, &treasury1, None, 0);
+    t.send_as_authority(&[collect]).await.unwrap();
+    let collected = [t.token_balance(&treasury0).await, t.token_balance(&treasury1).await];
+
+    for side in 0..2 {
+        assert_eq!(lp_growth[side] + collected[side] as i128, charged[side] as i128, "token{side}");
+    }
+    if protocol_fee_share_bps == 0 {
+        assert_eq!(collected, [0, 0]);
+    } else {
+        assert!(collected.iter().all(|&fees| fees > 0), "{collected:?}");
+    }
+    // nothing owed is left, and the vaults hold exactly the reserves
+    let pool = t.pool().await;
+    assert_eq!((pool.protocol_fees_owed0, pool.protocol_fees_owed1), (0, 0));
+    let (vault0, vault1) = (t.keys.vault0, t.keys.vault1);
+    assert_eq!(t.token_balance(&vault0).await, pool.reserve0);
+    assert_eq!(t.token_balance(&vault1).await, pool.reserve1);
+}
+
+#[tokio::test]
+async fn fees_on_input_are_conserved() {
+    fees_are_conserved(false, 2_500).await;
+}
+
+#[tokio::test]
+async fn fees_on_output_are_conserved() {
+    fees_are_conserved(true, 2_500).await;
+}
+
+#[tokio::test]
+async fn without_a_protocol_share_the_lps_keep_every_fee() {
+    fees_are_conserved(false, 0).await;
+}
@@ -0,0 +1,105 @@
+//! The round-trip penalty catches a sandwich's back-run with the victim's
+//! trade in between, and leaves organic two-way flow alone.
+
+mod common;
+
+use adaptive_cpamm::simulator::PoolSimulator;
+use adaptive_cpamm::{client, RECENT_TRADES};
+use common::*;
+use solana_sdk::signature::Signer;
+
+const PENALTY_BPS: u16 = 50;
+
+/// A deep pool with a 10-slot, 0.5% band, 50 bps round-trip penalty.
+async fn penalized_pool() -> TestPool {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let authority = t.authority.pubkey();
+    let penalty = client::build_set_reversion_penalty_ix(&PROGRAM_ID, &authority, 10, 50, PENALTY_BPS);
+    t.send_as_authority(&[penalty]).await.unwrap();
+    t
+}
+
+/// Swap and return (amount_out, penalty paid in ppm): the fee charged
+/// above what the same swap pays with the penalty off.
+async fn swap_penalty(t: &mut TestPool, user: &User, token_in_is_0: bool, amount_in: u64) -> (u64, u32) {
+    let mut pool = t.pool().await;
+    pool.reversion_penalty_bps = 0;
+    let clock = t.clock().await;
+    let mut sim = PoolSimulator::new(pool, clock.unix_timestamp, clock.slot);
+    let base_fee_ppm = sim.swap(token_in_is_0, amount_in, 0, NO_DEADLINE, 0).unwrap().fee_ppm;
+    let out = t.swap(user, token_in_is_0, amount_in, 1).await.unwrap();
+    let event = out.swap_events()[0];
+    (event.amount_out, event.fee_ppm - base_fee_ppm)
+}
+
+#[tokio::test]
+async fn a_sandwich_back_run_pays_the_penalty() {
+    let mut t = penalized_pool().await;
+    let attacker = t.fund_user(5_000_000, 0).await;
+    let victim = t.fund_user(2_000_000, 0).await;
+
+    let (bought, penalty) = swap_penalty(&mut t, &attacker, true, 5_000_000).await;
+    assert_eq!(penalty, 0);
+    t.next_slot().await;
+    let (_, penalty) = swap_penalty(&mut t, &victim, true, 2_000_000).await;
+    assert_eq!(penalty, 0);
+    t.next_slot().await;
+    // selling what the front-run bought, the victim's trade in between
+    let (_, penalty) = swap_penalty(&mut t, &attacker, false, bought).await;
+    assert_eq!(penalty, u32::from(PENALTY_BPS) * 100);
+}
+
+#[tokio::test]
+async fn a_back_run_behind_a_full_ring_of_trades_is_missed() {
+    let mut t = penalized_pool().await;
+    let attacker = t.fund_user(5_000_000, 0).await;
+    let others = t.fund_user(10_000_000, 0).await;
+
+    let (bought, _) = swap_penalty(&mut t, &attacker, true, 5_000_000).await;
+    // RECENT_TRADES buys push the front-run out of the ring: the check only
+    // remembers that many swaps per direction
+    for i in 0..RECENT_TRADES as u64 {
+        t.next_slot().await;
+        swap_penalty(&mut t, &others, true, 1_000_000 + i).await;
+    }
+    t.next_slot().await;
+    let (_, penalty) = swap_penalty(&mut t, &attacker, false, bought).await;
+    assert_eq!(penalty, 0);
+}
+
+#[tokio::test]
+async fn organic_two_way_flow_pays_no_penalty() {
+    let mut t = penalized_pool().await;
+    let buyer = t.fund_user(20_000_000, 0).await;
+    let seller = t.fund_user(0, 20_000_000).await;
+
+    // alternating directions, each size well outside the 0.5% band of
+    // every recent output the other way
+    let flow = [
+        (true, 1_000_000),
+        (false, 2_500_000),
+        (true, 700_000),
+        (false, 1_300_000),
+        (true, 4_000_000),
+        (false, 900_000),
+        (true, 2_200_000),
+        (false, 3_100_000),
+    ];
+    for (token_in_is_0, amount_in) in flow {
+        t.next_slot().await;
+        let user = if token_in_is_0 { &buyer } else { &seller };
+        let (_, penalty) = swap_penalty(&mut t, user, token_in_is_0, amount_in).await;
+        assert_eq!(penalty, 0, "in0 {token_in_is_0} amount_in {amount_in}");
+    }
+}
+
+#[tokio::test]
+async fn a_reversal_past_the_window_pays_no_penalty() {
+    let mut t = penalized_pool().await;
+    let trader = t.fund_user(5_000_000, 0).await;
+    let (bought, _) = swap_penalty(&mut t, &trader, true, 5_000_000).await;
+    t.warp_slots(11).await;
+    let (_, penalty) = swap_penalty(&mut t, &trader, false, bought).await;
+    assert_eq!(penalty, 0);
+}
@@ -0,0 +1,79 @@
+//! The reward vault and LP escrow are protected from rescue_tokens and
+//! must be empty, and are closed, when the pool is.
+
+mod common;
+
+use adaptive_cpamm::{client, AmmError};
+use anchor_spl::associated_token::get_associated_token_address;
+use common::*;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+
+const FUNDS: u64 = 1_000_000;
+
+/// An empty pool with rewards in a fresh mint and a campaign of FUNDS
+/// starting in 100s funded from the authority.
+async fn setup() -> (TestPool, Pubkey) {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    let reward_mint = t.create_mint(6).await;
+    let authority = t.authority.pubkey();
+    let payer = t.ctx.payer.pubkey();
+    t.airdrop(&authority, 1_000_000_000).await;
+    let init = client::build_init_rewards_ix(&PROGRAM_ID, &t.keys, &authority, &payer, &reward_mint);
+    t.send_as_authority(&[init]).await.unwrap();
+    t.refresh_keys().await;
+
+    t.create_token_account(&authority, &reward_mint, FUNDS).await;
+    let now = t.clock().await.unix_timestamp;
+    let fund = client::build_fund_campaign_ix(&PROGRAM_ID, &reward_mint, &authority, FUNDS, now + 100, now + 1_100);
+    t.send_as_authority(&[fund]).await.unwrap();
+    (t, reward_mint)
+}
+
+fn reward_vault(t: &TestPool, reward_mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(&t.keys.pool, reward_mint)
+}
+
+#[tokio::test]
+async fn rescue_cannot_drain_the_reward_vault() {
+    let (mut t, reward_mint) = setup().await;
+    let vault = reward_vault(&t, &reward_mint);
+    let authority = t.authority.pubkey();
+    let destination = get_associated_token_address(&authority, &reward_mint);
+    let rescue = client::build_rescue_tokens_ix(&PROGRAM_ID, &authority, &vault, &destination, FUNDS);
+    assert_amm_error(t.send_as_authority(&[rescue]).await, AmmError::ProtectedAccount);
+    assert_eq!(t.token_balance(&vault).await, FUNDS);
+}
+
+#[tokio::test]
+async fn close_pool_requires_the_reward_accounts() {
+    let (mut t, _) = setup().await;
+    let mut keys = t.keys;
+    keys.reward_mint = None;
+    let recipient = Pubkey::new_unique();
+    let close = client::build_close_pool_ix(&PROGRAM_ID, &keys, &t.authority.pubkey(), &recipient, false, &[]);
+    assert_amm_error(t.send_as_authority(&[close]).await, AmmError::RewardAccountsMissing);
+}
+
+#[tokio::test]
+async fn close_pool_refuses_a_funded_campaign_then_closes_the_reward_accounts() {
+    let (mut t, reward_mint) = setup().await;
+    let authority = t.authority.pubkey();
+    let recipient = Pubkey::new_unique();
+    let close = client::build_close_pool_ix(&PROGRAM_ID, &t.keys, &authority, &recipient, false, &[]);
+    assert_amm_error(t.send_as_authority(std::slice::from_ref(&close)).await, AmmError::PoolNotEmpty);
+
+    // nothing has streamed yet, so ending the campaign takes everything back
+    let end = client::build_end_campaign_early_ix(&PROGRAM_ID, &reward_mint, &authority);
+    t.send_as_authority(&[end]).await.unwrap();
+    t.next_slot().await;
+    t.send_as_authority(&[close]).await.unwrap();
+
+    let vault = reward_vault(&t, &reward_mint);
+    let escrow = get_associated_token_address(&t.keys.pool, &t.keys.lp_mint);
+    assert!(t.account(&vault).await.is_none());
+    assert!(t.account(&escrow).await.is_none());
+    let pool = t.keys.pool;
+    assert!(t.account(&pool).await.is_none());
+    assert!(t.account(&recipient).await.unwrap().lamports > 0);
+}
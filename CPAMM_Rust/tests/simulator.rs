@@ -0,0 +1,118 @@
+//! PoolSimulator against the program: the same sequence of deposits and
+//! swaps, successful or not, leaves both with the same pool state.
+
+mod common;
+
+use adaptive_cpamm::simulator::PoolSimulator;
+use adaptive_cpamm::{client, AmmError, Pool};
+use anchor_lang::AnchorSerialize;
+use common::*;
+use solana_sdk::signature::Signer;
+
+enum Step {
+    /// a deposit of the reserves themselves, or of exact amounts
+    DoubleReserves,
+    Deposit(u64, u64),
+    Swap { token_in_is_0: bool, amount_in: u64, min_amount_out: u64, deadline: i64 },
+}
+
+fn sim_code(error: anchor_lang::error::Error) -> Option<u32> {
+    match error {
+        anchor_lang::error::Error::AnchorError(error) => Some(error.error_code_number),
+        _ => None,
+    }
+}
+
+fn assert_same_pool(on_chain: &Pool, simulated: &Pool, step: usize) {
+    assert_eq!(
+        on_chain.try_to_vec().unwrap(),
+        simulated.try_to_vec().unwrap(),
+        "pool state diverged after step {step}"
+    );
+}
+
+#[tokio::test]
+async fn simulator_tracks_the_program_step_by_step() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 2_000_000_000).await;
+    let authority = t.authority.pubkey();
+    t.send_as_authority(&[client::build_set_min_trade_amounts_ix(&PROGRAM_ID, &authority, 1_000, 1_000)])
+        .await
+        .unwrap();
+    let trader = t.fund_user(10_000_000_000, 10_000_000_000).await;
+    let clock = t.clock().await;
+    let mut sim = PoolSimulator::new(t.pool().await, clock.unix_timestamp, clock.slot);
+
+    let swap = |token_in_is_0, amount_in, min_amount_out| Step::Swap {
+        token_in_is_0,
+        amount_in,
+        min_amount_out,
+        deadline: NO_DEADLINE,
+    };
+    let steps = [
+        swap(true, 500_000, 1),
+        swap(false, 4_000_000, 1),
+        swap(true, 3_000_000, 1),
+        // slippage, below the minimum trade, and an immediate reversal
+        swap(false, 1_000_000, u64::MAX),
+        swap(true, 999, 1),
+        swap(false, 6_000_000, 1),
+        Step::DoubleReserves,
+        Step::Deposit(10_000_000, 10_000_000),
+        // past the deadline and below the minimum: the deadline reports first
+        Step::Swap { token_in_is_0: true, amount_in: 1, min_amount_out: 1, deadline: 0 },
+        swap(true, 20_000_000, 1),
+        swap(false, 1_234_567, 1),
+    ];
+
+    let mut failures = vec![];
+    for (i, step) in steps.iter().enumerate() {
+        t.warp_seconds(7).await;
+        let clock = t.clock().await;
+        (sim.now, sim.slot) = (clock.unix_timestamp, clock.slot);
+        let (on_chain, simulated) = match *step {
+            Step::DoubleReserves | Step::Deposit(..) => {
+                let (amount0, amount1) = match *step {
+                    Step::Deposit(amount0, amount1) => (amount0, amount1),
+                    _ => (sim.pool.reserve0, sim.pool.reserve1),
+                };
+                let on_chain = t.add_liquidity(&trader, amount0, amount1).await;
+                let simulated = sim.add_liquidity(amount0, amount1);
+                (on_chain.map(|_| ()).map_err(|f| f.custom_code()), simulated.map(|_| ()).map_err(sim_code))
+            }
+            Step::Swap { token_in_is_0, amount_in, min_amount_out, deadline } => {
+                let (token_in, token_out) =
+                    if token_in_is_0 { (trader.token0, trader.token1) } else { (trader.token1, trader.token0) };
+                let ix = client::build_swap_ix(
+                    &PROGRAM_ID,
+                    &t.keys,
+                    &trader.pubkey(),
+                    &token_in,
+                    &token_out,
+                    token_in_is_0,
+                    amount_in,
+                    min_amount_out,
+                    deadline,
+                );
+                let on_chain = t.send(&[ix], &[&trader.keypair]).await;
+                let simulated = sim.swap(token_in_is_0, amount_in, min_amount_out, deadline, 0);
+                if let (Ok(out), Ok(quote)) = (&on_chain, &simulated) {
+                    assert_eq!(out.swap_events()[0].amount_out, quote.amount_out, "step {i}");
+                    assert_eq!(out.swap_events()[0].fee_ppm, quote.fee_ppm, "step {i}");
+                }
+                (on_chain.map(|_| ()).map_err(|f| f.custom_code()), simulated.map(|_| ()).map_err(sim_code))
+            }
+        };
+        assert_eq!(on_chain, simulated, "step {i}");
+        if let Err(code) = simulated {
+            failures.push(code);
+        }
+        assert_same_pool(&t.pool().await, &sim.pool, i);
+        let (vault0, vault1) = (t.keys.vault0, t.keys.vault1);
+        let (vault0, vault1) = (t.token_balance(&vault0).await, t.token_balance(&vault1).await);
+        assert_eq!((sim.vault0, sim.vault1), (vault0, vault1), "vaults after step {i}");
+    }
+    let expected =
+        [AmmError::SlippageExceeded, AmmError::TradeTooSmall, AmmError::BadRatio, AmmError::DeadlineExceeded];
+    assert_eq!(failures, expected.map(|error| Some(u32::from(error))));
+}
@@ -0,0 +1,87 @@
+//! InputFraction against PriceImpact: the exact impact of a constant-product
+//! fill is the input fraction, so the modes only differ by its rounding,
+//! and neither charges a small trade for the flooring of its payout.
+
+mod common;
+
+use adaptive_cpamm::math::{compute_dynamic_fee, price_impact_1e12, PoolParams, SCALE};
+use adaptive_cpamm::{client, PoolState, SlipMode};
+use anchor_lang::AnchorDeserialize;
+use common::*;
+use solana_sdk::signature::Signer;
+
+fn params(slip_price_impact: bool) -> PoolParams {
+    PoolParams {
+        min_fee_ppm: 3_000,
+        max_fee_ppm: 30_000,
+        gamma_slip_bps_per1e12: 500,
+        slip_price_impact,
+        ..Default::default()
+    }
+}
+
+/// Fee and slip signal of both modes, (InputFraction, PriceImpact).
+fn both_modes(amount_in: u128, rin: u128, rout: u128) -> ((u32, u128), (u32, u128)) {
+    let fee = |price_impact| {
+        let (fee_ppm, _, slip_1e12, _) =
+            compute_dynamic_fee(&params(price_impact), true, amount_in, rin, rout, 0).unwrap();
+        (fee_ppm, slip_1e12)
+    };
+    (fee(false), fee(true))
+}
+
+#[test]
+fn a_one_unit_trade_pays_the_base_fee_in_both_modes() {
+    // out = rout / (rin + 1) floors to 0 in every case but the first
+    for (rin, rout) in [(10, 1_000_000), (1_000, 10), (1_000_000_000, 1), (u64::MAX as u128, 3)] {
+        let ((proxy_fee, proxy_slip), (impact_fee, impact_slip)) = both_modes(1, rin, rout);
+        assert_eq!(proxy_fee, impact_fee, "rin {rin} rout {rout}");
+        assert!(impact_fee < 30_000, "a 1-unit trade priced at the max fee");
+        assert!(impact_slip - proxy_slip <= 1);
+    }
+    assert_eq!(price_impact_1e12(1, 1_000_000_000, 1).unwrap(), 1_000);
+}
+
+#[test]
+fn the_modes_differ_only_by_rounding_across_trade_sizes() {
+    let (rin, rout) = (1_000_000_000u128, 3_000_000_000u128);
+    let mut amount_in = 1u128;
+    while amount_in <= 1_000_000_000_000 {
+        let ((proxy_fee, proxy_slip), (impact_fee, impact_slip)) = both_modes(amount_in, rin, rout);
+        assert!(impact_slip >= proxy_slip && impact_slip - proxy_slip <= 1, "amount_in {amount_in}");
+        assert!(impact_fee >= proxy_fee && impact_fee - proxy_fee <= 1, "amount_in {amount_in}");
+        amount_in = amount_in * 3 + 1;
+    }
+}
+
+#[test]
+fn large_trades_move_both_signals_towards_one() {
+    let rin = 1_000_000u128;
+    for (amount_in, slip_1e12) in [(rin, SCALE / 2), (9 * rin, SCALE * 9 / 10), (99 * rin, SCALE * 99 / 100)] {
+        let ((_, proxy_slip), (_, impact_slip)) = both_modes(amount_in, rin, rin);
+        assert_eq!((proxy_slip, impact_slip), (slip_1e12, slip_1e12));
+    }
+    // the pool's whole in-side reserve pays the max fee either way
+    let ((proxy_fee, _), (impact_fee, _)) = both_modes(rin * 1_000, rin, rin);
+    assert_eq!((proxy_fee, impact_fee), (30_000, 30_000));
+}
+
+#[tokio::test]
+async fn pool_state_quotes_the_same_minimal_fee_in_both_modes() {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    // a 1-unit trade of token0 buys nothing of token1
+    t.seed_liquidity(1_000_000_000, 10).await;
+    let authority = t.authority.pubkey();
+    let mut quotes = vec![];
+    for slip_mode in [SlipMode::InputFraction, SlipMode::PriceImpact] {
+        t.send_as_authority(&[client::build_set_slip_mode_ix(&PROGRAM_ID, &authority, slip_mode)])
+            .await
+            .unwrap();
+        t.next_slot().await;
+        let out = t.send(&[client::build_get_pool_state_ix(&PROGRAM_ID, &t.keys)], &[]).await.unwrap();
+        let state = PoolState::deserialize(&mut out.return_data.as_deref().unwrap()).unwrap();
+        quotes.push(state.current_min_fee_ppm);
+    }
+    assert_eq!(quotes[0], quotes[1]);
+    assert!(quotes[1] < t.pool().await.fee_bounds_ppm().1);
+}
@@ -0,0 +1,134 @@
+//! Fixtures for the wasm quotes, taken from swaps and deposits the program
+//! executes: `test_vectors/wasm_quotes.json` holds the pool account before
+//! each step and what the program did with it. The JS test in `wasm_tests`
+//! replays them through the wasm build.
+//!
+//! The stored accounts embed the keys of the run that made them, so only
+//! the outcomes are compared with a fresh run; regenerate deliberately with
+//! `cargo test --test wasm_quotes regenerate_fixtures -- --ignored`.
+
+mod common;
+
+use adaptive_cpamm::event_parser::AmmEvent;
+use adaptive_cpamm::{client, wasm, Pool};
+use anchor_lang::AccountDeserialize;
+use base64::Engine;
+use common::*;
+use serde_json::{json, Value};
+use solana_sdk::signature::Signer;
+
+const FIXTURES: &str = include_str!("../test_vectors/wasm_quotes.json");
+const FIXTURES_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_vectors/wasm_quotes.json");
+
+async fn pool_account(t: &mut TestPool) -> Vec<u8> {
+    let address = t.keys.pool;
+    t.account(&address).await.unwrap().data
+}
+
+/// Swap on-chain and record the step, after checking the native build of
+/// `wasm::quote_swap` agrees with the program.
+async fn swap_step(t: &mut TestPool, name: &str, user: &User, token_in_is_0: bool, amount_in: u64) -> Value {
+    t.next_slot().await;
+    let before = pool_account(t).await;
+    let slot = t.clock().await.slot;
+    let out = t.swap(user, token_in_is_0, amount_in, 1).await.unwrap();
+    let event = out.swap_events()[0];
+    let quote = wasm::quote_swap(&before, token_in_is_0, amount_in, slot).unwrap();
+    assert_eq!((quote.amount_out, quote.fee_ppm), (event.amount_out, event.fee_ppm), "{name}");
+    json!({
+        "name": name,
+        "pool_account": base64::engine::general_purpose::STANDARD.encode(&before),
+        "slot": slot.to_string(),
+        "token_in_is_0": token_in_is_0,
+        "amount_in": amount_in.to_string(),
+        "expected": {
+            "amount_out": event.amount_out.to_string(),
+            "fee_ppm": event.fee_ppm,
+            "reversion_penalty_bps": quote.reversion_penalty_bps,
+        },
+    })
+}
+
+/// Deposit on-chain and record the step, checked like `swap_step`.
+async fn deposit_step(t: &mut TestPool, name: &str, amount0: u64, amount1: u64) -> Value {
+    t.next_slot().await;
+    let before = pool_account(t).await;
+    let lp = t.fund_user(amount0, amount1).await;
+    let out = t.add_liquidity(&lp, amount0, amount1).await.unwrap();
+    let [AmmEvent::Mint(event)] = out.events.as_slice() else { panic!("expected one MintEvent") };
+    let quote = wasm::quote_add_liquidity(&before, amount0, amount1).unwrap();
+    assert_eq!((quote.amount0, quote.amount1, quote.shares), (event.amount0, event.amount1, event.shares), "{name}");
+    json!({
+        "name": name,
+        "pool_account": base64::engine::general_purpose::STANDARD.encode(&before),
+        "amount0_desired": amount0.to_string(),
+        "amount1_desired": amount1.to_string(),
+        "expected": {
+            "amount0": event.amount0.to_string(),
+            "amount1": event.amount1.to_string(),
+            "shares": event.shares.to_string(),
+        },
+    })
+}
+
+/// A sandwich and some organic flow on a pool with a round-trip penalty,
+/// then deposits into the moved pool.
+async fn run_fixtures() -> Value {
+    let mut t = TestPool::new(PoolSetup::default()).await;
+    t.seed_liquidity(1_000_000_000, 1_000_000_000).await;
+    let authority = t.authority.pubkey();
+    let penalty = client::build_set_reversion_penalty_ix(&PROGRAM_ID, &authority, 10, 50, 50);
+    t.send_as_authority(&[penalty]).await.unwrap();
+    let attacker = t.fund_user(5_000_000, 0).await;
+    let victim = t.fund_user(2_000_000, 0).await;
+    let seller = t.fund_user(0, 30_000_000).await;
+
+    let mut swaps = vec![swap_step(&mut t, "front-run", &attacker, true, 5_000_000).await];
+    swaps.push(swap_step(&mut t, "victim", &victim, true, 2_000_000).await);
+    let bought = swaps[0]["expected"]["amount_out"].as_str().unwrap().parse().unwrap();
+    swaps.push(swap_step(&mut t, "back-run pays the penalty", &attacker, false, bought).await);
+    swaps.push(swap_step(&mut t, "organic sell", &seller, false, 3_000_000).await);
+    swaps.push(swap_step(&mut t, "large sell", &seller, false, 25_000_000).await);
+
+    // a deposit must match the reserve ratio exactly, as the reserves
+    // themselves always do
+    let pool = t.pool().await;
+    let (r0, r1) = (pool.reserve0, pool.reserve1);
+    let deposits = vec![
+        deposit_step(&mut t, "doubling the pool", r0, r1).await,
+        deposit_step(&mut t, "a third more", r0, r1).await,
+    ];
+    json!({ "quote_swap": swaps, "quote_add_liquidity": deposits })
+}
+
+/// The fixture with the pool accounts left out.
+fn outcomes(fixtures: &Value) -> Value {
+    let mut outcomes = fixtures.clone();
+    for (_, steps) in outcomes.as_object_mut().unwrap() {
+        for step in steps.as_array_mut().unwrap() {
+            step.as_object_mut().unwrap().remove("pool_account");
+        }
+    }
+    outcomes
+}
+
+#[tokio::test]
+async fn frozen_fixtures_match_the_program() {
+    let frozen: Value = serde_json::from_str(FIXTURES).unwrap();
+    assert_eq!(outcomes(&frozen), outcomes(&run_fixtures().await), "regenerate the fixtures");
+    // the stored accounts still decode as a Pool of this version
+    let engine = base64::engine::general_purpose::STANDARD;
+    for steps in [&frozen["quote_swap"], &frozen["quote_add_liquidity"]] {
+        for step in steps.as_array().unwrap() {
+            let bytes = engine.decode(step["pool_account"].as_str().unwrap()).unwrap();
+            Pool::try_deserialize(&mut &bytes[..]).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn regenerate_fixtures() {
+    let json = serde_json::to_string_pretty(&run_fixtures().await).unwrap();
+    std::fs::write(FIXTURES_PATH, json + "\n").unwrap();
+}
@@ -0,0 +1,142 @@
+// The wasm quotes against the program and the math golden vectors. Run
+// through run.sh, which builds the package this loads.
+
+import assert from "node:assert/strict";
+import { readFileSync } from "node:fs";
+import { createRequire } from "node:module";
+import { test } from "node:test";
+
+const require = createRequire(import.meta.url);
+const wasm = require("../target/wasm-pkg/adaptive_cpamm.js");
+
+const vectors = (name) => JSON.parse(readFileSync(new URL(`../test_vectors/${name}`, import.meta.url)));
+const onChain = vectors("wasm_quotes.json");
+const math = vectors("math.json");
+
+const U64_MAX = 2n ** 64n - 1n;
+const fitsU64 = (...values) => values.every((v) => BigInt(v) <= U64_MAX);
+
+// Run `quote` for vector `v`: an error vector must fail with the same
+// MathError, any other must pass `check`.
+function expectQuote(v, quote, check) {
+  if ("error" in v.expected) {
+    assert.throws(quote, { message: v.expected.error }, v.name);
+  } else {
+    check(quote());
+  }
+}
+
+test("quote_swap matches the swaps the program executed", () => {
+  for (const step of onChain.quote_swap) {
+    const pool = Buffer.from(step.pool_account, "base64");
+    const quote = wasm.quote_swap(pool, step.token_in_is_0, BigInt(step.amount_in), BigInt(step.slot));
+    assert.equal(quote.amount_out, BigInt(step.expected.amount_out), step.name);
+    assert.equal(quote.fee_ppm, step.expected.fee_ppm, step.name);
+    assert.equal(quote.reversion_penalty_bps, step.expected.reversion_penalty_bps, step.name);
+  }
+  // the fixtures include a swap the penalty applied to
+  assert.ok(onChain.quote_swap.some((step) => step.expected.reversion_penalty_bps > 0));
+});
+
+test("quote_add_liquidity matches the deposits the program executed", () => {
+  for (const step of onChain.quote_add_liquidity) {
+    const pool = Buffer.from(step.pool_account, "base64");
+    const quote = wasm.quote_add_liquidity(pool, BigInt(step.amount0_desired), BigInt(step.amount1_desired));
+    assert.equal(quote.amount0, BigInt(step.expected.amount0), step.name);
+    assert.equal(quote.amount1, BigInt(step.expected.amount1), step.name);
+    assert.equal(quote.shares, BigInt(step.expected.shares), step.name);
+  }
+});
+
+test("quote_swap_with_fields prices like the compute_dynamic_fee vectors", () => {
+  // the fields API has no oracle term and derives shallow_depth_k from
+  // 6-decimal mints; the quote also runs the swap, so the input must fit
+  // in the vault on top of its reserve
+  const cases = math.compute_dynamic_fee.filter(
+    (v) =>
+      v.oracle_conf_1e12 === "0" &&
+      v.params.shallow_depth_k === "1000000000" &&
+      fitsU64(BigInt(v.amount_in) + BigInt(v.token_in_is_0 ? v.r0 : v.r1), v.r0, v.r1, v.params.shallow_target_depth0),
+  );
+  assert.ok(cases.length >= 10, `only ${cases.length} vectors usable`);
+  for (const v of cases) {
+    const p = v.params;
+    const quote = () => wasm.quote_swap_with_fields(
+      p.min_fee_ppm,
+      p.max_fee_ppm,
+      p.beta_vol_bps_per1e12,
+      p.gamma_slip_bps_per1e12,
+      p.delta_shallow_bps_per1e12,
+      p.epsilon_oracle_bps_per1e12,
+      p.zeta_skew_bps_per1e12,
+      BigInt(p.ema_price_1e18),
+      U64_MAX,
+      p.slip_price_impact,
+      BigInt(p.shallow_target_depth0),
+      BigInt(p.ema_depth0),
+      BigInt(v.r0),
+      BigInt(v.r1),
+      6,
+      6,
+      v.token_in_is_0,
+      BigInt(v.amount_in),
+      false,
+    );
+    const saturated = (value) => (BigInt(value) > U64_MAX ? U64_MAX : BigInt(value));
+    expectQuote(v, quote, (quote) => {
+      assert.equal(quote.fee_ppm, v.expected.fee_ppm, v.name);
+      assert.equal(quote.vol_1e12, saturated(v.expected.vol_1e12), v.name);
+      assert.equal(quote.slip_1e12, saturated(v.expected.slip_1e12), v.name);
+      assert.equal(quote.shallow_1e12, saturated(v.expected.shallow_1e12), v.name);
+    });
+  }
+});
+
+test("quote_swap_with_fields pays out like the get_amount_out vectors", () => {
+  const cases = math.get_amount_out.filter((v) => fitsU64(v.amount_in, v.rin, v.rout));
+  assert.ok(cases.length >= 10, `only ${cases.length} vectors usable`);
+  for (const v of cases) {
+    // a flat fee: every dynamic term off
+    const quote = () => wasm.quote_swap_with_fields(
+      v.fee_ppm,
+      v.fee_ppm,
+      0,
+      0,
+      0,
+      0,
+      0,
+      0n,
+      U64_MAX,
+      false,
+      0n,
+      0n,
+      BigInt(v.rin),
+      BigInt(v.rout),
+      6,
+      6,
+      true,
+      BigInt(v.amount_in),
+      v.fee_on_output,
+    );
+    expectQuote(v, quote, (quote) => assert.equal(quote.amount_out, BigInt(v.expected.amount_out), v.name));
+  }
+});
+
+test("quote_add_liquidity_with_fields mints like the shares_for_deposit vectors", () => {
+  // the quote first trims the deposit to the reserve ratio, so only
+  // deposits already at it map onto the vectors
+  const atRatio = (v) =>
+    BigInt(v.total_supply) === 0n || BigInt(v.reserve0) * BigInt(v.amount1) === BigInt(v.reserve1) * BigInt(v.amount0);
+  const cases = math.shares_for_deposit.filter((v) => "shares" in v.expected && atRatio(v));
+  assert.ok(cases.length >= 3, `only ${cases.length} vectors usable`);
+  for (const v of cases) {
+    const quote = wasm.quote_add_liquidity_with_fields(
+      BigInt(v.reserve0),
+      BigInt(v.reserve1),
+      BigInt(v.total_supply),
+      BigInt(v.amount0),
+      BigInt(v.amount1),
+    );
+    assert.equal(quote.shares, BigInt(v.expected.shares), v.name);
+  }
+});
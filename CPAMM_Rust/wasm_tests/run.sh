@@ -0,0 +1,10 @@
+#!/usr/bin/env bash
+# Build the wasm quoting API for node and replay the fixtures through it.
+# Needs the wasm32-unknown-unknown target and the wasm-bindgen CLI of the
+# version Cargo.toml pins (cargo install wasm-bindgen-cli --version 0.2.100).
+set -euo pipefail
+cd "$(dirname "$0")/.."
+cargo build --lib --release --target wasm32-unknown-unknown --features wasm
+wasm-bindgen --target nodejs --out-dir target/wasm-pkg \
+    target/wasm32-unknown-unknown/release/adaptive_cpamm.wasm
+node --test wasm_tests/